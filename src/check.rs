@@ -0,0 +1,271 @@
+// check.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Validation of the task and UI state files.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::io::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::ser::state::UiState as SerUiState;
+use crate::ser::tags::Id as SerTagId;
+use crate::ser::tags::Templates as SerTemplates;
+use crate::ser::tasks::Tasks as SerTasks;
+use crate::state::load_versioned_state;
+use crate::state::State;
+use crate::storage;
+use crate::tags::COMPLETE_TAG;
+
+
+/// Check the tag templates defined in a task file for duplicates.
+fn check_templates(templates: &SerTemplates, path: &Path, problems: &mut Vec<String>) {
+  let mut ids = BTreeMap::<SerTagId, usize>::new();
+  let mut names = BTreeMap::<&str, usize>::new();
+  for template in templates.0.iter() {
+    *ids.entry(template.id).or_default() += 1;
+    *names.entry(&template.name).or_default() += 1;
+  }
+
+  for (id, count) in ids.into_iter().filter(|(_, count)| *count > 1) {
+    problems.push(format!("{}: Tag Id {} is used by {} templates", path.display(), id, count));
+  }
+  for (name, count) in names.into_iter().filter(|(_, count)| *count > 1) {
+    let problem = if name == COMPLETE_TAG {
+      format!("{}: Found {} '{}' templates", path.display(), count, name)
+    } else {
+      format!("{}: Tag name '{}' is used by {} templates", path.display(), name, count)
+    };
+    problems.push(problem);
+  }
+}
+
+/// Check that the given tasks only refer to known tags.
+fn check_tasks(tasks: &SerTasks,
+               ids: &BTreeSet<SerTagId>,
+               path: &Path,
+               problems: &mut Vec<String>) {
+  for task in tasks.0.iter() {
+    for tag in task.tags.iter().filter(|x| !ids.contains(&x.id)) {
+      problems.push(format!(
+        "{}: Task '{}' refers to unknown tag Id {}", path.display(), task.summary, tag.id
+      ));
+    }
+  }
+}
+
+/// Check the UI state for references to unknown tags and task files as
+/// well as for invalid selections.
+///
+/// `ids` contains the IDs of all tags defined by the task files and
+/// `tags` their names.
+fn check_ui_state(ui_state: &SerUiState,
+                  ids: &BTreeSet<SerTagId>,
+                  tags: &BTreeSet<String>,
+                  names: &[String],
+                  path: &Path,
+                  problems: &mut Vec<String>) {
+  // If the UI state lists the templates of the tags its queries refer
+  // to, those are matched by name.
+  let ids = if ui_state.templates.is_empty() {
+    ids.clone()
+  } else {
+    for template in ui_state.templates.0.iter().filter(|x| !tags.contains(&x.name)) {
+      problems.push(format!("{}: Tag '{}' is not defined", path.display(), template.name));
+    }
+    ui_state.templates.0.iter().map(|x| x.id).collect()
+  };
+
+  for (query, _) in ui_state.queries.iter() {
+    let tags = query.lits.iter().flatten().filter_map(|x| x.tag());
+    for tag in tags.filter(|x| !ids.contains(&x.id)) {
+      problems.push(format!(
+        "{}: Query '{}' refers to unknown tag Id {}", path.display(), query.name, tag.id
+      ));
+    }
+
+    if let Some(source) = &query.source {
+      if !names.contains(source) {
+        problems.push(format!(
+          "{}: Query '{}' refers to unknown task file '{}'", path.display(), query.name, source
+        ));
+      }
+    }
+  }
+
+  if let Some(selected) = ui_state.selected {
+    // Without any queries configured a default one is used.
+    let count = ui_state.queries.len().max(1);
+    if selected >= count {
+      problems.push(format!(
+        "{}: Selected query index {} is out of range (have {} queries)",
+        path.display(), selected, count
+      ));
+    }
+  }
+}
+
+
+/// Check task files, each along with the file storing its archive, and
+/// the UI state file for problems, without loading them into the UI.
+///
+/// The return value contains a description of each problem found. An
+/// error is returned only if one of the files could not be read or
+/// parsed at all.
+pub fn check<P>(files: Vec<(P, P)>, ui_path: P) -> Result<Vec<String>>
+where
+  P: Into<PathBuf> + AsRef<Path>,
+{
+  let mut problems = Vec::new();
+  let mut ids = BTreeSet::new();
+  let mut tags = BTreeSet::new();
+  let mut names = Vec::new();
+
+  let mut stores = Vec::with_capacity(files.len());
+
+  for (task_path, archive_path) in files {
+    // Checking must not alter anything, including creating a database
+    // that does not exist.
+    let store = storage::open_read_only(task_path.as_ref(), archive_path.as_ref())?;
+    let (task_state, archive) = store.load()?;
+
+    check_templates(&task_state.templates, task_path.as_ref(), &mut problems);
+
+    let file_ids = task_state.templates.0.iter().map(|x| x.id).collect();
+    check_tasks(&task_state.tasks, &file_ids, task_path.as_ref(), &mut problems);
+    check_tasks(&archive, &file_ids, archive_path.as_ref(), &mut problems);
+
+    ids.extend(file_ids);
+    tags.extend(task_state.templates.0.iter().map(|x| x.name.clone()));
+    names.push(
+      task_path
+        .as_ref()
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default(),
+    );
+    stores.push((store, task_state, task_path, archive, archive_path));
+  }
+
+  let ui_state = load_versioned_state::<SerUiState>(ui_path.as_ref())?;
+  check_ui_state(&ui_state, &ids, &tags, &names, ui_path.as_ref(), &mut problems);
+
+  // Everything else, e.g., the hierarchy of tasks or their due dates,
+  // is validated when loading the state, which we only attempt once the
+  // basic structure is known to be sound.
+  if problems.is_empty() {
+    if let Err(err) = State::with_serde_stores(stores, ui_state, ui_path, false) {
+      problems.push(err.to_string())
+    }
+  }
+  Ok(problems)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::fs::write;
+
+  use crate::test::NamedTempFile;
+
+
+  /// Check the given task, archive, and UI state file contents.
+  fn check_contents(tasks: &str, archive: &str, ui: &str) -> Vec<String> {
+    let task_file = NamedTempFile::new();
+    let archive_file = NamedTempFile::new();
+    let ui_file = NamedTempFile::new();
+    write(task_file.path(), tasks).unwrap();
+    write(archive_file.path(), archive).unwrap();
+    write(ui_file.path(), ui).unwrap();
+
+    let files = vec![(task_file.path(), archive_file.path())];
+    let problems = check(files, ui_file.path()).unwrap();
+    // Strip the temporary paths, they are not known in advance.
+    problems
+      .into_iter()
+      .map(|x| x.splitn(2, ": ").last().unwrap().to_string())
+      .collect()
+  }
+
+
+  #[test]
+  fn check_fixtures() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+
+    for version in &["v0-0.2.0", "v0-latest", "v1", "v1-readable", "v2"] {
+      let dir = fixtures.join(version);
+      let files = vec![(dir.join("tasks.json"), dir.join("archive.json"))];
+      let problems = check(files, dir.join("notnow.json")).unwrap();
+      assert!(problems.is_empty(), "{}: {:?}", version, problems);
+    }
+  }
+
+  #[test]
+  fn check_problems() {
+    let tasks = r#"{
+      "templates": [
+        {"id": 1, "name": "complete"},
+        {"id": 2, "name": "complete"},
+        {"id": 3, "name": "work"},
+        {"id": 3, "name": "home"},
+        {"id": 4, "name": "home"}
+      ],
+      "tasks": [
+        {"summary": "a task", "tags": [{"id": 3}, {"id": 5}]}
+      ]
+    }"#;
+    let archive = r#"[
+      {"summary": "an archived task", "tags": [{"id": 6}]}
+    ]"#;
+    let ui = r#"{
+      "queries": [
+        [{"name": "work", "lits": [[{"Pos": {"id": 3}}, {"Neg": {"id": 7}}]]}, null],
+        [{"name": "team", "lits": [], "source": "team"}, null]
+      ],
+      "selected": 2
+    }"#;
+
+    let problems = check_contents(tasks, archive, ui);
+    let expected = vec![
+      "Tag Id 3 is used by 2 templates",
+      "Found 2 'complete' templates",
+      "Tag name 'home' is used by 2 templates",
+      "Task 'a task' refers to unknown tag Id 5",
+      "Task 'an archived task' refers to unknown tag Id 6",
+      "Query 'work' refers to unknown tag Id 7",
+      "Query 'team' refers to unknown task file 'team'",
+      "Selected query index 2 is out of range (have 2 queries)",
+    ];
+    assert_eq!(problems, expected);
+  }
+
+  #[test]
+  fn check_loading() {
+    let tasks = r#"{"tasks": [{"summary": "a task", "due": "tomorrow"}]}"#;
+    let problems = check_contents(tasks, "[]", "{}");
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].starts_with("Encountered invalid date"), "{}", problems[0]);
+
+    let problems = check_contents(r#"{"tasks": []}"#, "[]", r#"{"selected": 0}"#);
+    assert!(problems.is_empty(), "{:?}", problems);
+  }
+}
@@ -0,0 +1,360 @@
+// date.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Calendar dates without a time component.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::mem::MaybeUninit;
+use std::ptr::null_mut;
+use std::str::FromStr;
+
+use libc::localtime_r;
+use libc::time;
+
+
+/// Check whether the given year is a leap year.
+fn is_leap_year(year: u16) -> bool {
+  (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Retrieve the number of days in the given month of the given year.
+fn days_in_month(year: u16, month: u8) -> u8 {
+  match month {
+    1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+    4 | 6 | 9 | 11 => 30,
+    2 if is_leap_year(year) => 29,
+    2 => 28,
+    _ => 0,
+  }
+}
+
+
+/// A day of the week.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[allow(missing_docs)]
+pub enum Weekday {
+  Monday,
+  Tuesday,
+  Wednesday,
+  Thursday,
+  Friday,
+  Saturday,
+  Sunday,
+}
+
+impl Weekday {
+  /// All weekdays, starting with Monday.
+  const ALL: [Weekday; 7] = [
+    Weekday::Monday,
+    Weekday::Tuesday,
+    Weekday::Wednesday,
+    Weekday::Thursday,
+    Weekday::Friday,
+    Weekday::Saturday,
+    Weekday::Sunday,
+  ];
+}
+
+impl Display for Weekday {
+  /// Format the weekday using its three letter abbreviation.
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    let name = match self {
+      Weekday::Monday => "mon",
+      Weekday::Tuesday => "tue",
+      Weekday::Wednesday => "wed",
+      Weekday::Thursday => "thu",
+      Weekday::Friday => "fri",
+      Weekday::Saturday => "sat",
+      Weekday::Sunday => "sun",
+    };
+    f.write_str(name)
+  }
+}
+
+impl FromStr for Weekday {
+  type Err = Error;
+
+  /// Parse a weekday from its full name or its three letter
+  /// abbreviation, ignoring case.
+  fn from_str(s: &str) -> Result<Self> {
+    let lower = s.to_lowercase();
+    Weekday::ALL
+      .iter()
+      .find(|x| {
+        let abbrev = x.to_string();
+        lower == abbrev || format!("{:?}", x).to_lowercase() == lower
+      })
+      .cloned()
+      .ok_or_else(|| {
+        let error = format!("Encountered invalid weekday '{}'", s);
+        Error::new(ErrorKind::InvalidInput, error)
+      })
+  }
+}
+
+
+/// A calendar date, without any time or time zone information.
+///
+/// Dates are ordered chronologically.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Date {
+  year: u16,
+  month: u8,
+  day: u8,
+}
+
+impl Date {
+  /// The latest date that can be represented, and that date arithmetic
+  /// saturates at.
+  pub const MAX: Date = Date {
+    year: 9999,
+    month: 12,
+    day: 31,
+  };
+
+  /// Create a new `Date` object, if the given components form a valid
+  /// date.
+  pub fn new(year: u16, month: u8, day: u8) -> Option<Date> {
+    if day >= 1 && day <= days_in_month(year, month) {
+      Some(Date { year, month, day })
+    } else {
+      None
+    }
+  }
+
+  /// Retrieve the current date in the local time zone.
+  pub fn today() -> Date {
+    unsafe {
+      let now = time(null_mut());
+      let mut tm = MaybeUninit::uninit();
+      let result = localtime_r(&now, tm.as_mut_ptr());
+      assert!(!result.is_null());
+
+      let tm = tm.assume_init();
+      Date::new((tm.tm_year + 1900) as u16, (tm.tm_mon + 1) as u8, tm.tm_mday as u8).unwrap()
+    }
+  }
+
+  /// Convert the date into the number of days since 1970-01-01.
+  // The algorithm is based on Howard Hinnant's `days_from_civil`, see
+  // http://howardhinnant.github.io/date_algorithms.html
+  fn to_days(self) -> i64 {
+    let month = i64::from(self.month);
+    let year = i64::from(self.year) - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + i64::from(self.day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+  }
+
+  /// Create a date from the number of days since 1970-01-01,
+  /// saturating at `Date::MAX`.
+  // The algorithm is based on Howard Hinnant's `civil_from_days`.
+  fn from_days(days: i64) -> Date {
+    if days >= Date::MAX.to_days() {
+      return Date::MAX
+    }
+
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    Date::new(year as u16, month as u8, day as u8).unwrap()
+  }
+
+  /// Retrieve the day of the week the date falls on.
+  pub fn weekday(self) -> Weekday {
+    // 1970-01-01 was a Thursday.
+    let idx = (self.to_days() + 3).rem_euclid(7);
+    Weekday::ALL[idx as usize]
+  }
+
+  /// Retrieve the date the given number of days after this one, or
+  /// `Date::MAX` if that date can not be represented.
+  pub fn add_days(self, days: u32) -> Date {
+    Date::from_days(self.to_days() + i64::from(days))
+  }
+
+  /// Retrieve the day of the month.
+  pub fn day(self) -> u8 {
+    self.day
+  }
+
+  /// Retrieve the date falling on the given day in the month after
+  /// the one of this date.
+  ///
+  /// If the next month is shorter than `day`, the last day of that
+  /// month is used. Dates past `Date::MAX` saturate at it.
+  pub fn add_month_on(self, day: u8) -> Date {
+    let (year, month) = if self.month == 12 {
+      if self.year >= Date::MAX.year {
+        return Date::MAX
+      }
+      (self.year + 1, 1)
+    } else {
+      (self.year, self.month + 1)
+    };
+    let day = day.max(1).min(days_in_month(year, month));
+
+    Date::new(year, month, day).unwrap()
+  }
+}
+
+impl Display for Date {
+  /// Format the date in ISO 8601 format, i.e., as YYYY-MM-DD.
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+  }
+}
+
+impl FromStr for Date {
+  type Err = Error;
+
+  /// Parse a date in ISO 8601 format, i.e., as YYYY-MM-DD.
+  fn from_str(s: &str) -> Result<Self> {
+    let invalid = || {
+      let error = format!("Encountered invalid date '{}'", s);
+      Error::new(ErrorKind::InvalidInput, error)
+    };
+
+    let mut parts = s.splitn(3, '-');
+    let mut next = |len| {
+      parts
+        .next()
+        .filter(|x: &&str| x.len() == len && x.bytes().all(|b| b.is_ascii_digit()))
+        .ok_or_else(invalid)
+    };
+
+    let year = next(4)?.parse().map_err(|_| invalid())?;
+    let month = next(2)?.parse().map_err(|_| invalid())?;
+    let day = next(2)?.parse().map_err(|_| invalid())?;
+
+    Date::new(year, month, day).ok_or_else(invalid)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  #[test]
+  fn create_date() {
+    assert!(Date::new(2019, 1, 31).is_some());
+    assert!(Date::new(2019, 2, 29).is_none());
+    assert!(Date::new(2020, 2, 29).is_some());
+    assert!(Date::new(1900, 2, 29).is_none());
+    assert!(Date::new(2000, 2, 29).is_some());
+    assert!(Date::new(2019, 4, 31).is_none());
+    assert!(Date::new(2019, 13, 1).is_none());
+    assert!(Date::new(2019, 1, 0).is_none());
+  }
+
+  #[test]
+  fn compare_dates() {
+    let date1 = Date::new(2019, 3, 31).unwrap();
+    let date2 = Date::new(2019, 4, 1).unwrap();
+    let date3 = Date::new(2020, 1, 1).unwrap();
+
+    assert!(date1 < date2);
+    assert!(date2 < date3);
+  }
+
+  #[test]
+  fn date_arithmetic() {
+    let date = Date::new(2019, 12, 31).unwrap();
+    assert_eq!(date.add_days(1), Date::new(2020, 1, 1).unwrap());
+    assert_eq!(date.add_days(60), Date::new(2020, 2, 29).unwrap());
+    assert_eq!(date.add_days(366), Date::new(2020, 12, 31).unwrap());
+    assert_eq!(date.add_month_on(31), Date::new(2020, 1, 31).unwrap());
+
+    let date = Date::new(2019, 1, 31).unwrap();
+    assert_eq!(date.add_month_on(31), Date::new(2019, 2, 28).unwrap());
+
+    let date = Date::new(2019, 2, 28).unwrap();
+    assert_eq!(date.add_month_on(31), Date::new(2019, 3, 31).unwrap());
+    assert_eq!(date.add_month_on(15), Date::new(2019, 3, 15).unwrap());
+
+    let date = Date::new(1970, 1, 1).unwrap();
+    assert_eq!(Date::from_days(date.to_days()), date);
+    assert_eq!(date.to_days(), 0);
+  }
+
+  #[test]
+  fn saturate_date_arithmetic() {
+    let date = Date::new(2019, 12, 31).unwrap();
+    assert_eq!(date.add_days(u32::MAX), Date::MAX);
+
+    let date = Date::new(9999, 12, 30).unwrap();
+    assert_eq!(date.add_days(1), Date::MAX);
+    assert_eq!(date.add_days(2), Date::MAX);
+
+    let date = Date::new(9999, 12, 15).unwrap();
+    assert_eq!(date.add_month_on(15), Date::MAX);
+    assert_eq!(Date::MAX.to_string().parse::<Date>().unwrap(), Date::MAX);
+  }
+
+  #[test]
+  fn date_weekday() {
+    assert_eq!(Date::new(1970, 1, 1).unwrap().weekday(), Weekday::Thursday);
+    assert_eq!(Date::new(2019, 5, 6).unwrap().weekday(), Weekday::Monday);
+    assert_eq!(Date::new(2020, 2, 29).unwrap().weekday(), Weekday::Saturday);
+    assert_eq!(Date::new(1969, 12, 28).unwrap().weekday(), Weekday::Sunday);
+  }
+
+  #[test]
+  fn parse_weekday() {
+    assert_eq!("mon".parse::<Weekday>().unwrap(), Weekday::Monday);
+    assert_eq!("Sunday".parse::<Weekday>().unwrap(), Weekday::Sunday);
+    assert_eq!("THU".parse::<Weekday>().unwrap(), Weekday::Thursday);
+    assert!("mo".parse::<Weekday>().is_err());
+    assert_eq!(Weekday::Friday.to_string(), "fri");
+  }
+
+  #[test]
+  fn format_parse_date() {
+    let date = Date::new(2019, 5, 7).unwrap();
+    let string = date.to_string();
+    assert_eq!(string, "2019-05-07");
+    assert_eq!(string.parse::<Date>().unwrap(), date);
+  }
+
+  #[test]
+  fn parse_invalid_date() {
+    assert!("".parse::<Date>().is_err());
+    assert!("2019".parse::<Date>().is_err());
+    assert!("2019-5-7".parse::<Date>().is_err());
+    assert!("2019-02-30".parse::<Date>().is_err());
+    assert!("2019-02-01-".parse::<Date>().is_err());
+    assert!("+019-02-01".parse::<Date>().is_err());
+  }
+}
@@ -0,0 +1,539 @@
+// history.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Recording of the task and UI state in a local git repository.
+
+use std::collections::BTreeMap;
+use std::fs::canonicalize;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Output;
+use std::rc::Rc;
+
+use cell::RefCell;
+
+use serde_json::from_slice;
+use serde_json::Value;
+
+use crate::state::parse_task_file;
+use crate::tags::TagMap;
+use crate::tags::Templates;
+use crate::tasks::Id as TaskId;
+use crate::tasks::Task;
+use crate::tasks::Tasks;
+
+
+/// The summary and completion state of every task, keyed by task ID.
+type Snapshot = BTreeMap<TaskId, (String, bool)>;
+
+/// Capture the summary and completion state of the given tasks.
+fn snapshot<'t>(tasks: impl Iterator<Item=&'t Task>) -> Snapshot {
+  tasks
+    .map(|task| (task.id(), (task.summary.clone(), task.is_complete())))
+    .collect()
+}
+
+
+/// Pluralize the word "task" according to the given count.
+fn tasks(count: usize) -> String {
+  if count == 1 {
+    "1 task".to_string()
+  } else {
+    format!("{} tasks", count)
+  }
+}
+
+
+/// The changes to the tasks between two saves.
+#[derive(Debug, Default, PartialEq)]
+struct Changes {
+  /// The summaries of the added tasks.
+  added: Vec<String>,
+  /// The summaries of the removed tasks.
+  removed: Vec<String>,
+  /// The summaries of the tasks that got completed.
+  completed: Vec<String>,
+}
+
+impl Changes {
+  /// Determine the changes between two snapshots.
+  ///
+  /// Tasks moving between the task list and the archive are neither
+  /// added nor removed, as both are part of a snapshot.
+  fn between(old: &Snapshot, new: &Snapshot) -> Self {
+    let mut changes = Changes::default();
+    for (id, (summary, complete)) in new.iter() {
+      match old.get(id) {
+        None => changes.added.push(summary.clone()),
+        Some((_, false)) if *complete => changes.completed.push(summary.clone()),
+        Some(_) => (),
+      }
+    }
+    changes.removed = old
+      .iter()
+      .filter(|(id, _)| !new.contains_key(id))
+      .map(|(_, (summary, _))| summary.clone())
+      .collect();
+    changes
+  }
+
+  /// Create a commit message describing the changes.
+  ///
+  /// The subject summarizes the changes, while the body lists every
+  /// affected task.
+  fn message(&self) -> String {
+    let lines = self
+      .added
+      .iter()
+      .map(|x| format!("Add task '{}'", x))
+      .chain(self.removed.iter().map(|x| format!("Remove task '{}'", x)))
+      .chain(self.completed.iter().map(|x| format!("Complete task '{}'", x)))
+      .collect::<Vec<_>>();
+
+    match lines.as_slice() {
+      [] => "Update tasks".to_string(),
+      [line] => line.clone(),
+      lines => {
+        let subject = [
+          ("Add", self.added.len()),
+          ("remove", self.removed.len()),
+          ("complete", self.completed.len()),
+        ]
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(verb, count)| format!("{} {}", verb, tasks(*count)))
+        .collect::<Vec<_>>()
+        .join(", ");
+        // Only the first verb is capitalized.
+        let mut chars = subject.chars();
+        let subject = match chars.next() {
+          Some(c) => c.to_uppercase().chain(chars).collect::<String>(),
+          None => subject,
+        };
+        format!("{}\n\n{}", subject, lines.join("\n"))
+      },
+    }
+  }
+}
+
+
+/// A version of the task and UI state, as recorded in the history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Version {
+  /// The ID of the commit recording the version.
+  pub rev: String,
+  /// The date and time at which the version got saved.
+  pub date: String,
+  /// The first line of the commit message.
+  pub summary: String,
+}
+
+
+/// A git repository stored locally.
+#[derive(Debug)]
+struct Repository {
+  dir: PathBuf,
+}
+
+impl Repository {
+  /// Create a `git` command operating on the repository.
+  fn git(&self, args: &[&str]) -> Command {
+    let mut command = Command::new("git");
+    let _ = command.arg("-C").arg(&self.dir).args(args);
+    command
+  }
+
+  /// Run a `git` command, failing if it reports an error.
+  fn run(mut command: Command) -> Result<Output> {
+    let output = command.output().map_err(|err| {
+      Error::new(err.kind(), format!("Failed to run git: {}", err))
+    })?;
+    if !output.status.success() {
+      let stderr = String::from_utf8_lossy(&output.stderr);
+      let error = format!("git failed: {}", stderr.trim());
+      return Err(Error::new(ErrorKind::Other, error))
+    }
+    Ok(output)
+  }
+
+  /// Check whether a `git` command succeeds, without treating failure
+  /// as an error.
+  fn check(mut command: Command) -> Result<bool> {
+    let output = command.output().map_err(|err| {
+      Error::new(err.kind(), format!("Failed to run git: {}", err))
+    })?;
+    Ok(output.status.success())
+  }
+
+  /// Check whether the repository exists and has at least one commit.
+  fn has_commits(&self) -> Result<bool> {
+    if !self.dir.join(".git").exists() {
+      return Ok(false)
+    }
+    Self::check(self.git(&["rev-parse", "--quiet", "--verify", "HEAD"]))
+  }
+
+  /// Retrieve the path of a file relative to the repository's
+  /// directory, if it is located inside of it.
+  fn relative(&self, path: &Path) -> Option<PathBuf> {
+    let dir = canonicalize(&self.dir).ok()?;
+    let path = canonicalize(path).ok()?;
+    path.strip_prefix(dir).ok().map(Path::to_path_buf)
+  }
+
+  /// Commit the given files, creating the repository if it does not
+  /// exist yet.
+  ///
+  /// Files located outside of the repository's directory are ignored.
+  /// Nothing is committed if none of the files changed.
+  fn commit(&self, paths: &[&Path], message: &str) -> Result<()> {
+    if !self.dir.join(".git").exists() {
+      let _ = Self::run(self.git(&["init", "--quiet"]))?;
+    }
+
+    let paths = paths
+      .iter()
+      .filter_map(|path| self.relative(path))
+      .collect::<Vec<_>>();
+    if paths.is_empty() {
+      return Ok(())
+    }
+
+    let mut add = self.git(&["add", "--"]);
+    let _ = add.args(&paths);
+    let _ = Self::run(add)?;
+
+    if Self::check(self.git(&["diff", "--cached", "--quiet"]))? {
+      return Ok(())
+    }
+
+    let mut commit = self.git(&[]);
+    // Fall back to a generic identity if the user did not configure
+    // one, so that committing never fails because of it.
+    for (key, value) in &[("user.name", "notnow"), ("user.email", "notnow@localhost")] {
+      if !Self::check(self.git(&["config", key]))? {
+        let _ = commit.arg("-c").arg(format!("{}={}", key, value));
+      }
+    }
+    let _ = commit.args(["commit", "--quiet", "--message", message]);
+    let _ = Self::run(commit)?;
+    Ok(())
+  }
+
+  /// Retrieve all versions, most recent first.
+  fn log(&self) -> Result<Vec<Version>> {
+    if !self.has_commits()? {
+      return Ok(Vec::new())
+    }
+
+    let log = self.git(&["log", "--date=format:%Y-%m-%d %H:%M", "--format=%H%x09%cd%x09%s"]);
+    let output = Self::run(log)?;
+    let versions = String::from_utf8_lossy(&output.stdout)
+      .lines()
+      .filter_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        Some(Version {
+          rev: fields.next()?.to_string(),
+          date: fields.next()?.to_string(),
+          summary: fields.next()?.to_string(),
+        })
+      })
+      .collect();
+    Ok(versions)
+  }
+
+  /// Read the contents of a file as of the given revision.
+  ///
+  /// `None` is returned if the file is not part of the revision.
+  fn read(&self, rev: &str, path: &Path) -> Result<Option<Vec<u8>>> {
+    let path = match self.relative(path) {
+      Some(path) => path,
+      None => return Ok(None),
+    };
+    let object = format!("{}:{}", rev, path.display());
+    if !Self::check(self.git(&["cat-file", "-e", &object]))? {
+      return Ok(None)
+    }
+    let output = Self::run(self.git(&["cat-file", "blob", &object]))?;
+    Ok(Some(output.stdout))
+  }
+}
+
+
+/// The history of the task and UI state, kept in a local git
+/// repository.
+///
+/// Every save is recorded as a commit of the task files, their
+/// archives, and the UI state file, provided they are located inside
+/// the repository's directory.
+#[derive(Debug)]
+pub struct History {
+  repo: Repository,
+  /// The paths to all task files, each along with its archive.
+  files: Vec<(PathBuf, PathBuf)>,
+  /// The path to the file storing the UI state.
+  ui_path: PathBuf,
+  /// The state of the tasks as of the last save.
+  snapshot: RefCell<Snapshot>,
+}
+
+impl History {
+  /// Create a `History` object for the given files, kept in a
+  /// repository in `dir`.
+  ///
+  /// `tasks` and `archive` represent the state the last save
+  /// produced.
+  pub fn new(dir: PathBuf,
+             files: Vec<(PathBuf, PathBuf)>,
+             ui_path: PathBuf,
+             tasks: &Tasks,
+             archive: &Tasks) -> Self {
+    Self {
+      repo: Repository { dir: dir },
+      files: files,
+      ui_path: ui_path,
+      snapshot: RefCell::new(snapshot(tasks.iter().chain(archive.iter()))),
+    }
+  }
+
+  /// Record a new version of the state, which got saved to the files
+  /// already, describing how the tasks changed since the last one.
+  pub fn record(&self, tasks: &Tasks, archive: &Tasks) -> Result<()> {
+    let new = snapshot(tasks.iter().chain(archive.iter()));
+    let message = Changes::between(&self.snapshot.borrow(), &new).message();
+
+    let paths = self
+      .files
+      .iter()
+      .flat_map(|(tasks, archive)| vec![tasks.as_path(), archive.as_path()])
+      .chain(Some(self.ui_path.as_path()))
+      .collect::<Vec<_>>();
+    self.repo.commit(&paths, &message)?;
+    let _ = self.snapshot.replace(new);
+    Ok(())
+  }
+
+  /// Retrieve all recorded versions, most recent first.
+  pub fn versions(&self) -> Result<Vec<Version>> {
+    self.repo.log()
+  }
+
+  /// Retrieve the tasks as of the given version.
+  ///
+  /// Tags are matched to the given templates by name, with tags that
+  /// no longer exist being dropped. The tasks are not part of the
+  /// current state, but can be added to it via `Tasks::adopt`.
+  pub fn tasks(&self, rev: &str, templates: &Rc<Templates>) -> Result<Vec<Task>> {
+    let mut result =
+      Tasks::with_serde(Default::default(), templates.clone(), &Default::default())?;
+
+    for (source, (task_path, archive_path)) in self.files.iter().enumerate() {
+      let task_state = match self.repo.read(rev, task_path)? {
+        Some(data) => from_slice::<Value>(&data)?,
+        None => continue,
+      };
+      let archive = match self.repo.read(rev, archive_path)? {
+        Some(data) => from_slice::<Value>(&data)?,
+        None => Value::Null,
+      };
+      let (task_state, _) = parse_task_file(task_state, archive)?;
+
+      let map = task_state
+        .templates
+        .0
+        .iter()
+        .filter_map(|x| Some((x.id, templates.find(&x.name)?)))
+        .collect::<TagMap>();
+      let mut tasks = task_state.tasks;
+      for task in tasks.0.iter_mut() {
+        task.tags.retain(|tag| map.contains_key(&tag.id));
+      }
+
+      let tasks = Tasks::with_serde(tasks, templates.clone(), &map)?;
+      result.append(tasks, source);
+    }
+
+    Ok(result.iter().cloned().collect())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::fs::write;
+
+  use crate::ser::state::TaskState as SerTaskState;
+  use crate::ser::state::UiState as SerUiState;
+  use crate::ser::tags::Templates as SerTemplates;
+  use crate::ser::tasks::Tasks as SerTasks;
+  use crate::state::State;
+  use crate::test::make_tasks_with_tags;
+  use crate::test::NamedTempFile;
+  use crate::test::TempDir;
+
+
+  /// Create a snapshot of the given summaries and completion states,
+  /// with the tasks identified by the given IDs.
+  fn make_snapshot(tasks: &[(TaskId, &str, bool)]) -> Snapshot {
+    tasks
+      .iter()
+      .map(|(id, summary, complete)| (*id, (summary.to_string(), *complete)))
+      .collect()
+  }
+
+
+  #[test]
+  fn changes_between_snapshots() {
+    let ids = (0..4).map(|_| TaskId::new()).collect::<Vec<_>>();
+    let old = make_snapshot(&[
+      (ids[0], "kept", false),
+      (ids[1], "removed", false),
+      (ids[2], "completed", false),
+    ]);
+    let new = make_snapshot(&[
+      (ids[0], "kept", false),
+      (ids[2], "completed", true),
+      (ids[3], "added", false),
+    ]);
+
+    let changes = Changes::between(&old, &new);
+    let expected = Changes {
+      added: vec!["added".to_string()],
+      removed: vec!["removed".to_string()],
+      completed: vec!["completed".to_string()],
+    };
+    assert_eq!(changes, expected);
+    assert_eq!(Changes::between(&new, &new), Changes::default());
+  }
+
+  #[test]
+  fn describe_changes() {
+    assert_eq!(Changes::default().message(), "Update tasks");
+
+    let changes = Changes {
+      completed: vec!["a".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(changes.message(), "Complete task 'a'");
+
+    let changes = Changes {
+      added: vec!["a".to_string(), "b".to_string()],
+      completed: vec!["c".to_string()],
+      ..Default::default()
+    };
+    let expected = "Add 2 tasks, complete 1 task\n\n\
+                    Add task 'a'\n\
+                    Add task 'b'\n\
+                    Complete task 'c'";
+    assert_eq!(changes.message(), expected);
+
+    let changes = Changes {
+      removed: vec!["a".to_string(), "b".to_string()],
+      ..Default::default()
+    };
+    assert_eq!(changes.message(), "Remove 2 tasks\n\nRemove task 'a'\nRemove task 'b'");
+  }
+
+  #[test]
+  fn commit_only_changed_files_inside_repository() {
+    let dir = TempDir::new();
+    let outside = NamedTempFile::new();
+    let inside = dir.path().join("file");
+    let repo = Repository { dir: dir.path().clone() };
+
+    assert_eq!(repo.log().unwrap(), Vec::new());
+
+    write(&inside, "1").unwrap();
+    repo.commit(&[&inside, outside.path()], "first").unwrap();
+    // Without any changes nothing gets committed.
+    repo.commit(&[&inside, outside.path()], "nothing").unwrap();
+    write(&inside, "2").unwrap();
+    repo.commit(&[&inside], "second").unwrap();
+
+    let versions = repo.log().unwrap();
+    let summaries = versions.iter().map(|x| x.summary.as_str()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["second", "first"]);
+
+    let read = |rev: &str, path: &Path| repo.read(rev, path).unwrap();
+    assert_eq!(read(&versions[0].rev, &inside), Some(b"2".to_vec()));
+    assert_eq!(read(&versions[1].rev, &inside), Some(b"1".to_vec()));
+    assert_eq!(read(&versions[1].rev, outside.path()), None);
+  }
+
+  #[test]
+  fn record_saves_and_retrieve_tasks() {
+    let dir = TempDir::new();
+    let task_path = dir.path().join("tasks.json");
+    let archive_path = dir.path().join("archive.json");
+    let ui_path = dir.path().join("notnow.json");
+
+    let (_, templates, tasks) = make_tasks_with_tags(4);
+    let task_state = SerTaskState {
+      templates: SerTemplates(templates),
+      tasks: SerTasks(tasks),
+      ..Default::default()
+    };
+    let ui_state = SerUiState {
+      history: true,
+      ..Default::default()
+    };
+    let state = State::with_serde(task_state, task_path.clone(),
+                                  Default::default(), archive_path,
+                                  ui_state, ui_path).unwrap();
+    let State(task_state, ui_state) = state;
+    let history = task_state.history().unwrap();
+
+    ui_state.save().unwrap();
+    task_state.save().unwrap();
+
+    {
+      let tasks = task_state.tasks();
+      let mut tasks = tasks.borrow_mut();
+      let ids = tasks.iter().map(Task::id).collect::<Vec<_>>();
+      tasks.remove(ids[0]);
+      tasks.toggle_complete(ids[2]);
+      let _ = tasks.add("5".to_string(), Vec::new(), None, 0);
+    }
+    ui_state.save().unwrap();
+    task_state.save().unwrap();
+
+    let versions = history.versions().unwrap();
+    let summaries = versions.iter().map(|x| x.summary.as_str()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["Add 1 task, remove 1 task, complete 1 task", "Update tasks"]);
+
+    // Tags are matched by name, so old tasks are still known to be
+    // complete.
+    let old = history.tasks(&versions[1].rev, &task_state.templates()).unwrap();
+    let summaries = old.iter().map(|x| x.summary.as_str()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "2", "3", "4"]);
+    let complete = old.iter().map(Task::is_complete).collect::<Vec<_>>();
+    assert_eq!(complete, vec![false, true, false, true]);
+
+    let new = history.tasks(&versions[0].rev, &task_state.templates()).unwrap();
+    let summaries = new.iter().map(|x| x.summary.as_str()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["2", "3", "4", "5"]);
+    assert!(new[1].is_complete());
+  }
+}
@@ -17,6 +17,8 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Unique identifiers for in-memory objects.
+
 pub use uid::Id;
 
 use crate::ser::id::Id as SerId;
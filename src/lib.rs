@@ -0,0 +1,119 @@
+// lib.rs
+
+// *************************************************************************
+// * Copyright (C) 2017-2019 Daniel Mueller (deso@posteo.net)              *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+// We basically deny most lints that "warn" by default, except for
+// those that may change in incompatible ways the future. We want to
+// avoid build breakages when upgrading to new Rust versions.
+#![warn(
+  bad_style,
+  dead_code,
+  future_incompatible,
+  improper_ctypes,
+  late_bound_lifetime_arguments,
+  missing_copy_implementations,
+  missing_debug_implementations,
+  missing_docs,
+  no_mangle_generic_items,
+  non_shorthand_field_patterns,
+  nonstandard_style,
+  overflowing_literals,
+  path_statements,
+  patterns_in_fns_without_body,
+  proc_macro_derive_resolution_fallback,
+  renamed_and_removed_lints,
+  rust_2018_compatibility,
+  rust_2018_idioms,
+  stable_features,
+  trivial_bounds,
+  trivial_numeric_casts,
+  type_alias_bounds,
+  tyvar_behind_raw_pointer,
+  unconditional_recursion,
+  unreachable_code,
+  unreachable_patterns,
+  unstable_features,
+  unstable_name_collisions,
+  unused,
+  unused_comparisons,
+  unused_import_braces,
+  unused_lifetimes,
+  unused_qualifications,
+  unused_results,
+  while_true,
+)]
+#![allow(
+  unreachable_pub,
+  clippy::collapsible_if,
+  clippy::io_other_error,
+  clippy::let_and_return,
+  clippy::let_unit_value,
+  clippy::manual_is_multiple_of,
+  clippy::manual_repeat_n,
+  clippy::new_ret_no_self,
+  clippy::redundant_field_names,
+  clippy::unnecessary_map_or,
+)]
+
+//! The data model of **notnow**, a terminal based task management
+//! application.
+//!
+//! The library allows for working with the files the program stores
+//! its state in, without having to parse them manually. The main entry
+//! point is [`State`][state::State], which loads the task files along
+//! with the UI state:
+//!
+//! ```no_run
+//! use notnow::state::State;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let state = State::with_files(
+//!   vec![("tasks.json", "archive.json")],
+//!   "notnow.json",
+//!   false,
+//! )?;
+//! let State(task_state, _ui_state) = state;
+//!
+//! let tasks = task_state.tasks();
+//! for task in tasks.borrow().iter().filter(|task| !task.is_complete()) {
+//!   println!("{}", task.summary);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Changes are persisted via `TaskState::save` and `UiState::save`.
+//! The [`ser`] module contains the serializable representation of the
+//! state, mirroring the in-memory types provided by [`tasks`],
+//! [`tags`], and [`query`].
+
+pub mod check;
+pub mod date;
+pub mod history;
+pub mod id;
+pub mod query;
+pub mod recurrence;
+pub mod search;
+pub mod ser;
+pub mod state;
+pub mod storage;
+pub mod tags;
+pub mod tasks;
+#[cfg(test)]
+#[allow(unsafe_code)]
+mod test;
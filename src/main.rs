@@ -17,78 +17,32 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
-// We basically deny most lints that "warn" by default, except for
-// those that may change in incompatible ways the future. We want to
-// avoid build breakages when upgrading to new Rust versions.
-#![warn(
-  bad_style,
-  dead_code,
-  future_incompatible,
-  illegal_floating_point_literal_pattern,
-  improper_ctypes,
-  intra_doc_link_resolution_failure,
-  late_bound_lifetime_arguments,
-  missing_copy_implementations,
-  missing_debug_implementations,
-  missing_docs,
-  no_mangle_generic_items,
-  non_shorthand_field_patterns,
-  nonstandard_style,
-  overflowing_literals,
-  path_statements,
-  patterns_in_fns_without_body,
-  plugin_as_library,
-  private_in_public,
-  proc_macro_derive_resolution_fallback,
-  renamed_and_removed_lints,
-  rust_2018_compatibility,
-  rust_2018_idioms,
-  safe_packed_borrows,
-  stable_features,
-  trivial_bounds,
-  trivial_numeric_casts,
-  type_alias_bounds,
-  tyvar_behind_raw_pointer,
-  unconditional_recursion,
-  unions_with_drop_fields,
-  unreachable_code,
-  unreachable_patterns,
-  unstable_features,
-  unstable_name_collisions,
-  unused,
-  unused_comparisons,
-  unused_import_braces,
-  unused_lifetimes,
-  unused_qualifications,
-  unused_results,
-  where_clauses_object_safety,
-  while_true,
-)]
+// Lints are configured in the library; the program merely shares its
+// code style.
 #![allow(
-  unreachable_pub,
   clippy::collapsible_if,
   clippy::let_and_return,
-  clippy::let_unit_value,
-  clippy::new_ret_no_self,
+  clippy::manual_is_multiple_of,
+  clippy::manual_repeat_n,
   clippy::redundant_field_names,
+  clippy::unnecessary_map_or,
 )]
 
 //! A terminal based task management application.
 
-mod id;
-mod query;
 mod resize;
-mod ser;
-mod state;
-mod tags;
-mod tasks;
+// The test utilities are shared with the library, whose modules they
+// refer to relative to the crate root.
 #[cfg(test)]
-#[allow(unsafe_code)]
+#[allow(dead_code, unsafe_code)]
+#[path = "test.rs"]
 mod test;
 mod ui;
 
 use std::alloc::System;
 use std::env::args_os;
+use std::env::var_os;
+use std::ffi::OsString;
 use std::fs::OpenOptions;
 use std::io::Error;
 use std::io::ErrorKind;
@@ -96,6 +50,8 @@ use std::io::Result;
 use std::io::stdin;
 use std::io::stdout;
 use std::io::Write;
+use std::iter::once;
+use std::path::Path;
 use std::path::PathBuf;
 use std::process::exit;
 use std::sync::mpsc::channel;
@@ -107,6 +63,8 @@ use dirs::config_dir;
 
 use termion::event::Event as TermEvent;
 use termion::event::Key;
+use termion::event::MouseEvent;
+use termion::input::MouseTerminal;
 use termion::input::TermReadEventsAndRaw;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
@@ -117,13 +75,21 @@ use gui::Ui;
 use gui::UnhandledEvent;
 use gui::UnhandledEvents;
 
+use notnow::check::check;
+use notnow::state::State;
+use notnow::storage;
+
 use crate::resize::receive_window_resizes;
-use crate::state::State;
 use crate::ui::event::Event as UiEvent;
 use crate::ui::term_renderer::TermRenderer;
 use crate::ui::termui::TermUi;
 use crate::ui::termui::TermUiEvent;
 
+#[cfg(test)]
+use notnow::ser;
+#[cfg(test)]
+use notnow::tags;
+
 // Switch from the default allocator (typically jemalloc) to the system
 // allocator (malloc based on Unix systems). Our application is by no
 // means allocation intensive and the default allocator is typically
@@ -144,35 +110,160 @@ type Continue = Option<bool>;
 pub enum Event {
   /// A key that has been received, including the raw input data.
   Key(Key, Vec<u8>),
+  /// A mouse event that has been received.
+  Mouse(MouseEvent),
   /// The window has been resized.
   Resize,
 }
 
 
-/// Retrieve the path to the program's task configuration file.
-fn task_config() -> Result<PathBuf> {
-  Ok(
-    config_dir()
-      .ok_or_else(|| Error::new(
-        ErrorKind::NotFound, "Unable to determine config directory"
-      ))?
-      .join("notnow")
-      .join("tasks.json"),
-  )
+/// The name of the environment variable selecting the profile to use.
+const PROFILE_VAR: &str = "NOTNOW_PROFILE";
+
+
+/// The paths to the files the program's state is stored in.
+#[derive(Debug, PartialEq)]
+struct Paths {
+  tasks: PathBuf,
+  archive: PathBuf,
+  ui: PathBuf,
+  /// Additional task files to open, along with their archives.
+  others: Vec<(PathBuf, PathBuf)>,
+}
+
+
+/// The arguments the program got invoked with.
+#[derive(Debug, PartialEq)]
+struct Args {
+  paths: Paths,
+  /// Whether to check the files for problems instead of running the
+  /// UI.
+  check: bool,
+  /// Whether to drop references to undefined tags instead of refusing
+  /// to load the files.
+  tolerant: bool,
+  /// The path to the terminal to render to, if not standard output.
+  out: Option<OsString>,
 }
 
-/// Retrieve the path to the UI's configuration file.
-fn ui_config() -> Result<PathBuf> {
+
+/// Retrieve the path to the program's configuration directory.
+fn config() -> Result<PathBuf> {
   Ok(
     config_dir()
       .ok_or_else(|| Error::new(
         ErrorKind::NotFound, "Unable to determine config directory"
       ))?
-      .join("notnow")
-      .join("notnow.json"),
+      .join("notnow"),
   )
 }
 
+/// Derive the path of the archive belonging to the given task file,
+/// i.e., `<name>-archive.json` next to it.
+fn archive_path(tasks: &Path) -> PathBuf {
+  let stem = tasks.file_stem().unwrap_or_default().to_string_lossy();
+  tasks.with_file_name(format!("{}-archive.json", stem))
+}
+
+/// Parse the program's arguments (excluding the program name).
+///
+/// Files not specified explicitly are located in the directory of the
+/// selected profile below the configuration directory, or directly in
+/// the latter if no profile is selected. The archive is kept next to an
+/// explicitly specified task file, named after it. A profile keeps its
+/// tasks in a database instead of a JSON file once it has one, which
+/// `--database` creates.
+/// `profile` is the profile to use if none is given as an argument.
+fn parse_args<I, F>(args: I, mut profile: Option<OsString>, config: F) -> Result<Args>
+where
+  I: IntoIterator<Item=OsString>,
+  F: FnOnce() -> Result<PathBuf>,
+{
+  let mut tasks = None;
+  let mut archive = None;
+  let mut ui = None;
+  let mut others = Vec::new();
+  let mut database = false;
+  let mut check = false;
+  let mut tolerant = false;
+  let mut out = None;
+
+  let mut args = args.into_iter();
+  while let Some(arg) = args.next() {
+    let mut value = || {
+      args.next().ok_or_else(|| {
+        let error = format!("Missing value for argument '{}'", arg.to_string_lossy());
+        Error::new(ErrorKind::InvalidInput, error)
+      })
+    };
+
+    match arg.to_str() {
+      Some("--profile") => profile = Some(value()?),
+      Some("--tasks") => tasks = Some(PathBuf::from(value()?)),
+      Some("--archive") => archive = Some(PathBuf::from(value()?)),
+      Some("--ui") => ui = Some(PathBuf::from(value()?)),
+      Some("--database") => database = true,
+      Some("--tolerant") => tolerant = true,
+      Some("--with") => {
+        // The archive of an additional task file is kept next to it.
+        let path = PathBuf::from(value()?);
+        let archive = archive_path(&path);
+        others.push((path, archive))
+      },
+      Some(option) if option.starts_with("--") => {
+        let error = format!("Encountered unsupported argument '{}'", option);
+        return Err(Error::new(ErrorKind::InvalidInput, error))
+      },
+      Some("check") if !check && out.is_none() => check = true,
+      _ if !check && out.is_none() => out = Some(arg),
+      _ => return Err(Error::new(ErrorKind::InvalidInput, "unsupported number of arguments")),
+    }
+  }
+
+  if database && tasks.is_some() {
+    let error = "--database can not be used together with --tasks";
+    return Err(Error::new(ErrorKind::InvalidInput, error))
+  }
+
+  let archive = archive.or_else(|| tasks.as_deref().map(archive_path));
+  let paths = match (tasks, archive, ui) {
+    (Some(tasks), Some(archive), Some(ui)) => Paths { tasks, archive, ui, others },
+    (tasks, archive, ui) => {
+      let mut dir = config()?;
+      if let Some(profile) = profile {
+        // A profile names a directory, it must not be used to refer to
+        // arbitrary paths.
+        let invalid = Path::new(&profile).components().count() != 1 ||
+                      profile == "." || profile == "..";
+        if invalid {
+          let error = format!("Encountered invalid profile '{}'", profile.to_string_lossy());
+          return Err(Error::new(ErrorKind::InvalidInput, error))
+        }
+        dir = dir.join(profile);
+      }
+
+      // The choice of a database sticks with the profile, as its
+      // presence selects it.
+      let tasks = tasks.unwrap_or_else(|| {
+        let path = dir.join("tasks.db");
+        if database || path.exists() {
+          path
+        } else {
+          dir.join("tasks.json")
+        }
+      });
+      Paths {
+        tasks: tasks,
+        archive: archive.unwrap_or_else(|| dir.join("archive.json")),
+        ui: ui.unwrap_or_else(|| dir.join("notnow.json")),
+        others: others,
+      }
+    },
+  };
+
+  Ok(Args { paths, check, tolerant, out })
+}
+
 /// Handle the given `UnhandledEvent`.
 fn handle_unhandled_event(event: UnhandledEvent<UiEvent>) -> Continue {
   match event {
@@ -203,13 +294,15 @@ fn handle_unhandled_events(events: UnhandledEvents<UiEvent>) -> Continue {
   }
 }
 
-/// Instantiate a key receiver thread and have it send key events through the given channel.
-fn receive_keys(send_event: Sender<Result<Event>>) {
+/// Instantiate an input receiver thread and have it send key and mouse
+/// events through the given channel.
+fn receive_input(send_event: Sender<Result<Event>>) {
   let _ = thread::spawn(move || {
     let events = stdin().events_and_raw();
     for event in events {
       let result = match event {
         Ok((TermEvent::Key(key), data)) => Ok(Event::Key(key, data)),
+        Ok((TermEvent::Mouse(mouse), _)) => Ok(Event::Mouse(mouse)),
         Ok(..) => continue,
         Err(err) => Err(err)
       };
@@ -236,7 +329,7 @@ where
     // point.
     let event = recv_event.recv().unwrap();
     for event in Some(event).into_iter().chain(recv_event.try_iter()) {
-      match event? {
+      let event = match event? {
         Event::Key(key, raw) => {
           // Attempt to convert the key. If we fail the reason could be that
           // the key is not supported. We just ignore the failure. The UI
@@ -245,15 +338,20 @@ where
           let event = { let _ = raw; UiEvent::Key(key, ()) };
           #[cfg(feature = "readline")]
           let event = UiEvent::Key(key, raw);
-
-          if let Some(event) = ui.handle(event) {
-            match handle_unhandled_events(event) {
-              Some(update) => render = update || render,
-              None => break 'handler,
-            }
-          }
+          event
+        },
+        Event::Mouse(mouse) => UiEvent::Mouse(mouse),
+        Event::Resize => {
+          render = true;
+          continue
         },
-        Event::Resize => render = true,
+      };
+
+      if let Some(event) = ui.handle(event) {
+        match handle_unhandled_events(event) {
+          Some(update) => render = update || render,
+          None => break 'handler,
+        }
       }
     }
 
@@ -264,24 +362,47 @@ where
   Ok(())
 }
 
+/// Retrieve the paths to all task files along with their archives.
+fn task_files(paths: &Paths) -> Vec<(&PathBuf, &PathBuf)> {
+  once((&paths.tasks, &paths.archive))
+    .chain(paths.others.iter().map(|(tasks, archive)| (tasks, archive)))
+    .collect()
+}
+
+/// Check the files for problems, reporting each on standard output.
+///
+/// The return value indicates whether the files are free of problems.
+fn run_check(paths: &Paths) -> Result<bool> {
+  let problems = check(task_files(paths), &paths.ui)?;
+  problems.iter().for_each(|problem| println!("{}", problem));
+  Ok(problems.is_empty())
+}
+
 /// Run the program.
-fn run_prog<W>(out: W) -> Result<()>
+fn run_prog<W>(out: W, paths: &Paths, tolerant: bool) -> Result<()>
 where
   W: Write,
 {
-  let task_path = task_config()?;
-  let ui_path = ui_config()?;
+  // A database that does not exist yet is populated from the JSON task
+  // file of the same name, easing the migration to it. The imported
+  // files are renamed, so that they no longer get picked up.
+  for (tasks, archive) in task_files(paths) {
+    if storage::is_database(tasks) {
+      let _ = storage::import(tasks, &tasks.with_extension("json"), archive)?;
+    }
+  }
 
-  let mut state = Some(State::new(&task_path, &ui_path)?);
-  let screen = AlternateScreen::from(out.into_raw_mode()?);
+  let mut state = Some(State::with_files(task_files(paths), &paths.ui, tolerant)?);
+  let screen = AlternateScreen::from(MouseTerminal::from(out.into_raw_mode()?));
   let renderer = TermRenderer::new(screen)?;
+  let layout = renderer.layout();
   let (ui, _) = Ui::new(&mut |id, cap| {
-    Box::new(TermUi::new(id, cap, state.take().unwrap()))
+    Box::new(TermUi::new(id, cap, state.take().unwrap(), layout.clone()))
   });
 
   let (send_event, recv_event) = channel();
   receive_window_resizes(send_event.clone())?;
-  receive_keys(send_event);
+  receive_input(send_event);
 
   // Initially we need to trigger a render in order to have the most
   // recent data presented.
@@ -291,23 +412,32 @@ where
 }
 
 /// Parse the arguments and run the program.
-fn run_with_args() -> Result<()> {
-  let mut it = args_os();
-  match it.len() {
-    0 | 1 => run_prog(stdout()),
-    2 => {
-      let path = it.nth(1).unwrap();
+///
+/// The return value indicates whether the program succeeded, which
+/// is not the case if a check found problems.
+fn run_with_args() -> Result<bool> {
+  let profile = var_os(PROFILE_VAR).filter(|x| !x.is_empty());
+  let Args { paths, check, tolerant, out } = parse_args(args_os().skip(1), profile, config)?;
+
+  if check {
+    return run_check(&paths)
+  }
+
+  match out {
+    None => run_prog(stdout(), &paths, tolerant)?,
+    Some(path) => {
       let file = OpenOptions::new().read(false).write(true).open(path)?;
-      run_prog(file)
+      run_prog(file, &paths, tolerant)?
     },
-    _ => Err(Error::new(ErrorKind::InvalidInput, "unsupported number of arguments")),
   }
+  Ok(true)
 }
 
 /// Run the program and handle errors.
 fn run() -> i32 {
   match run_with_args() {
-    Ok(_) => 0,
+    Ok(true) => 0,
+    Ok(false) => 1,
     Err(err) => {
       eprintln!("Error: {}", err);
       1
@@ -318,3 +448,170 @@ fn run() -> i32 {
 fn main() {
   exit(run());
 }
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::env::temp_dir;
+  use std::fs::create_dir_all;
+  use std::fs::remove_dir_all;
+  use std::fs::File;
+  use std::process;
+
+
+  /// Parse the given arguments, using `/config` as the configuration
+  /// directory.
+  fn parse(args: &[&str], profile: Option<&str>) -> Result<Args> {
+    let args = args.iter().map(OsString::from).collect::<Vec<_>>();
+    let profile = profile.map(OsString::from);
+    parse_args(args, profile, || Ok(PathBuf::from("/config")))
+  }
+
+  #[test]
+  fn parse_no_args() {
+    let args = parse(&[], None).unwrap();
+    let expected = Args {
+      paths: Paths {
+        tasks: PathBuf::from("/config/tasks.json"),
+        archive: PathBuf::from("/config/archive.json"),
+        ui: PathBuf::from("/config/notnow.json"),
+        others: Vec::new(),
+      },
+      check: false,
+      tolerant: false,
+      out: None,
+    };
+    assert_eq!(args, expected);
+  }
+
+  #[test]
+  fn parse_profile() {
+    let expected = Paths {
+      tasks: PathBuf::from("/config/work/tasks.json"),
+      archive: PathBuf::from("/config/work/archive.json"),
+      ui: PathBuf::from("/config/work/notnow.json"),
+      others: Vec::new(),
+    };
+
+    let args = parse(&["--profile", "work"], None).unwrap();
+    assert_eq!(args.paths, expected);
+
+    let args = parse(&[], Some("work")).unwrap();
+    assert_eq!(args.paths, expected);
+
+    // An explicitly specified profile overrules the default one.
+    let args = parse(&["--profile", "work"], Some("home")).unwrap();
+    assert_eq!(args.paths, expected);
+
+    assert!(parse(&["--profile", "../work"], None).is_err());
+    assert!(parse(&["--profile", ".."], None).is_err());
+    assert!(parse(&["--profile", ""], None).is_err());
+  }
+
+  #[test]
+  fn parse_profile_with_database() {
+    let args = parse(&["--profile", "work", "--database"], None).unwrap();
+    let expected = Paths {
+      tasks: PathBuf::from("/config/work/tasks.db"),
+      archive: PathBuf::from("/config/work/archive.json"),
+      ui: PathBuf::from("/config/work/notnow.json"),
+      others: Vec::new(),
+    };
+    assert_eq!(args.paths, expected);
+
+    let err = parse(&["--database", "--tasks", "tasks.db"], None).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn parse_profile_with_existing_database() {
+    let config = temp_dir().join(format!("notnow-config-{}", process::id()));
+    create_dir_all(config.join("work")).unwrap();
+    let _ = File::create(config.join("work").join("tasks.db")).unwrap();
+
+    let parse = |profile| {
+      let profile = Some(OsString::from(profile));
+      parse_args(Vec::new(), profile, || Ok(config.clone()))
+    };
+    // A profile keeps using its database without `--database`.
+    let work = parse("work");
+    let home = parse("home");
+    remove_dir_all(&config).unwrap();
+
+    assert_eq!(work.unwrap().paths.tasks, config.join("work").join("tasks.db"));
+    assert_eq!(home.unwrap().paths.tasks, config.join("home").join("tasks.json"));
+  }
+
+  #[test]
+  fn parse_paths() {
+    let args = parse(&["--tasks", "todo/tasks.json", "--ui", "ui.json"], None).unwrap();
+    let expected = Paths {
+      tasks: PathBuf::from("todo/tasks.json"),
+      archive: PathBuf::from("todo/tasks-archive.json"),
+      ui: PathBuf::from("ui.json"),
+      others: Vec::new(),
+    };
+    assert_eq!(args.paths, expected);
+
+    let args = parse(&["--ui", "ui.json", "--profile", "work"], None).unwrap();
+    let expected = Paths {
+      tasks: PathBuf::from("/config/work/tasks.json"),
+      archive: PathBuf::from("/config/work/archive.json"),
+      ui: PathBuf::from("ui.json"),
+      others: Vec::new(),
+    };
+    assert_eq!(args.paths, expected);
+
+    // With all paths given the configuration directory is not needed.
+    let args = ["--tasks", "t.json", "--archive", "a.json", "--ui", "u.json"]
+      .iter()
+      .map(OsString::from)
+      .collect::<Vec<_>>();
+    let result = parse_args(args, None, || Err(Error::new(ErrorKind::NotFound, "no config")));
+    assert!(result.is_ok());
+  }
+
+  #[test]
+  fn parse_additional_task_files() {
+    let args = parse(&["--with", "team/team.json", "--with", "other.json"], None).unwrap();
+    let expected = vec![
+      (PathBuf::from("team/team.json"), PathBuf::from("team/team-archive.json")),
+      (PathBuf::from("other.json"), PathBuf::from("other-archive.json")),
+    ];
+    assert_eq!(args.paths.tasks, PathBuf::from("/config/tasks.json"));
+    assert_eq!(args.paths.others, expected);
+  }
+
+  #[test]
+  fn parse_output() {
+    let args = parse(&["/dev/pts/1", "--profile", "work"], None).unwrap();
+    assert_eq!(args.out, Some(OsString::from("/dev/pts/1")));
+  }
+
+  #[test]
+  fn parse_check() {
+    let args = parse(&["check", "--profile", "work"], None).unwrap();
+    assert!(args.check);
+    assert_eq!(args.paths.tasks, PathBuf::from("/config/work/tasks.json"));
+    assert_eq!(args.out, None);
+
+    assert!(!parse(&[], None).unwrap().check);
+    assert!(parse(&["check", "/dev/pts/1"], None).is_err());
+    assert!(parse(&["/dev/pts/1", "check"], None).is_err());
+  }
+
+  #[test]
+  fn parse_tolerant() {
+    assert!(parse(&["--tolerant"], None).unwrap().tolerant);
+    assert!(!parse(&["--profile", "work"], None).unwrap().tolerant);
+  }
+
+  #[test]
+  fn parse_invalid_args() {
+    assert!(parse(&["--tasks"], None).is_err());
+    assert!(parse(&["--foo"], None).is_err());
+    assert!(parse(&["/dev/pts/1", "/dev/pts/2"], None).is_err());
+  }
+}
@@ -17,47 +17,275 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Queries selecting the tasks to display on a tab.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::io::Error;
 use std::io::ErrorKind;
 use std::io::Result as IoResult;
 use std::rc::Rc;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use std::vec;
 
 use cell::Ref;
 use cell::RefCell;
 use cell::RefVal;
 
+use crate::date::Date;
+use crate::search::Pattern;
+use crate::ser::query::Comparison as SerComparison;
 use crate::ser::query::Query as SerQuery;
+use crate::ser::query::Sort as SerSort;
 use crate::ser::query::TagLit as SerTagLit;
+use crate::ser::tags::Tag as SerTag;
 use crate::ser::ToSerde;
+use crate::tags::compare_values;
+use crate::tags::Id as TagId;
 use crate::tags::Tag;
 use crate::tags::TagMap;
 use crate::tags::Templates;
+use crate::tasks::Id as TaskId;
+use crate::tasks::Priority;
+use crate::tasks::Source;
 use crate::tasks::Task;
 use crate::tasks::TaskIter;
 use crate::tasks::Tasks;
 
 
-/// A literal describing whether a tag is negated or not.
+/// The order in which a query yields tasks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Sort {
+  /// Tasks are yielded in the order of the underlying `Tasks` object,
+  /// which can be changed by the user.
+  Manual,
+  /// Tasks are sorted alphabetically by summary, ignoring case.
+  Summary,
+  /// Tasks are sorted by creation time, oldest first.
+  Created,
+  /// Tasks are sorted by due date, earliest first. Tasks without a due
+  /// date come last.
+  Due,
+  /// Tasks are sorted by priority, highest first. Tasks without a
+  /// priority come last.
+  Priority,
+  /// Completed tasks come after all others.
+  CompletedLast,
+}
+
+impl Sort {
+  /// Sort the given tasks.
+  ///
+  /// Sorting is stable, meaning that tasks comparing equal retain the
+  /// manual order.
+  fn sort(self, tasks: &mut Vec<&Task>) {
+    match self {
+      Sort::Manual => (),
+      Sort::Summary => tasks.sort_by_cached_key(|x| x.summary.to_lowercase()),
+      Sort::Created => tasks.sort_by_key(|x| x.created()),
+      Sort::Due => tasks.sort_by_key(|x| (x.due().is_none(), x.due())),
+      Sort::Priority => tasks.sort_by_key(|x| (x.priority().is_none(), x.priority())),
+      Sort::CompletedLast => tasks.sort_by_key(|x| x.is_complete()),
+    }
+  }
+}
+
+impl From<SerSort> for Sort {
+  fn from(sort: SerSort) -> Self {
+    match sort {
+      SerSort::Manual => Sort::Manual,
+      SerSort::Summary => Sort::Summary,
+      SerSort::Created => Sort::Created,
+      SerSort::Due => Sort::Due,
+      SerSort::Priority => Sort::Priority,
+      SerSort::CompletedLast => Sort::CompletedLast,
+    }
+  }
+}
+
+impl ToSerde<SerSort> for Sort {
+  /// Convert this sort into a serializable one.
+  fn to_serde(&self) -> SerSort {
+    match self {
+      Sort::Manual => SerSort::Manual,
+      Sort::Summary => SerSort::Summary,
+      Sort::Created => SerSort::Created,
+      Sort::Due => SerSort::Due,
+      Sort::Priority => SerSort::Priority,
+      Sort::CompletedLast => SerSort::CompletedLast,
+    }
+  }
+}
+
+
+/// An operator for comparing tag values.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Comparison {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+}
+
+impl Comparison {
+  /// Check whether a value comparing to another as given satisfies
+  /// the comparison.
+  ///
+  /// Values that can not be compared are considered unequal, but
+  /// neither less nor greater than each other.
+  fn holds(self, ordering: Option<Ordering>) -> bool {
+    match self {
+      Comparison::Eq => ordering == Some(Ordering::Equal),
+      Comparison::Ne => ordering != Some(Ordering::Equal),
+      Comparison::Lt => ordering == Some(Ordering::Less),
+      Comparison::Le => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+      Comparison::Gt => ordering == Some(Ordering::Greater),
+      Comparison::Ge => matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal)),
+    }
+  }
+}
+
+impl From<SerComparison> for Comparison {
+  fn from(comparison: SerComparison) -> Self {
+    match comparison {
+      SerComparison::Eq => Comparison::Eq,
+      SerComparison::Ne => Comparison::Ne,
+      SerComparison::Lt => Comparison::Lt,
+      SerComparison::Le => Comparison::Le,
+      SerComparison::Gt => Comparison::Gt,
+      SerComparison::Ge => Comparison::Ge,
+    }
+  }
+}
+
+impl ToSerde<SerComparison> for Comparison {
+  /// Convert this comparison into a serializable one.
+  fn to_serde(&self) -> SerComparison {
+    match self {
+      Comparison::Eq => SerComparison::Eq,
+      Comparison::Ne => SerComparison::Ne,
+      Comparison::Lt => SerComparison::Lt,
+      Comparison::Le => SerComparison::Le,
+      Comparison::Gt => SerComparison::Gt,
+      Comparison::Ge => SerComparison::Ge,
+    }
+  }
+}
+
+
+/// The number of seconds in a day.
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+
+/// The tasks yielded by a query, referred to by their index, along
+/// with their depth in the task tree.
+type Order = Vec<(usize, usize)>;
+
+
+/// The point in time relative literals are evaluated against.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Now {
+  /// The current date.
+  today: Date,
+  /// The current time, in seconds since the Unix epoch.
+  secs: u64,
+}
+
+impl Now {
+  /// Retrieve the current point in time.
+  fn current() -> Self {
+    let secs = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|x| x.as_secs())
+      .unwrap_or_default();
+
+    Self {
+      today: Date::today(),
+      secs: secs,
+    }
+  }
+}
+
+
+/// A literal describing a requirement a task has to satisfy, e.g.,
+/// whether a tag is present or negated, or whether a task is blocked or
+/// not.
 #[derive(Clone, Debug)]
 enum TagLit {
   Pos(Tag),
   Neg(Tag),
+  Blocked,
+  Unblocked,
+  /// The summary contains the given lower case text, ignoring case.
+  Summary(String),
+  /// The task is due before the day the given number of days from
+  /// today.
+  DueBefore(u32),
+  /// The task is due after the day the given number of days from
+  /// today.
+  DueAfter(u32),
+  /// The task got created within the given number of days.
+  CreatedWithin(u32),
+  /// The task has at least the given priority.
+  PriorityAtLeast(Priority),
+  /// The task has at most the given priority. Tasks without a priority
+  /// rank below all others.
+  PriorityAtMost(Priority),
+  /// The task has no tags apart from possibly the one marking it
+  /// complete.
+  Untagged,
+  /// The task has the given tag, or one nested below it, with a value
+  /// comparing to the given one as requested.
+  Value(Tag, Comparison, String),
 }
 
 impl TagLit {
-  /// Retrieve the contained `Tag`.
-  fn tag(&self) -> &Tag {
+  /// Check whether the literal is satisfied by the given task at the
+  /// given point in time.
+  fn matches(&self, task: &Task, now: Now) -> bool {
     match self {
-      TagLit::Pos(tag) |
-      TagLit::Neg(tag) => tag,
+      // Tags match all tags nested below them as well.
+      TagLit::Pos(tag) => task.tags().any(|x| x.is_within(tag)),
+      TagLit::Neg(tag) => !task.tags().any(|x| x.is_within(tag)),
+      TagLit::Blocked => task.is_blocked(),
+      TagLit::Unblocked => !task.is_blocked(),
+      TagLit::Summary(text) => task.summary.to_lowercase().contains(text.as_str()),
+      TagLit::DueBefore(days) => {
+        matches!(task.due(), Some(due) if due < now.today.add_days(*days))
+      },
+      TagLit::DueAfter(days) => {
+        matches!(task.due(), Some(due) if due > now.today.add_days(*days))
+      },
+      TagLit::CreatedWithin(days) => {
+        // Tasks without a creation time are considered old.
+        let since = now.secs.saturating_sub(u64::from(*days) * SECS_PER_DAY);
+        matches!(task.created(), Some(created) if created >= since)
+      },
+      // Note that higher priorities compare less.
+      TagLit::PriorityAtLeast(required) => {
+        matches!(task.priority(), Some(priority) if priority <= *required)
+      },
+      TagLit::PriorityAtMost(required) => {
+        !matches!(task.priority(), Some(priority) if priority < *required)
+      },
+      TagLit::Untagged => task.tags().count() == usize::from(task.is_complete()),
+      TagLit::Value(tag, comparison, value) => {
+        task.tags().any(|x| {
+          x.is_within(tag) &&
+          matches!(x.value(), Some(v) if comparison.holds(compare_values(v, value)))
+        })
+      },
     }
   }
 
-  /// Check whether the literal is a positive one.
-  fn is_pos(&self) -> bool {
+  /// Retrieve the tag the literal refers to, if any.
+  fn tag(&self) -> Option<&Tag> {
     match self {
-      TagLit::Pos(_) => true,
-      TagLit::Neg(_) => false,
+      TagLit::Pos(tag) | TagLit::Neg(tag) | TagLit::Value(tag, ..) => Some(tag),
+      _ => None,
     }
   }
 }
@@ -68,61 +296,152 @@ impl ToSerde<SerTagLit> for TagLit {
     match self {
       TagLit::Pos(tag) => SerTagLit::Pos(tag.to_serde()),
       TagLit::Neg(tag) => SerTagLit::Neg(tag.to_serde()),
+      TagLit::Blocked => SerTagLit::Blocked,
+      TagLit::Unblocked => SerTagLit::Unblocked,
+      TagLit::Summary(text) => SerTagLit::Summary(text.clone()),
+      TagLit::DueBefore(days) => SerTagLit::DueBefore(*days),
+      TagLit::DueAfter(days) => SerTagLit::DueAfter(*days),
+      TagLit::CreatedWithin(days) => SerTagLit::CreatedWithin(*days),
+      TagLit::PriorityAtLeast(priority) => SerTagLit::PriorityAtLeast(priority.to_serde()),
+      TagLit::PriorityAtMost(priority) => SerTagLit::PriorityAtMost(priority.to_serde()),
+      TagLit::Untagged => SerTagLit::Untagged,
+      TagLit::Value(tag, comparison, value) => {
+        SerTagLit::Value(tag.to_serde(), comparison.to_serde(), value.clone())
+      },
     }
   }
 }
 
 
-/// An object providing filtered iteration over an iterator of tasks.
+/// An object providing filtered and sorted iteration over an iterator
+/// of tasks.
+///
+/// Tasks are yielded in tree order, i.e., subtasks directly follow
+/// their parent, unless the parent is collapsed. Subtasks of a task
+/// that is not matched by the query take the place of their closest
+/// matched ancestor.
 #[derive(Clone, Debug)]
 pub struct Filter<'t> {
-  iter: TaskIter<'t>,
-  lits: &'t [Vec<TagLit>],
+  iter: vec::IntoIter<(&'t Task, usize)>,
 }
 
 impl<'t> Filter<'t> {
-  /// Create a new `Filter` wrapping an iterator and filtering using the given set of literals.
-  fn new(iter: TaskIter<'t>, lits: &'t [Vec<TagLit>]) -> Self {
+  /// Create a new `Filter` yielding the tasks of an iterator in the
+  /// given order, as determined by `Filter::order`.
+  fn new(iter: TaskIter<'t>, order: &[(usize, usize)]) -> Self {
+    let all = iter.as_slice();
+    let tasks = order
+      .iter()
+      .map(|(idx, depth)| (&all[*idx], *depth))
+      .collect::<Vec<_>>();
+
     Self {
-      iter: iter,
-      lits: lits,
+      iter: tasks.into_iter(),
     }
   }
 
-  /// Check if one of the given tags matches the available ones.
-  fn matches<'tag, I>(lits: &[TagLit], avail_tags: &I) -> bool
-  where
-    I: Iterator<Item=&'tag Tag> + Clone,
-  {
-    // Iterate over disjunctions and check if any of them matches.
-    for lit in lits {
-      let tag = lit.tag();
-      let must_exist = lit.is_pos();
+  /// Determine the tasks to yield, along with their depth in the task
+  /// tree, by filtering using the given set of literals, task file, and
+  /// ad-hoc pattern, and sorting in the given order.
+  ///
+  /// Tasks are referred to by their index in `all`.
+  fn order(all: &[Task],
+           lits: &[Vec<TagLit>],
+           source: Option<Source>,
+           pattern: Option<&Pattern>,
+           sort: Sort,
+           now: Now) -> Order {
+    let matched = all
+      .iter()
+      .enumerate()
+      .filter(|(_, x)| source.is_none() || source == Some(x.source()))
+      .filter(|(_, x)| Self::matched_by(lits, now, x))
+      .filter(|(_, x)| pattern.iter().all(|pattern| pattern.matches(x)))
+      .map(|(idx, x)| (x.id(), idx))
+      .collect::<BTreeMap<_, _>>();
+
+    // Only tasks with an ancestor that is not matched require a lookup
+    // of other tasks, so we only index all of them if necessary.
+    let needs_lookup = matched
+      .values()
+      .any(|idx| matches!(all[*idx].parent(), Some(id) if !matched.contains_key(&id)));
+    let by_id = if needs_lookup {
+      all
+        .iter()
+        .map(|x| (x.id(), x))
+        .collect::<BTreeMap<_, _>>()
+    } else {
+      BTreeMap::new()
+    };
+
+    // Group all matched tasks by their closest matched ancestor,
+    // retaining the manual order.
+    let mut indices = matched.values().copied().collect::<Vec<_>>();
+    indices.sort_unstable();
+
+    let mut children = BTreeMap::<Option<TaskId>, Vec<&Task>>::new();
+    for idx in indices {
+      let task = &all[idx];
+      let mut parent = task.parent();
+      while let Some(id) = parent {
+        if matched.contains_key(&id) {
+          break
+        }
+        parent = by_id[&id].parent();
+      }
+      children.entry(parent).or_default().push(task);
+    }
+
+    for tasks in children.values_mut() {
+      sort.sort(tasks);
+    }
+
+    // Flatten the resulting tree, depth first.
+    let mut tasks = Vec::with_capacity(matched.len());
+    let mut stack = children
+      .get(&None)
+      .map(|roots| roots.iter().rev().map(|x| (*x, 0)).collect::<Vec<_>>())
+      .unwrap_or_default();
+
+    while let Some((task, depth)) = stack.pop() {
+      tasks.push((matched[&task.id()], depth));
 
-      if avail_tags.clone().any(|x| x == tag) == must_exist {
-        return true
+      if !task.is_collapsed() {
+        if let Some(subtasks) = children.get(&Some(task.id())) {
+          stack.extend(subtasks.iter().rev().map(|x| (*x, depth + 1)));
+        }
       }
     }
-    false
+    tasks
   }
 
-  /// Check if the given `tags` match this query's requirements.
-  fn matched_by<'tag, I>(&self, avail_tags: &I) -> bool
-  where
-    I: Iterator<Item=&'tag Tag> + Clone,
-  {
+  /// Check if one of the given literals matches the given task.
+  fn matches(lits: &[TagLit], now: Now, task: &Task) -> bool {
+    // Iterate over disjunctions and check if any of them matches.
+    lits.iter().any(|lit| lit.matches(task, now))
+  }
+
+  /// Check if the given task matches the given literals.
+  fn matched_by(lits: &[Vec<TagLit>], now: Now, task: &Task) -> bool {
     // Iterate over conjunctions; all of them need to match.
-    for req_lits in self.lits {
-      // We could create a set for faster inclusion checks instead of
-      // passing in an iterator. However, typically tasks only use a
-      // small set of tags and so the allocation overhead is assumed to
-      // be higher than the iteration cost we incur right now.
-      if !Self::matches(req_lits, avail_tags) {
+    for req_lits in lits {
+      // We could create a set of the task's tags for faster inclusion
+      // checks instead of iterating over them. However, typically tasks
+      // only use a small set of tags and so the allocation overhead is
+      // assumed to be higher than the iteration cost we incur right
+      // now.
+      if !Self::matches(req_lits, now, task) {
         return false
       }
     }
     true
   }
+
+  /// Convert this iterator into one that additionally yields the depth
+  /// of each task in the task tree.
+  pub fn with_depth(self) -> vec::IntoIter<(&'t Task, usize)> {
+    self.iter
+  }
 }
 
 impl<'t> Iterator for Filter<'t> {
@@ -130,34 +449,13 @@ impl<'t> Iterator for Filter<'t> {
 
   /// Advance the iterator yielding the next matching task or None.
   fn next(&mut self) -> Option<Self::Item> {
-    // TODO: Should really be a for loop or even just a .find()
-    //       invocation, however, both versions do not compile due to
-    //       borrowing/ownership conflicts.
-    loop {
-      match self.iter.next() {
-        Some(task) => {
-          if self.matched_by(&task.tags()) {
-            return Some(task)
-          }
-        },
-        None => return None,
-      }
-    }
+    self.iter.next().map(|(task, _)| task)
   }
 }
 
 impl<'t> DoubleEndedIterator for Filter<'t> {
   fn next_back(&mut self) -> Option<Self::Item> {
-    loop {
-      match self.iter.next_back() {
-        Some(task) => {
-          if self.matched_by(&task.tags()) {
-            return Some(task)
-          }
-        },
-        None => return None,
-      }
-    }
+    self.iter.next_back().map(|(task, _)| task)
   }
 }
 
@@ -167,9 +465,12 @@ impl<'t> DoubleEndedIterator for Filter<'t> {
 // `Query` object and, hence, could be merged into it easily. However,
 // the API would be rather unnatural and non-obvious. A `Query` is
 // supposed to be something that does not change over its lifetime.
+#[derive(Debug)]
 pub struct QueryBuilder {
   tasks: Rc<RefCell<Tasks>>,
   lits: Vec<Vec<TagLit>>,
+  source: Option<(Source, String)>,
+  sort: Sort,
 }
 
 impl QueryBuilder {
@@ -178,6 +479,8 @@ impl QueryBuilder {
     QueryBuilder {
       tasks: tasks,
       lits: Default::default(),
+      source: None,
+      sort: Sort::Manual,
     }
   }
 
@@ -248,12 +551,49 @@ impl QueryBuilder {
     self.or_lit(TagLit::Neg(tag.into()))
   }
 
+  /// Add a new conjunction requiring tasks to be blocked or unblocked
+  /// to the query.
+  #[cfg(test)]
+  pub fn and_blocked(self, blocked: bool) -> QueryBuilder {
+    if blocked {
+      self.and_lit(TagLit::Blocked)
+    } else {
+      self.and_lit(TagLit::Unblocked)
+    }
+  }
+
+  /// Add a new conjunction requiring tasks to have at least the given
+  /// priority to the query.
+  #[cfg(test)]
+  pub fn and_priority(self, priority: Priority) -> QueryBuilder {
+    self.and_lit(TagLit::PriorityAtLeast(priority))
+  }
+
+  /// Restrict the query to tasks of the given task file.
+  #[cfg(test)]
+  pub fn with_source(mut self, source: Source, name: impl Into<String>) -> QueryBuilder {
+    self.source = Some((source, name.into()));
+    self
+  }
+
+  /// Set the order in which the query yields tasks.
+  #[cfg(test)]
+  pub fn sorted_by(mut self, sort: Sort) -> QueryBuilder {
+    self.sort = sort;
+    self
+  }
+
   /// Build the final `Query` instance.
   pub fn build(self, name: impl Into<String>) -> Query {
     Query {
       name: name.into(),
       tasks: self.tasks,
       lits: self.lits,
+      source: self.source,
+      filter: None,
+      sort: self.sort,
+      sort_override: None,
+      cache: RefCell::new(None),
     }
   }
 }
@@ -282,26 +622,57 @@ pub struct Query {
   /// large AND (all elements in the outer vector) of ORs (all the
   /// elements in the inner vector).
   lits: Vec<Vec<TagLit>>,
+  /// The task file, along with its name, tasks have to belong to in
+  /// order to be matched, if any.
+  source: Option<(Source, String)>,
+  /// A pattern tasks have to match temporarily, in addition to all
+  /// other requirements, if any. The filter is not persisted.
+  filter: Option<Pattern>,
+  /// The order in which tasks are yielded.
+  sort: Sort,
+  /// An order temporarily replacing `sort`, if any. The override is not
+  /// persisted.
+  sort_override: Option<Sort>,
+  /// The tasks matched last, in order, along with the generation of
+  /// the tasks and the point in time they were determined for.
+  cache: RefCell<Option<(u64, Now, Rc<Order>)>>,
 }
 
 impl Query {
   /// Create a new `Query` object from a serializable one.
+  ///
+  /// `sources` contains the names of all task files, in order.
   pub fn with_serde(query: SerQuery,
                     templates: &Rc<Templates>,
                     map: &TagMap,
+                    sources: &[String],
                     tasks: Rc<RefCell<Tasks>>) -> IoResult<Self> {
     let mut and_lits = Vec::with_capacity(query.lits.len());
     for lits in query.lits.into_iter() {
       let mut or_lits = Vec::with_capacity(lits.len());
       for lit in lits.into_iter() {
-        let id = map.get(&lit.id()).ok_or_else(|| {
-          let error = format!("Encountered invalid tag Id {}", lit.id());
-          Error::new(ErrorKind::InvalidInput, error)
-        })?;
-        let tag = templates.instantiate(*id);
+        let instantiate = |tag: SerTag| {
+          let id = map.get(&tag.id).ok_or_else(|| {
+            let error = format!("Encountered invalid tag Id {}", tag.id);
+            Error::new(ErrorKind::InvalidInput, error)
+          })?;
+          Ok(templates.instantiate(*id)) as IoResult<_>
+        };
         let lit = match lit {
-          SerTagLit::Pos(_) => TagLit::Pos(tag),
-          SerTagLit::Neg(_) => TagLit::Neg(tag),
+          SerTagLit::Pos(tag) => TagLit::Pos(instantiate(tag)?),
+          SerTagLit::Neg(tag) => TagLit::Neg(instantiate(tag)?),
+          SerTagLit::Blocked => TagLit::Blocked,
+          SerTagLit::Unblocked => TagLit::Unblocked,
+          SerTagLit::Summary(text) => TagLit::Summary(text.to_lowercase()),
+          SerTagLit::DueBefore(days) => TagLit::DueBefore(days),
+          SerTagLit::DueAfter(days) => TagLit::DueAfter(days),
+          SerTagLit::CreatedWithin(days) => TagLit::CreatedWithin(days),
+          SerTagLit::PriorityAtLeast(priority) => TagLit::PriorityAtLeast(priority.into()),
+          SerTagLit::PriorityAtMost(priority) => TagLit::PriorityAtMost(priority.into()),
+          SerTagLit::Untagged => TagLit::Untagged,
+          SerTagLit::Value(tag, comparison, value) => {
+            TagLit::Value(instantiate(tag)?, comparison.into(), value)
+          },
         };
         or_lits.push(lit);
       }
@@ -309,16 +680,57 @@ impl Query {
       and_lits.push(or_lits);
     }
 
+    let source = match query.source {
+      Some(name) => {
+        let source = sources.iter().position(|x| *x == name).ok_or_else(|| {
+          let error = format!("Encountered unknown task file '{}'", name);
+          Error::new(ErrorKind::InvalidInput, error)
+        })?;
+        Some((source, name))
+      },
+      None => None,
+    };
+
     Ok(Query {
       name: query.name,
       tasks: tasks,
       lits: and_lits,
+      source: source,
+      filter: None,
+      sort: Sort::from(query.sort),
+      sort_override: None,
+      cache: RefCell::new(None),
     })
   }
 
   /// Retrieve an iterator over the tasks represented by this query.
   pub fn iter<'t, 's: 't>(&'s self) -> RefVal<'t, Filter<'t>> {
-    Ref::map_val(self.tasks.borrow(), |x| Filter::new(x.iter(), &self.lits))
+    let tasks = self.tasks.borrow();
+    let order = self.order(&tasks);
+    Ref::map_val(tasks, |x| Filter::new(x.iter(), &order))
+  }
+
+  /// Determine the tasks matched by the query, in order.
+  ///
+  /// The result is cached until the tasks change or the point in time
+  /// relative literals are evaluated against advances.
+  fn order(&self, tasks: &Tasks) -> Rc<Order> {
+    let generation = tasks.generation();
+    let now = Now::current();
+
+    if let Some((cached_generation, cached_now, order)) = &*self.cache.borrow() {
+      if *cached_generation == generation && *cached_now == now {
+        return order.clone()
+      }
+    }
+
+    let source = self.source.as_ref().map(|(source, _)| *source);
+    let filter = self.filter.as_ref();
+    let all = tasks.iter().as_slice();
+    let order = Filter::order(all, &self.lits, source, filter, self.sort(), now);
+    let order = Rc::new(order);
+    *self.cache.borrow_mut() = Some((generation, now, order.clone()));
+    order
   }
 
   /// Check whether the query is empty or not.
@@ -326,10 +738,89 @@ impl Query {
     self.iter().next().is_none()
   }
 
+  /// Retrieve the number of completed subtasks of the given task along
+  /// with the total number of its subtasks.
+  pub fn progress(&self, task: &Task) -> (usize, usize) {
+    self.tasks.borrow().progress(task.id())
+  }
+
+  /// Retrieve the order in which the query yields tasks.
+  pub fn sort(&self) -> Sort {
+    self.sort_override.unwrap_or(self.sort)
+  }
+
+  /// Create a copy of this query yielding tasks in the given order
+  /// instead of the configured one, or in the configured one again.
+  pub fn with_sort_override(&self, sort: Option<Sort>) -> Query {
+    Query {
+      sort_override: sort,
+      cache: RefCell::new(None),
+      ..self.clone()
+    }
+  }
+
+  /// Retrieve the order temporarily replacing the configured one, if
+  /// any.
+  pub fn sort_override(&self) -> Option<Sort> {
+    self.sort_override
+  }
+
   /// Retrieve the query's name.
   pub fn name(&self) -> &str {
     &self.name
   }
+
+  /// Retrieve the index and name of the task file the query is
+  /// restricted to, if any.
+  pub fn source(&self) -> Option<(Source, &str)> {
+    self.source.as_ref().map(|(source, name)| (*source, name.as_str()))
+  }
+
+  /// Create a copy of this query additionally narrowed down to tasks
+  /// matching the given pattern, or the query without any such
+  /// narrowing.
+  pub fn with_filter(&self, filter: Option<Pattern>) -> Query {
+    Query {
+      filter: filter,
+      cache: RefCell::new(None),
+      ..self.clone()
+    }
+  }
+
+  /// Retrieve the pattern the query is temporarily narrowed down to, if
+  /// any.
+  pub fn filter(&self) -> Option<&Pattern> {
+    self.filter.as_ref()
+  }
+
+  /// Retrieve an iterator over all tags the query refers to.
+  pub fn tags(&self) -> impl Iterator<Item=&Tag> {
+    self.lits.iter().flatten().filter_map(TagLit::tag)
+  }
+
+  /// Check whether the query refers to the tag template with the given
+  /// ID.
+  pub fn references_tag(&self, id: TagId) -> bool {
+    self
+      .lits
+      .iter()
+      .flatten()
+      .any(|lit| matches!(lit.tag(), Some(tag) if tag.id() == id))
+  }
+
+  /// Make all literals referring to the tag template with ID `from`
+  /// refer to the given tag instead.
+  pub fn replace_tag(&mut self, from: TagId, into: &Tag) {
+    *self.cache.get_mut() = None;
+    for lit in self.lits.iter_mut().flatten() {
+      match lit {
+        TagLit::Pos(tag) |
+        TagLit::Neg(tag) |
+        TagLit::Value(tag, ..) if tag.id() == from => *tag = into.clone(),
+        _ => (),
+      }
+    }
+  }
 }
 
 impl ToSerde<SerQuery> for Query {
@@ -344,6 +835,8 @@ impl ToSerde<SerQuery> for Query {
     SerQuery {
       name: self.name.clone(),
       lits: lits,
+      source: self.source.as_ref().map(|(_, name)| name.clone()),
+      sort: self.sort.to_serde(),
     }
   }
 }
@@ -353,7 +846,10 @@ impl ToSerde<SerQuery> for Query {
 mod tests {
   use super::*;
 
+  use crate::ser::id::Id as SerId;
+  use crate::ser::tags::Template as SerTemplate;
   use crate::ser::tags::Templates as SerTemplates;
+  use crate::ser::tasks::Priority as SerPriority;
   use crate::ser::tasks::Tasks as SerTasks;
   use crate::tags::Templates;
   use crate::test::make_tasks;
@@ -380,6 +876,42 @@ mod tests {
   }
 
 
+  #[test]
+  fn filter_source() {
+    let (templates, tasks) = make_tagged_tasks(4);
+    let others = Tasks::with_serde(SerTasks(make_tasks(2)), templates, &Default::default());
+    tasks.borrow_mut().append(others.unwrap(), 1);
+
+    let query = QueryBuilder::new(tasks.clone()).build("all");
+    assert_eq!(query.iter().clone().count(), 6);
+
+    let query = QueryBuilder::new(tasks.clone())
+      .with_source(1, "other")
+      .build("other");
+    assert_eq!(query.source(), Some((1, "other")));
+
+    let mut iter = query.iter();
+    assert_eq!(iter.next().unwrap().source(), 1);
+    assert_eq!(iter.next().unwrap().source(), 1);
+    assert!(iter.next().is_none());
+  }
+
+  #[test]
+  fn filter_pattern() {
+    let query = make_query(20);
+    let pattern = "2".parse::<Pattern>().unwrap();
+    let filtered = query.with_filter(Some(pattern.clone()));
+    assert_eq!(filtered.filter(), Some(&pattern));
+    assert_eq!(filtered.name(), query.name());
+
+    let summaries = filtered.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["2", "12", "20"]);
+
+    let unfiltered = filtered.with_filter(None);
+    assert_eq!(unfiltered.filter(), None);
+    assert_eq!(unfiltered.iter().clone().count(), 20);
+  }
+
   #[test]
   fn is_empty() {
     assert!(make_query(0).is_empty());
@@ -537,4 +1069,377 @@ mod tests {
     assert_eq!(iter.next().unwrap().summary, "19");
     assert!(iter.next().is_none());
   }
+
+  #[test]
+  fn filter_priority() {
+    let mut ser_tasks = make_tasks(5);
+    ser_tasks[0].priority = Some(SerPriority::C);
+    ser_tasks[1].priority = Some(SerPriority::A);
+    ser_tasks[3].priority = Some(SerPriority::D);
+    ser_tasks[4].priority = Some(SerPriority::B);
+
+    let tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let tasks = Rc::new(RefCell::new(tasks));
+    let query = QueryBuilder::new(tasks)
+      .and_priority(Priority::C)
+      .build("test");
+
+    let mut iter = query.iter();
+    assert_eq!(iter.next().unwrap().summary, "1");
+    assert_eq!(iter.next().unwrap().summary, "2");
+    assert_eq!(iter.next().unwrap().summary, "5");
+    assert!(iter.next().is_none());
+
+    let mut iter = query.iter();
+    assert_eq!(iter.next_back().unwrap().summary, "5");
+  }
+
+  #[test]
+  fn filter_blocked() {
+    let tasks = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
+    let id0 = tasks.iter().next().unwrap().id();
+    let mut task = tasks.iter().nth(1).unwrap().clone();
+    task.toggle_blocked_by(id0);
+
+    let tasks = Rc::new(RefCell::new(tasks));
+    tasks.borrow_mut().update(task);
+
+    let query = QueryBuilder::new(tasks.clone())
+      .and_blocked(true)
+      .build("test");
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["2"]);
+
+    let query = QueryBuilder::new(tasks)
+      .and_blocked(false)
+      .build("test");
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "3"]);
+  }
+
+  #[test]
+  fn match_task_lits() {
+    let now = Now {
+      today: Date::new(2019, 5, 7).unwrap(),
+      secs: 10 * SECS_PER_DAY,
+    };
+
+    let mut ser_tasks = make_tasks(3);
+    ser_tasks[0].summary = "Fix Bug".to_string();
+    ser_tasks[0].due = Some("2019-05-06".to_string());
+    ser_tasks[0].created = Some(9 * SECS_PER_DAY + 1);
+    ser_tasks[0].priority = Some(SerPriority::B);
+    ser_tasks[1].due = Some("2019-05-10".to_string());
+    ser_tasks[1].created = Some(SECS_PER_DAY);
+    ser_tasks[1].priority = Some(SerPriority::D);
+
+    let tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let matching = |lit: TagLit| {
+      tasks
+        .iter()
+        .filter(|x| lit.matches(x, now))
+        .map(|x| x.summary.clone())
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(matching(TagLit::Summary("bug".to_string())), vec!["Fix Bug"]);
+    assert_eq!(matching(TagLit::DueBefore(0)), vec!["Fix Bug"]);
+    assert_eq!(matching(TagLit::DueBefore(7)), vec!["Fix Bug", "2"]);
+    assert_eq!(matching(TagLit::DueAfter(0)), vec!["2"]);
+    assert_eq!(matching(TagLit::DueAfter(3)), Vec::<String>::new());
+    assert_eq!(matching(TagLit::DueBefore(u32::MAX)), vec!["Fix Bug", "2"]);
+    assert_eq!(matching(TagLit::DueAfter(u32::MAX)), Vec::<String>::new());
+    assert_eq!(matching(TagLit::CreatedWithin(1)), vec!["Fix Bug"]);
+    assert_eq!(matching(TagLit::CreatedWithin(9)), vec!["Fix Bug", "2"]);
+    assert_eq!(matching(TagLit::PriorityAtLeast(Priority::C)), vec!["Fix Bug"]);
+    assert_eq!(matching(TagLit::PriorityAtMost(Priority::C)), vec!["2", "3"]);
+    assert_eq!(matching(TagLit::Untagged), vec!["Fix Bug", "2", "3"]);
+  }
+
+  #[test]
+  fn match_value_lits() {
+    let templates = vec![SerTemplate {
+      id: SerId::new(1),
+      name: "estimate".to_string(),
+      style: Default::default(),
+    }];
+    let (templates, map) = Templates::with_serde(SerTemplates(templates));
+    let templates = Rc::new(templates);
+
+    let mut ser_tasks = make_tasks(5);
+    for (task, value) in ser_tasks.iter_mut().zip(&["1h", "2h", "10h", "2d"]) {
+      task.tags = vec![SerTag {
+        id: SerId::new(1),
+        value: Some(value.to_string()),
+      }];
+    }
+    // The last task has the tag, but without a value.
+    ser_tasks[4].tags = vec![SerTag { id: SerId::new(1), value: None }];
+
+    let tasks = Tasks::with_serde(SerTasks(ser_tasks), templates.clone(), &map).unwrap();
+    let estimate = templates.instantiate(map[&SerId::new(1)]);
+    let now = Now::current();
+    let matching = |comparison, value: &str| {
+      let lit = TagLit::Value(estimate.clone(), comparison, value.to_string());
+      tasks
+        .iter()
+        .filter(|x| lit.matches(x, now))
+        .map(|x| x.summary.clone())
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(matching(Comparison::Eq, "2h"), vec!["2"]);
+    assert_eq!(matching(Comparison::Ne, "2h"), vec!["1", "3", "4"]);
+    assert_eq!(matching(Comparison::Lt, "2h"), vec!["1"]);
+    assert_eq!(matching(Comparison::Le, "2h"), vec!["1", "2"]);
+    assert_eq!(matching(Comparison::Gt, "2h"), vec!["3", "4"]);
+    assert_eq!(matching(Comparison::Ge, "3h"), vec!["3", "4"]);
+    assert_eq!(matching(Comparison::Le, "3h"), vec!["1", "2"]);
+    assert_eq!(matching(Comparison::Eq, "48h"), vec!["4"]);
+    // Values of different units can not be compared.
+    assert_eq!(matching(Comparison::Lt, "2kg"), Vec::<String>::new());
+    assert_eq!(matching(Comparison::Ge, "2kg"), Vec::<String>::new());
+  }
+
+  #[test]
+  fn filter_nested_tags() {
+    let names = ["project", "project/ui", "projects"];
+    let templates = names
+      .iter()
+      .enumerate()
+      .map(|(i, name)| SerTemplate {
+        id: SerId::new(i + 1),
+        name: name.to_string(),
+        style: Default::default(),
+      })
+      .collect();
+    let (templates, map) = Templates::with_serde(SerTemplates(templates));
+    let templates = Rc::new(templates);
+
+    let mut ser_tasks = make_tasks(4);
+    for (i, task) in ser_tasks.iter_mut().take(3).enumerate() {
+      task.tags = vec![SerTag { id: SerId::new(i + 1), value: None }];
+    }
+    let tasks = Tasks::with_serde(SerTasks(ser_tasks), templates.clone(), &map).unwrap();
+    let tasks = Rc::new(RefCell::new(tasks));
+
+    let project = templates.instantiate(map[&SerId::new(1)]);
+    let query = QueryBuilder::new(tasks.clone())
+      .and(project.clone())
+      .build("test");
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "2"]);
+
+    let query = QueryBuilder::new(tasks)
+      .and_not(project)
+      .build("test");
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["3", "4"]);
+  }
+
+  #[test]
+  fn invalidate_cached_tasks() {
+    let names = ["project", "project/ui"];
+    let templates = names
+      .iter()
+      .enumerate()
+      .map(|(i, name)| SerTemplate {
+        id: SerId::new(i + 1),
+        name: name.to_string(),
+        style: Default::default(),
+      })
+      .collect();
+    let (templates, map) = Templates::with_serde(SerTemplates(templates));
+    let templates = Rc::new(templates);
+
+    let mut ser_tasks = make_tasks(3);
+    for (i, task) in ser_tasks.iter_mut().take(2).enumerate() {
+      task.tags = vec![SerTag { id: SerId::new(i + 1), value: None }];
+    }
+    let tasks = Tasks::with_serde(SerTasks(ser_tasks), templates.clone(), &map).unwrap();
+    let tasks = Rc::new(RefCell::new(tasks));
+
+    let project = templates.instantiate(map[&SerId::new(1)]);
+    let query = QueryBuilder::new(tasks.clone())
+      .and(project.clone())
+      .build("test");
+    let summaries = || query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries(), vec!["1", "2"]);
+    assert_eq!(summaries(), vec!["1", "2"]);
+
+    let _ = tasks
+      .borrow_mut()
+      .add("4".to_string(), vec![project], None, 0);
+    assert_eq!(summaries(), vec!["1", "2", "4"]);
+
+    // Renaming a tag may change whether it is nested in another.
+    templates.rename(map[&SerId::new(2)], "ui").unwrap();
+    assert_eq!(summaries(), vec!["1", "4"]);
+  }
+
+  #[test]
+  fn replace_tags() {
+    let (templates, tasks) = make_tagged_tasks(20);
+    let tag1 = templates.instantiate(templates.find("tag1").unwrap());
+    let tag2 = templates.instantiate(templates.find("tag2").unwrap());
+    let tag3 = templates.instantiate(templates.find("tag3").unwrap());
+    let mut query = QueryBuilder::new(tasks)
+      .and(tag1.clone())
+      .or_not(tag2.clone())
+      .build("test");
+
+    assert!(query.references_tag(tag1.id()));
+    assert!(query.references_tag(tag2.id()));
+    assert!(!query.references_tag(tag3.id()));
+
+    query.replace_tag(tag1.id(), &tag3);
+    assert!(!query.references_tag(tag1.id()));
+    assert!(query.references_tag(tag3.id()));
+
+    let expected = QueryBuilder::new(query.tasks.clone())
+      .and(tag3)
+      .or_not(tag2)
+      .build("test");
+    assert_eq!(query.to_serde(), expected.to_serde());
+  }
+
+  #[test]
+  fn filter_untagged() {
+    let (_, tasks) = make_tagged_tasks(8);
+    let query = QueryBuilder::new(tasks)
+      .and_lit(TagLit::Untagged)
+      .build("test");
+
+    // Tasks only tagged as complete count as untagged.
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "2", "3", "4"]);
+  }
+
+  #[test]
+  fn sort_tasks() {
+    fn test(sort: Sort, expected: &[&str]) {
+      let mut ser_tasks = make_tasks(4);
+      ser_tasks[0].summary = "b".to_string();
+      ser_tasks[0].created = Some(30);
+      ser_tasks[0].due = Some("2019-05-07".to_string());
+      ser_tasks[1].summary = "C".to_string();
+      ser_tasks[1].priority = Some(SerPriority::D);
+      ser_tasks[1].created = Some(10);
+      ser_tasks[2].summary = "a".to_string();
+      ser_tasks[2].created = Some(20);
+      ser_tasks[2].due = Some("2019-04-30".to_string());
+      ser_tasks[3].summary = "d".to_string();
+      ser_tasks[3].priority = Some(SerPriority::A);
+
+      let tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+      let tasks = Rc::new(RefCell::new(tasks));
+      let query = QueryBuilder::new(tasks)
+        .sorted_by(sort)
+        .build("test");
+
+      let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+      assert_eq!(summaries, expected, "{:?}", sort);
+
+      let summaries = query.iter().clone().rev().map(|x| x.summary.clone()).collect::<Vec<_>>();
+      let expected = expected.iter().rev().cloned().collect::<Vec<_>>();
+      assert_eq!(summaries, expected, "{:?}", sort);
+    }
+
+    test(Sort::Manual, &["b", "C", "a", "d"]);
+    test(Sort::Summary, &["a", "b", "C", "d"]);
+    test(Sort::Created, &["d", "C", "a", "b"]);
+    test(Sort::Due, &["a", "b", "C", "d"]);
+    test(Sort::Priority, &["d", "C", "b", "a"]);
+  }
+
+  #[test]
+  fn sort_completed_last() {
+    let (templates, tasks) = make_tagged_tasks(4);
+    let complete_tag = templates.instantiate(templates.complete_tag().id());
+    let query = QueryBuilder::new(tasks)
+      .sorted_by(Sort::CompletedLast)
+      .build("test");
+
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "3", "2", "4"]);
+
+    let query = QueryBuilder::new(query.tasks.clone())
+      .and(complete_tag)
+      .sorted_by(Sort::CompletedLast)
+      .build("test");
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["2", "4"]);
+  }
+
+  #[test]
+  fn tree_order() {
+    let mut ser_tasks = make_tasks(6);
+    ser_tasks[0].parent = Some(3);
+    ser_tasks[1].parent = Some(5);
+    ser_tasks[2].parent = Some(3);
+    ser_tasks[4].parent = Some(0);
+
+    let tasks = Tasks::with_serde_tasks(ser_tasks.clone()).unwrap();
+    let tasks = Rc::new(RefCell::new(tasks));
+    let query = QueryBuilder::new(tasks).build("test");
+
+    let tree = query
+      .iter()
+      .clone()
+      .with_depth()
+      .map(|(x, depth)| (x.summary.clone(), depth))
+      .collect::<Vec<_>>();
+    let expected = vec![
+      ("4".to_string(), 0),
+      ("1".to_string(), 1),
+      ("5".to_string(), 2),
+      ("3".to_string(), 1),
+      ("6".to_string(), 0),
+      ("2".to_string(), 1),
+    ];
+    assert_eq!(tree, expected);
+
+    let summaries = query.iter().clone().rev().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["2", "6", "3", "5", "1", "4"]);
+
+    // Subtasks of collapsed tasks are not reported.
+    ser_tasks[0].collapsed = true;
+    ser_tasks[5].collapsed = true;
+    let tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let tasks = Rc::new(RefCell::new(tasks));
+    let query = QueryBuilder::new(tasks).build("test");
+
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["4", "1", "3", "6"]);
+  }
+
+  #[test]
+  fn tree_order_unmatched_parent() {
+    let mut ser_tasks = make_tasks(4);
+    ser_tasks[0].priority = Some(SerPriority::A);
+    ser_tasks[1].parent = Some(0);
+    ser_tasks[2].parent = Some(1);
+    ser_tasks[2].priority = Some(SerPriority::A);
+    ser_tasks[3].priority = Some(SerPriority::A);
+
+    let tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let tasks = Rc::new(RefCell::new(tasks));
+    let query = QueryBuilder::new(tasks)
+      .and_priority(Priority::A)
+      .sorted_by(Sort::Summary)
+      .build("test");
+
+    let tree = query
+      .iter()
+      .clone()
+      .with_depth()
+      .map(|(x, depth)| (x.summary.clone(), depth))
+      .collect::<Vec<_>>();
+    let expected = vec![
+      ("1".to_string(), 0),
+      ("3".to_string(), 1),
+      ("4".to_string(), 0),
+    ];
+    assert_eq!(tree, expected);
+  }
 }
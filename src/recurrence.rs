@@ -0,0 +1,292 @@
+// recurrence.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Rules describing when a recurring task is due again.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::str::FromStr;
+
+use crate::date::Date;
+use crate::date::Weekday;
+
+
+/// The maximum number of days an `after <N> days` rule may use.
+const MAX_DAYS: u32 = 36_500;
+
+
+/// A rule describing when a recurring task is due next.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Recurrence {
+  /// The task is due every day.
+  Daily,
+  /// The task is due every week on the given days. If no days are
+  /// given, the task is due on the same weekday as before.
+  Weekly(Vec<Weekday>),
+  /// The task is due every month on the given day, or on the last day
+  /// of months too short for it. If no day is given, the task is due
+  /// on the same day of the month as before.
+  Monthly(Option<u8>),
+  /// The task is due the given number of days after it got completed.
+  AfterCompletion(u32),
+}
+
+impl Recurrence {
+  /// Calculate the date the next instance of a task recurring
+  /// according to this rule is due.
+  ///
+  /// Calendar based rules advance from the task's current due date (or
+  /// `today`, if it has none) until a date after `today` is reached,
+  /// i.e., missed instances are skipped.
+  pub fn next_due(&self, due: Option<Date>, today: Date) -> Date {
+    let start = due.unwrap_or(today);
+    // Each month's date is derived from the same day, so that a month
+    // too short for it does not affect the ones after it.
+    let anchor = match self {
+      Recurrence::Monthly(day) => day.unwrap_or_else(|| start.day()),
+      _ => 0,
+    };
+    let advance = |date: Date| match self {
+      Recurrence::Daily => date.add_days(1),
+      Recurrence::Weekly(days) if days.is_empty() => date.add_days(7),
+      Recurrence::Weekly(days) => (1..=7)
+        .map(|x| date.add_days(x))
+        .find(|x| days.contains(&x.weekday()))
+        .unwrap_or(Date::MAX),
+      Recurrence::Monthly(..) => date.add_month_on(anchor),
+      Recurrence::AfterCompletion(..) => unreachable!(),
+    };
+
+    match self {
+      Recurrence::AfterCompletion(days) => today.add_days(*days),
+      _ => {
+        let mut date = advance(start);
+        // Dates saturate, so stop once the latest one is reached.
+        while date <= today && date < Date::MAX {
+          date = advance(date);
+        }
+        date
+      },
+    }
+  }
+
+  /// Retrieve the rule the next instance of a task recurring according
+  /// to this rule uses, given the same arguments as `next_due`.
+  ///
+  /// A monthly rule without a day remembers the day the task started
+  /// out on, as the next instance may be due on an earlier one.
+  pub fn anchored(&self, due: Option<Date>, today: Date) -> Recurrence {
+    match self {
+      Recurrence::Monthly(None) => Recurrence::Monthly(Some(due.unwrap_or(today).day())),
+      rule => rule.clone(),
+    }
+  }
+}
+
+impl Display for Recurrence {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Recurrence::Daily => f.write_str("daily"),
+      Recurrence::Weekly(days) if days.is_empty() => f.write_str("weekly"),
+      Recurrence::Weekly(days) => {
+        let days = days
+          .iter()
+          .map(|x| x.to_string())
+          .collect::<Vec<_>>()
+          .join(",");
+        write!(f, "weekly {}", days)
+      },
+      Recurrence::Monthly(None) => f.write_str("monthly"),
+      Recurrence::Monthly(Some(day)) => write!(f, "monthly {}", day),
+      Recurrence::AfterCompletion(1) => f.write_str("after 1 day"),
+      Recurrence::AfterCompletion(days) => write!(f, "after {} days", days),
+    }
+  }
+}
+
+impl FromStr for Recurrence {
+  type Err = Error;
+
+  /// Parse a recurrence rule, i.e., one of `daily`, `weekly`,
+  /// `weekly <day>[,<day>...]`, `monthly`, `monthly <day of month>`,
+  /// or `after <N> days`.
+  fn from_str(s: &str) -> Result<Self> {
+    let invalid = || {
+      let error = format!("Encountered invalid recurrence '{}'", s);
+      Error::new(ErrorKind::InvalidInput, error)
+    };
+
+    let words = s.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+      ["daily"] => Ok(Recurrence::Daily),
+      ["weekly"] => Ok(Recurrence::Weekly(Vec::new())),
+      ["weekly", days] => {
+        let mut days = days
+          .split(',')
+          .map(|x| x.parse())
+          .collect::<Result<Vec<Weekday>>>()
+          .map_err(|_| invalid())?;
+        days.sort();
+        days.dedup();
+        Ok(Recurrence::Weekly(days))
+      },
+      ["monthly"] => Ok(Recurrence::Monthly(None)),
+      ["monthly", day] => {
+        match day.parse() {
+          Ok(day) if (1..=31).contains(&day) => Ok(Recurrence::Monthly(Some(day))),
+          _ => Err(invalid()),
+        }
+      },
+      ["after", days, unit] if *unit == "day" || *unit == "days" => {
+        match days.parse() {
+          Ok(days) if days > 0 && days <= MAX_DAYS => Ok(Recurrence::AfterCompletion(days)),
+          _ => Err(invalid()),
+        }
+      },
+      _ => Err(invalid()),
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  fn date(year: u16, month: u8, day: u8) -> Date {
+    Date::new(year, month, day).unwrap()
+  }
+
+  #[test]
+  fn format_parse_recurrence() {
+    let rules = [
+      Recurrence::Daily,
+      Recurrence::Weekly(Vec::new()),
+      Recurrence::Weekly(vec![Weekday::Monday, Weekday::Thursday]),
+      Recurrence::Monthly(None),
+      Recurrence::Monthly(Some(31)),
+      Recurrence::AfterCompletion(1),
+      Recurrence::AfterCompletion(3),
+    ];
+
+    for rule in rules.iter() {
+      assert_eq!(rule.to_string().parse::<Recurrence>().unwrap(), *rule);
+    }
+
+    let rule = "weekly thu,Monday,mon".parse::<Recurrence>().unwrap();
+    assert_eq!(rule.to_string(), "weekly mon,thu");
+  }
+
+  #[test]
+  fn parse_invalid_recurrence() {
+    assert!("".parse::<Recurrence>().is_err());
+    assert!("yearly".parse::<Recurrence>().is_err());
+    assert!("weekly foo".parse::<Recurrence>().is_err());
+    assert!("monthly 0".parse::<Recurrence>().is_err());
+    assert!("monthly 32".parse::<Recurrence>().is_err());
+    assert!("after 0 days".parse::<Recurrence>().is_err());
+    assert!("after x days".parse::<Recurrence>().is_err());
+    assert!("after 3 weeks".parse::<Recurrence>().is_err());
+    assert!("after 36501 days".parse::<Recurrence>().is_err());
+    assert!("after 4294967295 days".parse::<Recurrence>().is_err());
+  }
+
+  #[test]
+  fn next_due_saturates() {
+    let today = date(2019, 5, 7);
+    let rule = Recurrence::AfterCompletion(36_500);
+    assert_eq!(rule.next_due(None, today), date(2119, 4, 13));
+    let rule = Recurrence::AfterCompletion(u32::MAX);
+    assert_eq!(rule.next_due(None, today), Date::MAX);
+
+    let rule = Recurrence::Weekly(vec![Weekday::Monday]);
+    assert_eq!(rule.next_due(None, Date::MAX), Date::MAX);
+    let rule = Recurrence::Monthly(None);
+    assert_eq!(rule.next_due(Some(date(9999, 12, 15)), today), Date::MAX);
+  }
+
+  #[test]
+  fn next_due_daily() {
+    let today = date(2019, 5, 7);
+    let rule = Recurrence::Daily;
+    assert_eq!(rule.next_due(None, today), date(2019, 5, 8));
+    assert_eq!(rule.next_due(Some(date(2019, 5, 10)), today), date(2019, 5, 11));
+    assert_eq!(rule.next_due(Some(date(2019, 5, 1)), today), date(2019, 5, 8));
+  }
+
+  #[test]
+  fn next_due_weekly() {
+    // 2019-05-07 is a Tuesday.
+    let today = date(2019, 5, 7);
+    let rule = Recurrence::Weekly(Vec::new());
+    assert_eq!(rule.next_due(None, today), date(2019, 5, 14));
+    assert_eq!(rule.next_due(Some(date(2019, 5, 6)), today), date(2019, 5, 13));
+
+    let rule = Recurrence::Weekly(vec![Weekday::Monday, Weekday::Thursday]);
+    assert_eq!(rule.next_due(None, today), date(2019, 5, 9));
+    assert_eq!(rule.next_due(Some(date(2019, 5, 9)), today), date(2019, 5, 13));
+    assert_eq!(rule.next_due(Some(date(2019, 4, 1)), today), date(2019, 5, 9));
+  }
+
+  #[test]
+  fn next_due_monthly() {
+    let today = date(2019, 5, 7);
+    let rule = Recurrence::Monthly(None);
+    assert_eq!(rule.next_due(None, today), date(2019, 6, 7));
+    assert_eq!(rule.next_due(Some(date(2019, 5, 31)), today), date(2019, 6, 30));
+    assert_eq!(rule.next_due(Some(date(2019, 3, 10)), today), date(2019, 5, 10));
+    // Skipping a short month does not move the day.
+    assert_eq!(rule.next_due(Some(date(2019, 3, 31)), today), date(2019, 5, 31));
+  }
+
+  #[test]
+  fn next_due_monthly_across_february() {
+    let mut rule = Recurrence::Monthly(None);
+    let mut due = date(2019, 1, 31);
+    let mut dates = Vec::new();
+    for _ in 0..4 {
+      let next = rule.next_due(Some(due), due);
+      rule = rule.anchored(Some(due), due);
+      due = next;
+      dates.push(due);
+    }
+
+    let expected = vec![
+      date(2019, 2, 28),
+      date(2019, 3, 31),
+      date(2019, 4, 30),
+      date(2019, 5, 31),
+    ];
+    assert_eq!(dates, expected);
+    assert_eq!(rule, Recurrence::Monthly(Some(31)));
+  }
+
+  #[test]
+  fn next_due_after_completion() {
+    let today = date(2019, 5, 7);
+    let rule = Recurrence::AfterCompletion(3);
+    assert_eq!(rule.next_due(None, today), date(2019, 5, 10));
+    assert_eq!(rule.next_due(Some(date(2019, 4, 1)), today), date(2019, 5, 10));
+  }
+}
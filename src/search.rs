@@ -0,0 +1,281 @@
+// search.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Matching of tasks against search patterns.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::ops::Range;
+use std::str::FromStr;
+
+use regex::Regex;
+use regex::RegexBuilder;
+
+use crate::tasks::Task;
+
+/// The prefix introducing a regular expression search.
+const REGEX_PREFIX: &str = "re:";
+/// The prefix introducing a fuzzy search.
+const FUZZY_PREFIX: &str = "fz:";
+
+/// The score bonus for a fuzzily matched character directly following
+/// the previously matched one.
+const CONSECUTIVE_BONUS: i64 = 4;
+/// The score bonus for a fuzzily matched character starting a word.
+const WORD_START_BONUS: i64 = 2;
+
+
+/// Find the characters of `pattern`, in order, in `text`.
+///
+/// Of all the possible ways the pattern matches, the one with the
+/// highest score is reported, with consecutive characters and
+/// characters starting a word scoring higher and gaps between matched
+/// characters scoring lower.
+fn fuzzy_find(pattern: &[char], text: &str) -> Option<(i64, Vec<Range<usize>>)> {
+  let chars = text
+    .char_indices()
+    .map(|(idx, c)| (idx, c.to_ascii_lowercase()))
+    .collect::<Vec<_>>();
+  let (first, rest) = pattern.split_first()?;
+  let mut best = None::<(i64, Vec<usize>)>;
+
+  'starts: for start in (0..chars.len()).filter(|x| chars[*x].1 == *first) {
+    let mut positions = vec![start];
+    for c in rest {
+      let next = positions.last().unwrap() + 1;
+      match chars[next..].iter().position(|(_, x)| x == c) {
+        Some(offset) => positions.push(next + offset),
+        // If the remainder of the pattern can not be found starting at
+        // this position it will not be found starting at a later one
+        // either.
+        None => break 'starts,
+      }
+    }
+
+    let score = positions
+      .iter()
+      .enumerate()
+      .map(|(i, pos)| {
+        let word_start = *pos == 0 || !chars[pos - 1].1.is_alphanumeric();
+        let bonus = if word_start { WORD_START_BONUS } else { 0 };
+        match i.checked_sub(1).map(|x| positions[x]) {
+          Some(prev) if prev + 1 == *pos => 1 + bonus + CONSECUTIVE_BONUS,
+          Some(prev) => 1 + bonus - (pos - prev - 1) as i64,
+          None => 1 + bonus,
+        }
+      })
+      .sum::<i64>();
+
+    let better = match best {
+      Some((best, _)) => score > best,
+      None => true,
+    };
+    if better {
+      best = Some((score, positions))
+    }
+  }
+
+  best.map(|(score, positions)| {
+    let mut ranges = Vec::<Range<usize>>::new();
+    for pos in positions {
+      let (idx, _) = chars[pos];
+      let end = chars.get(pos + 1).map_or(text.len(), |(idx, _)| *idx);
+      match ranges.last_mut() {
+        Some(range) if range.end == idx => range.end = end,
+        _ => ranges.push(idx..end),
+      }
+    }
+    (score, ranges)
+  })
+}
+
+
+/// A pattern to search tasks for.
+///
+/// All patterns ignore case.
+#[derive(Clone, Debug)]
+pub enum Pattern {
+  /// The pattern matches texts containing the given string.
+  Substring(String),
+  /// The pattern matches texts matching the given regular expression.
+  Regex(Regex),
+  /// The pattern matches texts containing the given characters in
+  /// order, but not necessarily consecutively.
+  Fuzzy(String),
+}
+
+impl Pattern {
+  /// Find the pattern in the given text, returning the byte ranges of
+  /// the matched parts, if any.
+  pub fn find(&self, text: &str) -> Option<Vec<Range<usize>>> {
+    match self {
+      Pattern::Substring(string) => {
+        // Lowercasing ASCII characters only preserves all byte offsets.
+        text
+          .to_ascii_lowercase()
+          .find(string.as_str())
+          .map(|idx| {
+            let range = idx..idx + string.len();
+            vec![range]
+          })
+      },
+      Pattern::Regex(regex) => regex
+        .find_iter(text)
+        .find(|x| !x.range().is_empty())
+        .map(|x| vec![x.range()]),
+      Pattern::Fuzzy(string) => {
+        let pattern = string.chars().collect::<Vec<_>>();
+        fuzzy_find(&pattern, text).map(|(_, ranges)| ranges)
+      },
+    }
+  }
+
+  /// Check whether the pattern matches a task, i.e., its summary or the
+  /// name of any of its tags.
+  pub fn matches(&self, task: &Task) -> bool {
+    self.find(&task.summary).is_some() || task.tags().any(|x| self.find(&x.name()).is_some())
+  }
+}
+
+impl PartialEq for Pattern {
+  fn eq(&self, other: &Pattern) -> bool {
+    match (self, other) {
+      (Pattern::Substring(x), Pattern::Substring(y)) |
+      (Pattern::Fuzzy(x), Pattern::Fuzzy(y)) => x == y,
+      (Pattern::Regex(x), Pattern::Regex(y)) => x.as_str() == y.as_str(),
+      _ => false,
+    }
+  }
+}
+
+impl Display for Pattern {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match self {
+      Pattern::Substring(string) => write!(f, "{}", string),
+      Pattern::Regex(regex) => write!(f, "{}{}", REGEX_PREFIX, regex.as_str()),
+      Pattern::Fuzzy(string) => write!(f, "{}{}", FUZZY_PREFIX, string),
+    }
+  }
+}
+
+impl FromStr for Pattern {
+  type Err = Error;
+
+  /// Parse a pattern from a string.
+  ///
+  /// Strings starting with "re:" are interpreted as regular
+  /// expressions, strings starting with "fz:" introduce a fuzzy search,
+  /// and all others are searched for verbatim.
+  fn from_str(s: &str) -> Result<Self> {
+    let empty = || {
+      let error = format!("Encountered empty search pattern '{}'", s);
+      Err(Error::new(ErrorKind::InvalidInput, error))
+    };
+
+    if let Some(string) = s.strip_prefix(REGEX_PREFIX) {
+      if string.is_empty() {
+        return empty()
+      }
+
+      let regex = RegexBuilder::new(string)
+        .case_insensitive(true)
+        .build()
+        .map_err(|err| {
+          let error = format!("Encountered invalid regular expression '{}': {}", string, err);
+          Error::new(ErrorKind::InvalidInput, error)
+        })?;
+      Ok(Pattern::Regex(regex))
+    } else if let Some(string) = s.strip_prefix(FUZZY_PREFIX) {
+      let string = string
+        .chars()
+        .filter(|x| !x.is_whitespace())
+        .collect::<String>();
+      if string.is_empty() {
+        return empty()
+      }
+      Ok(Pattern::Fuzzy(string.to_ascii_lowercase()))
+    } else if !s.is_empty() {
+      Ok(Pattern::Substring(s.to_ascii_lowercase()))
+    } else {
+      empty()
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  /// Find the given pattern in the given text, returning the matched
+  /// parts of the text.
+  fn find<'t>(pattern: &str, text: &'t str) -> Vec<&'t str> {
+    let pattern = pattern.parse::<Pattern>().unwrap();
+    pattern
+      .find(text)
+      .unwrap_or_default()
+      .into_iter()
+      .map(|x| &text[x])
+      .collect()
+  }
+
+  #[test]
+  fn parse_pattern() {
+    for string in &["foo", "re:f.o", "fz:fo o"] {
+      let pattern = string.parse::<Pattern>().unwrap();
+      assert_eq!(pattern.to_string(), string.replace(' ', ""));
+    }
+
+    assert!("re:".parse::<Pattern>().is_err());
+    assert!("re:(".parse::<Pattern>().is_err());
+    assert!("fz: ".parse::<Pattern>().is_err());
+  }
+
+  #[test]
+  fn find_substring() {
+    assert_eq!(find("oo", "a Foo b"), vec!["oo"]);
+    assert_eq!(find("FOO", "a Foo b"), vec!["Foo"]);
+    assert_eq!(find("bar", "a Foo b"), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn find_regex() {
+    assert_eq!(find("re:f.o", "a Foo b"), vec!["Foo"]);
+    assert_eq!(find("re:^b", "a Foo b"), Vec::<&str>::new());
+    assert_eq!(find("re:b$", "a Foo b"), vec!["b"]);
+    // Empty matches do not count.
+    assert_eq!(find("re:x*", "a Foo b"), Vec::<&str>::new());
+  }
+
+  #[test]
+  fn find_fuzzy() {
+    assert_eq!(find("fz:fb", "a Foo b"), vec!["F", "b"]);
+    assert_eq!(find("fz:bf", "a Foo b"), Vec::<&str>::new());
+    // Consecutive matches are preferred over scattered ones.
+    assert_eq!(find("fz:fix", "fancy index fix"), vec!["fix"]);
+    // As are matches at the start of words.
+    assert_eq!(find("fz:ui", "build ui"), vec!["ui"]);
+    assert_eq!(find("fz:äb", "xäyb"), vec!["ä", "b"]);
+  }
+}
@@ -17,6 +17,8 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Serializable identifiers.
+
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
@@ -43,12 +45,18 @@ impl<T> Id<T>
 where
   T: Copy,
 {
+  /// Create a new `Id` with the given numeric value.
   pub fn new(id: usize) -> Self {
     Id {
       id: id,
       phantom: PhantomData,
     }
   }
+
+  /// Retrieve the numeric value of the `Id`.
+  pub fn get(self) -> usize {
+    self.id
+  }
 }
 
 impl<T> Debug for Id<T>
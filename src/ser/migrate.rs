@@ -0,0 +1,131 @@
+// migrate.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Upgrading of documents stored in older format versions.
+
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+
+use serde_json::Map;
+use serde_json::Value;
+
+
+/// The name of the attribute storing a document's format version.
+const VERSION_KEY: &str = "version";
+
+
+/// A step upgrading a serialized document from one format version to
+/// the next one.
+pub type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+
+/// A trait for serializable documents whose format is versioned.
+pub trait Versioned {
+  /// The migrations upgrading older documents, with the migration at
+  /// index `i` upgrading a document of version `i` to version `i + 1`.
+  /// Documents lacking a version predate versioning and are of version
+  /// 0.
+  const MIGRATIONS: &'static [Migration];
+  /// The current version of the format.
+  const VERSION: u32 = Self::MIGRATIONS.len() as u32;
+}
+
+
+/// Upgrade a serialized document to the current format version of `T`,
+/// applying all necessary migrations in order.
+pub fn migrate<T>(mut value: Value) -> Result<Value>
+where
+  T: Versioned,
+{
+  let object = value.as_object_mut().ok_or_else(|| {
+    Error::new(ErrorKind::InvalidInput, "Encountered document that is not an object")
+  })?;
+
+  let version = match object.get(VERSION_KEY) {
+    Some(version) => version.as_u64().ok_or_else(|| {
+      let error = format!("Encountered invalid format version {}", version);
+      Error::new(ErrorKind::InvalidInput, error)
+    })?,
+    None => 0,
+  };
+  if version > u64::from(T::VERSION) {
+    let error = format!(
+      "Encountered unsupported format version {} (newest supported: {})", version, T::VERSION
+    );
+    return Err(Error::new(ErrorKind::InvalidInput, error))
+  }
+
+  for (idx, migration) in T::MIGRATIONS.iter().enumerate().skip(version as usize) {
+    migration(object)?;
+    let _ = object.insert(VERSION_KEY.to_string(), Value::from(idx + 1));
+  }
+  Ok(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use serde_json::json;
+
+
+  /// A document type used for testing migrations.
+  struct Doc;
+
+  impl Versioned for Doc {
+    const MIGRATIONS: &'static [Migration] = &[
+      |doc| {
+        let _ = doc.insert("v1".to_string(), Value::from(true));
+        Ok(())
+      },
+      |doc| {
+        let v1 = doc.remove("v1").unwrap_or_default();
+        let _ = doc.insert("v2".to_string(), v1);
+        Ok(())
+      },
+    ];
+  }
+
+
+  #[test]
+  fn migrate_step_by_step() {
+    let doc = migrate::<Doc>(json!({})).unwrap();
+    assert_eq!(doc, json!({"version": 2, "v2": true}));
+
+    let doc = migrate::<Doc>(json!({"version": 1, "v1": false})).unwrap();
+    assert_eq!(doc, json!({"version": 2, "v2": false}));
+
+    let doc = migrate::<Doc>(json!({"version": 2, "v2": false})).unwrap();
+    assert_eq!(doc, json!({"version": 2, "v2": false}));
+  }
+
+  #[test]
+  fn migrate_invalid_documents() {
+    let err = migrate::<Doc>(json!({"version": 3})).unwrap_err();
+    assert_eq!(err.to_string(), "Encountered unsupported format version 3 (newest supported: 2)");
+
+    let err = migrate::<Doc>(json!({"version": "1"})).unwrap_err();
+    assert_eq!(err.to_string(), "Encountered invalid format version \"1\"");
+
+    let err = migrate::<Doc>(json!([])).unwrap_err();
+    assert_eq!(err.to_string(), "Encountered document that is not an object");
+  }
+}
@@ -17,14 +17,28 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Serializable representations of the program's state.
+//!
+//! The types in this module mirror the in-memory ones and describe
+//! the format of the files the state is stored in.
+
 pub mod id;
+pub mod migrate;
 pub mod query;
+pub mod readable;
 pub mod state;
 pub mod tags;
 pub mod tasks;
 
 
+/// Check whether a boolean is `false`.
+fn is_false(value: &bool) -> bool {
+  !*value
+}
+
+
 /// A trait for types that can be converted into a serializable representation.
 pub trait ToSerde<T> {
+  /// Convert the object into its serializable representation.
   fn to_serde(&self) -> T;
 }
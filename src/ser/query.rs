@@ -17,36 +17,119 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Serializable queries.
+
 use serde::Deserialize;
 use serde::Serialize;
 
-use crate::ser::tags::Id;
 use crate::ser::tags::Tag;
+use crate::ser::tasks::Priority;
+
+
+/// An operator for comparing tag values, in a form that can be
+/// serialized and deserialized.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum Comparison {
+  /// The values are equal.
+  Eq,
+  /// The values are not equal.
+  Ne,
+  /// The task's value is less than the given one.
+  Lt,
+  /// The task's value is less than or equal to the given one.
+  Le,
+  /// The task's value is greater than the given one.
+  Gt,
+  /// The task's value is greater than or equal to the given one.
+  Ge,
+}
 
 
 /// A literal that can be serialized and deserialized.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub enum TagLit {
+  /// The task has the given tag.
   Pos(Tag),
+  /// The task does not have the given tag.
   Neg(Tag),
+  /// The task is blocked by another one.
+  Blocked,
+  /// The task is not blocked by any other one.
+  Unblocked,
+  /// The summary contains the given text, ignoring case.
+  Summary(String),
+  /// The task is due before the day the given number of days from
+  /// today.
+  DueBefore(u32),
+  /// The task is due after the day the given number of days from
+  /// today.
+  DueAfter(u32),
+  /// The task got created within the given number of days.
+  CreatedWithin(u32),
+  /// The task has at least the given priority.
+  PriorityAtLeast(Priority),
+  /// The task has at most the given priority, or none at all.
+  PriorityAtMost(Priority),
+  /// The task has no tags, apart from possibly the one marking it
+  /// complete.
+  Untagged,
+  /// The task has the given tag with a value comparing to the given
+  /// one as requested.
+  Value(Tag, Comparison, String),
 }
 
 impl TagLit {
-  /// Retrieve the ID of the wrapped tag.
-  pub fn id(&self) -> Id {
+  /// Retrieve the tag the literal refers to, if any.
+  pub fn tag(&self) -> Option<&Tag> {
     match self {
-      TagLit::Pos(tag) |
-      TagLit::Neg(tag) => tag.id,
+      TagLit::Pos(tag) | TagLit::Neg(tag) | TagLit::Value(tag, ..) => Some(tag),
+      _ => None,
     }
   }
 }
 
 
+/// The order in which a query yields tasks, in a form that can be
+/// serialized and deserialized.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum Sort {
+  /// Tasks are kept in the order the user arranged them in.
+  #[default]
+  Manual,
+  /// Tasks are sorted alphabetically by summary.
+  Summary,
+  /// Tasks are sorted by creation time, oldest first.
+  Created,
+  /// Tasks are sorted by due date, with tasks not due coming last.
+  Due,
+  /// Tasks are sorted by priority, highest first.
+  Priority,
+  /// Completed tasks come after all others.
+  CompletedLast,
+}
+
+impl Sort {
+  /// Check whether the sort is the manual one.
+  fn is_manual(&self) -> bool {
+    *self == Sort::Manual
+  }
+}
+
 /// A query that can be serialized and deserialized.
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Query {
+  /// The name of the query, as displayed as the title of its tab.
   pub name: String,
+  /// The literals in conjunctive normal form, i.e., a conjunction of
+  /// disjunctions.
   pub lits: Vec<Vec<TagLit>>,
+  /// The name of the task file tasks need to belong to in order to
+  /// match.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub source: Option<String>,
+  /// The order in which matching tasks are presented.
+  #[serde(default, skip_serializing_if = "Sort::is_manual")]
+  pub sort: Sort,
 }
 
 
@@ -64,24 +147,113 @@ mod tests {
   fn serialize_deserialize_query() {
     let tag1 = Tag {
       id: Id::new(1),
+      value: None,
     };
     let tag2 = Tag {
       id: Id::new(2),
+      value: None,
     };
     let tag3 = Tag {
       id: Id::new(3),
+      value: None,
     };
     let tag4 = Tag {
       id: Id::new(4),
+      value: None,
     };
 
     let query = Query {
       name: "test-query".to_string(),
       lits: vec![
         vec![TagLit::Pos(tag1)],
-        vec![TagLit::Pos(tag2), TagLit::Neg(tag3)],
+        vec![TagLit::Pos(tag2.clone()), TagLit::Neg(tag3)],
         vec![TagLit::Neg(tag4), TagLit::Pos(tag2)],
       ],
+      ..Default::default()
+    };
+
+    let serialized = to_json(&query).unwrap();
+    let deserialized = from_json::<Query>(&serialized).unwrap();
+
+    assert_eq!(deserialized, query);
+  }
+
+  #[test]
+  fn deserialize_query_without_sort() {
+    let serialized = r#"{"name":"all","lits":[]}"#;
+    let deserialized = from_json::<Query>(serialized).unwrap();
+
+    assert_eq!(deserialized.sort, Sort::Manual);
+
+    // The default sort is not persisted, leaving existing files as is.
+    assert_eq!(to_json(&deserialized).unwrap(), serialized);
+  }
+
+  #[test]
+  fn serialize_deserialize_query_with_sort() {
+    let query = Query {
+      name: "urgent".to_string(),
+      lits: vec![],
+      source: None,
+      sort: Sort::Priority,
+    };
+
+    let serialized = to_json(&query).unwrap();
+    let deserialized = from_json::<Query>(&serialized).unwrap();
+
+    assert_eq!(deserialized, query);
+  }
+
+  #[test]
+  fn serialize_deserialize_query_with_task_lits() {
+    let query = Query {
+      name: "overdue".to_string(),
+      lits: vec![
+        vec![TagLit::DueBefore(0), TagLit::DueAfter(7)],
+        vec![TagLit::Summary("foo".to_string())],
+        vec![TagLit::CreatedWithin(3), TagLit::Untagged],
+        vec![TagLit::PriorityAtLeast(Priority::B), TagLit::PriorityAtMost(Priority::D)],
+      ],
+      ..Default::default()
+    };
+
+    let serialized = to_json(&query).unwrap();
+    let deserialized = from_json::<Query>(&serialized).unwrap();
+
+    assert_eq!(deserialized, query);
+  }
+
+  #[test]
+  fn deserialize_task_lits() {
+    let serialized = r#"{"name":"inbox","lits":[[{"DueBefore":0}],["Untagged"]]}"#;
+    let deserialized = from_json::<Query>(serialized).unwrap();
+
+    assert_eq!(deserialized.lits, vec![vec![TagLit::DueBefore(0)], vec![TagLit::Untagged]]);
+  }
+
+  #[test]
+  fn deserialize_value_lits() {
+    let serialized = r#"{"name":"small","lits":[[{"Value":[{"id":3},"Le","2h"]}]]}"#;
+    let deserialized = from_json::<Query>(serialized).unwrap();
+
+    let tag = Tag {
+      id: Id::new(3),
+      value: None,
+    };
+    let expected = vec![vec![TagLit::Value(tag, Comparison::Le, "2h".to_string())]];
+    assert_eq!(deserialized.lits, expected);
+
+    let serialized = to_json(&deserialized).unwrap();
+    let deserialized = from_json::<Query>(&serialized).unwrap();
+    assert_eq!(deserialized.lits, expected);
+  }
+
+  #[test]
+  fn serialize_deserialize_query_with_source() {
+    let query = Query {
+      name: "team".to_string(),
+      source: Some("team".to_string()),
+      ..Default::default()
     };
 
     let serialized = to_json(&query).unwrap();
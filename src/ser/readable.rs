@@ -0,0 +1,326 @@
+// readable.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Conversion of task files between the compact and the readable
+//! format.
+//!
+//! In the readable format templates carry no IDs and tags refer to them
+//! by name. Every task has an ID that stays the same across saves and
+//! a task's parent is referred to by this ID, as opposed to its
+//! position in the list of tasks. Templates as well as the tags of each
+//! task are ordered by name.
+
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+
+use serde_json::Map;
+use serde_json::Value;
+
+
+/// Create an error reporting an invalid document.
+fn invalid(what: &str) -> Error {
+  let error = format!("Encountered invalid {} in readable task file", what);
+  Error::new(ErrorKind::InvalidInput, error)
+}
+
+/// Retrieve the given value as an array, treating a missing one as
+/// empty.
+fn array_mut<'v>(value: &'v mut Value, what: &str) -> Result<&'v mut Vec<Value>> {
+  if value.is_null() {
+    *value = Value::Array(Vec::new());
+  }
+  value.as_array_mut().ok_or_else(|| invalid(what))
+}
+
+/// Retrieve the given value as an object.
+fn object_mut<'v>(value: &'v mut Value, what: &str) -> Result<&'v mut Map<String, Value>> {
+  value.as_object_mut().ok_or_else(|| invalid(what))
+}
+
+/// Retrieve the tags of a task, if it has any.
+fn tags_mut(task: &mut Value) -> Result<Option<&mut Vec<Value>>> {
+  match object_mut(task, "task")?.get_mut("tags") {
+    Some(tags) => Ok(Some(array_mut(tags, "tags")?)),
+    None => Ok(None),
+  }
+}
+
+
+/// Convert the tasks of a document from the readable into the compact
+/// format, with `ids` mapping tag names to the IDs of their templates.
+///
+/// Tags referring to names not yet known get a template created.
+fn tasks_from_readable(tasks: &mut Value,
+                       ids: &mut BTreeMap<String, u64>,
+                       created: &mut Vec<String>) -> Result<()> {
+  let tasks = array_mut(tasks, "tasks")?;
+  let indices = tasks
+    .iter()
+    .enumerate()
+    .filter_map(|(idx, task)| Some((task.get("id")?.as_u64()?, idx)))
+    .collect::<BTreeMap<_, _>>();
+
+  for task in tasks.iter_mut() {
+    for tag in tags_mut(task)?.into_iter().flatten() {
+      let tag = object_mut(tag, "tag")?;
+      let name = tag
+        .remove("name")
+        .and_then(|x| x.as_str().map(String::from))
+        .ok_or_else(|| invalid("tag"))?;
+      let next = ids.len() as u64 + 1;
+      let id = *ids.entry(name.clone()).or_insert_with(|| {
+        created.push(name);
+        next
+      });
+      let _ = tag.insert("id".to_string(), Value::from(id));
+    }
+
+    let task = object_mut(task, "task")?;
+    if let Some(parent) = task.get_mut("parent") {
+      let index = parent
+        .as_u64()
+        .and_then(|x| indices.get(&x))
+        .ok_or_else(|| invalid("parent task ID"))?;
+      *parent = Value::from(*index);
+    }
+  }
+  Ok(())
+}
+
+/// Check whether a task file is stored in the readable format.
+///
+/// The check is based on the structure of the document as opposed to
+/// its `format` attribute, which governs the format in which it is
+/// saved. That way, switching the format of a file by changing the
+/// attribute just takes effect on the next save.
+pub fn is_readable(task_state: &Value) -> bool {
+  match task_state.get("templates").and_then(Value::as_array) {
+    Some(templates) if !templates.is_empty() => templates.iter().any(|x| x.get("id").is_none()),
+    _ => task_state.get("format").and_then(Value::as_str) == Some("readable"),
+  }
+}
+
+/// Convert a task file and its archive from the readable into the
+/// compact format.
+pub fn from_readable(task_state: &mut Value, archive: &mut Value) -> Result<()> {
+  let task_state = object_mut(task_state, "document")?;
+  let mut ids = BTreeMap::new();
+  let templates = array_mut(task_state.entry("templates").or_insert(Value::Null), "templates")?;
+  for template in templates.iter_mut() {
+    let template = object_mut(template, "template")?;
+    let name = template
+      .get("name")
+      .and_then(Value::as_str)
+      .ok_or_else(|| invalid("template"))?;
+    // Duplicate names are preserved, with all tags referring to the
+    // first template of a name.
+    let next = ids.len() as u64 + 1;
+    let id = *ids.entry(name.to_string()).or_insert(next);
+    let _ = template.insert("id".to_string(), Value::from(id));
+  }
+
+  let mut created = Vec::new();
+  tasks_from_readable(task_state.entry("tasks").or_insert(Value::Null), &mut ids, &mut created)?;
+  tasks_from_readable(archive, &mut ids, &mut created)?;
+
+  let templates = array_mut(task_state.entry("templates").or_insert(Value::Null), "templates")?;
+  for name in created {
+    let mut template = Map::new();
+    let _ = template.insert("id".to_string(), Value::from(ids[&name]));
+    let _ = template.insert("name".to_string(), Value::from(name));
+    templates.push(Value::Object(template));
+  }
+  Ok(())
+}
+
+
+/// Convert the tags of a task from the compact into the readable
+/// format, with `names` mapping template IDs to the names of tags.
+///
+/// The task's parent, if any, is left untouched.
+pub fn task_to_readable(task: &mut Value, names: &BTreeMap<u64, Value>) -> Result<()> {
+  if let Some(tags) = tags_mut(task)? {
+    for tag in tags.iter_mut() {
+      let tag = object_mut(tag, "tag")?;
+      let name = tag
+        .remove("id")
+        .and_then(|x| x.as_u64())
+        .and_then(|x| names.get(&x).cloned())
+        .ok_or_else(|| invalid("tag ID"))?;
+      let _ = tag.insert("name".to_string(), name);
+    }
+    tags.sort_by(|x, y| x["name"].as_str().cmp(&y["name"].as_str()));
+  }
+  Ok(())
+}
+
+/// Convert the tasks of a document from the compact into the readable
+/// format, with `names` mapping template IDs to the names of tags.
+///
+/// All tasks are expected to carry an ID.
+fn tasks_to_readable(tasks: &mut Value, names: &BTreeMap<u64, Value>) -> Result<()> {
+  let tasks = array_mut(tasks, "tasks")?;
+  let ids = tasks
+    .iter()
+    .map(|task| task.get("id").cloned().ok_or_else(|| invalid("task ID")))
+    .collect::<Result<Vec<_>>>()?;
+
+  for task in tasks.iter_mut() {
+    task_to_readable(task, names)?;
+
+    let task = object_mut(task, "task")?;
+    if let Some(parent) = task.get_mut("parent") {
+      let id = parent
+        .as_u64()
+        .and_then(|x| ids.get(x as usize))
+        .ok_or_else(|| invalid("parent task index"))?;
+      *parent = id.clone();
+    }
+  }
+  Ok(())
+}
+
+/// Convert tag templates from the compact into the readable format.
+///
+/// The return value maps the IDs the templates had to their names, for
+/// use with `task_to_readable`.
+pub fn templates_to_readable(templates: &mut Value) -> Result<BTreeMap<u64, Value>> {
+  let templates = array_mut(templates, "templates")?;
+  let mut names = BTreeMap::new();
+  for template in templates.iter_mut() {
+    let template = object_mut(template, "template")?;
+    let id = template
+      .remove("id")
+      .and_then(|x| x.as_u64())
+      .ok_or_else(|| invalid("template"))?;
+    let name = template.get("name").cloned().ok_or_else(|| invalid("template"))?;
+    let _ = names.insert(id, name);
+  }
+  templates.sort_by(|x, y| x["name"].as_str().cmp(&y["name"].as_str()));
+  Ok(names)
+}
+
+/// Convert a task file and its archive from the compact into the
+/// readable format.
+pub fn to_readable(task_state: &mut Value, archive: &mut Value) -> Result<()> {
+  let task_state = object_mut(task_state, "document")?;
+  let names = templates_to_readable(task_state.entry("templates").or_insert(Value::Null))?;
+
+  tasks_to_readable(task_state.entry("tasks").or_insert(Value::Null), &names)?;
+  tasks_to_readable(archive, &names)
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use serde_json::json;
+
+
+  /// Create a task file in the compact format along with its archive.
+  fn compact() -> (Value, Value) {
+    let task_state = json!({
+      "templates": [
+        {"id": 4, "name": "work"},
+        {"id": 2, "name": "complete"},
+        {"id": 7, "name": "estimate", "color": "#d70000"},
+      ],
+      "tasks": [
+        {"id": 11, "summary": "parent", "tags": [{"id": 7, "value": "2h"}, {"id": 4}]},
+        {"id": 12, "summary": "child", "parent": 0, "blocked_by": [11]},
+      ],
+    });
+    let archive = json!([
+      {"id": 13, "summary": "done", "tags": [{"id": 2}]},
+    ]);
+    (task_state, archive)
+  }
+
+  #[test]
+  fn convert_to_readable() {
+    let (mut task_state, mut archive) = compact();
+    to_readable(&mut task_state, &mut archive).unwrap();
+
+    let expected = json!({
+      "templates": [
+        {"name": "complete"},
+        {"name": "estimate", "color": "#d70000"},
+        {"name": "work"},
+      ],
+      "tasks": [
+        {"id": 11, "summary": "parent", "tags": [{"name": "estimate", "value": "2h"}, {"name": "work"}]},
+        {"id": 12, "summary": "child", "parent": 11, "blocked_by": [11]},
+      ],
+    });
+    assert_eq!(task_state, expected);
+    assert_eq!(archive, json!([{"id": 13, "summary": "done", "tags": [{"name": "complete"}]}]));
+    assert!(is_readable(&task_state));
+  }
+
+  #[test]
+  fn convert_round_trip() {
+    let (mut task_state, mut archive) = compact();
+    assert!(!is_readable(&task_state));
+
+    to_readable(&mut task_state, &mut archive).unwrap();
+    from_readable(&mut task_state, &mut archive).unwrap();
+
+    let expected = json!({
+      "templates": [
+        {"id": 1, "name": "complete"},
+        {"id": 2, "name": "estimate", "color": "#d70000"},
+        {"id": 3, "name": "work"},
+      ],
+      "tasks": [
+        {"id": 11, "summary": "parent", "tags": [{"id": 2, "value": "2h"}, {"id": 3}]},
+        {"id": 12, "summary": "child", "parent": 0, "blocked_by": [11]},
+      ],
+    });
+    assert_eq!(task_state, expected);
+    assert_eq!(archive, json!([{"id": 13, "summary": "done", "tags": [{"id": 1}]}]));
+  }
+
+  #[test]
+  fn convert_from_readable_with_unknown_tag() {
+    let mut task_state = json!({
+      "format": "readable",
+      "tasks": [{"summary": "task", "tags": [{"name": "new"}]}],
+    });
+    let mut archive = Value::Null;
+    assert!(is_readable(&task_state));
+
+    from_readable(&mut task_state, &mut archive).unwrap();
+    assert_eq!(task_state["templates"], json!([{"id": 1, "name": "new"}]));
+    assert_eq!(task_state["tasks"][0]["tags"], json!([{"id": 1}]));
+    assert_eq!(archive, json!([]));
+  }
+
+  #[test]
+  fn convert_from_readable_with_invalid_parent() {
+    let mut task_state = json!({
+      "templates": [{"name": "complete"}],
+      "tasks": [{"id": 1, "summary": "task", "parent": 2}],
+    });
+    let err = from_readable(&mut task_state, &mut Value::Null).unwrap_err();
+    assert_eq!(err.to_string(), "Encountered invalid parent task ID in readable task file");
+  }
+}
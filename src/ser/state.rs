@@ -17,28 +17,232 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Serializable task and UI state.
+
+use std::io::Result;
+
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
 
+use crate::ser::is_false;
+use crate::ser::migrate::Migration;
+use crate::ser::migrate::Versioned;
 use crate::ser::query::Query;
 use crate::ser::tags::Templates;
 use crate::ser::tasks::Tasks;
 
 
+/// Upgrade a document predating format versioning.
+///
+/// Such documents are structurally equal to those of version 1, which
+/// merely introduced the version itself.
+fn introduce_version(_: &mut Map<String, Value>) -> Result<()> {
+  Ok(())
+}
+
+
+/// Fold the minimum priority of queries into their literals.
+///
+/// Version 2 replaced the `priority` attribute of queries with an
+/// equivalent conjunction containing a `PriorityAtLeast` literal.
+fn fold_query_priority(state: &mut Map<String, Value>) -> Result<()> {
+  let queries = match state.get_mut("queries").and_then(Value::as_array_mut) {
+    Some(queries) => queries,
+    None => return Ok(()),
+  };
+
+  // Each query is stored along with the index of its selected task.
+  for query in queries.iter_mut().filter_map(|x| x.get_mut(0)?.as_object_mut()) {
+    if let Some(priority) = query.remove("priority").filter(|x| !x.is_null()) {
+      let lit = json!([{"PriorityAtLeast": priority}]);
+      match query.get_mut("lits").and_then(Value::as_array_mut) {
+        Some(lits) => lits.push(lit),
+        None => {
+          let _ = query.insert("lits".to_string(), json!([lit]));
+        },
+      }
+    }
+  }
+  Ok(())
+}
+
+
+/// The format in which a task file is stored.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+  /// Tags are referred to by numeric IDs that may change on every save.
+  #[default]
+  Compact,
+  /// Tags are referred to by name and every task carries a stable ID,
+  /// keeping diffs between versions of the file minimal.
+  Readable,
+}
+
+impl Format {
+  /// Check whether the format is the compact one.
+  fn is_compact(&self) -> bool {
+    *self == Format::Compact
+  }
+}
+
+
 /// A struct comprising the task state of the program.
-#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct TaskState {
+  /// The version of the format the state is stored in.
+  #[serde(default)]
+  pub version: u32,
+  /// The format tasks are stored in.
+  #[serde(default, skip_serializing_if = "Format::is_compact")]
+  pub format: Format,
+  /// The templates of the tags the tasks refer to.
   #[serde(default)]
   pub templates: Templates,
+  /// The tasks, in the order they are displayed in.
   pub tasks: Tasks,
 }
 
+impl Versioned for TaskState {
+  const MIGRATIONS: &'static [Migration] = &[introduce_version];
+}
+
+impl Default for TaskState {
+  fn default() -> Self {
+    Self {
+      version: Self::VERSION,
+      format: Default::default(),
+      templates: Default::default(),
+      tasks: Default::default(),
+    }
+  }
+}
+
 
 /// A struct comprising the program state itself.
-#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub struct UiState {
+  /// The version of the format the state is stored in.
+  #[serde(default)]
+  pub version: u32,
+  /// The templates of the tags the queries refer to.
+  #[serde(default, skip_serializing_if = "Templates::is_empty")]
+  pub templates: Templates,
+  /// The queries of all tabs, each along with the index of the task
+  /// selected on it.
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub queries: Vec<(Query, Option<usize>)>,
+  /// The index of the selected tab.
   #[serde(default, skip_serializing_if = "Option::is_none")]
   pub selected: Option<usize>,
+  /// The number of days after which completed tasks are moved into the
+  /// archive automatically.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub archive_after: Option<u32>,
+  /// Whether to record every save in a git repository.
+  #[serde(default, skip_serializing_if = "is_false")]
+  pub history: bool,
+}
+
+impl Versioned for UiState {
+  const MIGRATIONS: &'static [Migration] = &[introduce_version, fold_query_priority];
+}
+
+impl Default for UiState {
+  fn default() -> Self {
+    Self {
+      version: Self::VERSION,
+      templates: Default::default(),
+      queries: Default::default(),
+      selected: Default::default(),
+      archive_after: Default::default(),
+      history: Default::default(),
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use serde_json::from_str as from_json;
+  use serde_json::from_value;
+  use serde_json::to_value;
+
+  use crate::ser::migrate::migrate;
+  use crate::ser::query::TagLit;
+  use crate::ser::tasks::Priority;
+
+
+  /// Load a document of type `T` from a fixture, returning it in the
+  /// current format.
+  fn load<T>(fixture: &str) -> Value
+  where
+    T: Versioned,
+    for<'de> T: Deserialize<'de> + Serialize,
+  {
+    let value = from_json::<Value>(fixture).unwrap();
+    let state = from_value::<T>(migrate::<T>(value).unwrap()).unwrap();
+    to_value(state).unwrap()
+  }
+
+
+  #[test]
+  fn migrate_task_state_0_2_0() {
+    let state = load::<TaskState>(include_str!("../../tests/fixtures/v0-0.2.0/tasks.json"));
+    let state = from_value::<TaskState>(state).unwrap();
+    assert_eq!(state.version, TaskState::VERSION);
+    assert_eq!(state.templates.0.len(), 2);
+    assert_eq!(state.tasks.0.len(), 2);
+    assert_eq!(state.tasks.0[0].summary, "write report");
+  }
+
+  #[test]
+  fn migrate_ui_state_0_2_0() {
+    let state = load::<UiState>(include_str!("../../tests/fixtures/v0-0.2.0/notnow.json"));
+    let state = from_value::<UiState>(state).unwrap();
+    assert_eq!(state.version, UiState::VERSION);
+    assert_eq!(state.queries.len(), 2);
+    assert_eq!(state.queries[1].0.name, "work");
+    assert_eq!(state.queries[1].1, Some(0));
+    assert_eq!(state.selected, Some(1));
+  }
+
+  #[test]
+  fn migrate_task_state_unversioned() {
+    let old = load::<TaskState>(include_str!("../../tests/fixtures/v0-latest/tasks.json"));
+    let new = load::<TaskState>(include_str!("../../tests/fixtures/v1/tasks.json"));
+    assert_eq!(old, new);
+    assert_eq!(new, from_json::<Value>(include_str!("../../tests/fixtures/v1/tasks.json")).unwrap());
+  }
+
+  #[test]
+  fn migrate_ui_state_unversioned() {
+    let old = load::<UiState>(include_str!("../../tests/fixtures/v0-latest/notnow.json"));
+    let new = load::<UiState>(include_str!("../../tests/fixtures/v1/notnow.json"));
+    assert_eq!(old, new);
+  }
+
+  #[test]
+  fn migrate_ui_state_1() {
+    let old = load::<UiState>(include_str!("../../tests/fixtures/v1/notnow.json"));
+    let new = load::<UiState>(include_str!("../../tests/fixtures/v2/notnow.json"));
+    assert_eq!(old, new);
+    assert_eq!(new, from_json::<Value>(include_str!("../../tests/fixtures/v2/notnow.json")).unwrap());
+
+    let old = json!({"version": 1, "queries": [[{"name": "all", "priority": "C"}, null]]});
+    let new = from_value::<UiState>(migrate::<UiState>(old).unwrap()).unwrap();
+    let (query, _) = &new.queries[0];
+    assert_eq!(query.lits, vec![vec![TagLit::PriorityAtLeast(Priority::C)]]);
+  }
+
+  #[test]
+  fn default_state_is_current() {
+    assert_eq!(TaskState::default().version, TaskState::VERSION);
+    assert_eq!(UiState::default().version, UiState::VERSION);
+  }
 }
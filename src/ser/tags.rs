@@ -17,33 +17,120 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Serializable tags and tag templates.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::str::FromStr;
+
+use serde::de::Deserializer;
+use serde::de::Error as DeError;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::Serializer;
 
 use crate::ser::id::Id as IdT;
+use crate::ser::is_false;
 
 
+/// The type tag distinguishing template IDs from other ones.
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct T(());
 
+/// The ID of a tag template, as persisted.
 pub type Id = IdT<T>;
 
 
+/// A color in RGB form that can be serialized and deserialized.
+///
+/// Colors are persisted as strings of the form `#rrggbb`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Color(pub u8, pub u8, pub u8);
+
+impl Display for Color {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+  }
+}
+
+impl FromStr for Color {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let error = || format!("Encountered invalid color '{}'", s);
+    let hex = s.strip_prefix('#').filter(|x| x.len() == 6).ok_or_else(error)?;
+    let component = |idx: usize| {
+      hex
+        .get(idx..idx + 2)
+        .and_then(|x| u8::from_str_radix(x, 16).ok())
+        .ok_or_else(error)
+    };
+    Ok(Color(component(0)?, component(2)?, component(4)?))
+  }
+}
+
+// We manually implement Serialize and Deserialize in order to have the
+// color represented in the common hexadecimal notation.
+impl Serialize for Color {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serializer.serialize_str(&self.to_string())
+  }
+}
+
+impl<'de> Deserialize<'de> for Color {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+  }
+}
+
+
+/// The display attributes of a tag template.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Style {
+  /// The color to render tags with, if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub color: Option<Color>,
+  /// Whether to render tags in bold.
+  #[serde(default, skip_serializing_if = "is_false")]
+  pub bold: bool,
+  /// Whether to render entire tasks carrying the tag in its color.
+  #[serde(default, skip_serializing_if = "is_false")]
+  pub tint: bool,
+}
+
+
 /// A struct for serializing the concept of a tag.
 ///
 /// Objects of this type are used to describe what a tag looks like and
 /// are the form in which the concept of a particular tag is persisted.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Template {
+  /// The ID tags refer to the template by.
   pub id: Id,
+  /// The name of the template, shared by all its tags.
   pub name: String,
+  /// The attributes tags are displayed with.
+  #[serde(default, flatten)]
+  pub style: Style,
 }
 
 
 /// A serializable tag instance.
-#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct Tag {
+  /// The ID of the template the tag is an instance of.
   pub id: Id,
+  /// The value the tag carries, if any, e.g., `3h` for an `estimate`
+  /// tag.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub value: Option<String>,
 }
 
 
@@ -51,6 +138,13 @@ pub struct Tag {
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Templates(pub Vec<Template>);
 
+impl Templates {
+  /// Check whether there are no templates.
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -65,6 +159,7 @@ mod tests {
     let template = Template {
       id: Id::new(32),
       name: "test-tag".to_string(),
+      style: Default::default(),
     };
     let serialized = to_json(&template).unwrap();
     let deserialized = from_json::<Template>(&serialized).unwrap();
@@ -72,10 +167,44 @@ mod tests {
     assert_eq!(deserialized, template);
   }
 
+  #[test]
+  fn serialize_deserialize_styled_template() {
+    let template = Template {
+      id: Id::new(1),
+      name: "urgent".to_string(),
+      style: Style {
+        color: Some(Color(0xff, 0x00, 0x5f)),
+        bold: true,
+        tint: false,
+      },
+    };
+    let serialized = to_json(&template).unwrap();
+    assert_eq!(serialized, r##"{"id":1,"name":"urgent","color":"#ff005f","bold":true}"##);
+
+    let deserialized = from_json::<Template>(&serialized).unwrap();
+    assert_eq!(deserialized, template);
+
+    // Templates without display attributes are stored as before.
+    let template = from_json::<Template>(r#"{"id":1,"name":"urgent"}"#).unwrap();
+    assert_eq!(template.style, Style::default());
+  }
+
+  #[test]
+  fn parse_color() {
+    assert_eq!("#00d7ff".parse::<Color>(), Ok(Color(0x00, 0xd7, 0xff)));
+    assert!("00d7ff".parse::<Color>().is_err());
+    assert!("#00d7f".parse::<Color>().is_err());
+    assert!("#00d7fg".parse::<Color>().is_err());
+
+    let result = from_json::<Template>(r#"{"id":1,"name":"x","color":"red"}"#);
+    assert!(result.is_err());
+  }
+
   #[test]
   fn serialize_deserialize_tag() {
     let tag = Tag {
       id: Id::new(42),
+      value: None,
     };
     let serialized = to_json(&tag).unwrap();
     let deserialized = from_json::<Tag>(&serialized).unwrap();
@@ -83,16 +212,35 @@ mod tests {
     assert_eq!(deserialized, tag);
   }
 
+  #[test]
+  fn serialize_deserialize_valued_tag() {
+    let tag = Tag {
+      id: Id::new(7),
+      value: Some("3h".to_string()),
+    };
+    let serialized = to_json(&tag).unwrap();
+    assert_eq!(serialized, r#"{"id":7,"value":"3h"}"#);
+
+    let deserialized = from_json::<Tag>(&serialized).unwrap();
+    assert_eq!(deserialized, tag);
+
+    // Tags without a value are stored without the attribute.
+    let tag = from_json::<Tag>(r#"{"id":7}"#).unwrap();
+    assert_eq!(tag.value, None);
+  }
+
   #[test]
   fn serialize_deserialize_templates() {
     let templates = vec![
       Template {
         id: Id::new(3),
         name: "tag1".to_string(),
+        style: Default::default(),
       },
       Template {
         id: Id::new(990),
         name: "tag990".to_string(),
+        style: Default::default(),
       },
     ];
     let templates = Templates(templates);
@@ -17,18 +17,71 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Serializable tasks.
+
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::ser::id::Id as IdT;
+use crate::ser::is_false;
 use crate::ser::tags::Tag;
 
 
+/// The type tag distinguishing task IDs from other ones.
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct T(());
+
+/// The ID of a task, as persisted.
+pub type Id = IdT<T>;
+
+
+/// The priority of a task that can be serialized and deserialized.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[allow(missing_docs)]
+pub enum Priority {
+  A,
+  B,
+  C,
+  D,
+  E,
+}
+
+
 /// A task that can be serialized and deserialized.
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct Task {
+  /// The ID of the task, present only if other tasks refer to it.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub id: Option<Id>,
+  /// The summary describing the task.
   pub summary: String,
+  /// The tags the task carries.
   #[serde(default, skip_serializing_if = "Vec::is_empty")]
   pub tags: Vec<Tag>,
+  /// The priority of the task, if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub priority: Option<Priority>,
+  /// The time the task was created, in seconds since the Unix epoch.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub created: Option<u64>,
+  /// The time the task was completed, in seconds since the Unix epoch.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub completed: Option<u64>,
+  /// The date the task is due, in YYYY-MM-DD format.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub due: Option<String>,
+  /// The rule according to which the task recurs, if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub recurrence: Option<String>,
+  /// The index of the task's parent in the surrounding list of tasks.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub parent: Option<usize>,
+  /// Whether the task's subtasks are hidden from view.
+  #[serde(default, skip_serializing_if = "is_false")]
+  pub collapsed: bool,
+  /// The IDs of the tasks that need to be completed before this one.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub blocked_by: Vec<Id>,
 }
 
 
@@ -52,6 +105,7 @@ mod tests {
     let task = Task {
       summary: "task without tags".to_string(),
       tags: Vec::new(),
+      ..Default::default()
     };
     let serialized = to_json(&task).unwrap();
     let deserialized = from_json::<Task>(&serialized).unwrap();
@@ -64,14 +118,30 @@ mod tests {
     let tags = vec![
       Tag {
         id: TagId::new(2),
+        value: None,
       },
       Tag {
         id: TagId::new(4),
+        value: None,
       },
     ];
     let task = Task {
       summary: "this is a task".to_string(),
       tags: tags,
+      ..Default::default()
+    };
+    let serialized = to_json(&task).unwrap();
+    let deserialized = from_json::<Task>(&serialized).unwrap();
+
+    assert_eq!(deserialized, task);
+  }
+
+  #[test]
+  fn serialize_deserialize_task_with_priority() {
+    let task = Task {
+      summary: "an important task".to_string(),
+      priority: Some(Priority::B),
+      ..Default::default()
     };
     let serialized = to_json(&task).unwrap();
     let deserialized = from_json::<Task>(&serialized).unwrap();
@@ -79,6 +149,42 @@ mod tests {
     assert_eq!(deserialized, task);
   }
 
+  #[test]
+  fn serialize_deserialize_task_with_dates() {
+    let task = Task {
+      summary: "a task with dates".to_string(),
+      created: Some(1557000000),
+      due: Some("2019-05-07".to_string()),
+      ..Default::default()
+    };
+    let serialized = to_json(&task).unwrap();
+    let deserialized = from_json::<Task>(&serialized).unwrap();
+
+    assert_eq!(deserialized, task);
+  }
+
+  #[test]
+  fn serialize_deserialize_task_with_parent() {
+    let task = Task {
+      summary: "a subtask".to_string(),
+      parent: Some(3),
+      collapsed: true,
+      ..Default::default()
+    };
+    let serialized = to_json(&task).unwrap();
+    let deserialized = from_json::<Task>(&serialized).unwrap();
+
+    assert_eq!(deserialized, task);
+  }
+
+  #[test]
+  fn deserialize_task_without_priority() {
+    let serialized = r#"{"summary":"a task"}"#;
+    let deserialized = from_json::<Task>(serialized).unwrap();
+
+    assert_eq!(deserialized.priority, None);
+  }
+
   #[test]
   fn serialize_deserialize_tasks() {
     let task_vec = vec![
@@ -87,22 +193,28 @@ mod tests {
         tags: vec![
           Tag {
             id: TagId::new(10000),
+            value: None,
           },
           Tag {
             id: TagId::new(5),
+            value: None,
           },
         ],
+        ..Default::default()
       },
       Task {
         tags: vec![
           Tag {
             id: TagId::new(5),
+            value: None,
           },
           Tag {
             id: TagId::new(6),
+            value: None,
           },
         ],
         summary: "task 2".to_string(),
+        ..Default::default()
       },
     ];
     let tasks = Tasks(task_vec);
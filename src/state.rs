@@ -17,34 +17,59 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Loading and saving of the program's state.
+
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::fs::OpenOptions;
+use std::io::Error;
 use std::io::ErrorKind;
 use std::io::Result;
 use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use cell::RefCell;
 
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::from_reader;
+use serde_json::from_value;
 use serde_json::to_string_pretty as to_json;
+use serde_json::Value;
 
+use crate::history::History;
 use crate::query::Query;
 use crate::query::QueryBuilder;
+use crate::ser::migrate::migrate;
+use crate::ser::migrate::Versioned;
+use crate::ser::query::Query as SerQuery;
+use crate::ser::readable::from_readable;
+use crate::ser::readable::is_readable;
+use crate::ser::state::Format as SerFormat;
 use crate::ser::state::TaskState as SerTaskState;
 use crate::ser::state::UiState as SerUiState;
+use crate::ser::tags::Template as SerTemplate;
+use crate::ser::tags::Templates as SerTemplates;
+use crate::ser::tasks::Tasks as SerTasks;
 use crate::ser::ToSerde;
+use crate::storage;
+use crate::storage::Contents;
+use crate::storage::TaskStore;
+use crate::tags::Id as TagId;
+use crate::tags::TagMap;
 use crate::tags::Templates;
 use crate::tasks::Tasks;
 
 
 /// Load some serialized state from a file.
-fn load_state<T>(path: &Path) -> Result<T>
+pub fn load_state<T>(path: &Path) -> Result<T>
 where
   T: Default,
   for<'de> T: Deserialize<'de>,
@@ -63,8 +88,73 @@ where
   }
 }
 
+/// Load a document stored in a versioned format from a file, upgrading
+/// it to the current version if necessary.
+fn load_document<T>(path: &Path) -> Result<Option<Value>>
+where
+  T: Versioned,
+{
+  match load_state::<Option<Value>>(path)? {
+    Some(value) => {
+      let value = migrate::<T>(value).map_err(|err| {
+        let error = format!("Failed to load {}: {}", path.display(), err);
+        Error::new(err.kind(), error)
+      })?;
+      Ok(Some(value))
+    },
+    None => Ok(None),
+  }
+}
+
+/// Load some serialized state stored in a versioned format from a
+/// file, upgrading it to the current version if necessary.
+pub fn load_versioned_state<T>(path: &Path) -> Result<T>
+where
+  T: Default + Versioned,
+  for<'de> T: Deserialize<'de>,
+{
+  match load_document::<T>(path)? {
+    Some(value) => Ok(from_value::<T>(value)?),
+    None => Ok(Default::default()),
+  }
+}
+
+/// Convert the contents of a task file along with the file storing its
+/// archive into their compact serializable form, upgrading them to the
+/// current version if necessary.
+///
+/// A missing archive is represented as `Value::Null`.
+pub fn parse_task_file(task_state: Value, mut archive: Value) -> Result<(SerTaskState, SerTasks)> {
+  let mut task_state = migrate::<SerTaskState>(task_state)?;
+  if is_readable(&task_state) {
+    from_readable(&mut task_state, &mut archive)?;
+  }
+
+  let archive = if archive.is_null() {
+    Default::default()
+  } else {
+    from_value::<SerTasks>(archive)?
+  };
+  Ok((from_value::<SerTaskState>(task_state)?, archive))
+}
+
+/// Load a task file along with the file storing its archive,
+/// converting both into the compact format if necessary.
+pub fn load_task_file(task_path: &Path, archive_path: &Path) -> Result<(SerTaskState, SerTasks)> {
+  let task_state = match load_state::<Option<Value>>(task_path)? {
+    Some(task_state) => task_state,
+    None => return Ok((Default::default(), load_state::<SerTasks>(archive_path)?)),
+  };
+  let archive = load_state::<Value>(archive_path)?;
+
+  parse_task_file(task_state, archive).map_err(|err| {
+    let error = format!("Failed to load {}: {}", task_path.display(), err);
+    Error::new(err.kind(), error)
+  })
+}
+
 /// Save some state into a file.
-fn save_state<T>(path: &Path, state: T) -> Result<()>
+pub fn save_state<T>(path: &Path, state: T) -> Result<()>
 where
   T: Serialize,
 {
@@ -82,34 +172,173 @@ where
   Ok(())
 }
 
+/// Remove all references to tags not known to `map` from the given
+/// tasks, recording a description of each in `dropped`.
+fn drop_invalid_task_tags(tasks: &mut SerTasks,
+                          map: &TagMap,
+                          path: &Path,
+                          dropped: &mut Vec<String>) {
+  for task in tasks.0.iter_mut() {
+    let summary = &task.summary;
+    task.tags.retain(|tag| {
+      let valid = map.contains_key(&tag.id);
+      if !valid {
+        dropped.push(format!(
+          "Task '{}' in {} referred to invalid tag Id {}", summary, path.display(), tag.id
+        ));
+      }
+      valid
+    })
+  }
+}
+
+/// Remove all literals referring to tags not known to `map` from the
+/// given query, recording a description of each in `dropped`.
+fn drop_invalid_query_tags(query: &mut SerQuery, map: &TagMap, dropped: &mut Vec<String>) {
+  let name = &query.name;
+  for lits in query.lits.iter_mut() {
+    lits.retain(|lit| match lit.tag() {
+      Some(tag) if !map.contains_key(&tag.id) => {
+        dropped.push(format!("Query '{}' referred to invalid tag Id {}", name, tag.id));
+        false
+      },
+      _ => true,
+    })
+  }
+  // A disjunction without any literals left matches nothing. We keep
+  // it as such, as dropping it would widen the query to tasks the user
+  // never asked for.
+}
+
+
+/// The contents of a task file, along with the store they were loaded
+/// from and the paths to the file and its archive.
+pub type StoredFile<P> = (Box<dyn TaskStore>, SerTaskState, P, SerTasks, P);
+
+
+/// A file tasks are loaded from and saved to.
+#[derive(Debug)]
+struct TaskFile {
+  /// The name of the file, as used for referring to it in queries.
+  name: String,
+  /// The path to the file storing the tasks.
+  path: PathBuf,
+  /// The path to the file storing archived tasks.
+  archive_path: PathBuf,
+  /// The tag templates defined in the file.
+  templates: BTreeSet<TagId>,
+  /// The format the file is stored in.
+  format: SerFormat,
+  /// The store the tasks are saved to.
+  store: Box<dyn TaskStore>,
+}
+
 
 /// A struct encapsulating the task state of the program.
+///
+/// Tasks from all task files are managed together, with each task
+/// remembering the file it belongs to.
 #[derive(Debug)]
 pub struct TaskState {
-  path: PathBuf,
+  files: Vec<TaskFile>,
   templates: Rc<Templates>,
   tasks: Rc<RefCell<Tasks>>,
+  archive: Rc<RefCell<Tasks>>,
+  /// Descriptions of the invalid tag references dropped while loading.
+  dropped: Vec<String>,
+  /// The history every save is recorded in, if enabled.
+  history: Option<Rc<History>>,
+  /// The generation of the tag templates as of the last save.
+  saved: Cell<u64>,
 }
 
 impl TaskState {
-  /// Persist the state into a file.
+  /// Persist the state into files, each task file separately.
+  ///
+  /// If the history is enabled, the files are committed along with the
+  /// UI state file, which consequently should be saved beforehand.
   pub fn save(&self) -> Result<()> {
-    save_state(&self.path, self.to_serde())
+    let mut tasks = self.tasks.borrow_mut();
+    let mut archive = self.archive.borrow_mut();
+
+    for (source, file) in self.files.iter().enumerate() {
+      let readable = file.format == SerFormat::Readable;
+      if readable {
+        // Tasks keep their keys when moving between the task list and
+        // the archive, so both share a key space.
+        let mut keys = BTreeSet::new();
+        tasks.claim_keys(source, &mut keys);
+        archive.claim_keys(source, &mut keys);
+        tasks.mint_keys(source, &mut keys);
+        archive.mint_keys(source, &mut keys);
+      }
+
+      // Besides the tag templates originally defined in the file we
+      // persist all the ones its tasks refer to.
+      let mut templates = file.templates.clone();
+      templates.extend(
+        tasks
+          .iter()
+          .chain(archive.iter())
+          .filter(|task| task.source() == source)
+          .flat_map(|task| task.tags().map(|tag| tag.id())),
+      );
+
+      let contents = Contents {
+        format: file.format,
+        templates: self.templates.to_serde_with(&templates),
+        tasks: &tasks,
+        archive: &archive,
+        source: source,
+        retagged: self.templates.generation() != self.saved.get(),
+      };
+      file.store.save(&contents)?;
+      tasks.clear_changed(source);
+      archive.clear_changed(source);
+    }
+    self.saved.set(self.templates.generation());
+
+    if let Some(history) = &self.history {
+      history.record(&tasks, &archive)?;
+    }
+    Ok(())
+  }
+
+  /// Retrieve the tag templates shared by all tasks.
+  pub fn templates(&self) -> Rc<Templates> {
+    self.templates.clone()
   }
 
   /// Retrieve the `Tasks` object associated with this `State` object.
   pub fn tasks(&self) -> Rc<RefCell<Tasks>> {
     self.tasks.clone()
   }
-}
 
-impl ToSerde<SerTaskState> for TaskState {
-  /// Convert this object into a serializable one.
-  fn to_serde(&self) -> SerTaskState {
-    SerTaskState {
-      templates: self.templates.to_serde(),
-      tasks: self.tasks.borrow().to_serde(),
-    }
+  /// Retrieve the `Tasks` object comprising all archived tasks.
+  pub fn archive(&self) -> Rc<RefCell<Tasks>> {
+    self.archive.clone()
+  }
+
+  /// Retrieve descriptions of the invalid tag references that got
+  /// dropped while loading the state.
+  ///
+  /// The references are gone from the in-memory state already, meaning
+  /// that the next save repairs the affected files.
+  pub fn dropped(&self) -> &[String] {
+    &self.dropped
+  }
+
+  /// Retrieve the history every save is recorded in, if enabled.
+  pub fn history(&self) -> Option<Rc<History>> {
+    self.history.clone()
+  }
+
+  /// Move all completed tasks into the archive.
+  ///
+  /// The return value indicates whether any task was moved.
+  pub fn archive_completed(&self) -> bool {
+    let mut archive = self.archive.borrow_mut();
+    self.tasks.borrow_mut().archive(&mut archive, None)
   }
 }
 
@@ -123,6 +352,10 @@ pub struct UiState {
   pub queries: Vec<(Query, Option<usize>)>,
   /// The currently selected `Query`.
   pub selected: Option<usize>,
+  /// The number of days after which completed tasks are archived.
+  pub archive_after: Option<u32>,
+  /// Whether every save is recorded in a git repository.
+  pub history: bool,
 }
 
 impl UiState {
@@ -142,10 +375,31 @@ impl ToSerde<SerUiState> for UiState {
       .iter()
       .map(|(q, s)| (q.to_serde(), *s))
       .collect();
+    // Task files in the readable format do not persist tag IDs, so we
+    // record the names of the tags the queries refer to.
+    let templates = self
+      .queries
+      .iter()
+      .flat_map(|(query, _)| query.tags())
+      .map(|tag| (tag.id(), tag.name()))
+      .collect::<BTreeMap<_, _>>()
+      .into_iter()
+      .map(|(id, name)| {
+        SerTemplate {
+          id: id.to_serde(),
+          name: name,
+          style: Default::default(),
+        }
+      })
+      .collect();
 
     SerUiState {
+      version: SerUiState::VERSION,
+      templates: SerTemplates(templates),
       queries: queries,
       selected: self.selected,
+      archive_after: self.archive_after,
+      history: self.history,
     }
   }
 }
@@ -162,29 +416,203 @@ pub struct State(pub TaskState, pub UiState);
 
 impl State {
   /// Create a new `State` object, loaded from files.
-  pub fn new<P>(task_path: P, ui_path: P) -> Result<Self>
+  #[cfg(test)]
+  pub fn new<P>(task_path: P, archive_path: P, ui_path: P) -> Result<Self>
   where
     P: Into<PathBuf> + AsRef<Path>,
   {
-    let task_state = load_state::<SerTaskState>(task_path.as_ref())?;
-    let ui_state = load_state::<SerUiState>(ui_path.as_ref())?;
+    Self::with_files(vec![(task_path, archive_path)], ui_path, false)
+  }
 
-    Self::with_serde(task_state, task_path, ui_state, ui_path)
+  /// Create a new `State` object comprising several task files, each
+  /// along with the file storing its archive, loaded from files.
+  ///
+  /// Please see `State::with_serde_files` for the meaning of
+  /// `tolerant`.
+  pub fn with_files<P>(files: Vec<(P, P)>, ui_path: P, tolerant: bool) -> Result<Self>
+  where
+    P: Into<PathBuf> + AsRef<Path>,
+  {
+    let mut ser_files = Vec::with_capacity(files.len());
+    for (task_path, archive_path) in files {
+      let store = storage::open(task_path.as_ref(), archive_path.as_ref())?;
+      let (task_state, archive) = store.load()?;
+      ser_files.push((store, task_state, task_path, archive, archive_path));
+    }
+    let ui_state = load_versioned_state::<SerUiState>(ui_path.as_ref())?;
+
+    Self::with_serde_stores(ser_files, ui_state, ui_path, tolerant)
   }
 
-  /// Create a new `State` object from a serializable one.
+  /// Create a new `State` object from a serializable one, dropping
+  /// references to tags that are not defined.
+  #[cfg(test)]
   pub fn with_serde<P>(task_state: SerTaskState, task_path: P,
+                       archive: SerTasks, archive_path: P,
                        ui_state: SerUiState, ui_path: P) -> Result<Self>
+  where
+    P: Into<PathBuf> + AsRef<Path>,
+  {
+    let files = vec![(task_state, task_path, archive, archive_path)];
+    Self::with_serde_files(files, ui_state, ui_path, true)
+  }
+
+  /// Create a new `State` object from serializable ones, comprising
+  /// several task files along with their archives.
+  ///
+  /// Task files are referred to by the stem of their path. Tag
+  /// templates of the same name are shared between all of them.
+  /// References to tags that are not defined cause an error, unless
+  /// `tolerant` is set, in which case they are dropped and can be
+  /// retrieved via `TaskState::dropped`.
+  pub fn with_serde_files<P>(files: Vec<(SerTaskState, P, SerTasks, P)>,
+                             ui_state: SerUiState,
+                             ui_path: P,
+                             tolerant: bool) -> Result<Self>
+  where
+    P: Into<PathBuf> + AsRef<Path>,
+  {
+    let mut stores = Vec::with_capacity(files.len());
+    for (task_state, task_path, archive, archive_path) in files {
+      let store = storage::open(task_path.as_ref(), archive_path.as_ref())?;
+      stores.push((store, task_state, task_path, archive, archive_path));
+    }
+    Self::with_serde_stores(stores, ui_state, ui_path, tolerant)
+  }
+
+  /// Create a new `State` object from serializable ones, each loaded
+  /// from the given store, which is used for saving it later on.
+  pub fn with_serde_stores<P>(files: Vec<StoredFile<P>>,
+                              mut ui_state: SerUiState,
+                              ui_path: P,
+                              tolerant: bool) -> Result<Self>
   where
     P: Into<PathBuf>,
   {
-    let (templates, map) = Templates::with_serde(task_state.templates);
+    debug_assert!(!files.is_empty());
+
+    let (mut templates, _) = Templates::with_serde(Default::default());
+    let files = files
+      .into_iter()
+      .map(|(store, task_state, task_path, archive, archive_path)| {
+        let map = templates.merge(task_state.templates);
+        let task_path = task_path.into();
+        // Databases identify tasks by their keys, which only the
+        // readable format maintains.
+        let format = if storage::is_database(&task_path) {
+          SerFormat::Readable
+        } else {
+          task_state.format
+        };
+        (store, map, format, task_state.tasks, task_path, archive, archive_path.into())
+      })
+      .collect::<Vec<_>>();
     let templates = Rc::new(templates);
-    let tasks = Tasks::with_serde(task_state.tasks, templates.clone(), &map)?;
+
+    let mut tasks = Tasks::with_serde(Default::default(), templates.clone(), &Default::default())?;
+    let mut archive = Tasks::with_serde(Default::default(), templates.clone(), &Default::default())?;
+    let mut task_files = Vec::<TaskFile>::with_capacity(files.len());
+    // Queries may refer to tags of all task files. In case of
+    // ambiguities, earlier files take precedence.
+    let mut map = TagMap::new();
+    let mut dropped = Vec::new();
+
+    for (source, file) in files.into_iter().enumerate() {
+      let (store, file_map, format, mut file_tasks, path, mut file_archive, archive_path) = file;
+      let name = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+      if task_files.iter().any(|x| x.name == name) {
+        let error = format!("Encountered duplicate task file name '{}'", name);
+        return Err(Error::new(ErrorKind::InvalidInput, error))
+      }
+
+      drop_invalid_task_tags(&mut file_tasks, &file_map, &path, &mut dropped);
+      drop_invalid_task_tags(&mut file_archive, &file_map, &archive_path, &mut dropped);
+
+      let file_tasks = Tasks::with_serde(file_tasks, templates.clone(), &file_map)?;
+      tasks.append(file_tasks, source);
+      // Archived tasks refer to the same tag templates as active ones.
+      let file_archive = Tasks::with_serde(file_archive, templates.clone(), &file_map)?;
+      archive.append(file_archive, source);
+
+      for (serde_id, id) in file_map.iter() {
+        let _ = map.entry(*serde_id).or_insert(*id);
+      }
+
+      task_files.push(TaskFile {
+        name: name,
+        path: path,
+        archive_path: archive_path,
+        templates: file_map.values().copied().collect(),
+        format: format,
+        store: store,
+      });
+    }
+
+    // If the UI state lists the templates its queries refer to, tags are
+    // matched by name instead, as task files in the readable format do
+    // not persist tag IDs.
+    if !ui_state.templates.is_empty() {
+      map = ui_state
+        .templates
+        .0
+        .iter()
+        .filter_map(|x| Some((x.id, templates.find(&x.name)?)))
+        .collect();
+    }
+
+    for (query, _) in ui_state.queries.iter_mut() {
+      drop_invalid_query_tags(query, &map, &mut dropped);
+    }
+
+    if !tolerant {
+      if let Some(dropped) = dropped.first() {
+        return Err(Error::new(ErrorKind::InvalidInput, dropped.clone()))
+      }
+    }
+
+    if let Some(days) = ui_state.archive_after {
+      let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+      let before = now.saturating_sub(u64::from(days) * 24 * 60 * 60);
+      let _ = tasks.archive(&mut archive, Some(before));
+    }
+
+    let ui_path = ui_path.into();
+    // The repository lives next to the UI state, i.e., typically in the
+    // configuration directory.
+    let history = if ui_state.history {
+      // Databases are not suitable for being tracked by git and we
+      // would rather not enable the history than silently leave out
+      // some of the tasks.
+      if let Some(file) = task_files.iter().find(|x| storage::is_database(&x.path)) {
+        let error = format!(
+          "The history can not be enabled for task database {}", file.path.display()
+        );
+        return Err(Error::new(ErrorKind::InvalidInput, error))
+      }
+
+      let dir = ui_path.parent().map(Path::to_path_buf).unwrap_or_default();
+      let files = task_files
+        .iter()
+        .map(|x| (x.path.clone(), x.archive_path.clone()))
+        .collect();
+      let history = History::new(dir, files, ui_path.clone(), &tasks, &archive);
+      Some(Rc::new(history))
+    } else {
+      None
+    };
+
+    let names = task_files.iter().map(|x| x.name.clone()).collect::<Vec<_>>();
     let tasks = Rc::new(RefCell::new(tasks));
+    let archive = Rc::new(RefCell::new(archive));
     let mut queries = Vec::new();
     for (query, selected) in ui_state.queries.into_iter() {
-      let query = Query::with_serde(query, &templates, &map, tasks.clone())?;
+      let query = Query::with_serde(query, &templates, &map, &names, tasks.clone())?;
       queries.push((query, selected))
     }
     // For convenience for the user, we add a default query capturing
@@ -193,15 +621,22 @@ impl State {
       queries.push((QueryBuilder::new(tasks.clone()).build("all"), None))
     }
 
+    let saved = Cell::new(templates.generation());
     let task_state = TaskState {
-      path: task_path.into(),
+      files: task_files,
       templates: templates,
       tasks: tasks,
+      archive: archive,
+      dropped: dropped,
+      history: history,
+      saved: saved,
     };
     let ui_state = UiState {
-      path: ui_path.into(),
+      path: ui_path,
       queries: queries,
       selected: ui_state.selected,
+      archive_after: ui_state.archive_after,
+      history: ui_state.history,
     };
     Ok(State(task_state, ui_state))
   }
@@ -209,7 +644,7 @@ impl State {
 
 
 #[cfg(test)]
-pub mod tests {
+mod tests {
   use super::*;
 
   use std::env::temp_dir;
@@ -217,6 +652,11 @@ pub mod tests {
   use std::fs::remove_dir_all;
   use std::io::Read;
 
+  use serde_json::from_str as from_json;
+  use serde_json::json;
+
+  use crate::ser::query::Query as SerQuery;
+  use crate::ser::query::TagLit as SerTagLit;
   use crate::ser::tags::Id as SerId;
   use crate::ser::tags::Tag as SerTag;
   use crate::ser::tags::Template as SerTemplate;
@@ -227,17 +667,25 @@ pub mod tests {
   use crate::test::NamedTempFile;
 
 
-  /// Create a state object based off of two temporary configuration files.
-  fn make_state(count: usize) -> (State, NamedTempFile, NamedTempFile) {
+  /// Create a state object based off of three temporary configuration
+  /// files.
+  fn make_state(count: usize) -> (State, NamedTempFile, NamedTempFile, NamedTempFile) {
     let task_state = SerTaskState {
       templates: Default::default(),
       tasks: SerTasks(make_tasks(count)),
+      ..Default::default()
     };
     let ui_state = Default::default();
     let task_file = NamedTempFile::new();
+    let archive_file = NamedTempFile::new();
     let ui_file = NamedTempFile::new();
-    let state = State::with_serde(task_state, task_file.path(), ui_state, ui_file.path());
-    (state.unwrap(), task_file, ui_file)
+    let state = State::with_serde(task_state,
+                                  task_file.path(),
+                                  Default::default(),
+                                  archive_file.path(),
+                                  ui_state,
+                                  ui_file.path());
+    (state.unwrap(), task_file, archive_file, ui_file)
   }
 
   #[test]
@@ -256,11 +704,11 @@ pub mod tests {
 
   #[test]
   fn save_and_load_state() {
-    let (state, task_file, ui_file) = make_state(3);
+    let (state, task_file, archive_file, ui_file) = make_state(3);
     state.0.save().unwrap();
     state.1.save().unwrap();
 
-    let new_state = State::new(task_file.path(), ui_file.path()).unwrap();
+    let new_state = State::new(task_file.path(), archive_file.path(), ui_file.path()).unwrap();
     let new_task_vec = new_state
       .0
       .tasks
@@ -273,17 +721,21 @@ pub mod tests {
 
   #[test]
   fn load_state_file_not_found() {
-    let (task_path, ui_path) = {
-      let (state, task_file, ui_file) = make_state(1);
+    let (task_path, archive_path, ui_path) = {
+      let (state, task_file, archive_file, ui_file) = make_state(1);
       state.0.save().unwrap();
       state.1.save().unwrap();
 
-      (task_file.path().clone(), ui_file.path().clone())
+      (
+        task_file.path().clone(),
+        archive_file.path().clone(),
+        ui_file.path().clone(),
+      )
     };
 
     // The files are removed by now, so we can test that `State` handles
     // such missing files gracefully.
-    let new_state = State::new(task_path, ui_path).unwrap();
+    let new_state = State::new(task_path, archive_path, ui_path).unwrap();
     let new_task_vec = new_state
       .0
       .tasks
@@ -294,29 +746,356 @@ pub mod tests {
     assert_eq!(new_task_vec, make_tasks(0));
   }
 
+  #[test]
+  fn save_and_load_multiple_task_files() {
+    let templates = |id| {
+      SerTemplates(vec![
+        SerTemplate {
+          id: SerId::new(id),
+          name: "shared".to_string(),
+          style: Default::default(),
+        },
+      ])
+    };
+    let tasks = |summary: &str, id| {
+      SerTasks(vec![
+        SerTask {
+          summary: summary.to_string(),
+          tags: vec![SerTag { id: SerId::new(id), value: None }],
+          ..Default::default()
+        },
+      ])
+    };
+    let personal = SerTaskState {
+      templates: templates(1),
+      tasks: tasks("personal", 1),
+      ..Default::default()
+    };
+    let team = SerTaskState {
+      templates: templates(2),
+      tasks: tasks("team", 2),
+      ..Default::default()
+    };
+
+    let personal_file = NamedTempFile::new();
+    let personal_archive = NamedTempFile::new();
+    let team_file = NamedTempFile::new();
+    let team_archive = NamedTempFile::new();
+    let ui_file = NamedTempFile::new();
+
+    let team_name = team_file.path().file_stem().unwrap().to_string_lossy().into_owned();
+    let ui_state = SerUiState {
+      queries: vec![
+        (SerQuery {
+          name: "team".to_string(),
+          source: Some(team_name.clone()),
+          ..Default::default()
+        }, None),
+      ],
+      ..Default::default()
+    };
+
+    let files = vec![
+      (personal, personal_file.path(), Default::default(), personal_archive.path()),
+      (team, team_file.path(), Default::default(), team_archive.path()),
+    ];
+    let state = State::with_serde_files(files, ui_state, ui_file.path(), false).unwrap();
+    {
+      let tasks = state.0.tasks.borrow();
+      let tasks = tasks.iter().collect::<Vec<_>>();
+      assert_eq!(tasks.len(), 2);
+      // Tags of the same name are shared between task files.
+      let tag1 = tasks[0].tags().next().unwrap().id();
+      let tag2 = tasks[1].tags().next().unwrap().id();
+      assert_eq!(tag1, tag2);
+    }
+
+    let query = &state.1.queries[0].0;
+    assert_eq!(query.source(), Some((1, team_name.as_str())));
+    let summaries = query.iter().clone().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["team"]);
+
+    state.0.save().unwrap();
+    state.1.save().unwrap();
+
+    // Each file only stores its own tasks.
+    let team = super::load_state::<SerTaskState>(team_file.path()).unwrap();
+    assert_eq!(team.tasks.0.len(), 1);
+    assert_eq!(team.tasks.0[0].summary, "team");
+
+    let files = vec![
+      (personal_file.path(), personal_archive.path()),
+      (team_file.path(), team_archive.path()),
+    ];
+    let new_state = State::with_files(files, ui_file.path(), false).unwrap();
+    let summaries = new_state
+      .0
+      .tasks
+      .borrow()
+      .iter()
+      .map(|x| (x.summary.clone(), x.source()))
+      .collect::<Vec<_>>();
+    let expected = vec![("personal".to_string(), 0), ("team".to_string(), 1)];
+    assert_eq!(summaries, expected);
+    assert_eq!(new_state.1.queries[0].0.source(), Some((1, team_name.as_str())));
+  }
+
+  #[test]
+  fn load_duplicate_task_file_names() {
+    let files = vec![
+      (Default::default(), PathBuf::from("a/tasks.json"),
+       Default::default(), PathBuf::from("a/archive.json")),
+      (Default::default(), PathBuf::from("b/tasks.json"),
+       Default::default(), PathBuf::from("b/archive.json")),
+    ];
+    let err = State::with_serde_files(files, Default::default(), PathBuf::default(), false).unwrap_err();
+    assert_eq!(err.to_string(), "Encountered duplicate task file name 'tasks'");
+  }
+
+  #[test]
+  fn save_and_load_readable_state() {
+    let id_tag = SerId::new(3);
+    let task_state = SerTaskState {
+      format: SerFormat::Readable,
+      templates: SerTemplates(vec![
+        SerTemplate {
+          id: id_tag,
+          name: "tag".to_string(),
+          style: Default::default(),
+        },
+      ]),
+      tasks: SerTasks(vec![
+        SerTask {
+          summary: "parent".to_string(),
+          tags: vec![SerTag { id: id_tag, value: Some("1".to_string()) }],
+          ..Default::default()
+        },
+        SerTask {
+          summary: "child".to_string(),
+          parent: Some(0),
+          ..Default::default()
+        },
+      ]),
+      ..Default::default()
+    };
+    let ui_state = SerUiState {
+      queries: vec![
+        (SerQuery {
+          name: "tagged".to_string(),
+          lits: vec![vec![SerTagLit::Pos(SerTag { id: id_tag, value: None })]],
+          ..Default::default()
+        }, None),
+      ],
+      ..Default::default()
+    };
+    let task_file = NamedTempFile::new();
+    let archive_file = NamedTempFile::new();
+    let ui_file = NamedTempFile::new();
+
+    let state = State::with_serde(task_state,
+                                  task_file.path(),
+                                  Default::default(),
+                                  archive_file.path(),
+                                  ui_state,
+                                  ui_file.path()).unwrap();
+    state.0.save().unwrap();
+    state.1.save().unwrap();
+
+    let read = |path: &Path| {
+      let mut content = String::new();
+      let _ = File::open(path).unwrap().read_to_string(&mut content).unwrap();
+      content
+    };
+    let content = read(task_file.path());
+    let value = from_json::<Value>(&content).unwrap();
+    assert_eq!(value["format"], "readable");
+    assert_eq!(value["templates"][1], json!({"name": "tag"}));
+    assert_eq!(value["tasks"][0]["tags"][0], json!({"name": "tag", "value": "1"}));
+    assert_eq!(value["tasks"][1]["parent"], value["tasks"][0]["id"]);
+
+    // Saving the loaded state again does not change anything, even
+    // after new tasks got added in front.
+    let state = State::new(task_file.path(), archive_file.path(), ui_file.path()).unwrap();
+    assert_eq!(state.1.queries[0].0.iter().clone().count(), 1);
+    state.0.save().unwrap();
+    state.1.save().unwrap();
+    assert_eq!(read(task_file.path()), content);
+
+    {
+      let mut tasks = state.0.tasks.borrow_mut();
+      let id = tasks.add("new".to_string(), Vec::new(), None, 0);
+      let first = tasks.iter().next().unwrap().id();
+      tasks.move_before(id, first);
+    }
+    state.0.save().unwrap();
+
+    let value = from_json::<Value>(&read(task_file.path())).unwrap();
+    let ids = value["tasks"]
+      .as_array()
+      .unwrap()
+      .iter()
+      .map(|x| x["id"].as_u64().unwrap())
+      .collect::<Vec<_>>();
+    let old = from_json::<Value>(&content).unwrap();
+    assert_eq!(ids[1], old["tasks"][0]["id"].as_u64().unwrap());
+    assert_eq!(ids[2], old["tasks"][1]["id"].as_u64().unwrap());
+    assert!(ids[0] != ids[1] && ids[0] != ids[2]);
+  }
+
+  #[test]
+  fn load_state_of_all_versions() {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures");
+
+    let versions = [
+      ("v0-0.2.0", 2, 2),
+      ("v0-latest", 2, 1),
+      ("v1", 2, 1),
+      ("v1-readable", 2, 1),
+      ("v2", 2, 1),
+    ];
+    for (version, tasks, queries) in &versions {
+      let dir = fixtures.join(version);
+      let state = State::new(dir.join("tasks.json"),
+                             dir.join("archive.json"),
+                             dir.join("notnow.json")).unwrap();
+      assert!(state.0.dropped().is_empty(), "{}", version);
+      assert_eq!(state.0.tasks.borrow().iter().count(), *tasks, "{}", version);
+      assert_eq!(state.1.queries.len(), *queries, "{}", version);
+    }
+  }
+
+  #[test]
+  fn load_state_of_unsupported_version() {
+    let ui_file = NamedTempFile::new();
+    let version = SerUiState::VERSION + 1;
+    let ui_state = format!("{{\"version\": {}}}", version);
+    save_state(ui_file.path(), from_json::<Value>(&ui_state).unwrap()).unwrap();
+
+    let err = load_versioned_state::<SerUiState>(ui_file.path()).unwrap_err();
+    let expected = format!(
+      "Failed to load {}: Encountered unsupported format version {} (newest supported: {})",
+      ui_file.path().display(), version, SerUiState::VERSION,
+    );
+    assert_eq!(err.to_string(), expected);
+  }
+
   #[test]
   fn load_state_with_invalid_tag() {
-    let templates = SerTemplates(Default::default());
+    let id_tag = SerId::new(7);
+    let templates = SerTemplates(vec![
+      SerTemplate {
+        id: id_tag,
+        name: "tag".to_string(),
+        style: Default::default(),
+      },
+    ]);
     let tasks = SerTasks(vec![
       SerTask {
         summary: "a task!".to_string(),
         tags: vec![
           SerTag {
             id: SerId::new(42),
+            value: None,
+          },
+          SerTag {
+            id: id_tag,
+            value: None,
           },
         ],
+        ..Default::default()
       },
     ]);
     let task_state = SerTaskState {
       templates: templates,
       tasks: tasks,
+      ..Default::default()
     };
-    let task_path = PathBuf::default();
-    let ui_state = Default::default();
-    let ui_path = PathBuf::default();
+    let ui_state = SerUiState {
+      queries: vec![
+        (SerQuery {
+          name: "query".to_string(),
+          lits: vec![
+            vec![SerTagLit::Pos(SerTag { id: SerId::new(43), value: None })],
+            vec![
+              SerTagLit::Neg(SerTag { id: SerId::new(42), value: None }),
+              SerTagLit::Pos(SerTag { id: id_tag, value: None }),
+            ],
+          ],
+          ..Default::default()
+        }, None),
+      ],
+      ..Default::default()
+    };
+    let task_file = NamedTempFile::new();
+    let archive_file = NamedTempFile::new();
+    let ui_file = NamedTempFile::new();
+
+    let state = State::with_serde(task_state,
+                                  task_file.path(),
+                                  Default::default(),
+                                  archive_file.path(),
+                                  ui_state,
+                                  ui_file.path()).unwrap();
+    let expected = vec![
+      format!("Task 'a task!' in {} referred to invalid tag Id 42", task_file.path().display()),
+      "Query 'query' referred to invalid tag Id 43".to_string(),
+      "Query 'query' referred to invalid tag Id 42".to_string(),
+    ];
+    assert_eq!(state.0.dropped(), expected.as_slice());
 
-    let err = State::with_serde(task_state, task_path, ui_state, ui_path).unwrap_err();
-    assert_eq!(err.to_string(), "Encountered invalid tag Id 42")
+    {
+      let tasks = state.0.tasks.borrow();
+      let task = tasks.iter().next().unwrap();
+      let tags = task.tags().map(|x| x.name()).collect::<Vec<_>>();
+      assert_eq!(tags, vec!["tag".to_string()]);
+    }
+
+    // The conjunction that lost all its literals matches nothing.
+    let (query, _) = &state.1.queries[0];
+    assert!(query.is_empty());
+    let lits = query.to_serde().lits;
+    assert_eq!(lits.len(), 2);
+    assert!(lits[0].is_empty());
+    assert_eq!(lits[1].len(), 1);
+    assert!(matches!(lits[1][0], SerTagLit::Pos(..)));
+
+    // Saving the state persists it without the invalid references.
+    state.0.save().unwrap();
+    state.1.save().unwrap();
+
+    let state = State::new(task_file.path(), archive_file.path(), ui_file.path()).unwrap();
+    assert!(state.0.dropped().is_empty());
+  }
+
+  #[test]
+  fn load_state_with_invalid_tag_strictly() {
+    let task_state = SerTaskState {
+      tasks: SerTasks(vec![
+        SerTask {
+          summary: "a task!".to_string(),
+          tags: vec![SerTag { id: SerId::new(42), value: None }],
+          ..Default::default()
+        },
+      ]),
+      ..Default::default()
+    };
+    let task_file = NamedTempFile::new();
+    let archive_file = NamedTempFile::new();
+    let ui_file = NamedTempFile::new();
+    save_state(task_file.path(), task_state).unwrap();
+    save_state(archive_file.path(), SerTasks::default()).unwrap();
+    save_state(ui_file.path(), SerUiState::default()).unwrap();
+
+    let err = State::new(task_file.path(), archive_file.path(), ui_file.path()).unwrap_err();
+    let expected = format!(
+      "Task 'a task!' in {} referred to invalid tag Id 42", task_file.path().display()
+    );
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert_eq!(err.to_string(), expected);
+
+    let files = vec![(task_file.path(), archive_file.path())];
+    let state = State::with_files(files, ui_file.path(), true).unwrap();
+    assert_eq!(state.0.dropped(), &[expected]);
   }
 
   #[test]
@@ -328,10 +1107,12 @@ pub mod tests {
       SerTemplate {
         id: id_tag1,
         name: "tag1".to_string(),
+        style: Default::default(),
       },
       SerTemplate {
         id: id_tag2,
         name: "tag2".to_string(),
+        style: Default::default(),
       },
     ]);
 
@@ -341,43 +1122,57 @@ pub mod tests {
         tags: vec![
           SerTag {
             id: id_tag2,
+            value: None,
           },
         ],
+        ..Default::default()
       },
       SerTask {
         summary: "an untagged task".to_string(),
         tags: Default::default(),
+        ..Default::default()
       },
       SerTask {
         summary: "a tag1 task".to_string(),
         tags: vec![
           SerTag {
             id: id_tag1,
+            value: None,
           },
         ],
+        ..Default::default()
       },
       SerTask {
         summary: "a doubly tagged task".to_string(),
         tags: vec![
           SerTag {
             id: id_tag2,
+            value: None,
           },
           SerTag {
             id: id_tag1,
+            value: None,
           },
         ],
+        ..Default::default()
       },
     ]);
     let task_state = SerTaskState {
       templates: templates,
       tasks: tasks,
+      ..Default::default()
     };
     let task_path = PathBuf::default();
 
     let ui_state = Default::default();
     let ui_path = PathBuf::default();
 
-    let state = State::with_serde(task_state, task_path, ui_state, ui_path).unwrap();
+    let state = State::with_serde(task_state,
+                                  task_path,
+                                  Default::default(),
+                                  PathBuf::default(),
+                                  ui_state,
+                                  ui_path).unwrap();
     let tasks = state.0.tasks.borrow();
     let mut it = tasks.iter();
 
@@ -400,4 +1195,67 @@ pub mod tests {
     assert!(tags.next().is_some());
     assert!(tags.next().is_none());
   }
+
+  #[test]
+  fn archive_automatically() {
+    let id_complete = SerId::new(1);
+    let templates = SerTemplates(vec![
+      SerTemplate {
+        id: id_complete,
+        name: "complete".to_string(),
+        style: Default::default(),
+      },
+    ]);
+
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap()
+      .as_secs();
+    let complete = vec![SerTag { id: id_complete, value: None }];
+    let tasks = SerTasks(vec![
+      SerTask {
+        summary: "completed long ago".to_string(),
+        tags: complete.clone(),
+        completed: Some(now - 8 * 24 * 60 * 60),
+        ..Default::default()
+      },
+      SerTask {
+        summary: "completed recently".to_string(),
+        tags: complete,
+        completed: Some(now),
+        ..Default::default()
+      },
+      SerTask {
+        summary: "not completed".to_string(),
+        ..Default::default()
+      },
+    ]);
+    let task_state = SerTaskState {
+      templates: templates,
+      tasks: tasks,
+      ..Default::default()
+    };
+    let ui_state = SerUiState {
+      archive_after: Some(7),
+      ..Default::default()
+    };
+
+    let state = State::with_serde(task_state,
+                                  PathBuf::default(),
+                                  Default::default(),
+                                  PathBuf::default(),
+                                  ui_state,
+                                  PathBuf::default()).unwrap();
+
+    let summaries = |tasks: Rc<RefCell<Tasks>>| {
+      tasks
+        .borrow()
+        .iter()
+        .map(|x| x.summary.clone())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(summaries(state.0.tasks()), vec!["completed recently", "not completed"]);
+    assert_eq!(summaries(state.0.archive()), vec!["completed long ago"]);
+    assert_eq!(state.1.archive_after, Some(7));
+  }
 }
@@ -0,0 +1,76 @@
+// json.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+use std::io::Result;
+use std::path::PathBuf;
+
+use serde_json::to_value;
+
+use crate::ser::readable::to_readable;
+use crate::ser::state::Format as SerFormat;
+use crate::ser::state::TaskState as SerTaskState;
+use crate::ser::tasks::Tasks as SerTasks;
+use crate::state::load_task_file;
+use crate::state::save_state;
+
+use super::Contents;
+use super::TaskStore;
+
+
+/// A task file stored as JSON, with its archive in a separate file.
+///
+/// Every save rewrites both files in their entirety.
+#[derive(Debug)]
+pub struct JsonFile {
+  /// The path to the file storing the tasks.
+  path: PathBuf,
+  /// The path to the file storing archived tasks.
+  archive_path: PathBuf,
+}
+
+impl JsonFile {
+  /// Create a `JsonFile` object for the given files, which do not have
+  /// to exist yet.
+  pub fn new(path: impl Into<PathBuf>, archive_path: impl Into<PathBuf>) -> Self {
+    Self {
+      path: path.into(),
+      archive_path: archive_path.into(),
+    }
+  }
+}
+
+impl TaskStore for JsonFile {
+  fn load(&self) -> Result<(SerTaskState, SerTasks)> {
+    load_task_file(&self.path, &self.archive_path)
+  }
+
+  fn save(&self, contents: &Contents<'_>) -> Result<()> {
+    let (task_state, archive) = contents.to_serde();
+    if task_state.format == SerFormat::Readable {
+      let mut task_state = to_value(task_state)?;
+      let mut archive = to_value(archive)?;
+      to_readable(&mut task_state, &mut archive)?;
+      save_state(&self.path, task_state)?;
+      save_state(&self.archive_path, archive)
+    } else {
+      save_state(&self.path, task_state)?;
+      save_state(&self.archive_path, archive)
+    }
+  }
+}
@@ -0,0 +1,228 @@
+// mod.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Storage backends for task files.
+//!
+//! A task file, along with its archive, is stored either as a pair of
+//! JSON files or, if support for it is enabled via the `sqlite`
+//! feature, in a SQLite database. The backend is chosen based on the
+//! path of the task file. A database can be populated from the JSON
+//! files it replaces via `import`, which renames them afterwards.
+
+mod json;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::fmt::Debug;
+use std::fs::remove_file;
+use std::fs::rename;
+use std::io::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::ser::migrate::Versioned;
+use crate::ser::state::Format as SerFormat;
+use crate::ser::state::TaskState as SerTaskState;
+use crate::ser::tags::Templates as SerTemplates;
+use crate::ser::tasks::Tasks as SerTasks;
+use crate::state::State;
+use crate::tasks::Source;
+use crate::tasks::Tasks;
+
+pub use self::json::JsonFile;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::Database;
+
+
+/// A place a task file, along with its archive, is stored in.
+pub trait TaskStore: Debug {
+  /// Load the tasks along with the archived ones.
+  ///
+  /// A store not containing any tasks yet yields the default state.
+  fn load(&self) -> Result<(SerTaskState, SerTasks)>;
+
+  /// Save the tasks along with the archived ones.
+  ///
+  /// Tasks are expected to carry a key if the format is the readable
+  /// one.
+  fn save(&self, contents: &Contents<'_>) -> Result<()>;
+}
+
+
+/// The contents of a task file to save.
+#[derive(Debug)]
+pub struct Contents<'c> {
+  /// The format to save the task file in.
+  pub format: SerFormat,
+  /// The tag templates to save.
+  pub templates: SerTemplates,
+  /// The tasks, of which only the ones belonging to `source` are saved.
+  pub tasks: &'c Tasks,
+  /// The archived tasks, of which only the ones belonging to `source`
+  /// are saved.
+  pub archive: &'c Tasks,
+  /// The task file the tasks to save belong to.
+  pub source: Source,
+  /// Whether the tag templates changed since the last save, in which
+  /// case the tags of all tasks have to be considered changed as well.
+  pub retagged: bool,
+}
+
+impl Contents<'_> {
+  /// Convert the contents into a serializable task file along with its
+  /// archive.
+  pub fn to_serde(&self) -> (SerTaskState, SerTasks) {
+    let (tasks, archive) = if self.format == SerFormat::Readable {
+      (
+        self.tasks.to_serde_source_with_keys(self.source),
+        self.archive.to_serde_source_with_keys(self.source),
+      )
+    } else {
+      (self.tasks.to_serde_source(self.source), self.archive.to_serde_source(self.source))
+    };
+    let task_state = SerTaskState {
+      version: SerTaskState::VERSION,
+      format: self.format,
+      templates: self.templates.clone(),
+      tasks: tasks,
+    };
+    (task_state, archive)
+  }
+}
+
+
+/// Check whether the given path refers to a database, as opposed to a
+/// JSON file.
+pub fn is_database(path: &Path) -> bool {
+  matches!(path.extension().and_then(|x| x.to_str()), Some("db") | Some("sqlite"))
+}
+
+/// Open the store for the task file at `task_path`, with `archive_path`
+/// being the file storing its archive.
+///
+/// Databases keep the archive alongside the tasks, which is why
+/// `archive_path` is only used for JSON files.
+pub fn open(task_path: &Path, archive_path: &Path) -> Result<Box<dyn TaskStore>> {
+  if is_database(task_path) {
+    open_database(task_path)
+  } else {
+    Ok(Box::new(JsonFile::new(task_path, archive_path)))
+  }
+}
+
+/// Open the store for the task file at `task_path` for reading only.
+///
+/// Contrary to `open`, a database that does not exist is reported as
+/// an error instead of being created.
+pub fn open_read_only(task_path: &Path, archive_path: &Path) -> Result<Box<dyn TaskStore>> {
+  if is_database(task_path) {
+    open_database_read_only(task_path)
+  } else {
+    Ok(Box::new(JsonFile::new(task_path, archive_path)))
+  }
+}
+
+/// Retrieve the path an imported JSON file is renamed to, i.e.,
+/// `<name>.imported` next to it.
+fn imported_path(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(".imported");
+  path.with_file_name(name)
+}
+
+/// Rename the imported JSON task file and its archive, if any, out of
+/// the way, so that they can not be mistaken for the current tasks.
+fn retire(task_path: &Path, archive_path: &Path) -> Result<()> {
+  rename(task_path, imported_path(task_path))?;
+  if archive_path.exists() {
+    if let Err(err) = rename(archive_path, imported_path(archive_path)) {
+      let _ = rename(imported_path(task_path), task_path);
+      return Err(err)
+    }
+  }
+  Ok(())
+}
+
+/// Import the JSON task file at `task_path`, along with its archive at
+/// `archive_path`, into the database at `path`, unless the database
+/// exists already or there is nothing to import.
+///
+/// Once imported, the JSON files are renamed as per `imported_path`.
+/// The return value indicates whether tasks got imported.
+pub fn import(path: &Path, task_path: &Path, archive_path: &Path) -> Result<bool> {
+  if path.exists() || !task_path.exists() {
+    return Ok(false)
+  }
+
+  let (task_state, archive) = JsonFile::new(task_path, archive_path).load()?;
+  let store = open_database(path)?;
+  let files = vec![(store, task_state, path.to_path_buf(), archive, archive_path.to_path_buf())];
+  // The state does not have a UI state file, as we never save it.
+  let result = State::with_serde_stores(files, Default::default(), PathBuf::new(), false)
+    .and_then(|State(task_state, _)| task_state.save())
+    .and_then(|()| retire(task_path, archive_path));
+  // Do not leave a partial database behind, or we would not attempt
+  // another import.
+  if result.is_err() {
+    let _ = remove_file(path);
+  }
+  result.map(|()| true)
+}
+
+/// Open the database at the given path.
+#[cfg(feature = "sqlite")]
+fn open_database(path: &Path) -> Result<Box<dyn TaskStore>> {
+  Ok(Box::new(Database::open(path)?))
+}
+
+/// Open the existing database at the given path for reading only.
+#[cfg(feature = "sqlite")]
+fn open_database_read_only(path: &Path) -> Result<Box<dyn TaskStore>> {
+  Ok(Box::new(Database::open_read_only(path)?))
+}
+
+/// Report that databases are not supported.
+#[cfg(not(feature = "sqlite"))]
+fn open_database_read_only(path: &Path) -> Result<Box<dyn TaskStore>> {
+  open_database(path)
+}
+
+/// Report that databases are not supported.
+#[cfg(not(feature = "sqlite"))]
+fn open_database(path: &Path) -> Result<Box<dyn TaskStore>> {
+  let error = format!(
+    "Unable to open {}: support for SQLite databases is not enabled", path.display()
+  );
+  Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, error))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  #[test]
+  fn detect_databases() {
+    assert!(is_database(Path::new("tasks.db")));
+    assert!(is_database(Path::new("dir/tasks.sqlite")));
+    assert!(!is_database(Path::new("tasks.json")));
+    assert!(!is_database(Path::new("db")));
+  }
+}
@@ -0,0 +1,669 @@
+// sqlite.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::create_dir_all;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+use std::path::Path;
+
+use rusqlite::params;
+use rusqlite::Connection;
+use rusqlite::OpenFlags;
+
+use serde_json::from_str as from_json;
+use serde_json::json;
+use serde_json::to_string as to_json;
+use serde_json::to_value;
+use serde_json::Value;
+
+use crate::ser::migrate::Versioned;
+use crate::ser::readable::task_to_readable;
+use crate::ser::readable::templates_to_readable;
+use crate::ser::state::TaskState as SerTaskState;
+use crate::ser::tasks::Id as SerTaskId;
+use crate::ser::tasks::Tasks as SerTasks;
+use crate::state::parse_task_file;
+use crate::tasks::Task;
+
+use super::Contents;
+use super::TaskStore;
+
+
+/// The version of the database schema.
+const SCHEMA_VERSION: u32 = 1;
+
+/// The statements creating the database schema.
+///
+/// Templates and tasks are stored the way they are represented in the
+/// readable format, i.e., with tags referring to templates by name and
+/// tasks being identified by stable IDs. That way, rows only change if
+/// the object they store does.
+const SCHEMA: &str = "
+  CREATE TABLE IF NOT EXISTS templates (
+    name TEXT PRIMARY KEY NOT NULL,
+    data TEXT NOT NULL
+  );
+  CREATE TABLE IF NOT EXISTS tasks (
+    id INTEGER PRIMARY KEY NOT NULL,
+    archived INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    data TEXT NOT NULL
+  );
+";
+
+
+/// The gap left between the positions of adjacent tasks, so that tasks
+/// can be inserted in between without moving others.
+const GAP: i64 = 1 << 32;
+
+
+/// The rows of the `templates` table, keyed by template name.
+type Templates = BTreeMap<String, String>;
+/// The location of each row of the `tasks` table, i.e., whether the
+/// task is archived along with its position, keyed by task ID.
+type Locations = BTreeMap<i64, (bool, i64)>;
+
+
+/// Convert a database error into an I/O error.
+fn error(err: rusqlite::Error) -> Error {
+  Error::new(ErrorKind::Other, format!("Database operation failed: {}", err))
+}
+
+/// Create an error reporting an invalid object in the database.
+fn invalid(what: &str) -> Error {
+  let error = format!("Encountered invalid {} in database", what);
+  Error::new(ErrorKind::InvalidInput, error)
+}
+
+
+/// Connect to the database at the given path, making sure that we
+/// understand its schema.
+fn connect(path: &Path, flags: OpenFlags) -> Result<Connection> {
+  let connection = Connection::open_with_flags(path, flags).map_err(|err| {
+    let error = format!("Failed to open database {}: {}", path.display(), err);
+    Error::new(ErrorKind::Other, error)
+  })?;
+  let version = connection
+    .pragma_query_value(None, "user_version", |row| row.get::<_, u32>(0))
+    .map_err(error)?;
+  if version > SCHEMA_VERSION {
+    let error = format!(
+      "Encountered unsupported database version {} in {} (newest supported: {})",
+      version, path.display(), SCHEMA_VERSION
+    );
+    return Err(Error::new(ErrorKind::InvalidInput, error))
+  }
+  Ok(connection)
+}
+
+/// Read all templates from the database.
+fn read_templates(connection: &Connection) -> Result<Templates> {
+  let mut statement = connection
+    .prepare("SELECT name, data FROM templates")
+    .map_err(error)?;
+  let rows = statement
+    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+    .map_err(error)?;
+  rows.collect::<rusqlite::Result<_>>().map_err(error)
+}
+
+/// Read the locations of all tasks from the database.
+fn read_locations(connection: &Connection) -> Result<Locations> {
+  let mut statement = connection
+    .prepare("SELECT id, archived, position FROM tasks")
+    .map_err(error)?;
+  let rows = statement
+    .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))
+    .map_err(error)?;
+  rows.collect::<rusqlite::Result<_>>().map_err(error)
+}
+
+/// Read all tasks from the database, ordered by their location.
+fn read_tasks(connection: &Connection) -> Result<Vec<(bool, String)>> {
+  let mut statement = connection
+    .prepare("SELECT archived, data FROM tasks ORDER BY archived, position")
+    .map_err(error)?;
+  let rows = statement
+    .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+    .map_err(error)?;
+  rows.collect::<rusqlite::Result<_>>().map_err(error)
+}
+
+/// Retrieve the ID of the row storing the task with the given key.
+fn row_id(key: SerTaskId) -> i64 {
+  key.get() as i64
+}
+
+/// Parse the JSON documents stored in the given rows, in order.
+fn parse<'r>(rows: impl Iterator<Item=&'r String>) -> Result<Vec<Value>> {
+  rows
+    .map(|data| from_json::<Value>(data).map_err(Error::from))
+    .collect()
+}
+
+/// Determine the position of the first of `count` tasks to place
+/// between the given neighbors, along with the distance between
+/// subsequent ones.
+fn spread(before: Option<i64>, after: Option<i64>, count: i64) -> Option<(i64, i64)> {
+  match (before, after) {
+    (Some(before), Some(after)) => {
+      let step = after.checked_sub(before)? / (count + 1);
+      if step > 0 {
+        Some((before + step, step))
+      } else {
+        None
+      }
+    },
+    (Some(before), None) => {
+      let _ = before.checked_add(GAP.checked_mul(count)?)?;
+      Some((before + GAP, GAP))
+    },
+    (None, Some(after)) => Some((after.checked_sub(GAP.checked_mul(count)?)?, GAP)),
+    (None, None) => Some((GAP, GAP)),
+  }
+}
+
+/// Determine the positions of a list of tasks, given the position each
+/// one is stored at, if it is to keep it.
+///
+/// Tasks keep their position as long as that preserves their order.
+/// All others are spread evenly between their neighbors. Only if there
+/// is no room left in between all tasks are renumbered.
+fn positions(stored: &[Option<i64>]) -> Vec<i64> {
+  let mut last = None;
+  let mut positions = stored
+    .iter()
+    .map(|position| match (*position, last) {
+      (Some(position), Some(last)) if position <= last => None,
+      (Some(position), _) => {
+        last = Some(position);
+        Some(position)
+      },
+      (None, _) => None,
+    })
+    .collect::<Vec<_>>();
+
+  let mut start = 0;
+  while start < positions.len() {
+    if positions[start].is_some() {
+      start += 1;
+      continue
+    }
+
+    let end = (start..positions.len())
+      .find(|idx| positions[*idx].is_some())
+      .unwrap_or(positions.len());
+    let count = (end - start) as i64;
+    let before = start.checked_sub(1).and_then(|idx| positions[idx]);
+    let after = positions.get(end).copied().flatten();
+    match spread(before, after, count) {
+      Some((first, step)) => {
+        for (idx, position) in positions[start..end].iter_mut().enumerate() {
+          *position = Some(first + step * idx as i64);
+        }
+        start = end;
+      },
+      None => return (1..=stored.len() as i64).map(|idx| idx * GAP).collect(),
+    }
+  }
+
+  positions.into_iter().flatten().collect()
+}
+
+
+/// The rows stored in the database, as far as we need to know them for
+/// deciding what to write.
+#[derive(Debug)]
+struct Cache {
+  /// The rows of the `templates` table.
+  templates: Templates,
+  /// The location of every row of the `tasks` table.
+  tasks: Locations,
+}
+
+
+/// A task file stored in a SQLite database, along with its archive.
+///
+/// Saving only writes the templates and tasks that changed since the
+/// last save, in a single transaction.
+#[derive(Debug)]
+pub struct Database {
+  connection: Connection,
+  /// The rows stored in the database as of the last save.
+  cache: RefCell<Cache>,
+}
+
+impl Database {
+  /// Open the database at the given path, creating it if it does not
+  /// exist yet.
+  pub fn open(path: &Path) -> Result<Self> {
+    if let Some(dir) = path.parent() {
+      create_dir_all(dir)?;
+    }
+
+    let connection = connect(path, OpenFlags::default())?;
+    connection.execute_batch(SCHEMA).map_err(error)?;
+    connection
+      .pragma_update(None, "user_version", SCHEMA_VERSION)
+      .map_err(error)?;
+
+    Self::with_connection(connection)
+  }
+
+  /// Open the existing database at the given path for reading only.
+  ///
+  /// Saving to a database opened this way fails.
+  pub fn open_read_only(path: &Path) -> Result<Self> {
+    let connection = connect(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    Self::with_connection(connection)
+  }
+
+  /// Create a `Database` object for the given connection.
+  fn with_connection(connection: Connection) -> Result<Self> {
+    let cache = Cache {
+      templates: read_templates(&connection)?,
+      tasks: read_locations(&connection)?,
+    };
+
+    Ok(Self {
+      connection: connection,
+      cache: RefCell::new(cache),
+    })
+  }
+}
+
+impl TaskStore for Database {
+  fn load(&self) -> Result<(SerTaskState, SerTasks)> {
+    let templates = read_templates(&self.connection)?;
+    let (archive, tasks) = read_tasks(&self.connection)?
+      .into_iter()
+      .partition::<Vec<_>, _>(|(archived, _)| *archived);
+
+    let task_state = json!({
+      "version": SerTaskState::VERSION,
+      "format": "readable",
+      "templates": parse(templates.values())?,
+      "tasks": parse(tasks.iter().map(|(_, data)| data))?,
+    });
+    let archive = Value::Array(parse(archive.iter().map(|(_, data)| data))?);
+    parse_task_file(task_state, archive)
+  }
+
+  fn save(&self, contents: &Contents<'_>) -> Result<()> {
+    let mut cache = self.cache.borrow_mut();
+
+    let mut templates = to_value(&contents.templates)?;
+    let names = templates_to_readable(&mut templates)?;
+    let mut new_templates = Templates::new();
+    for template in templates.as_array().into_iter().flatten() {
+      let name = template["name"].as_str().ok_or_else(|| invalid("template"))?;
+      let _ = new_templates.insert(name.to_string(), to_json(template)?);
+    }
+
+    let transaction = self.connection.unchecked_transaction().map_err(error)?;
+    for name in cache.templates.keys().filter(|x| !new_templates.contains_key(*x)) {
+      let _ = transaction
+        .execute("DELETE FROM templates WHERE name = ?1", params![name])
+        .map_err(error)?;
+    }
+    for (name, data) in new_templates.iter().filter(|(x, y)| cache.templates.get(*x) != Some(y)) {
+      let _ = transaction
+        .execute(
+          "INSERT OR REPLACE INTO templates (name, data) VALUES (?1, ?2)",
+          params![name, data],
+        )
+        .map_err(error)?;
+    }
+
+    let mut new_tasks = Locations::new();
+    for (archived, tasks) in [(false, contents.tasks), (true, contents.archive)] {
+      let key = |task: &Task| row_id(task.key().expect("task without key"));
+      // A task can keep its row if neither it nor its tags changed and
+      // it is still located in the same list.
+      let unchanged = |task: &Task| -> Option<i64> {
+        match cache.tasks.get(&key(task)) {
+          Some((was_archived, position)) if !contents.retagged &&
+                                            !task.is_changed() &&
+                                            *was_archived == archived => Some(*position),
+          _ => None,
+        }
+      };
+
+      let list = tasks
+        .iter()
+        .filter(|task| task.source() == contents.source)
+        .collect::<Vec<_>>();
+      let stored = list.iter().map(|task| unchanged(task)).collect::<Vec<_>>();
+      let new_positions = positions(&stored);
+
+      for ((task, position), stored) in list.iter().zip(new_positions.iter()).zip(stored.iter()) {
+        let _ = new_tasks.insert(key(task), (archived, *position));
+        if stored.is_some() && *stored != Some(*position) {
+          let _ = transaction
+            .execute(
+              "UPDATE tasks SET position = ?1 WHERE id = ?2",
+              params![position, key(task)],
+            )
+            .map_err(error)?;
+        }
+      }
+
+      let changed = tasks.to_serde_keyed(contents.source, |task| unchanged(task).is_none());
+      for (task, parent) in changed {
+        let id = row_id(task.id.expect("task without key"));
+        let mut task = to_value(task)?;
+        task_to_readable(&mut task, &names)?;
+        if let (Some(parent), Some(task)) = (parent, task.as_object_mut()) {
+          let _ = task.insert("parent".to_string(), to_value(parent)?);
+        }
+        let position = new_tasks[&id].1;
+        let _ = transaction
+          .execute(
+            "INSERT OR REPLACE INTO tasks (id, archived, position, data) VALUES (?1, ?2, ?3, ?4)",
+            params![id, archived, position, to_json(&task)?],
+          )
+          .map_err(error)?;
+      }
+    }
+
+    for id in cache.tasks.keys().filter(|x| !new_tasks.contains_key(*x)) {
+      let _ = transaction
+        .execute("DELETE FROM tasks WHERE id = ?1", params![id])
+        .map_err(error)?;
+    }
+
+    transaction.commit().map_err(error)?;
+    // Only the tasks of this file are stored in the database, so what we
+    // just wrote is all there is.
+    cache.templates = new_templates;
+    cache.tasks = new_tasks;
+    Ok(())
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use crate::check::check;
+  use crate::storage::import;
+  use crate::storage::imported_path;
+  use crate::ser::state::UiState as SerUiState;
+  use crate::ser::tags::Templates as SerTemplates;
+  use crate::ser::tasks::Tasks as SerTasks;
+  use crate::state::State;
+  use crate::tags::COMPLETE_TAG;
+  use crate::test::make_tasks_with_tags;
+  use crate::test::TempDir;
+
+
+  /// Create a `State` object storing its tasks in a database in the
+  /// given directory.
+  fn make_state(dir: &TempDir, count: usize) -> State {
+    let (_, templates, tasks) = make_tasks_with_tags(count);
+    let task_state = SerTaskState {
+      templates: SerTemplates(templates),
+      tasks: SerTasks(tasks),
+      ..Default::default()
+    };
+    State::with_serde(task_state, dir.path().join("tasks.db"),
+                      Default::default(), dir.path().join("archive.json"),
+                      SerUiState::default(), dir.path().join("notnow.json")).unwrap()
+  }
+
+  /// Load the state stored in the given directory.
+  fn load_state(dir: &TempDir) -> State {
+    State::new(dir.path().join("tasks.db"),
+               dir.path().join("archive.json"),
+               dir.path().join("notnow.json")).unwrap()
+  }
+
+  /// Retrieve the summaries and tag names of the given tasks.
+  fn contents(tasks: &[Task]) -> Vec<(String, Vec<String>)> {
+    tasks
+      .iter()
+      .map(|task| (task.summary.clone(), task.tags().map(|tag| tag.name()).collect()))
+      .collect()
+  }
+
+
+  #[test]
+  fn save_and_load_database() {
+    let dir = TempDir::new();
+    let State(task_state, ui_state) = make_state(&dir, 8);
+    {
+      let tasks = task_state.tasks();
+      let mut tasks = tasks.borrow_mut();
+      let ids = tasks.iter().map(Task::id).collect::<Vec<_>>();
+      tasks.toggle_complete(ids[1]);
+      tasks.toggle_complete(ids[5]);
+    }
+    assert!(task_state.archive_completed());
+    ui_state.save().unwrap();
+    task_state.save().unwrap();
+
+    // The archive lives in the database as well.
+    assert!(!dir.path().join("archive.json").exists());
+
+    let State(new_state, _) = load_state(&dir);
+    let tasks = task_state.tasks().borrow().iter().cloned().collect::<Vec<_>>();
+    let new_tasks = new_state.tasks().borrow().iter().cloned().collect::<Vec<_>>();
+    assert_eq!(contents(&new_tasks), contents(&tasks));
+
+    let archive = task_state.archive().borrow().iter().cloned().collect::<Vec<_>>();
+    let new_archive = new_state.archive().borrow().iter().cloned().collect::<Vec<_>>();
+    assert_eq!(contents(&new_archive), contents(&archive));
+    assert_eq!(new_archive.len(), 2);
+  }
+
+  #[test]
+  fn save_changes_incrementally() {
+    let dir = TempDir::new();
+    let State(task_state, ui_state) = make_state(&dir, 16);
+    ui_state.save().unwrap();
+    task_state.save().unwrap();
+
+    // Count all rows written from here on.
+    let connection = Connection::open(dir.path().join("tasks.db")).unwrap();
+    connection
+      .execute_batch("
+        CREATE TABLE writes (count INTEGER NOT NULL);
+        INSERT INTO writes VALUES (0);
+        CREATE TRIGGER count_inserts AFTER INSERT ON tasks
+        BEGIN
+          UPDATE writes SET count = count + 1;
+        END;
+        CREATE TRIGGER count_updates AFTER UPDATE ON tasks
+        BEGIN
+          UPDATE writes SET count = count + 1;
+        END;
+      ")
+      .unwrap();
+    let writes = || -> i64 {
+      connection.query_row("SELECT count FROM writes", [], |row| row.get(0)).unwrap()
+    };
+    let rows = || -> i64 {
+      connection.query_row("SELECT COUNT(*) FROM tasks", [], |row| row.get(0)).unwrap()
+    };
+
+    task_state.save().unwrap();
+    assert_eq!(writes(), 0);
+
+    {
+      let tasks = task_state.tasks();
+      let mut tasks = tasks.borrow_mut();
+      let ids = tasks.iter().map(Task::id).collect::<Vec<_>>();
+      tasks.toggle_complete(ids[3]);
+      tasks.remove(ids[15]);
+    }
+    task_state.save().unwrap();
+    assert_eq!(writes(), 1);
+    assert_eq!(rows(), 15);
+
+    // Adding a task at the end leaves all others untouched.
+    let _ = task_state
+      .tasks()
+      .borrow_mut()
+      .add("new".to_string(), Vec::new(), None, 0);
+    task_state.save().unwrap();
+    assert_eq!(writes(), 2);
+    assert_eq!(rows(), 16);
+
+    // So does inserting one in the middle of the list.
+    {
+      let tasks = task_state.tasks();
+      let mut tasks = tasks.borrow_mut();
+      let ids = tasks.iter().map(Task::id).collect::<Vec<_>>();
+      let id = tasks.add("inserted".to_string(), Vec::new(), None, 0);
+      tasks.move_before(id, ids[7]);
+    }
+    task_state.save().unwrap();
+    assert_eq!(writes(), 3);
+    assert_eq!(rows(), 17);
+
+    // Renaming a tag changes all tasks carrying it.
+    let templates = task_state.templates();
+    let tag = task_state
+      .tasks()
+      .borrow()
+      .iter()
+      .flat_map(|x| x.tags().cloned().collect::<Vec<_>>())
+      .find(|x| x.name() != COMPLETE_TAG)
+      .unwrap();
+    templates.rename(tag.id(), "renamed").unwrap();
+    task_state.save().unwrap();
+    assert!(writes() > 3);
+
+    let State(new_state, _) = load_state(&dir);
+    let tasks = task_state.tasks().borrow().iter().cloned().collect::<Vec<_>>();
+    let new_tasks = new_state.tasks().borrow().iter().cloned().collect::<Vec<_>>();
+    assert_eq!(contents(&new_tasks), contents(&tasks));
+  }
+
+  #[test]
+  fn assign_positions() {
+    assert_eq!(positions(&[]), Vec::<i64>::new());
+    assert_eq!(positions(&[None, None]), vec![GAP, 2 * GAP]);
+    assert_eq!(positions(&[Some(10), None, Some(20)]), vec![10, 15, 20]);
+    assert_eq!(positions(&[None, Some(5 * GAP)]), vec![4 * GAP, 5 * GAP]);
+    assert_eq!(positions(&[Some(GAP), None]), vec![GAP, 2 * GAP]);
+    // Tasks stored out of order get moved.
+    assert_eq!(positions(&[Some(30), Some(10), Some(40)]), vec![30, 35, 40]);
+    // Without room in between all tasks get renumbered.
+    assert_eq!(positions(&[Some(1), None, Some(2)]), vec![GAP, 2 * GAP, 3 * GAP]);
+  }
+
+  #[test]
+  fn reject_history() {
+    let dir = TempDir::new();
+    let (_, templates, tasks) = make_tasks_with_tags(2);
+    let task_state = SerTaskState {
+      templates: SerTemplates(templates),
+      tasks: SerTasks(tasks),
+      ..Default::default()
+    };
+    let ui_state = SerUiState {
+      history: true,
+      ..Default::default()
+    };
+    let err = State::with_serde(task_state, dir.path().join("tasks.db"),
+                                Default::default(), dir.path().join("archive.json"),
+                                ui_state, dir.path().join("notnow.json")).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn check_missing_database() {
+    let dir = TempDir::new();
+    let path = dir.path().join("tasks.db");
+    let files = vec![(path.clone(), dir.path().join("archive.json"))];
+    let _ = check(files, dir.path().join("notnow.json")).unwrap_err();
+    assert!(!path.exists());
+
+    let State(task_state, ui_state) = make_state(&dir, 4);
+    ui_state.save().unwrap();
+    task_state.save().unwrap();
+
+    let files = vec![(path, dir.path().join("archive.json"))];
+    let problems = check(files, dir.path().join("notnow.json")).unwrap();
+    assert_eq!(problems, Vec::<String>::new());
+  }
+
+  #[test]
+  fn import_json_files() {
+    let dir = TempDir::new();
+    let (_, templates, tasks) = make_tasks_with_tags(6);
+    let task_state = SerTaskState {
+      templates: SerTemplates(templates),
+      tasks: SerTasks(tasks),
+      ..Default::default()
+    };
+    let State(task_state, ui_state) =
+      State::with_serde(task_state, dir.path().join("tasks.json"),
+                        Default::default(), dir.path().join("archive.json"),
+                        SerUiState::default(), dir.path().join("notnow.json")).unwrap();
+    {
+      let tasks = task_state.tasks();
+      let mut tasks = tasks.borrow_mut();
+      let ids = tasks.iter().map(Task::id).collect::<Vec<_>>();
+      tasks.toggle_complete(ids[2]);
+    }
+    assert!(task_state.archive_completed());
+    ui_state.save().unwrap();
+    task_state.save().unwrap();
+
+    let path = dir.path().join("tasks.db");
+    let (tasks, archive) = (dir.path().join("tasks.json"), dir.path().join("archive.json"));
+    assert!(import(&path, &tasks, &archive).unwrap());
+    // The imported files are renamed, so that they are not used anymore.
+    assert!(!tasks.exists());
+    assert!(!archive.exists());
+    assert!(imported_path(&tasks).exists());
+    assert!(imported_path(&archive).exists());
+    // An existing database is never overwritten.
+    assert!(!import(&path, &imported_path(&tasks), &imported_path(&archive)).unwrap());
+
+    let State(new_state, _) = load_state(&dir);
+    let tasks = task_state.tasks().borrow().iter().cloned().collect::<Vec<_>>();
+    let new_tasks = new_state.tasks().borrow().iter().cloned().collect::<Vec<_>>();
+    assert_eq!(contents(&new_tasks), contents(&tasks));
+
+    let archive = task_state.archive().borrow().iter().cloned().collect::<Vec<_>>();
+    let new_archive = new_state.archive().borrow().iter().cloned().collect::<Vec<_>>();
+    assert_eq!(contents(&new_archive), contents(&archive));
+    assert!(!new_archive.is_empty());
+  }
+
+  #[test]
+  fn reject_newer_schema() {
+    let dir = TempDir::new();
+    let path = dir.path().join("tasks.db");
+    let connection = Connection::open(&path).unwrap();
+    connection
+      .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+      .unwrap();
+
+    let err = Database::open(&path).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+}
@@ -17,34 +17,89 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Tags and the templates they are instantiated from.
+
+use std::cell::Cell;
+use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
 use std::hash::Hash;
 use std::hash::Hasher;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
 use std::rc::Rc;
+use std::vec;
 
 use crate::id::Id as IdT;
+use crate::ser::tags::Color as SerColor;
 use crate::ser::tags::Id as SerTagId;
+use crate::ser::tags::Style as SerStyle;
 use crate::ser::tags::Tag as SerTag;
 use crate::ser::tags::Template as SerTemplate;
 use crate::ser::tags::Templates as SerTemplates;
 use crate::ser::ToSerde;
 
+/// The type tag distinguishing template IDs from other ones.
 #[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct T(());
 
+/// The ID of a tag template.
 pub type Id = IdT<T>;
 
 /// The name of a tag describing the completion state of a task.
 pub const COMPLETE_TAG: &str = "complete";
+/// The separator between the components of hierarchical tag names,
+/// e.g., `project/notnow/ui`.
+pub const TAG_SEPARATOR: char = '/';
+
+
+/// The display attributes of a tag template.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Style {
+  /// The color to render tags with, as RGB components, if any.
+  pub color: Option<(u8, u8, u8)>,
+  /// Whether to render tags in bold.
+  pub bold: bool,
+  /// Whether to render entire tasks carrying the tag in its color.
+  pub tint: bool,
+}
+
+impl From<SerStyle> for Style {
+  fn from(style: SerStyle) -> Self {
+    Style {
+      color: style.color.map(|SerColor(r, g, b)| (r, g, b)),
+      bold: style.bold,
+      tint: style.tint,
+    }
+  }
+}
+
+impl ToSerde<SerStyle> for Style {
+  /// Convert the style into a serializable one.
+  fn to_serde(&self) -> SerStyle {
+    SerStyle {
+      color: self.color.map(|(r, g, b)| SerColor(r, g, b)),
+      bold: self.bold,
+      tint: self.tint,
+    }
+  }
+}
 
 
 /// A struct defining a particular tag.
 #[derive(Clone, Debug, Eq)]
 pub struct Template {
   id: Id,
-  name: String,
+  /// The template's name. Templates can be renamed while being shared
+  /// by all the tags instantiated from them.
+  name: RefCell<String>,
+  /// The attributes to display the template's tags with.
+  style: Style,
 }
 
 impl Template {
@@ -52,7 +107,8 @@ impl Template {
   fn new(name: impl Into<String>) -> Self {
     Template {
       id: Id::new(),
-      name: name.into(),
+      name: RefCell::new(name.into()),
+      style: Default::default(),
     }
   }
 
@@ -62,9 +118,24 @@ impl Template {
   }
 
   /// Retrieve the tag template's name.
-  #[cfg(test)]
-  pub fn name(&self) -> &str {
-    &self.name
+  pub fn name(&self) -> String {
+    self.name.borrow().clone()
+  }
+
+  /// Retrieve the attributes to display the template's tags with.
+  pub fn style(&self) -> Style {
+    self.style
+  }
+
+  /// Check whether the template is the given one or nested below it,
+  /// i.e., whether its name continues the other's name with another
+  /// component.
+  pub fn is_within(&self, other: &Template) -> bool {
+    let name = self.name.borrow();
+    match name.strip_prefix(other.name.borrow().as_str()) {
+      Some(rest) => rest.is_empty() || rest.starts_with(TAG_SEPARATOR),
+      None => false,
+    }
   }
 }
 
@@ -101,7 +172,8 @@ impl From<SerTemplate> for Template {
   fn from(template: SerTemplate) -> Self {
     Template {
       id: Id::new(),
-      name: template.name,
+      name: RefCell::new(template.name),
+      style: template.style.into(),
     }
   }
 }
@@ -111,16 +183,67 @@ impl ToSerde<SerTemplate> for Template {
   fn to_serde(&self) -> SerTemplate {
     SerTemplate {
       id: self.id.to_serde(),
-      name: self.name.clone(),
+      name: self.name(),
+      style: self.style.to_serde(),
     }
   }
 }
 
 
+/// Split a tag value into its leading number, if any, and the rest.
+fn split_number(value: &str) -> Option<(f64, &str)> {
+  let end = value
+    .char_indices()
+    .find(|(i, c)| !(c.is_ascii_digit() || *c == '.' || (*i == 0 && *c == '-')))
+    .map_or(value.len(), |(i, _)| i);
+
+  value[..end].parse().ok().map(|number| (number, &value[end..]))
+}
+
+/// Retrieve the number of minutes the given duration unit stands for,
+/// if it is one we know.
+fn minutes(unit: &str) -> Option<f64> {
+  match unit {
+    "m" | "min" => Some(1.0),
+    "h" => Some(60.0),
+    "d" => Some(24.0 * 60.0),
+    "w" => Some(7.0 * 24.0 * 60.0),
+    _ => None,
+  }
+}
+
+/// Compare two tag values.
+///
+/// Values starting with a number, e.g., `3h` or `42`, are ordered by
+/// that number, provided they share the same unit, i.e., the remainder
+/// following the number. Durations given in minutes (`m`), hours (`h`),
+/// days (`d`), or weeks (`w`) are converted to a common scale first.
+/// Values not starting with a number are compared textually. All other
+/// pairs of values, e.g., `2d` and `2kg`, can not be compared, in which
+/// case `None` is returned.
+pub fn compare_values(value: &str, other: &str) -> Option<Ordering> {
+  match (split_number(value), split_number(other)) {
+    (Some((number, unit)), Some((other_number, other_unit))) => {
+      if unit == other_unit {
+        number.partial_cmp(&other_number)
+      } else {
+        let scale = minutes(unit)?;
+        let other_scale = minutes(other_unit)?;
+        (number * scale).partial_cmp(&(other_number * other_scale))
+      }
+    },
+    (None, None) => Some(value.cmp(other)),
+    _ => None,
+  }
+}
+
+
 /// An actual tag instance, which may be associated with a task.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Tag {
   template: Rc<Template>,
+  /// The value the tag carries, if any.
+  value: Option<String>,
 }
 
 impl Tag {
@@ -128,6 +251,15 @@ impl Tag {
   fn new(template: Rc<Template>) -> Tag {
     Tag {
       template: template,
+      value: None,
+    }
+  }
+
+  /// Create a copy of the tag carrying the given value.
+  pub fn with_value(self, value: Option<String>) -> Tag {
+    Tag {
+      value: value,
+      ..self
     }
   }
 
@@ -137,10 +269,34 @@ impl Tag {
   }
 
   /// Retrieve the tag's name.
-  #[cfg(test)]
-  pub fn name(&self) -> &str {
+  pub fn name(&self) -> String {
     self.template.name()
   }
+
+  /// Retrieve the attributes to display the tag with.
+  pub fn style(&self) -> Style {
+    self.template.style()
+  }
+
+  /// Retrieve the tag's value, if any.
+  pub fn value(&self) -> Option<&str> {
+    self.value.as_deref()
+  }
+
+  /// Check whether the tag is the given one or nested below it.
+  pub fn is_within(&self, other: &Tag) -> bool {
+    self.template.is_within(&other.template)
+  }
+}
+
+impl Display for Tag {
+  /// Format the tag as its name, followed by its value, if any.
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    match &self.value {
+      Some(value) => write!(f, "{}:{}", self.name(), value),
+      None => write!(f, "{}", self.name()),
+    }
+  }
 }
 
 impl ToSerde<SerTag> for Tag {
@@ -148,6 +304,7 @@ impl ToSerde<SerTag> for Tag {
   fn to_serde(&self) -> SerTag {
     SerTag {
       id: self.template.id.to_serde(),
+      value: self.value.clone(),
     }
   }
 }
@@ -158,17 +315,34 @@ impl ToSerde<SerTag> for Tag {
 pub type TagMap = BTreeMap<SerTagId, Id>;
 
 
+/// An entry in the hierarchy of tag templates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TreeEntry {
+  /// The nesting depth of the entry.
+  pub depth: usize,
+  /// The last component of the entry's name.
+  pub name: String,
+  /// The ID of the template the entry represents. Entries merely
+  /// grouping nested templates without being a template themselves do
+  /// not have one.
+  pub id: Option<Id>,
+}
+
+
 /// Ensure the given template set contains a tag with the given name.
+// Templates are ordered by their ID, which never changes, so their
+// mutable name does not affect the set.
+#[allow(clippy::mutable_key_type)]
 fn ensure_contains<S>(templates: &mut BTreeSet<Rc<Template>>, name: S) -> Rc<Template>
 where
   S: Into<String> + AsRef<str>,
 {
-  let found = templates.iter().any(|x| x.name == name.as_ref());
+  let found = templates.iter().any(|x| *x.name.borrow() == name.as_ref());
 
   if found {
     templates
       .iter()
-      .find(|x| x.name == name.as_ref())
+      .find(|x| *x.name.borrow() == name.as_ref())
       .unwrap()
       .clone()
   } else {
@@ -180,17 +354,28 @@ where
 }
 
 /// A management structure for tag templates.
+///
+/// The set of templates is shared by all tasks and can be changed in
+/// place, e.g., when a template is removed.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Templates {
   /// A set of all the tag templates.
-  templates: BTreeSet<Rc<Template>>,
+  templates: RefCell<BTreeSet<Rc<Template>>>,
   /// Reference to the tag template representing task completion.
   complete: Rc<Template>,
+  /// A counter increased on every change to the templates.
+  generation: Cell<u64>,
+}
+
+impl Default for Templates {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Templates {
-  /// Create an empty `Templates` object.
-  #[cfg(test)]
+  /// Create a `Templates` object containing only the template
+  /// representing task completion.
   pub fn new() -> Self {
     Self::with_serde(Default::default()).0
   }
@@ -213,15 +398,34 @@ impl Templates {
 
     let complete = ensure_contains(&mut templates, COMPLETE_TAG);
     let templates = Templates {
-      templates: templates,
+      templates: RefCell::new(templates),
       complete: complete,
+      generation: Cell::new(0),
     };
     (templates, map)
   }
 
+  /// Merge the templates of another `SerTemplates` object into this
+  /// one.
+  ///
+  /// Templates are matched by name, i.e., a template with a name that
+  /// is already known maps to the existing template. The returned map
+  /// converts the persisted IDs of `templates` into the in-memory ones.
+  #[allow(clippy::mutable_key_type)]
+  pub fn merge(&mut self, templates: SerTemplates) -> TagMap {
+    *self.generation.get_mut() += 1;
+    let set = self.templates.get_mut();
+    templates
+      .0
+      .into_iter()
+      .map(|x| (x.id, ensure_contains(set, x.name).id))
+      .collect()
+  }
+
   /// Instantiate a new tag from the referenced template.
   pub fn instantiate(&self, id: Id) -> Tag {
-    let result = self.templates.iter().find(|x| x.id == id);
+    let templates = self.templates.borrow();
+    let result = templates.iter().find(|x| x.id == id);
 
     match result {
       Some(template) => Tag::new(template.clone()),
@@ -229,22 +433,154 @@ impl Templates {
     }
   }
 
+  /// Retrieve a value that changes whenever a template changes.
+  pub fn generation(&self) -> u64 {
+    self.generation.get()
+  }
+
   /// Retrieve a reference to the 'complete' tag template.
   pub fn complete_tag(&self) -> &Template {
     &self.complete
   }
 
+  /// Find the template with the given name, if any.
+  pub fn find(&self, name: &str) -> Option<Id> {
+    self
+      .templates
+      .borrow()
+      .iter()
+      .find(|x| *x.name.borrow() == name)
+      .map(|x| x.id)
+  }
+
+  /// Rename the template with the given ID.
+  ///
+  /// The template representing task completion can not be renamed and
+  /// names have to stay unique.
+  pub fn rename(&self, id: Id, name: &str) -> Result<()> {
+    if id == self.complete.id {
+      let error = format!("Tag '{}' can not be renamed", COMPLETE_TAG);
+      return Err(Error::new(ErrorKind::InvalidInput, error))
+    }
+
+    let name = name.trim();
+    if name.is_empty() || name.split(TAG_SEPARATOR).any(str::is_empty) {
+      let error = format!("Invalid tag name '{}'", name);
+      return Err(Error::new(ErrorKind::InvalidInput, error))
+    }
+
+    match self.find(name) {
+      Some(other) if other == id => Ok(()),
+      Some(_) => {
+        let error = format!("Tag '{}' exists already", name);
+        Err(Error::new(ErrorKind::InvalidInput, error))
+      },
+      None => {
+        let templates = self.templates.borrow();
+        let template = templates.iter().find(|x| x.id == id).ok_or_else(|| {
+          let error = format!("Encountered invalid tag Id {}", id);
+          Error::new(ErrorKind::InvalidInput, error)
+        })?;
+        *template.name.borrow_mut() = name.to_string();
+        self.generation.set(self.generation.get() + 1);
+        Ok(())
+      },
+    }
+  }
+
+  /// Remove the template with the given ID.
+  ///
+  /// It is the caller's responsibility to make sure that no tags
+  /// instantiated from the template remain in use.
+  pub fn remove(&self, id: Id) -> Result<()> {
+    if id == self.complete.id {
+      let error = format!("Tag '{}' can not be removed", COMPLETE_TAG);
+      return Err(Error::new(ErrorKind::InvalidInput, error))
+    }
+
+    let mut templates = self.templates.borrow_mut();
+    let len = templates.len();
+    templates.retain(|x| x.id != id);
+    self.generation.set(self.generation.get() + 1);
+
+    if templates.len() == len {
+      let error = format!("Encountered invalid tag Id {}", id);
+      Err(Error::new(ErrorKind::InvalidInput, error))
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Convert the tag templates with the given IDs into a serializable
+  /// form.
+  ///
+  /// The template representing task completion is always included.
+  pub fn to_serde_with(&self, ids: &BTreeSet<Id>) -> SerTemplates {
+    let templates = self
+      .templates
+      .borrow()
+      .iter()
+      .filter(|x| x.id == self.complete.id || ids.contains(&x.id))
+      .map(|x| x.to_serde())
+      .collect();
+
+    SerTemplates(templates)
+  }
+
   /// Retrieve an iterator over all the tag templates.
-  #[cfg(test)]
-  pub fn iter(&self) -> impl Iterator<Item=&Template> {
-    self.templates.iter().map(|x| x.as_ref())
+  pub fn iter(&self) -> vec::IntoIter<Rc<Template>> {
+    self.templates.borrow().iter().cloned().collect::<Vec<_>>().into_iter()
+  }
+
+  /// Arrange all tag templates, except the one representing task
+  /// completion, in a tree based on their hierarchical names.
+  ///
+  /// Entries are reported depth first, with siblings sorted by name.
+  pub fn tree(&self) -> Vec<TreeEntry> {
+    let mut templates = self
+      .iter()
+      .filter(|x| x.id != self.complete.id)
+      .map(|x| {
+        let path = x.name().split(TAG_SEPARATOR).map(str::to_string).collect::<Vec<_>>();
+        (path, x.id)
+      })
+      .collect::<Vec<_>>();
+    templates.sort();
+
+    let mut entries = Vec::new();
+    let mut prev = Vec::<String>::new();
+    for (path, id) in templates {
+      let common = path
+        .iter()
+        .zip(prev.iter())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+      // Ancestors not yet reported and not being templates themselves
+      // are included merely for grouping purposes.
+      for (depth, name) in path.iter().enumerate().take(path.len() - 1).skip(common) {
+        entries.push(TreeEntry {
+          depth: depth,
+          name: name.clone(),
+          id: None,
+        })
+      }
+
+      entries.push(TreeEntry {
+        depth: path.len() - 1,
+        name: path[path.len() - 1].clone(),
+        id: Some(id),
+      });
+      prev = path;
+    }
+    entries
   }
 }
 
 impl ToSerde<SerTemplates> for Templates {
   /// Convert the tag templates object into a serializable form.
   fn to_serde(&self) -> SerTemplates {
-    SerTemplates(self.templates.iter().map(|x| x.to_serde()).collect())
+    SerTemplates(self.templates.borrow().iter().map(|x| x.to_serde()).collect())
   }
 }
 
@@ -260,10 +596,143 @@ mod tests {
   #[test]
   fn ensure_complete_tag_exists() {
     let templates = Templates::new();
-    let template = templates.iter().find(|x| x.name == COMPLETE_TAG).unwrap();
+    let template = templates.iter().find(|x| x.name() == COMPLETE_TAG).unwrap();
     assert_eq!(template.id(), templates.complete_tag().id());
   }
 
+  #[test]
+  fn tag_values() {
+    let (templates, _) = Templates::with_serde(SerTemplates(vec![SerTemplate {
+      id: SerTagId::new(1),
+      name: "estimate".to_string(),
+      style: Default::default(),
+    }]));
+    let id = templates.find("estimate").unwrap();
+    let tag = templates.instantiate(id);
+    assert_eq!(tag.value(), None);
+    assert_eq!(tag.to_string(), "estimate");
+
+    let tag = tag.with_value(Some("3h".to_string()));
+    assert_eq!(tag.value(), Some("3h"));
+    assert_eq!(tag.to_string(), "estimate:3h");
+    assert_eq!(tag.to_serde().value, Some("3h".to_string()));
+  }
+
+  #[test]
+  fn styled_templates() {
+    let style = SerStyle {
+      color: Some(SerColor(0xd7, 0x00, 0x00)),
+      bold: false,
+      tint: true,
+    };
+    let template = SerTemplate {
+      id: SerTagId::new(1),
+      name: "urgent".to_string(),
+      style: style,
+    };
+    let (templates, _) = Templates::with_serde(SerTemplates(vec![template]));
+    let tag = templates.instantiate(templates.find("urgent").unwrap());
+
+    let expected = Style {
+      color: Some((0xd7, 0x00, 0x00)),
+      bold: false,
+      tint: true,
+    };
+    assert_eq!(tag.style(), expected);
+
+    let serialized = templates.to_serde();
+    let template = serialized.0.iter().find(|x| x.name == "urgent").unwrap();
+    assert_eq!(template.style, style);
+  }
+
+  #[test]
+  fn compare_tag_values() {
+    assert_eq!(compare_values("3h", "3h"), Some(Ordering::Equal));
+    assert_eq!(compare_values("3h", "10h"), Some(Ordering::Less));
+    assert_eq!(compare_values("42", "7"), Some(Ordering::Greater));
+    assert_eq!(compare_values("-1", "0.5"), Some(Ordering::Less));
+    assert_eq!(compare_values("2d", "2h"), Some(Ordering::Greater));
+    assert_eq!(compare_values("90m", "1.5h"), Some(Ordering::Equal));
+    assert_eq!(compare_values("1w", "8d"), Some(Ordering::Less));
+    assert_eq!(compare_values("2d", "2kg"), None);
+    assert_eq!(compare_values("2", "2h"), None);
+    assert_eq!(compare_values("alice", "bob"), Some(Ordering::Less));
+    assert_eq!(compare_values("10", "alice"), None);
+  }
+
+  #[test]
+  fn nested_templates() {
+    let ui = Template::new("project/notnow/ui");
+    let notnow = Template::new("project/notnow");
+    let other = Template::new("project/notnowx");
+
+    assert!(ui.is_within(&notnow));
+    assert!(notnow.is_within(&notnow));
+    assert!(!notnow.is_within(&ui));
+    assert!(!other.is_within(&notnow));
+  }
+
+  #[test]
+  fn template_tree() {
+    let names = ["work", "project/notnow/ui", "project/notnow", "project/gui/x"];
+    let templates = names
+      .iter()
+      .map(|x| SerTemplate {
+        id: SerTagId::new(x.len()),
+        name: x.to_string(),
+        style: Default::default(),
+      })
+      .collect();
+    let (templates, _) = Templates::with_serde(SerTemplates(templates));
+
+    let tree = templates
+      .tree()
+      .into_iter()
+      .map(|x| (x.depth, x.name, x.id.is_some()))
+      .collect::<Vec<_>>();
+    let expected = vec![
+      (0, "project".to_string(), false),
+      (1, "gui".to_string(), false),
+      (2, "x".to_string(), true),
+      (1, "notnow".to_string(), true),
+      (2, "ui".to_string(), true),
+      (0, "work".to_string(), true),
+    ];
+    assert_eq!(tree, expected);
+  }
+
+  #[test]
+  fn rename_and_remove_templates() {
+    let names = ["work", "home"];
+    let templates = names
+      .iter()
+      .enumerate()
+      .map(|(i, x)| SerTemplate {
+        id: SerTagId::new(i),
+        name: x.to_string(),
+        style: Default::default(),
+      })
+      .collect();
+    let (templates, _) = Templates::with_serde(SerTemplates(templates));
+    let work = templates.find("work").unwrap();
+    let tag = templates.instantiate(work);
+
+    templates.rename(work, "job").unwrap();
+    assert_eq!(tag.name(), "job");
+    assert_eq!(templates.find("job"), Some(work));
+    assert_eq!(templates.find("work"), None);
+
+    assert!(templates.rename(work, "home").is_err());
+    assert!(templates.rename(work, "job/").is_err());
+    assert!(templates.rename(work, " ").is_err());
+    assert!(templates.rename(templates.complete_tag().id(), "done").is_err());
+
+    templates.remove(work).unwrap();
+    assert_eq!(templates.find("job"), None);
+    assert!(templates.remove(work).is_err());
+    assert!(templates.remove(templates.complete_tag().id()).is_err());
+  }
+
   #[test]
   fn ensure_complete_tag_is_not_duplicated() {
     let templates = Templates::new();
@@ -273,7 +742,7 @@ mod tests {
 
     let count = new_templates
       .iter()
-      .fold(0, |c, x| if x.name == COMPLETE_TAG { c + 1 } else { c });
+      .fold(0, |c, x| if x.name() == COMPLETE_TAG { c + 1 } else { c });
     assert_eq!(count, 1);
   }
 }
@@ -17,15 +17,27 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+//! Tasks and the collection managing them.
+
 use std::cmp::PartialEq;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
 use std::io::Error;
 use std::io::ErrorKind;
 use std::io::Result;
 use std::rc::Rc;
 use std::slice;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
+use crate::date::Date;
 use crate::id::Id as IdT;
+use crate::recurrence::Recurrence;
+use crate::ser::tasks::Id as SerTaskId;
+use crate::ser::tasks::Priority as SerPriority;
 use crate::ser::tasks::Task as SerTask;
 use crate::ser::tasks::Tasks as SerTasks;
 use crate::ser::ToSerde;
@@ -34,19 +46,126 @@ use crate::tags::Tag;
 use crate::tags::TagMap;
 use crate::tags::Templates;
 
+/// The type tag distinguishing task IDs from other ones.
 #[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub struct T(());
 
+/// The ID of a task, unique for the lifetime of the program.
 pub type Id = IdT<T>;
 
+/// The index of the task file a task belongs to, in the order in which
+/// task files were loaded.
+pub type Source = usize;
+
+
+/// Retrieve the current time, in seconds since the Unix epoch.
+fn now() -> Option<u64> {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|x| x.as_secs())
+    .ok()
+}
+
+
+/// The priority of a task.
+///
+/// Priorities are ordered such that `A`, the highest priority, compares
+/// less than all others.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[allow(missing_docs)]
+pub enum Priority {
+  A,
+  B,
+  C,
+  D,
+  E,
+}
+
+impl Priority {
+  /// Retrieve the priority one level above this one, if any.
+  pub fn higher(self) -> Option<Priority> {
+    match self {
+      Priority::A => None,
+      Priority::B => Some(Priority::A),
+      Priority::C => Some(Priority::B),
+      Priority::D => Some(Priority::C),
+      Priority::E => Some(Priority::D),
+    }
+  }
+
+  /// Retrieve the priority one level below this one, if any.
+  pub fn lower(self) -> Option<Priority> {
+    match self {
+      Priority::A => Some(Priority::B),
+      Priority::B => Some(Priority::C),
+      Priority::C => Some(Priority::D),
+      Priority::D => Some(Priority::E),
+      Priority::E => None,
+    }
+  }
+}
+
+impl Display for Priority {
+  fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+    let c = match self {
+      Priority::A => 'A',
+      Priority::B => 'B',
+      Priority::C => 'C',
+      Priority::D => 'D',
+      Priority::E => 'E',
+    };
+    write!(f, "{}", c)
+  }
+}
+
+impl From<SerPriority> for Priority {
+  fn from(priority: SerPriority) -> Self {
+    match priority {
+      SerPriority::A => Priority::A,
+      SerPriority::B => Priority::B,
+      SerPriority::C => Priority::C,
+      SerPriority::D => Priority::D,
+      SerPriority::E => Priority::E,
+    }
+  }
+}
+
+impl ToSerde<SerPriority> for Priority {
+  /// Convert this priority into a serializable one.
+  fn to_serde(&self) -> SerPriority {
+    match self {
+      Priority::A => SerPriority::A,
+      Priority::B => SerPriority::B,
+      Priority::C => SerPriority::C,
+      Priority::D => SerPriority::D,
+      Priority::E => SerPriority::E,
+    }
+  }
+}
+
 
 /// A struct representing a task item.
 #[derive(Clone, Debug)]
 pub struct Task {
   id: Id,
+  /// The ID the task is persisted with in the readable format, if it
+  /// got one already.
+  key: Option<SerTaskId>,
+  /// The summary describing the task.
   pub summary: String,
   tags: BTreeMap<TagId, Tag>,
+  priority: Option<Priority>,
+  created: Option<u64>,
+  completed: Option<u64>,
+  due: Option<Date>,
+  recurrence: Option<Recurrence>,
+  parent: Option<Id>,
+  collapsed: bool,
+  blocked_by: Vec<Id>,
+  source: Source,
   templates: Rc<Templates>,
+  /// Whether the task changed since it was last saved.
+  changed: bool,
 }
 
 impl Task {
@@ -55,9 +174,20 @@ impl Task {
   pub fn new(summary: impl Into<String>) -> Self {
     Task {
       id: Id::new(),
+      key: None,
       summary: summary.into(),
       tags: Default::default(),
+      priority: None,
+      created: None,
+      completed: None,
+      due: None,
+      recurrence: None,
+      parent: None,
+      collapsed: false,
+      blocked_by: Vec::new(),
+      source: 0,
       templates: Rc::new(Templates::new()),
+      changed: true,
     }
   }
 
@@ -65,13 +195,27 @@ impl Task {
   fn with_summary_and_tags(summary: String, tags: Vec<Tag>, templates: Rc<Templates>) -> Self {
     Task {
       id: Id::new(),
+      key: None,
       summary: summary,
       tags: tags.into_iter().map(|x| (x.id(), x)).collect(),
+      priority: None,
+      created: now(),
+      completed: None,
+      due: None,
+      recurrence: None,
+      parent: None,
+      collapsed: false,
+      blocked_by: Vec::new(),
+      source: 0,
       templates: templates,
+      changed: true,
     }
   }
 
   /// Create a new task from a serializable one.
+  ///
+  /// Note that the task's parent and blockers are not resolved here, as
+  /// that requires knowledge of all other tasks.
   fn with_serde(task: SerTask, templates: Rc<Templates>, map: &TagMap) -> Result<Task> {
     let mut tags = BTreeMap::new();
     for tag in task.tags.into_iter() {
@@ -79,14 +223,34 @@ impl Task {
         let error = format!("Encountered invalid tag Id {}", tag.id);
         Error::new(ErrorKind::InvalidInput, error)
       })?;
-      let _ = tags.insert(*id, templates.instantiate(*id));
+      let _ = tags.insert(*id, templates.instantiate(*id).with_value(tag.value));
     }
 
+    let due = match task.due {
+      Some(due) => Some(due.parse()?),
+      None => None,
+    };
+    let recurrence = match task.recurrence {
+      Some(recurrence) => Some(recurrence.parse()?),
+      None => None,
+    };
+
     Ok(Task {
       id: Id::new(),
+      key: task.id,
       summary: task.summary,
       tags: tags,
+      priority: task.priority.map(Priority::from),
+      created: task.created,
+      completed: task.completed,
+      due: due,
+      recurrence: recurrence,
+      parent: None,
+      collapsed: task.collapsed,
+      blocked_by: Vec::new(),
+      source: 0,
       templates: templates,
+      changed: false,
     })
   }
 
@@ -95,11 +259,65 @@ impl Task {
     self.id
   }
 
+  /// Retrieve the key the task is persisted with in the readable
+  /// format, if it got one already.
+  pub fn key(&self) -> Option<SerTaskId> {
+    self.key
+  }
+
+  /// Check whether the task changed since it was last saved.
+  pub fn is_changed(&self) -> bool {
+    self.changed
+  }
+
   /// Retrieve an iterator over this task's tags.
   pub fn tags(&self) -> impl Iterator<Item=&Tag> + Clone {
     self.tags.values()
   }
 
+  /// Check whether the task has a tag instantiated from the template
+  /// with the given ID.
+  pub fn has_tag(&self, id: TagId) -> bool {
+    self.tags.contains_key(&id)
+  }
+
+  /// Add a tag instantiated from the template with the given ID to the
+  /// task, or remove it if the task has it already.
+  pub fn toggle_tag(&mut self, id: TagId) {
+    if self.tags.remove(&id).is_none() {
+      let _ = self.tags.insert(id, self.templates.instantiate(id));
+    }
+  }
+
+  /// Set the value of the tag instantiated from the template with the
+  /// given ID, adding the tag if the task does not have it yet.
+  pub fn set_tag_value(&mut self, id: TagId, value: Option<String>) {
+    let tag = self.templates.instantiate(id).with_value(value);
+    let _ = self.tags.insert(id, tag);
+  }
+
+  /// Replace the tag instantiated from the template with ID `from`, if
+  /// present, with one instantiated from `into`, preserving its value.
+  /// A tag the task has from `into` already is kept as it is.
+  fn replace_tag(&mut self, from: TagId, into: TagId) -> bool {
+    if let Some(tag) = self.tags.remove(&from) {
+      let value = tag.value().map(str::to_string);
+      let templates = &self.templates;
+      let _ = self
+        .tags
+        .entry(into)
+        .or_insert_with(|| templates.instantiate(into).with_value(value));
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Retrieve the tag templates the task's tags are instantiated from.
+  pub fn templates(&self) -> &Templates {
+    &self.templates
+  }
+
   /// Check whether the task is tagged as complete or not.
   pub fn is_complete(&self) -> bool {
     let id = self.templates.complete_tag().id();
@@ -107,7 +325,17 @@ impl Task {
   }
 
   /// Toggle the completion state of the task.
-  pub fn toggle_complete(&mut self) {
+  ///
+  /// Completing a recurring task archives it: the task itself stops
+  /// recurring and the next instance, due according to the task's
+  /// recurrence rule, is returned instead.
+  pub fn toggle_complete(&mut self) -> Option<Task> {
+    self.toggle_complete_on(Date::today())
+  }
+
+  /// Toggle the completion state of the task, treating `today` as the
+  /// current date.
+  fn toggle_complete_on(&mut self, today: Date) -> Option<Task> {
     let id = self.templates.complete_tag().id();
 
     // Try removing the complete tag, if that succeeds we are done (as
@@ -116,6 +344,144 @@ impl Task {
     if self.tags.remove(&id).is_none() {
       let tag = self.templates.instantiate(id);
       let _ = self.tags.insert(id, tag);
+      self.completed = now();
+
+      self.recurrence.take().map(|recurrence| {
+        let mut next = Task::with_summary_and_tags(
+          self.summary.clone(),
+          self.tags.values().filter(|x| x.id() != id).cloned().collect(),
+          self.templates.clone(),
+        );
+        next.priority = self.priority;
+        next.due = Some(recurrence.next_due(self.due, today));
+        next.recurrence = Some(recurrence.anchored(self.due, today));
+        next.parent = self.parent;
+        next.source = self.source;
+        next
+      })
+    } else {
+      self.completed = None;
+      None
+    }
+  }
+
+  /// Retrieve the task's priority, if any.
+  pub fn priority(&self) -> Option<Priority> {
+    self.priority
+  }
+
+  /// Retrieve the time the task was created, in seconds since the Unix
+  /// epoch.
+  ///
+  /// Tasks created before creation times were recorded do not have
+  /// one.
+  pub fn created(&self) -> Option<u64> {
+    self.created
+  }
+
+  /// Retrieve the time the task was completed, in seconds since the
+  /// Unix epoch.
+  ///
+  /// Tasks completed before completion times were recorded do not
+  /// have one.
+  pub fn completed(&self) -> Option<u64> {
+    self.completed
+  }
+
+  /// Retrieve the date the task is due, if any.
+  pub fn due(&self) -> Option<Date> {
+    self.due
+  }
+
+  /// Set or clear the date the task is due.
+  pub fn set_due(&mut self, due: Option<Date>) {
+    self.due = due
+  }
+
+  /// Retrieve the rule according to which the task recurs, if any.
+  pub fn recurrence(&self) -> Option<&Recurrence> {
+    self.recurrence.as_ref()
+  }
+
+  /// Set or clear the rule according to which the task recurs.
+  pub fn set_recurrence(&mut self, recurrence: Option<Recurrence>) {
+    self.recurrence = recurrence
+  }
+
+  /// Retrieve the `Id` of the task's parent, if any.
+  pub fn parent(&self) -> Option<Id> {
+    self.parent
+  }
+
+  /// Check whether the task's subtasks are hidden.
+  pub fn is_collapsed(&self) -> bool {
+    self.collapsed
+  }
+
+  /// Check whether the task is blocked by other tasks that have yet to
+  /// be completed.
+  pub fn is_blocked(&self) -> bool {
+    !self.blocked_by.is_empty()
+  }
+
+  /// Retrieve the `Id`s of the tasks blocking this one.
+  pub fn blocked_by(&self) -> impl Iterator<Item=Id> + '_ {
+    self.blocked_by.iter().cloned()
+  }
+
+  /// Retrieve the index of the task file the task belongs to.
+  pub fn source(&self) -> Source {
+    self.source
+  }
+
+  /// Toggle whether the task is blocked by the task with the given
+  /// `Id`.
+  pub fn toggle_blocked_by(&mut self, id: Id) {
+    debug_assert!(id != self.id);
+
+    match self.blocked_by.iter().position(|x| *x == id) {
+      Some(idx) => {
+        let _ = self.blocked_by.remove(idx);
+      },
+      None => self.blocked_by.push(id),
+    }
+  }
+
+  /// Toggle whether the task's subtasks are hidden.
+  pub fn toggle_collapsed(&mut self) {
+    self.collapsed = !self.collapsed
+  }
+
+  /// Raise the priority of the task by one level.
+  ///
+  /// A task without a priority is assigned the lowest one. The return
+  /// value indicates whether the priority was changed.
+  pub fn raise_priority(&mut self) -> bool {
+    let priority = match self.priority {
+      Some(priority) => priority.higher(),
+      None => Some(Priority::E),
+    };
+
+    match priority {
+      Some(priority) => {
+        self.priority = Some(priority);
+        true
+      },
+      None => false,
+    }
+  }
+
+  /// Lower the priority of the task by one level.
+  ///
+  /// Lowering the lowest priority removes the priority altogether. The
+  /// return value indicates whether the priority was changed.
+  pub fn lower_priority(&mut self) -> bool {
+    match self.priority {
+      Some(priority) => {
+        self.priority = priority.lower();
+        true
+      },
+      None => false,
     }
   }
 }
@@ -125,20 +491,43 @@ impl PartialEq for Task {
     let result = self.id == other.id;
     assert!(!result || self.summary == other.summary);
     assert!(!result || self.tags == other.tags);
+    assert!(!result || self.priority == other.priority);
+    assert!(!result || self.created == other.created);
+    assert!(!result || self.completed == other.completed);
+    assert!(!result || self.due == other.due);
+    assert!(!result || self.recurrence == other.recurrence);
+    assert!(!result || self.parent == other.parent);
+    assert!(!result || self.collapsed == other.collapsed);
+    assert!(!result || self.blocked_by == other.blocked_by);
+    assert!(!result || self.source == other.source);
     result
   }
 }
 
 impl ToSerde<SerTask> for Task {
   /// Convert this task into a serializable one.
+  ///
+  /// Note that the parent of the task as well as its own ID are not
+  /// part of the result. They can only be expressed relative to all
+  /// other tasks, which is taken care of by `Tasks::to_serde`.
   fn to_serde(&self) -> SerTask {
     SerTask {
+      id: None,
       summary: self.summary.clone(),
       tags: self.tags.iter().map(|(_, x)| x.to_serde()).collect(),
+      priority: self.priority.map(|x| x.to_serde()),
+      created: self.created,
+      completed: self.completed,
+      due: self.due.map(|x| x.to_string()),
+      recurrence: self.recurrence.as_ref().map(|x| x.to_string()),
+      parent: None,
+      collapsed: self.collapsed,
+      blocked_by: self.blocked_by.iter().map(|x| x.to_serde()).collect(),
     }
   }
 }
 
+/// An iterator over the tasks of a `Tasks` object.
 pub type TaskIter<'a> = slice::Iter<'a, Task>;
 
 
@@ -147,20 +536,72 @@ pub type TaskIter<'a> = slice::Iter<'a, Task>;
 pub struct Tasks {
   templates: Rc<Templates>,
   tasks: Vec<Task>,
+  /// A counter increased on every change to the tasks.
+  generation: u64,
 }
 
 impl Tasks {
   /// Create a new `Tasks` object from a serializable one.
   pub fn with_serde(tasks: SerTasks, templates: Rc<Templates>, map: &TagMap) -> Result<Self> {
+    let parents = tasks.0.iter().map(|x| x.parent).collect::<Vec<_>>();
+    let mut ids = BTreeMap::<SerTaskId, Id>::new();
+    let mut blockers = Vec::with_capacity(tasks.0.len());
     let mut new_tasks = Vec::with_capacity(tasks.0.len());
-    for task in tasks.0.into_iter() {
+    for mut task in tasks.0.into_iter() {
+      let serde_id = task.id;
+      blockers.push(task.blocked_by.split_off(0));
+
       let task = Task::with_serde(task, templates.clone(), &map)?;
+      if let Some(serde_id) = serde_id {
+        if ids.insert(serde_id, task.id).is_some() {
+          let error = format!("Encountered duplicate task Id {}", serde_id);
+          return Err(Error::new(ErrorKind::InvalidInput, error))
+        }
+      }
       new_tasks.push(task);
     }
 
+    for (task, blockers) in new_tasks.iter_mut().zip(blockers) {
+      for blocker in blockers {
+        let id = ids.get(&blocker).ok_or_else(|| {
+          let error = format!("Encountered invalid task Id {}", blocker);
+          Error::new(ErrorKind::InvalidInput, error)
+        })?;
+        task.blocked_by.push(*id);
+      }
+    }
+
+    for (idx, parent) in parents.iter().enumerate() {
+      if let Some(parent) = *parent {
+        if parent >= new_tasks.len() {
+          let error = format!("Encountered invalid parent task index {}", parent);
+          return Err(Error::new(ErrorKind::InvalidInput, error))
+        }
+
+        // Make sure that the hierarchy is free of cycles, by checking
+        // that the task reaches a root within as many steps as there
+        // are tasks.
+        let mut ancestor = Some(parent);
+        let mut steps = 0;
+        while let Some(idx) = ancestor {
+          steps += 1;
+          if steps > parents.len() {
+            let error = format!(
+              "Encountered cyclic parent relationship for task '{}'", new_tasks[idx].summary
+            );
+            return Err(Error::new(ErrorKind::InvalidInput, error))
+          }
+          ancestor = parents[idx];
+        }
+
+        new_tasks[idx].parent = Some(new_tasks[parent].id);
+      }
+    }
+
     Ok(Tasks {
       templates: templates,
       tasks: new_tasks,
+      generation: 0,
     })
   }
 
@@ -177,9 +618,206 @@ impl Tasks {
     Self::with_serde(SerTasks(tasks), templates, &map)
   }
 
+  /// Record that the tasks changed.
+  fn touch(&mut self) {
+    self.generation += 1;
+  }
+
+  /// Retrieve a value that changes whenever the tasks or the tag
+  /// templates they refer to change, allowing for caching of data
+  /// derived from them.
+  pub fn generation(&self) -> u64 {
+    self.generation + self.templates.generation()
+  }
+
+  /// Move all tasks of another `Tasks` object sharing our templates
+  /// over, making them belong to the given task file.
+  pub fn append(&mut self, tasks: Tasks, source: Source) {
+    self.touch();
+    debug_assert!(Rc::ptr_eq(&self.templates, &tasks.templates));
+
+    self.tasks.extend(tasks.tasks.into_iter().map(|mut task| {
+      task.source = source;
+      task
+    }))
+  }
+
   /// Convert this object into a serializable one.
+  #[cfg(test)]
   pub fn to_serde(&self) -> SerTasks {
-    SerTasks(self.tasks.iter().map(|x| x.to_serde()).collect())
+    self.to_serde_filtered(|_| true, false)
+  }
+
+  /// Convert the tasks belonging to the given task file into a
+  /// serializable object.
+  pub fn to_serde_source(&self, source: Source) -> SerTasks {
+    self.to_serde_filtered(|task| task.source == source, false)
+  }
+
+  /// Convert the tasks belonging to the given task file into a
+  /// serializable object in which all tasks are identified by their
+  /// keys.
+  ///
+  /// All the tasks are expected to have a key, see `mint_keys`.
+  pub fn to_serde_source_with_keys(&self, source: Source) -> SerTasks {
+    self.to_serde_filtered(|task| task.source == source, true)
+  }
+
+  /// Claim the keys of all tasks belonging to the given task file,
+  /// recording them in `keys`. Tasks whose key got claimed already lose
+  /// it.
+  pub fn claim_keys(&mut self, source: Source, keys: &mut BTreeSet<SerTaskId>) {
+    for task in self.tasks.iter_mut().filter(|x| x.source == source) {
+      if let Some(key) = task.key {
+        if !keys.insert(key) {
+          task.key = None;
+        }
+      }
+    }
+  }
+
+  /// Assign a key not yet contained in `keys` to all tasks belonging to
+  /// the given task file that lack one.
+  ///
+  /// Tasks getting a key as well as the ones referring to them are
+  /// considered changed.
+  pub fn mint_keys(&mut self, source: Source, keys: &mut BTreeSet<SerTaskId>) {
+    let mut next = 1;
+    let mut minted = BTreeSet::new();
+    for task in self.tasks.iter_mut().filter(|x| x.source == source && x.key.is_none()) {
+      let key = loop {
+        let key = SerTaskId::new(next);
+        next += 1;
+        if keys.insert(key) {
+          break key
+        }
+      };
+      task.key = Some(key);
+      task.changed = true;
+      let _ = minted.insert(task.id);
+    }
+
+    if !minted.is_empty() {
+      self
+        .tasks
+        .iter_mut()
+        .filter(|x| {
+          x.parent.map_or(false, |x| minted.contains(&x)) ||
+          x.blocked_by.iter().any(|x| minted.contains(x))
+        })
+        .for_each(|x| x.changed = true);
+    }
+  }
+
+  /// Mark all tasks belonging to the given task file as unchanged, as
+  /// is appropriate once they got saved.
+  pub fn clear_changed(&mut self, source: Source) {
+    self
+      .tasks
+      .iter_mut()
+      .filter(|x| x.source == source)
+      .for_each(|x| x.changed = false);
+  }
+
+  /// Convert the tasks belonging to the given task file that satisfy
+  /// the given predicate into serializable ones identified by their
+  /// keys, each along with the key of its parent, if any.
+  ///
+  /// All tasks of the file are expected to have a key, see `mint_keys`.
+  pub fn to_serde_keyed<F>(&self, source: Source, filter: F) -> Vec<(SerTask, Option<SerTaskId>)>
+  where
+    F: Fn(&Task) -> bool,
+  {
+    let keys = self
+      .tasks
+      .iter()
+      .filter(|task| task.source == source)
+      .map(|task| (task.id, task.key.expect("task without key")))
+      .collect::<BTreeMap<_, _>>();
+
+    self
+      .tasks
+      .iter()
+      .filter(|task| task.source == source && filter(task))
+      .map(|task| {
+        let parent = task.parent.and_then(|id| keys.get(&id).copied());
+        let blocked_by = task
+          .blocked_by()
+          .filter_map(|id| keys.get(&id).copied())
+          .collect();
+        let task = SerTask {
+          id: task.key,
+          blocked_by: blocked_by,
+          ..task.to_serde()
+        };
+        (task, parent)
+      })
+      .collect()
+  }
+
+  /// Convert the tasks satisfying the given predicate into a
+  /// serializable object.
+  ///
+  /// Only relationships between tasks that are both part of the result
+  /// are persisted.
+  ///
+  /// If `keyed` is set all tasks are identified by their keys, otherwise
+  /// by their in-memory IDs, and only if other tasks refer to them.
+  fn to_serde_filtered<F>(&self, filter: F, keyed: bool) -> SerTasks
+  where
+    F: Fn(&Task) -> bool,
+  {
+    let tasks = self
+      .tasks
+      .iter()
+      .filter(|task| filter(task))
+      .collect::<Vec<_>>();
+    let indices = tasks
+      .iter()
+      .enumerate()
+      .map(|(idx, task)| (task.id, idx))
+      .collect::<BTreeMap<_, _>>();
+    // Only tasks that block others need to have their ID persisted.
+    let blockers = tasks
+      .iter()
+      .flat_map(|task| task.blocked_by())
+      .filter(|id| indices.contains_key(id))
+      .collect::<BTreeSet<_>>();
+
+    let ser_id = |id: Id| {
+      if keyed {
+        let idx = indices[&id];
+        tasks[idx].key.expect("task without key")
+      } else {
+        id.to_serde()
+      }
+    };
+
+    let tasks = tasks
+      .iter()
+      .map(|task| {
+        let id = if keyed || blockers.contains(&task.id) {
+          Some(ser_id(task.id))
+        } else {
+          None
+        };
+        let parent = task.parent.and_then(|id| indices.get(&id).copied());
+        let blocked_by = task
+          .blocked_by()
+          .filter(|id| indices.contains_key(id))
+          .map(ser_id)
+          .collect();
+
+        SerTask {
+          id: id,
+          parent: parent,
+          blocked_by: blocked_by,
+          ..task.to_serde()
+        }
+      })
+      .collect();
+
+    SerTasks(tasks)
   }
 
   /// Retrieve an iterator over the tasks.
@@ -187,26 +825,189 @@ impl Tasks {
     self.tasks.iter()
   }
 
-  /// Add a new task.
-  pub fn add(&mut self, summary: String, tags: Vec<Tag>) -> Id {
-    let task = Task::with_summary_and_tags(summary, tags, self.templates.clone());
+  /// Add a new task to the given task file, optionally as a subtask of
+  /// another one.
+  pub fn add(&mut self, summary: String, tags: Vec<Tag>, parent: Option<Id>, source: Source) -> Id {
+    self.touch();
+    let mut task = Task::with_summary_and_tags(summary, tags, self.templates.clone());
+    task.parent = parent;
+    task.source = source;
     let id = task.id;
     self.tasks.push(task);
     id
   }
 
+  /// Retrieve an iterator over the direct subtasks of a task.
+  pub fn children(&self, id: Id) -> impl Iterator<Item=&Task> {
+    self.tasks.iter().filter(move |x| x.parent == Some(id))
+  }
+
+  /// Retrieve all subtasks of a task, including indirect ones.
+  pub fn descendants(&self, id: Id) -> Vec<&Task> {
+    let mut descendants = Vec::new();
+    let mut parents = vec![id];
+
+    while let Some(parent) = parents.pop() {
+      for child in self.children(parent) {
+        parents.push(child.id);
+        descendants.push(child);
+      }
+    }
+    descendants
+  }
+
+  /// Retrieve the number of completed subtasks of a task along with
+  /// the total number of its subtasks, including indirect ones.
+  pub fn progress(&self, id: Id) -> (usize, usize) {
+    let descendants = self.descendants(id);
+    let done = descendants.iter().filter(|x| x.is_complete()).count();
+    (done, descendants.len())
+  }
+
+  /// Toggle the completion state of a task.
+  ///
+  /// If the task recurs, its next instance is inserted right after it.
+  /// Completing a task unblocks all tasks that it was blocking.
+  pub fn toggle_complete(&mut self, id: Id) {
+    self.touch();
+    let idx = self.tasks.iter().position(|x| x.id == id).unwrap();
+    self.tasks[idx].changed = true;
+    if let Some(next) = self.tasks[idx].toggle_complete() {
+      self.tasks.insert(idx + 1, next);
+    }
+
+    if self.tasks[idx].is_complete() {
+      self.unblock(id);
+    }
+  }
+
+  /// Remove the task with the given `Id` from the blockers of all
+  /// tasks.
+  fn unblock(&mut self, id: Id) {
+    self
+      .tasks
+      .iter_mut()
+      .filter(|x| x.blocked_by.contains(&id))
+      .for_each(|x| {
+        x.blocked_by.retain(|x| *x != id);
+        x.changed = true;
+      });
+  }
+
+  /// Check whether the task with the given `Id` is blocked by
+  /// `blocker`, either directly or through other blocked tasks.
+  pub fn depends_on(&self, id: Id, blocker: Id) -> bool {
+    let mut visited = BTreeSet::new();
+    let mut ids = vec![id];
+
+    while let Some(id) = ids.pop() {
+      if !visited.insert(id) {
+        continue
+      }
+
+      if let Some(task) = self.tasks.iter().find(|x| x.id == id) {
+        if task.blocked_by.contains(&blocker) {
+          return true
+        }
+        ids.extend(task.blocked_by.iter().cloned());
+      }
+    }
+    false
+  }
+
+  /// Toggle the completion state of a task, setting the state of all
+  /// its subtasks to match.
+  pub fn toggle_complete_with_subtasks(&mut self, id: Id) {
+    self.touch();
+    let mut ids = self
+      .descendants(id)
+      .into_iter()
+      .map(|x| x.id)
+      .collect::<Vec<_>>();
+    ids.push(id);
+
+    let complete = !self.tasks.iter().find(|x| x.id == id).unwrap().is_complete();
+    for id in ids {
+      let task = self.tasks.iter().find(|x| x.id == id).unwrap();
+      if task.is_complete() != complete {
+        self.toggle_complete(id)
+      }
+    }
+  }
+
+  /// Make a task a subtask of another one.
+  ///
+  /// The task becomes the last subtask of its new parent, which is
+  /// expanded if it was collapsed.
+  pub fn indent(&mut self, id: Id, parent: Id) {
+    self.touch();
+    debug_assert!(id != parent);
+    debug_assert!(!self.descendants(id).iter().any(|x| x.id == parent));
+
+    let last = self.children(parent).last().map_or(parent, |x| x.id);
+    self.move_after(id, last);
+
+    for task in self.tasks.iter_mut() {
+      if task.id == id {
+        task.parent = Some(parent);
+        task.changed = true;
+      } else if task.id == parent && task.collapsed {
+        task.collapsed = false;
+        task.changed = true;
+      }
+    }
+  }
+
+  /// Make a task a sibling of its parent, placing it right after the
+  /// latter.
+  ///
+  /// The return value indicates whether the task had a parent.
+  pub fn outdent(&mut self, id: Id) -> bool {
+    self.touch();
+    let parent = self.tasks.iter().find(|x| x.id == id).unwrap().parent;
+    match parent {
+      Some(parent) => {
+        let grandparent = self.tasks.iter().find(|x| x.id == parent).unwrap().parent;
+        self.move_after(id, parent);
+
+        let task = self.tasks.iter_mut().find(|x| x.id == id).unwrap();
+        task.parent = grandparent;
+        task.changed = true;
+        true
+      },
+      None => false,
+    }
+  }
+
   /// Remove a task.
+  ///
+  /// Subtasks of the removed task are moved up one level in the task
+  /// hierarchy. Tasks blocked by it are unblocked.
   pub fn remove(&mut self, id: Id) {
-    let _ = self
+    self.touch();
+    let task = self
       .tasks
       .iter()
       .position(|x| x.id() == id)
       .map(|x| self.tasks.remove(x))
       .unwrap();
+
+    self
+      .tasks
+      .iter_mut()
+      .filter(|x| x.parent == Some(id))
+      .for_each(|x| {
+        x.parent = task.parent;
+        x.changed = true;
+      });
+
+    self.unblock(id);
   }
 
   /// Update a task.
-  pub fn update(&mut self, task: Task) {
+  pub fn update(&mut self, mut task: Task) {
+    self.touch();
+    task.changed = true;
     let _ = self
       .tasks
       .iter_mut()
@@ -215,15 +1016,48 @@ impl Tasks {
       .unwrap();
   }
 
+  /// Add a task originating elsewhere, e.g., an earlier version of the
+  /// tasks, as a top level task not blocked by any other.
+  ///
+  /// The task is assigned a new ID, which is returned.
+  pub fn adopt(&mut self, mut task: Task) -> Id {
+    self.touch();
+    let id = Id::new();
+    task.id = id;
+    task.parent = None;
+    task.blocked_by.clear();
+    task.changed = true;
+    self.tasks.push(task);
+    id
+  }
+
+  /// Retag all tasks tagged with the template with ID `from` to carry
+  /// a tag instantiated from `into` instead.
+  ///
+  /// The return value indicates whether any task was retagged.
+  pub fn replace_tag(&mut self, from: TagId, into: TagId) -> bool {
+    self.touch();
+    let mut replaced = false;
+    for task in self.tasks.iter_mut() {
+      if task.replace_tag(from, into) {
+        task.changed = true;
+        replaced = true;
+      }
+    }
+    replaced
+  }
+
   /// Move a task relative to another.
   fn move_relative_to(&mut self, to_move: Id, other: Id, add: usize) {
+    self.touch();
     if to_move != other {
       self
         .tasks
         .iter_mut()
         .position(|x| x.id() == to_move)
         .map(|x| {
-          let task = self.tasks.remove(x);
+          let mut task = self.tasks.remove(x);
+          task.changed = true;
           let idx = self
             .tasks
             .iter_mut()
@@ -236,6 +1070,102 @@ impl Tasks {
     }
   }
 
+  /// Move completed tasks into the given archive.
+  ///
+  /// If `completed_before` is set, only tasks completed before that
+  /// time (in seconds since the Unix epoch) are moved; completed tasks
+  /// lacking a completion time are considered completed just now. A
+  /// task is only moved along with all its subtasks, i.e., if all of
+  /// them qualify as well. Tasks keep their parent if it got archived
+  /// as well. The return value indicates whether any task was moved.
+  pub fn archive(&mut self, archive: &mut Tasks, completed_before: Option<u64>) -> bool {
+    self.touch();
+    archive.touch();
+
+    if completed_before.is_some() {
+      let now = now();
+      self
+        .tasks
+        .iter_mut()
+        .filter(|x| x.is_complete() && x.completed.is_none())
+        .for_each(|x| {
+          x.completed = now;
+          x.changed = true;
+        });
+    }
+
+    let qualifies = |task: &Task| {
+      task.is_complete() &&
+      match (completed_before, task.completed()) {
+        (Some(before), Some(completed)) => completed < before,
+        (Some(_), None) => false,
+        (None, _) => true,
+      }
+    };
+
+    let ids = self
+      .tasks
+      .iter()
+      .filter(|x| qualifies(x) && self.descendants(x.id).into_iter().all(qualifies))
+      .map(|x| x.id)
+      .collect::<BTreeSet<_>>();
+
+    let (archived, tasks) = self
+      .tasks
+      .drain(..)
+      .partition::<Vec<_>, _>(|x| ids.contains(&x.id));
+    self.tasks = tasks;
+
+    for mut task in archived {
+      if let Some(parent) = task.parent {
+        if !ids.contains(&parent) {
+          task.parent = None;
+        }
+      }
+      task.blocked_by.clear();
+      task.changed = true;
+      archive.tasks.push(task);
+    }
+
+    for id in ids.iter() {
+      self.unblock(*id);
+    }
+    !ids.is_empty()
+  }
+
+  /// Move a task along with all its subtasks out of this archive and
+  /// back into the given tasks.
+  ///
+  /// The restored task is marked as not completed and becomes a top
+  /// level task.
+  pub fn restore(&mut self, id: Id, tasks: &mut Tasks) {
+    self.touch();
+    tasks.touch();
+    let mut ids = self
+      .descendants(id)
+      .into_iter()
+      .map(|x| x.id)
+      .collect::<BTreeSet<_>>();
+    let _ = ids.insert(id);
+
+    let (restored, archived) = self
+      .tasks
+      .drain(..)
+      .partition::<Vec<_>, _>(|x| ids.contains(&x.id));
+    self.tasks = archived;
+
+    for mut task in restored {
+      task.changed = true;
+      if task.id == id {
+        task.parent = None;
+        if task.is_complete() {
+          let _ = task.toggle_complete();
+        }
+      }
+      tasks.tasks.push(task);
+    }
+  }
+
   /// Reorder the tasks referenced by `to_move` before `other`.
   pub fn move_before(&mut self, to_move: Id, other: Id) {
     self.move_relative_to(to_move, other, 0)
@@ -250,7 +1180,7 @@ impl Tasks {
 
 #[allow(unused_results)]
 #[cfg(test)]
-pub mod tests {
+mod tests {
   use super::*;
 
   use serde_json::from_str as from_json;
@@ -258,15 +1188,21 @@ pub mod tests {
 
   use crate::ser::tags::Templates as SerTemplates;
   use crate::test::make_tasks;
+  use crate::test::make_tasks_with_tags;
 
 
   #[test]
   fn add_task() {
     let mut tasks = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
     let tags = Default::default();
-    tasks.add("4".to_string(), tags);
+    let id = tasks.add("4".to_string(), tags, None, 0);
+
+    let task = tasks.iter().find(|x| x.id() == id).unwrap();
+    assert!(task.created().is_some());
 
-    assert_eq!(tasks.to_serde().0, make_tasks(4));
+    let mut tasks = tasks.to_serde().0;
+    tasks[3].created = None;
+    assert_eq!(tasks, make_tasks(4));
   }
 
   #[test]
@@ -281,6 +1217,48 @@ pub mod tests {
     assert_eq!(tasks.to_serde().0, expected);
   }
 
+  #[test]
+  fn track_changed_tasks() {
+    let changed = |tasks: &Tasks| {
+      tasks.iter().map(Task::is_changed).collect::<Vec<_>>()
+    };
+
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(4)).unwrap();
+    let ids = tasks.iter().map(Task::id).collect::<Vec<_>>();
+    assert_eq!(changed(&tasks), vec![false; 4]);
+
+    tasks.indent(ids[3], ids[1]);
+    assert_eq!(changed(&tasks), vec![false, false, true, false]);
+
+    tasks.clear_changed(0);
+    tasks.remove(ids[1]);
+    assert_eq!(changed(&tasks), vec![false, true, false]);
+
+    tasks.clear_changed(0);
+    let mut keys = BTreeSet::new();
+    tasks.mint_keys(0, &mut keys);
+    assert_eq!(changed(&tasks), vec![true; 3]);
+  }
+
+  #[test]
+  fn adopt_task() {
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(2)).unwrap();
+    let mut other = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
+    let parent = other.iter().next().unwrap().id();
+    let child = other.iter().nth(2).unwrap().id();
+    other.indent(child, parent);
+
+    let task = other.iter().find(|x| x.id() == child).unwrap().clone();
+    assert_eq!(task.parent(), Some(parent));
+    let id = tasks.adopt(task);
+    assert_ne!(id, child);
+
+    let task = tasks.iter().nth(2).unwrap();
+    assert_eq!(task.id(), id);
+    assert_eq!(task.parent(), None);
+    assert_eq!(tasks.to_serde().0.len(), 3);
+  }
+
   #[test]
   fn update_task() {
     let mut tasks = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
@@ -294,6 +1272,24 @@ pub mod tests {
     assert_eq!(tasks.to_serde().0, expected);
   }
 
+  #[test]
+  fn replace_tags() {
+    let (_, templates, tasks) = make_tasks_with_tags(12);
+    let (templates, map) = Templates::with_serde(SerTemplates(templates));
+    let templates = Rc::new(templates);
+    let mut tasks = Tasks::with_serde(SerTasks(tasks), templates.clone(), &map).unwrap();
+
+    let tag1 = templates.find("tag1").unwrap();
+    let tag2 = templates.find("tag2").unwrap();
+
+    assert!(tasks.replace_tag(tag1, tag2));
+    assert!(!tasks.replace_tag(tag1, tag2));
+    assert!(tasks.iter().all(|x| !x.has_tag(tag1)));
+    // Tasks tagged with both templates keep a single tag.
+    let tagged = tasks.iter().filter(|x| x.has_tag(tag2)).count();
+    assert_eq!(tagged, 8);
+  }
+
   #[test]
   fn move_before_for_first() {
     let mut tasks = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
@@ -345,8 +1341,422 @@ pub mod tests {
   fn task_completion() {
     let mut task = Task::new("test task");
     assert!(!task.is_complete());
-    task.toggle_complete();
+    assert!(task.toggle_complete().is_none());
+    assert!(task.is_complete());
+  }
+
+  #[test]
+  fn recurring_task_completion() {
+    let today = Date::new(2019, 5, 7).unwrap();
+    let mut task = Task::new("recurring");
+    task.priority = Some(Priority::B);
+    task.set_due(Some(Date::new(2019, 5, 6).unwrap()));
+    task.set_recurrence(Some(Recurrence::Daily));
+
+    let next = task.toggle_complete_on(today).unwrap();
     assert!(task.is_complete());
+    assert_eq!(task.recurrence(), None);
+    assert_eq!(task.due(), Some(Date::new(2019, 5, 6).unwrap()));
+
+    assert!(next.id() != task.id());
+    assert!(!next.is_complete());
+    assert_eq!(next.summary, "recurring");
+    assert_eq!(next.priority(), Some(Priority::B));
+    assert_eq!(next.recurrence(), Some(&Recurrence::Daily));
+    assert_eq!(next.due(), Some(Date::new(2019, 5, 8).unwrap()));
+
+    // Reopening the archived instance does not spawn another one.
+    assert!(task.toggle_complete_on(today).is_none());
+    assert!(!task.is_complete());
+  }
+
+  #[test]
+  fn recurring_task_in_tasks() {
+    let mut ser_tasks = make_tasks(3);
+    ser_tasks[1].recurrence = Some("weekly".to_string());
+    let mut tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let id = tasks.iter().nth(1).unwrap().id();
+    tasks.toggle_complete(id);
+
+    let tasks = tasks.iter().collect::<Vec<_>>();
+    assert_eq!(tasks.len(), 4);
+    assert!(tasks[1].is_complete());
+    assert_eq!(tasks[1].recurrence(), None);
+    assert_eq!(tasks[2].summary, "2");
+    assert!(!tasks[2].is_complete());
+    assert_eq!(tasks[2].recurrence(), Some(&Recurrence::Weekly(Vec::new())));
+    assert!(tasks[2].due().is_some());
+  }
+
+  #[test]
+  fn task_with_invalid_recurrence() {
+    let mut ser_tasks = make_tasks(1);
+    ser_tasks[0].recurrence = Some("yearly".to_string());
+    assert!(Tasks::with_serde_tasks(ser_tasks).is_err());
+  }
+
+  #[test]
+  fn task_priority() {
+    let mut task = Task::new("test task");
+    assert_eq!(task.priority(), None);
+    assert!(!task.lower_priority());
+
+    assert!(task.raise_priority());
+    assert_eq!(task.priority(), Some(Priority::E));
+    assert!(task.raise_priority());
+    assert_eq!(task.priority(), Some(Priority::D));
+
+    (0..3).for_each(|_| assert!(task.raise_priority()));
+    assert_eq!(task.priority(), Some(Priority::A));
+    assert!(!task.raise_priority());
+    assert_eq!(task.priority(), Some(Priority::A));
+
+    assert!(task.lower_priority());
+    assert_eq!(task.priority(), Some(Priority::B));
+    (0..3).for_each(|_| assert!(task.lower_priority()));
+    assert_eq!(task.priority(), Some(Priority::E));
+    assert!(task.lower_priority());
+    assert_eq!(task.priority(), None);
+  }
+
+  #[test]
+  fn task_with_invalid_due_date() {
+    let mut ser_tasks = make_tasks(1);
+    ser_tasks[0].due = Some("2019-02-30".to_string());
+
+    let err = Tasks::with_serde_tasks(ser_tasks).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn blocked_task_serde() {
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
+    let id0 = tasks.iter().next().unwrap().id();
+    let mut task = tasks.iter().nth(2).unwrap().clone();
+    task.toggle_blocked_by(id0);
+    tasks.update(task);
+
+    let ser_tasks = tasks.to_serde().0;
+    assert_eq!(ser_tasks[0].id, Some(id0.to_serde()));
+    assert_eq!(ser_tasks[1].id, None);
+    assert_eq!(ser_tasks[2].id, None);
+    assert_eq!(ser_tasks[2].blocked_by, vec![id0.to_serde()]);
+
+    let tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let tasks = tasks.iter().collect::<Vec<_>>();
+    assert!(!tasks[0].is_blocked());
+    assert!(!tasks[1].is_blocked());
+    assert!(tasks[2].is_blocked());
+    assert_eq!(tasks[2].blocked_by().collect::<Vec<_>>(), vec![tasks[0].id()]);
+  }
+
+  #[test]
+  fn tasks_from_multiple_sources_serde() {
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(2)).unwrap();
+    let templates = tasks.templates.clone();
+    let others = Tasks::with_serde(SerTasks(make_tasks(2)), templates, &Default::default());
+    tasks.append(others.unwrap(), 1);
+
+    let sources = tasks.iter().map(|x| x.source()).collect::<Vec<_>>();
+    assert_eq!(sources, vec![0, 0, 1, 1]);
+
+    let ids = tasks.iter().map(|x| x.id()).collect::<Vec<_>>();
+    let mut task = tasks.iter().nth(2).unwrap().clone();
+    task.toggle_blocked_by(ids[0]);
+    tasks.update(task);
+    tasks.indent(ids[3], ids[2]);
+
+    assert_eq!(tasks.to_serde_source(0).0, make_tasks(2));
+
+    // The blocker is part of another task file and so the relationship
+    // can not be persisted.
+    let ser_tasks = tasks.to_serde_source(1).0;
+    assert_eq!(ser_tasks.len(), 2);
+    assert_eq!(ser_tasks[0].summary, "1");
+    assert_eq!(ser_tasks[0].blocked_by, Vec::new());
+    assert_eq!(ser_tasks[1].summary, "2");
+    assert_eq!(ser_tasks[1].parent, Some(0));
+  }
+
+  #[test]
+  fn tasks_with_keys_serde() {
+    let mut ser_tasks = make_tasks(4);
+    ser_tasks[1].id = Some(SerTaskId::new(2));
+    ser_tasks[2].id = Some(SerTaskId::new(1));
+    ser_tasks[2].blocked_by = vec![SerTaskId::new(2)];
+    let mut tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let mut other = Tasks::with_serde_tasks(make_tasks(1)).unwrap();
+    other.tasks[0].key = Some(SerTaskId::new(1));
+
+    let mut keys = BTreeSet::new();
+    tasks.claim_keys(0, &mut keys);
+    other.claim_keys(0, &mut keys);
+    tasks.mint_keys(0, &mut keys);
+    other.mint_keys(0, &mut keys);
+
+    // Existing keys are retained, duplicates and missing ones minted.
+    let ser_tasks = tasks.to_serde_source_with_keys(0).0;
+    let ids = ser_tasks.iter().map(|x| x.id.unwrap()).collect::<Vec<_>>();
+    let expected = [3, 2, 1, 4].iter().map(|x| SerTaskId::new(*x)).collect::<Vec<_>>();
+    assert_eq!(ids, expected);
+    assert_eq!(ser_tasks[2].blocked_by, vec![SerTaskId::new(2)]);
+    assert_eq!(other.to_serde_source_with_keys(0).0[0].id, Some(SerTaskId::new(5)));
+  }
+
+  #[test]
+  fn task_with_invalid_blocker() {
+    let mut ser_tasks = make_tasks(2);
+    ser_tasks[0].id = Some(SerTaskId::new(1));
+    ser_tasks[1].blocked_by = vec![SerTaskId::new(2)];
+
+    let err = Tasks::with_serde_tasks(ser_tasks).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let mut ser_tasks = make_tasks(2);
+    ser_tasks[0].id = Some(SerTaskId::new(1));
+    ser_tasks[1].id = Some(SerTaskId::new(1));
+
+    let err = Tasks::with_serde_tasks(ser_tasks).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn complete_blocker_unblocks() {
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
+    let id0 = tasks.iter().next().unwrap().id();
+    let id1 = tasks.iter().nth(1).unwrap().id();
+    let mut task = tasks.iter().nth(2).unwrap().clone();
+    task.toggle_blocked_by(id0);
+    task.toggle_blocked_by(id1);
+    tasks.update(task);
+
+    tasks.toggle_complete(id0);
+    let task = tasks.iter().nth(2).unwrap();
+    assert!(task.is_blocked());
+    assert_eq!(task.blocked_by().collect::<Vec<_>>(), vec![id1]);
+
+    tasks.remove(id1);
+    assert!(!tasks.iter().nth(1).unwrap().is_blocked());
+  }
+
+  #[test]
+  fn transitive_dependencies() {
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(3)).unwrap();
+    let id0 = tasks.iter().next().unwrap().id();
+    let id1 = tasks.iter().nth(1).unwrap().id();
+    let id2 = tasks.iter().nth(2).unwrap().id();
+
+    let mut task = tasks.iter().nth(1).unwrap().clone();
+    task.toggle_blocked_by(id0);
+    tasks.update(task);
+    let mut task = tasks.iter().nth(2).unwrap().clone();
+    task.toggle_blocked_by(id1);
+    tasks.update(task);
+
+    assert!(tasks.depends_on(id1, id0));
+    assert!(tasks.depends_on(id2, id0));
+    assert!(!tasks.depends_on(id0, id2));
+    assert!(!tasks.depends_on(id1, id2));
+  }
+
+  #[test]
+  fn archive_and_restore_tasks() {
+    let mut ser_tasks = make_tasks(5);
+    ser_tasks[2].parent = Some(1);
+    ser_tasks[3].parent = Some(1);
+    let mut tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let mut archive = Tasks::with_serde_tasks(Vec::new()).unwrap();
+    let ids = tasks.iter().map(|x| x.id()).collect::<Vec<_>>();
+
+    assert!(!tasks.archive(&mut archive, None));
+
+    // Task 2 has an incomplete subtask and must not be archived.
+    tasks.toggle_complete(ids[0]);
+    tasks.toggle_complete(ids[1]);
+    tasks.toggle_complete(ids[2]);
+    assert!(tasks.archive(&mut archive, None));
+
+    let summaries = |tasks: &Tasks| tasks.iter().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries(&tasks), vec!["2", "4", "5"]);
+    assert_eq!(summaries(&archive), vec!["1", "3"]);
+    assert_eq!(archive.iter().nth(1).unwrap().parent(), None);
+
+    tasks.toggle_complete(ids[3]);
+    assert!(tasks.archive(&mut archive, None));
+    assert_eq!(summaries(&tasks), vec!["5"]);
+    assert_eq!(summaries(&archive), vec!["1", "3", "2", "4"]);
+    assert_eq!(archive.iter().nth(3).unwrap().parent(), Some(ids[1]));
+
+    archive.restore(ids[1], &mut tasks);
+    assert_eq!(summaries(&tasks), vec!["5", "2", "4"]);
+    assert_eq!(summaries(&archive), vec!["1", "3"]);
+
+    let restored = tasks.iter().collect::<Vec<_>>();
+    assert!(!restored[1].is_complete());
+    assert_eq!(restored[1].parent(), None);
+    assert!(restored[2].is_complete());
+    assert_eq!(restored[2].parent(), Some(ids[1]));
+  }
+
+  #[test]
+  fn archive_tasks_completed_before() {
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(2)).unwrap();
+    let mut archive = Tasks::with_serde_tasks(Vec::new()).unwrap();
+    let id = tasks.iter().next().unwrap().id();
+    tasks.toggle_complete(id);
+
+    let completed = tasks.iter().next().unwrap().completed().unwrap();
+    assert!(!tasks.archive(&mut archive, Some(completed)));
+    assert!(tasks.archive(&mut archive, Some(completed + 1)));
+    assert_eq!(tasks.iter().count(), 1);
+    assert_eq!(archive.iter().count(), 1);
+
+    // Legacy tasks without completion time count as completed now.
+    let (_, templates, tasks) = make_tasks_with_tags(4);
+    let (templates, map) = Templates::with_serde(SerTemplates(templates));
+    let mut tasks = Tasks::with_serde(SerTasks(tasks), Rc::new(templates), &map).unwrap();
+    assert!(tasks.iter().nth(1).unwrap().is_complete());
+    assert_eq!(tasks.iter().nth(1).unwrap().completed(), None);
+
+    let mut archive = Tasks::with_serde_tasks(Vec::new()).unwrap();
+    assert!(!tasks.archive(&mut archive, Some(0)));
+    assert_eq!(tasks.iter().count(), 4);
+    let completed = tasks.iter().nth(1).unwrap().completed().unwrap();
+    assert!(tasks.archive(&mut archive, Some(completed + 1)));
+    assert_eq!(tasks.iter().count(), 2);
+    assert_eq!(archive.iter().count(), 2);
+  }
+
+  #[test]
+  fn archive_unblocks_tasks() {
+    let mut tasks = Tasks::with_serde_tasks(make_tasks(2)).unwrap();
+    let mut archive = Tasks::with_serde_tasks(Vec::new()).unwrap();
+    let id0 = tasks.iter().next().unwrap().id();
+    tasks.toggle_complete(id0);
+
+    // Blocking by a completed task is possible, but archiving the
+    // latter must not leave a dangling reference behind.
+    let mut task = tasks.iter().nth(1).unwrap().clone();
+    task.toggle_blocked_by(id0);
+    tasks.update(task);
+
+    assert!(tasks.archive(&mut archive, None));
+    assert!(!tasks.iter().next().unwrap().is_blocked());
+  }
+
+  #[test]
+  fn task_hierarchy_serde() {
+    let mut ser_tasks = make_tasks(4);
+    ser_tasks[1].parent = Some(0);
+    ser_tasks[2].parent = Some(1);
+    ser_tasks[3].parent = Some(0);
+
+    let tasks = Tasks::with_serde_tasks(ser_tasks.clone()).unwrap();
+    let ids = tasks.iter().map(|x| x.id()).collect::<Vec<_>>();
+    let parents = tasks.iter().map(|x| x.parent()).collect::<Vec<_>>();
+
+    assert_eq!(parents, vec![None, Some(ids[0]), Some(ids[1]), Some(ids[0])]);
+    assert_eq!(tasks.to_serde().0, ser_tasks);
+  }
+
+  #[test]
+  fn task_hierarchy_invalid_parent() {
+    let mut ser_tasks = make_tasks(2);
+    ser_tasks[1].parent = Some(2);
+
+    let err = Tasks::with_serde_tasks(ser_tasks).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn task_hierarchy_cycle() {
+    let mut ser_tasks = make_tasks(3);
+    ser_tasks[0].parent = Some(0);
+    let err = Tasks::with_serde_tasks(ser_tasks).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    let mut ser_tasks = make_tasks(3);
+    ser_tasks[0].parent = Some(2);
+    ser_tasks[1].parent = Some(0);
+    ser_tasks[2].parent = Some(1);
+    let err = Tasks::with_serde_tasks(ser_tasks).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+  }
+
+  #[test]
+  fn indent_outdent_task() {
+    let mut ser_tasks = make_tasks(4);
+    ser_tasks[1].parent = Some(0);
+    ser_tasks[0].collapsed = true;
+
+    let mut tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let id0 = tasks.iter().next().unwrap().id();
+    let id3 = tasks.iter().nth(3).unwrap().id();
+    tasks.indent(id3, id0);
+
+    let mut expected = make_tasks(4);
+    expected.swap(2, 3);
+    expected[1].parent = Some(0);
+    expected[2].parent = Some(0);
+    assert_eq!(tasks.to_serde().0, expected);
+
+    assert!(tasks.outdent(id3));
+    assert!(!tasks.outdent(id3));
+
+    let expected = vec![
+      make_tasks(4)[0].clone(),
+      make_tasks(4)[3].clone(),
+      SerTask {
+        parent: Some(0),
+        ..make_tasks(4)[1].clone()
+      },
+      make_tasks(4)[2].clone(),
+    ];
+    assert_eq!(tasks.to_serde().0, expected);
+  }
+
+  #[test]
+  fn remove_task_with_subtasks() {
+    let mut ser_tasks = make_tasks(3);
+    ser_tasks[1].parent = Some(0);
+    ser_tasks[2].parent = Some(1);
+
+    let mut tasks = Tasks::with_serde_tasks(ser_tasks).unwrap();
+    let id = tasks.iter().nth(1).unwrap().id();
+    tasks.remove(id);
+
+    let mut expected = make_tasks(3);
+    expected.remove(1);
+    expected[1].parent = Some(0);
+    assert_eq!(tasks.to_serde().0, expected);
+  }
+
+  #[test]
+  fn subtask_progress() {
+    let (templates, map) = Templates::with_serde(SerTemplates(Default::default()));
+    let templates = Rc::new(templates);
+    let mut ser_tasks = make_tasks(4);
+    ser_tasks[1].parent = Some(0);
+    ser_tasks[2].parent = Some(1);
+    ser_tasks[3].parent = Some(0);
+
+    let mut tasks = Tasks::with_serde(SerTasks(ser_tasks), templates, &map).unwrap();
+    let id0 = tasks.iter().next().unwrap().id();
+    let id1 = tasks.iter().nth(1).unwrap().id();
+    let id2 = tasks.iter().nth(2).unwrap().id();
+    assert_eq!(tasks.progress(id0), (0, 3));
+    assert_eq!(tasks.progress(id2), (0, 0));
+
+    tasks.toggle_complete_with_subtasks(id1);
+    assert_eq!(tasks.progress(id0), (2, 3));
+    assert_eq!(tasks.progress(id1), (1, 1));
+
+    tasks.toggle_complete_with_subtasks(id0);
+    assert_eq!(tasks.progress(id0), (3, 3));
+    assert!(tasks.iter().all(|x| x.is_complete()));
+
+    tasks.toggle_complete_with_subtasks(id0);
+    assert!(tasks.iter().all(|x| !x.is_complete()));
   }
 
   #[test]
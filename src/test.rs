@@ -19,12 +19,14 @@
 
 use std::env::temp_dir;
 use std::ffi::CString;
+use std::fs::remove_dir_all;
 use std::fs::remove_file;
 use std::os::unix::ffi::OsStringExt;
 use std::path::PathBuf;
 
 use libc::c_int;
 use libc::close;
+use libc::mkdtemp;
 use libc::mkstemp;
 
 use crate::ser::tags::Id as SerId;
@@ -71,12 +73,46 @@ impl Drop for NamedTempFile {
 }
 
 
+/// A temporary directory, removed along with its contents when
+/// dropped.
+///
+/// This class is only meant for our internal testing!
+pub struct TempDir {
+  path: PathBuf,
+}
+
+impl TempDir {
+  pub fn new() -> Self {
+    let path = temp_dir().join("tempXXXXXX");
+    let template = CString::new(path.into_os_string().into_vec()).unwrap();
+    let raw = template.into_raw();
+    let result = unsafe { mkdtemp(raw) };
+    assert!(!result.is_null());
+
+    TempDir {
+      path: unsafe { PathBuf::from(CString::from_raw(raw).into_string().unwrap()) },
+    }
+  }
+
+  pub fn path(&self) -> &PathBuf {
+    &self.path
+  }
+}
+
+impl Drop for TempDir {
+  fn drop(&mut self) {
+    remove_dir_all(&self.path).unwrap();
+  }
+}
+
+
 pub fn make_tasks(count: usize) -> Vec<SerTask> {
   (0..count)
     .map(|i| {
       SerTask {
         summary: format!("{}", i + 1),
         tags: Default::default(),
+        ..Default::default()
       }
     })
     .collect()
@@ -116,6 +152,7 @@ pub fn make_tasks_with_tags(count: usize) -> (Vec<SerTag>, Vec<SerTemplate>, Vec
     .map(|x| {
       SerTag {
         id: SerId::new(x),
+        value: None,
       }
     })
     .collect::<Vec<_>>();
@@ -124,11 +161,13 @@ pub fn make_tasks_with_tags(count: usize) -> (Vec<SerTag>, Vec<SerTemplate>, Vec
       SerTemplate {
         id: tags[x].id,
         name: COMPLETE_TAG.to_string(),
+        style: Default::default(),
       }
     } else {
       SerTemplate {
         id: tags[x].id,
         name: format!("tag{}", x),
+        style: Default::default(),
       }
     })
     .collect::<Vec<_>>();
@@ -137,11 +176,11 @@ pub fn make_tasks_with_tags(count: usize) -> (Vec<SerTag>, Vec<SerTemplate>, Vec
       let mut task_tags = Vec::new();
       // Add 'complete' tag for uneven tasks.
       if x % 2 == 1 {
-        task_tags.push(tags[0])
+        task_tags.push(tags[0].clone())
       }
       // Add the "newest" tag.
       if x >= 4 {
-        task_tags.push(tags[x / 4])
+        task_tags.push(tags[x / 4].clone())
       }
       // Add all previous tags.
       if x >= 8 && x % 4 >= 2 {
@@ -150,6 +189,7 @@ pub fn make_tasks_with_tags(count: usize) -> (Vec<SerTag>, Vec<SerTemplate>, Vec
       SerTask {
         summary: format!("{}", x + 1),
         tags: task_tags,
+        ..Default::default()
       }
     })
     .collect();
@@ -0,0 +1,495 @@
+// bindings.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+use super::event::Key;
+
+
+/// The context in which a binding applies.
+///
+/// Overlays look up keys in their own context first and fall back to
+/// the bindings of the task list, so that navigation keys work the
+/// same everywhere.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Context {
+  /// The task list, i.e., the main view.
+  Tasks,
+  /// The overlay listing key bindings.
+  Help,
+  /// The overlay for editing the tags of a task.
+  Tags,
+  /// The overlay for managing tag templates.
+  TagManager,
+  /// The overlay for browsing the history.
+  History,
+}
+
+impl Context {
+  /// Retrieve a human readable name of the context, if it is not the
+  /// main one.
+  fn title(&self) -> Option<&'static str> {
+    match self {
+      Context::Tasks => None,
+      Context::Help => Some("Key bindings"),
+      Context::Tags => Some("Tag editor"),
+      Context::TagManager => Some("Tag manager"),
+      Context::History => Some("History"),
+    }
+  }
+}
+
+
+/// An action that can be bound to a key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+  /// Add a new task.
+  AddTask,
+  /// Edit the selected task.
+  EditTask,
+  /// Delete the selected task.
+  RemoveTask,
+  /// Set the due date of the selected task.
+  SetDueDate,
+  /// Set the recurrence rule of the selected task.
+  SetRecurrence,
+  /// Edit the tags of the selected task.
+  EditTags,
+  /// Rename, merge, or delete tag templates.
+  ManageTags,
+  /// Browse the history of saved versions.
+  ShowHistory,
+  /// Move all completed tasks into the archive.
+  ArchiveTasks,
+  /// Restore the selected task from the archive.
+  RestoreTask,
+  /// Mark the selected task as the one to block other tasks with.
+  MarkBlocker,
+  /// Toggle whether the selected task is blocked by the marked one.
+  ToggleBlocked,
+  /// Move the task selection down.
+  SelectNextTask,
+  /// Move the task selection up.
+  SelectPreviousTask,
+  /// Move the selected task down.
+  MoveTaskDown,
+  /// Move the selected task up.
+  MoveTaskUp,
+  /// Make the selected task a subtask of the one above.
+  IndentTask,
+  /// Make the selected task a sibling of its parent.
+  OutdentTask,
+  /// Show or hide the subtasks of the selected task.
+  ToggleCollapsed,
+  /// Select the first task on the current tab.
+  SelectFirstTask,
+  /// Select the last task on the current tab.
+  SelectLastTask,
+  /// Toggle the completion state of the selected task.
+  ToggleComplete,
+  /// Toggle the completion state of the selected task and its subtasks.
+  ToggleCompleteWithSubtasks,
+  /// Raise the priority of the selected task.
+  RaisePriority,
+  /// Lower the priority of the selected task.
+  LowerPriority,
+  /// Toggle sorting the tasks of the current tab by priority.
+  SortByPriority,
+  /// Select the tab to the left.
+  SelectTabLeft,
+  /// Select the tab to the right.
+  SelectTabRight,
+  /// Select the tab with the given (zero based) index.
+  SelectTab(usize),
+  /// Select the last tab.
+  SelectLastTab,
+  /// Select the previously selected tab.
+  SelectPreviousTab,
+  /// Start a forward task search.
+  SearchForward,
+  /// Start a backward task search.
+  SearchBackward,
+  /// Continue the task search forward.
+  SearchNext,
+  /// Continue the task search backward.
+  SearchPrevious,
+  /// Narrow down the tasks on the current tab.
+  FilterTasks,
+  /// Remove the narrowing of the tasks on the current tab.
+  ClearFilter,
+  /// Show the key bindings.
+  Help,
+  /// Save tasks to file.
+  Save,
+  /// Quit the program.
+  Quit,
+  /// Move the selection of an overlay down.
+  SelectNext,
+  /// Move the selection of an overlay up.
+  SelectPrevious,
+  /// Close an overlay, discarding changes.
+  Close,
+  /// Go back a level, closing the overlay from the topmost one.
+  Back,
+  /// Toggle the selected tag.
+  ToggleTag,
+  /// Edit the value of the selected tag.
+  EditTagValue,
+  /// Apply the tag changes and close the overlay.
+  ApplyTags,
+  /// Rename the selected tag template.
+  RenameTag,
+  /// Merge the selected tag template into another one.
+  MergeTag,
+  /// Delete the selected tag template.
+  DeleteTag,
+  /// Open the selected version or restore the selected task.
+  OpenEntry,
+}
+
+impl Action {
+  /// Check whether the action modifies the tasks of a tab.
+  pub fn modifies_tasks(&self) -> bool {
+    matches!(
+      self,
+      Action::AddTask |
+      Action::EditTask |
+      Action::RemoveTask |
+      Action::SetDueDate |
+      Action::SetRecurrence |
+      Action::EditTags |
+      Action::MarkBlocker |
+      Action::ToggleBlocked |
+      Action::MoveTaskDown |
+      Action::MoveTaskUp |
+      Action::IndentTask |
+      Action::OutdentTask |
+      Action::ToggleCollapsed |
+      Action::ToggleComplete |
+      Action::ToggleCompleteWithSubtasks |
+      Action::RaisePriority |
+      Action::LowerPriority
+    )
+  }
+
+  /// Retrieve a human readable description of the action.
+  pub fn description(&self) -> String {
+    match self {
+      Action::AddTask => "Add a new task".to_string(),
+      Action::EditTask => "Edit selected task".to_string(),
+      Action::RemoveTask => "Delete selected task".to_string(),
+      Action::SetDueDate => "Set due date of selected task".to_string(),
+      Action::SetRecurrence => "Set recurrence of selected task".to_string(),
+      Action::EditTags => "Edit tags of selected task".to_string(),
+      Action::ManageTags => "Manage tags".to_string(),
+      Action::ShowHistory => "Browse history and restore tasks".to_string(),
+      Action::ArchiveTasks => "Archive completed tasks".to_string(),
+      Action::RestoreTask => "Restore selected task from archive".to_string(),
+      Action::MarkBlocker => "Mark selected task as blocker".to_string(),
+      Action::ToggleBlocked => "Toggle whether selected task is blocked by marked one".to_string(),
+      Action::SelectNextTask => "Move task selection down".to_string(),
+      Action::SelectPreviousTask => "Move task selection up".to_string(),
+      Action::MoveTaskDown => "Move selected task down".to_string(),
+      Action::MoveTaskUp => "Move selected task up".to_string(),
+      Action::IndentTask => "Make selected task a subtask of the one above".to_string(),
+      Action::OutdentTask => "Make selected task a sibling of its parent".to_string(),
+      Action::ToggleCollapsed => "Show or hide subtasks of selected task".to_string(),
+      Action::SelectFirstTask => "Select first task on the current tab".to_string(),
+      Action::SelectLastTask => "Select last task on the current tab".to_string(),
+      Action::ToggleComplete => "Toggle completion state of selected task".to_string(),
+      Action::ToggleCompleteWithSubtasks => {
+        "Toggle completion state of selected task and its subtasks".to_string()
+      },
+      Action::RaisePriority => "Raise priority of selected task".to_string(),
+      Action::LowerPriority => "Lower priority of selected task".to_string(),
+      Action::SortByPriority => "Toggle sorting current tab by priority".to_string(),
+      Action::SelectTabLeft => "Select tab to the left".to_string(),
+      Action::SelectTabRight => "Select tab to the right".to_string(),
+      Action::SelectTab(idx) => format!("Select tab #{}", idx + 1),
+      Action::SelectLastTab => "Select last tab".to_string(),
+      Action::SelectPreviousTab => "Select previous tab".to_string(),
+      Action::SearchForward => "Start task search forward".to_string(),
+      Action::SearchBackward => "Start task search backward".to_string(),
+      Action::SearchNext => "Continue task search forward".to_string(),
+      Action::SearchPrevious => "Continue task search backward".to_string(),
+      Action::FilterTasks => "Filter tasks on the current tab".to_string(),
+      Action::ClearFilter => "Clear filter of the current tab".to_string(),
+      Action::Help => "Show key bindings".to_string(),
+      Action::Save => "Save tasks to file".to_string(),
+      Action::Quit => "Quit program".to_string(),
+      Action::SelectNext => "Move selection down".to_string(),
+      Action::SelectPrevious => "Move selection up".to_string(),
+      Action::Close => "Close overlay".to_string(),
+      Action::Back => "Go back, or close overlay".to_string(),
+      Action::ToggleTag => "Toggle selected tag".to_string(),
+      Action::EditTagValue => "Edit value of selected tag".to_string(),
+      Action::ApplyTags => "Apply changes and close overlay".to_string(),
+      Action::RenameTag => "Rename selected tag".to_string(),
+      Action::MergeTag => "Merge selected tag into another one".to_string(),
+      Action::DeleteTag => "Delete selected tag".to_string(),
+      Action::OpenEntry => "Show tasks of selected version, or restore selected task".to_string(),
+    }
+  }
+}
+
+
+/// Retrieve a human readable name of a key.
+pub fn key_name(key: Key) -> String {
+  match key {
+    Key::Char(' ') => "Space".to_string(),
+    Key::Char('\n') => "Return".to_string(),
+    Key::Char('\t') => "Tab".to_string(),
+    Key::Char(c) => c.to_string(),
+    Key::Alt(c) => format!("Alt-{}", c),
+    Key::Ctrl(c) => format!("Ctrl-{}", c),
+    Key::F(n) => format!("F{}", n),
+    Key::Backspace => "Backspace".to_string(),
+    Key::Left => "Left".to_string(),
+    Key::Right => "Right".to_string(),
+    Key::Up => "Up".to_string(),
+    Key::Down => "Down".to_string(),
+    Key::Home => "Home".to_string(),
+    Key::End => "End".to_string(),
+    Key::PageUp => "PageUp".to_string(),
+    Key::PageDown => "PageDown".to_string(),
+    Key::BackTab => "BackTab".to_string(),
+    Key::Delete => "Delete".to_string(),
+    Key::Insert => "Insert".to_string(),
+    Key::Esc => "Esc".to_string(),
+    _ => format!("{:?}", key),
+  }
+}
+
+
+/// A table mapping keys to the actions they trigger.
+#[derive(Debug)]
+pub struct Bindings {
+  bindings: Vec<(Context, Key, Action)>,
+}
+
+impl Bindings {
+  /// Retrieve the action bound to the given key in the task list, if
+  /// any.
+  pub fn action(&self, key: Key) -> Option<Action> {
+    self.action_in(Context::Tasks, key)
+  }
+
+  /// Retrieve the action bound to the given key in the given context,
+  /// falling back to the bindings of the task list.
+  pub fn action_in(&self, context: Context, key: Key) -> Option<Action> {
+    let find = |context| {
+      self
+        .bindings
+        .iter()
+        .find(|(c, k, _)| *c == context && *k == key)
+        .map(|(_, _, action)| *action)
+    };
+    find(context).or_else(|| find(Context::Tasks))
+  }
+
+  /// Retrieve an iterator over all bindings, in definition order.
+  pub fn iter(&self) -> impl Iterator<Item=&(Context, Key, Action)> {
+    self.bindings.iter()
+  }
+
+  /// Retrieve a list of all actions along with the names of the keys
+  /// they are bound to.
+  ///
+  /// Actions are reported in the order of their first binding, with
+  /// the bindings of each overlay following in a separate section
+  /// introduced by a line naming it.
+  pub fn describe(&self) -> Vec<(String, String)> {
+    let mut contexts = Vec::<(Context, Vec<(Action, Vec<Key>)>)>::new();
+    for (context, key, action) in self.iter() {
+      let actions = match contexts.iter_mut().position(|(c, _)| c == context) {
+        Some(idx) => &mut contexts[idx].1,
+        None => {
+          contexts.push((*context, Vec::new()));
+          &mut contexts.last_mut().unwrap().1
+        },
+      };
+      match actions.iter_mut().find(|(a, _)| a == action) {
+        Some((_, keys)) => keys.push(*key),
+        None => actions.push((*action, vec![*key])),
+      }
+    }
+
+    let mut lines = Vec::new();
+    for (context, actions) in contexts {
+      if let Some(title) = context.title() {
+        if !lines.is_empty() {
+          lines.push((String::new(), String::new()));
+        }
+        lines.push((String::new(), format!("{}:", title)));
+      }
+
+      lines.extend(actions.into_iter().map(|(action, keys)| {
+        let keys = keys
+          .into_iter()
+          .map(key_name)
+          .collect::<Vec<_>>()
+          .join(", ");
+        (keys, action.description())
+      }));
+    }
+    lines
+  }
+}
+
+impl Default for Bindings {
+  /// Create the default set of key bindings.
+  fn default() -> Self {
+    let mut bindings = vec![
+      (Key::Char('a'), Action::AddTask),
+      (Key::Char('e'), Action::EditTask),
+      (Key::Char('d'), Action::RemoveTask),
+      (Key::Char('D'), Action::SetDueDate),
+      (Key::Char('R'), Action::SetRecurrence),
+      (Key::Char('t'), Action::EditTags),
+      (Key::Char('T'), Action::ManageTags),
+      (Key::Char('L'), Action::ShowHistory),
+      (Key::Char('A'), Action::ArchiveTasks),
+      (Key::Char('U'), Action::RestoreTask),
+      (Key::Char('b'), Action::MarkBlocker),
+      (Key::Char('B'), Action::ToggleBlocked),
+      (Key::Char('j'), Action::SelectNextTask),
+      (Key::Char('k'), Action::SelectPreviousTask),
+      (Key::Char('J'), Action::MoveTaskDown),
+      (Key::Char('K'), Action::MoveTaskUp),
+      (Key::Char('>'), Action::IndentTask),
+      (Key::Char('<'), Action::OutdentTask),
+      (Key::Char('z'), Action::ToggleCollapsed),
+      (Key::Char('g'), Action::SelectFirstTask),
+      (Key::Char('G'), Action::SelectLastTask),
+      (Key::Char(' '), Action::ToggleComplete),
+      (Key::Char('X'), Action::ToggleCompleteWithSubtasks),
+      (Key::Char('+'), Action::RaisePriority),
+      (Key::Char('-'), Action::LowerPriority),
+      (Key::Char('P'), Action::SortByPriority),
+      (Key::Char('h'), Action::SelectTabLeft),
+      (Key::Char('l'), Action::SelectTabRight),
+    ];
+    bindings.extend((1..=9).map(|x| {
+      let c = (b'0' + x as u8) as char;
+      (Key::Char(c), Action::SelectTab(x - 1))
+    }));
+    bindings.extend(vec![
+      (Key::Char('0'), Action::SelectLastTab),
+      (Key::Char('`'), Action::SelectPreviousTab),
+      (Key::Char('/'), Action::SearchForward),
+      (Key::Char('?'), Action::SearchBackward),
+      (Key::Char('n'), Action::SearchNext),
+      (Key::Char('N'), Action::SearchPrevious),
+      (Key::Char('f'), Action::FilterTasks),
+      (Key::Char('F'), Action::ClearFilter),
+      (Key::Char('H'), Action::Help),
+      (Key::F(1), Action::Help),
+      (Key::Char('w'), Action::Save),
+      (Key::Char('q'), Action::Quit),
+    ]);
+
+    let mut bindings = bindings
+      .into_iter()
+      .map(|(key, action)| (Context::Tasks, key, action))
+      .collect::<Vec<_>>();
+    bindings.extend(vec![
+      (Context::Help, Key::Down, Action::SelectNext),
+      (Context::Help, Key::Up, Action::SelectPrevious),
+      (Context::Help, Key::Esc, Action::Close),
+      (Context::Tags, Key::Down, Action::SelectNext),
+      (Context::Tags, Key::Up, Action::SelectPrevious),
+      (Context::Tags, Key::Char(' '), Action::ToggleTag),
+      (Context::Tags, Key::Char('='), Action::EditTagValue),
+      (Context::Tags, Key::Char('\n'), Action::ApplyTags),
+      (Context::Tags, Key::Esc, Action::Close),
+      (Context::TagManager, Key::Down, Action::SelectNext),
+      (Context::TagManager, Key::Up, Action::SelectPrevious),
+      (Context::TagManager, Key::Char('r'), Action::RenameTag),
+      (Context::TagManager, Key::Char('m'), Action::MergeTag),
+      (Context::TagManager, Key::Char('d'), Action::DeleteTag),
+      (Context::TagManager, Key::Esc, Action::Close),
+      (Context::History, Key::Down, Action::SelectNext),
+      (Context::History, Key::Up, Action::SelectPrevious),
+      (Context::History, Key::Char('\n'), Action::OpenEntry),
+      (Context::History, Key::Esc, Action::Back),
+    ]);
+
+    Bindings {
+      bindings: bindings,
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  #[test]
+  fn default_bindings_are_unique() {
+    let bindings = Bindings::default();
+    for (i, (context, key, _)) in bindings.iter().enumerate() {
+      let duplicate = bindings
+        .iter()
+        .skip(i + 1)
+        .any(|(c, k, _)| c == context && k == key);
+      assert!(!duplicate, "{:?}", key);
+    }
+  }
+
+  #[test]
+  fn lookup_action() {
+    let bindings = Bindings::default();
+    assert_eq!(bindings.action(Key::Char('a')), Some(Action::AddTask));
+    assert_eq!(bindings.action(Key::Char('3')), Some(Action::SelectTab(2)));
+    assert_eq!(bindings.action(Key::F(1)), Some(Action::Help));
+    assert_eq!(bindings.action(Key::Char('Z')), None);
+  }
+
+  #[test]
+  fn lookup_action_in_context() {
+    let bindings = Bindings::default();
+    assert_eq!(bindings.action_in(Context::Tags, Key::Char(' ')), Some(Action::ToggleTag));
+    assert_eq!(bindings.action(Key::Char(' ')), Some(Action::ToggleComplete));
+    // Keys not bound in a context fall back to the task list ones.
+    assert_eq!(bindings.action_in(Context::Tags, Key::Char('j')), Some(Action::SelectNextTask));
+    assert_eq!(bindings.action(Key::Esc), None);
+  }
+
+  #[test]
+  fn describe_bindings() {
+    let bindings = Bindings {
+      bindings: vec![
+        (Context::Tasks, Key::Char(' '), Action::ToggleComplete),
+        (Context::Tasks, Key::Char('H'), Action::Help),
+        (Context::Tags, Key::Char(' '), Action::ToggleTag),
+        (Context::Tasks, Key::Char('q'), Action::Quit),
+        (Context::Tasks, Key::F(1), Action::Help),
+      ],
+    };
+    let expected = vec![
+      ("Space".to_string(), "Toggle completion state of selected task".to_string()),
+      ("H, F1".to_string(), "Show key bindings".to_string()),
+      ("q".to_string(), "Quit program".to_string()),
+      (String::new(), String::new()),
+      (String::new(), "Tag editor:".to_string()),
+      ("Space".to_string(), "Toggle selected tag".to_string()),
+    ];
+    assert_eq!(bindings.describe(), expected);
+  }
+}
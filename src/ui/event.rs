@@ -28,6 +28,10 @@ use super::termui::TermUiEvent;
 
 /// A key as used by the UI.
 pub use termion::event::Key;
+/// A mouse button as used by the UI.
+pub use termion::event::MouseButton;
+/// A mouse event as used by the UI.
+pub use termion::event::MouseEvent;
 
 
 /// An event as used by the UI.
@@ -37,6 +41,7 @@ pub enum Event {
   Key(Key, ()),
   #[cfg(feature = "readline")]
   Key(Key, Vec<u8>),
+  Mouse(MouseEvent),
 }
 
 impl From<u8> for Event {
@@ -0,0 +1,174 @@
+// help_box.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+use std::any::Any;
+use std::cmp::max;
+use std::cmp::min;
+use std::rc::Rc;
+
+use gui::derive::Widget;
+use gui::Handleable;
+use gui::Id;
+use gui::MutCap;
+use gui::UiEvent;
+use gui::UiEvents;
+
+use super::bindings::Action;
+use super::bindings::Bindings;
+use super::bindings::Context;
+use super::event::Event;
+use super::event::EventUpdate;
+use super::event::MouseButton;
+use super::event::MouseEvent;
+use super::termui::TermUiEvent;
+
+
+/// Sanitize a selection index.
+fn sanitize_selection(selection: isize, count: usize) -> usize {
+  if count == 0 {
+    0
+  } else {
+    max(0, min(count as isize - 1, selection)) as usize
+  }
+}
+
+
+/// A widget displaying the key bindings in effect on top of everything
+/// else.
+#[derive(Debug, Widget)]
+#[gui(Event = "Event")]
+pub struct HelpBox {
+  id: Id,
+  prev_focused: Option<Id>,
+  bindings: Rc<Bindings>,
+  selection: isize,
+}
+
+impl HelpBox {
+  /// Create a new `HelpBox` widget.
+  ///
+  /// The widget starts out hidden.
+  pub fn new(id: Id, cap: &mut dyn MutCap<Event>, bindings: Rc<Bindings>) -> Self {
+    cap.hide(id);
+
+    HelpBox {
+      id: id,
+      prev_focused: None,
+      bindings: bindings,
+      selection: 0,
+    }
+  }
+
+  /// Retrieve the lines to display, each consisting of the names of
+  /// the keys and the description of the action they are bound to.
+  pub fn lines(&self) -> Vec<(String, String)> {
+    self.bindings.describe()
+  }
+
+  /// Retrieve the index of the currently selected line.
+  pub fn selection(&self) -> usize {
+    sanitize_selection(self.selection, self.lines().len())
+  }
+
+  /// Change the currently selected line.
+  fn set_select(&mut self, selection: isize) -> bool {
+    let count = self.lines().len();
+    let old_selection = sanitize_selection(self.selection, count);
+    let new_selection = sanitize_selection(selection, count);
+
+    self.selection = new_selection as isize;
+    new_selection != old_selection
+  }
+
+  /// Change the currently selected line by the given amount.
+  fn select(&mut self, change: isize) -> bool {
+    let selection = self.selection() as isize;
+    self.set_select(selection + change)
+  }
+
+  /// Show the widget and take over the focus.
+  fn show(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    self.prev_focused = cap.focused();
+    cap.focus(self.id);
+    (None as Option<Event>).update()
+  }
+
+  /// Hide the widget and hand the focus back to where it was before.
+  fn hide(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    cap.hide(self.id);
+    if let Some(prev_focused) = self.prev_focused.take() {
+      cap.focus(prev_focused);
+    }
+    (None as Option<Event>).update()
+  }
+}
+
+impl Handleable<Event> for HelpBox {
+  /// Check for new input and react to it.
+  fn handle(&mut self, event: Event, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    // While we are displayed we swallow all input, so that nothing
+    // happens behind our back.
+    match event {
+      Event::Key(key, _) => {
+        match self.bindings.action_in(Context::Help, key) {
+          Some(Action::Close) |
+          Some(Action::Help) |
+          Some(Action::Quit) => self.hide(cap),
+          Some(Action::SelectNext) |
+          Some(Action::SelectNextTask) => {
+            (None as Option<Event>).maybe_update(self.select(1))
+          },
+          Some(Action::SelectPrevious) |
+          Some(Action::SelectPreviousTask) => {
+            (None as Option<Event>).maybe_update(self.select(-1))
+          },
+          Some(Action::SelectFirstTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(0))
+          },
+          Some(Action::SelectLastTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(isize::MAX))
+          },
+          _ => None,
+        }
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(1))
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(-1))
+      },
+      Event::Mouse(..) => None,
+    }
+  }
+
+  /// Handle a custom event.
+  fn handle_custom(&mut self,
+                   event: Box<dyn Any>,
+                   cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match event.downcast::<TermUiEvent>() {
+      Ok(e) => {
+        match *e {
+          TermUiEvent::ShowHelp => self.show(cap),
+          _ => Some(UiEvent::Custom(e).into()),
+        }
+      },
+      Err(e) => panic!("Received unexpected custom event: {:?}", e),
+    }
+  }
+}
@@ -0,0 +1,281 @@
+// history_view.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+use std::any::Any;
+use std::cmp::max;
+use std::cmp::min;
+use std::rc::Rc;
+
+use cell::RefCell;
+
+use gui::derive::Widget;
+use gui::Handleable;
+use gui::Id;
+use gui::MutCap;
+use gui::UiEvent;
+use gui::UiEvents;
+
+use notnow::history::History;
+use notnow::history::Version;
+use notnow::state::TaskState;
+use notnow::tags::Templates;
+use notnow::tasks::Task;
+use notnow::tasks::Tasks;
+
+use super::bindings::Action;
+use super::bindings::Bindings;
+use super::bindings::Context;
+use super::event::Event;
+use super::event::EventUpdate;
+use super::event::MouseButton;
+use super::event::MouseEvent;
+use super::in_out::InOut;
+use super::termui::TermUiEvent;
+
+
+/// Sanitize a selection index.
+fn sanitize_selection(selection: isize, count: usize) -> usize {
+  if count == 0 {
+    0
+  } else {
+    max(0, min(count as isize - 1, selection)) as usize
+  }
+}
+
+
+/// A widget for browsing the versions recorded in the history,
+/// displayed on top of everything else.
+///
+/// Selecting a version lists the tasks as of that version, any of
+/// which can be restored into the current state.
+#[derive(Debug, Widget)]
+#[gui(Event = "Event")]
+pub struct HistoryView {
+  id: Id,
+  prev_focused: Option<Id>,
+  bindings: Rc<Bindings>,
+  history: Option<Rc<History>>,
+  templates: Rc<Templates>,
+  tasks: Rc<RefCell<Tasks>>,
+  versions: Vec<Version>,
+  /// The index of the version whose tasks are listed, along with
+  /// these tasks.
+  version: Option<(usize, Vec<Task>)>,
+  selection: isize,
+}
+
+impl HistoryView {
+  /// Create a new `HistoryView` widget.
+  ///
+  /// The widget starts out hidden.
+  pub fn new(id: Id,
+             cap: &mut dyn MutCap<Event>,
+             task_state: &TaskState,
+             bindings: Rc<Bindings>) -> Self {
+    cap.hide(id);
+
+    HistoryView {
+      id: id,
+      prev_focused: None,
+      bindings: bindings,
+      history: task_state.history(),
+      templates: task_state.templates(),
+      tasks: task_state.tasks(),
+      versions: Vec::new(),
+      version: None,
+      selection: 0,
+    }
+  }
+
+  /// Retrieve the listed versions.
+  pub fn versions(&self) -> &[Version] {
+    &self.versions
+  }
+
+  /// Retrieve the version whose tasks are listed, if any, along with
+  /// these tasks.
+  pub fn version(&self) -> Option<(&Version, &[Task])> {
+    self
+      .version
+      .as_ref()
+      .map(|(idx, tasks)| (&self.versions[*idx], tasks.as_slice()))
+  }
+
+  /// Retrieve the number of listed entries.
+  fn count(&self) -> usize {
+    match &self.version {
+      Some((_, tasks)) => tasks.len(),
+      None => self.versions.len(),
+    }
+  }
+
+  /// Retrieve the index of the currently selected entry.
+  pub fn selection(&self) -> usize {
+    sanitize_selection(self.selection, self.count())
+  }
+
+  /// Change the currently selected entry.
+  fn set_select(&mut self, selection: isize) -> bool {
+    let count = self.count();
+    let old_selection = sanitize_selection(self.selection, count);
+    let new_selection = sanitize_selection(selection, count);
+
+    self.selection = new_selection as isize;
+    new_selection != old_selection
+  }
+
+  /// Change the currently selected entry by the given amount.
+  fn select(&mut self, change: isize) -> bool {
+    let selection = self.selection() as isize;
+    self.set_select(selection + change)
+  }
+
+  /// Create an event reporting the given error.
+  fn error(error: String) -> Option<UiEvents<Event>> {
+    let event = TermUiEvent::SetInOut(InOut::Error(error));
+    Some(UiEvent::Custom(Box::new(event))).update()
+  }
+
+  /// Show the widget listing all versions and take over the focus.
+  fn show(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    let history = match &self.history {
+      Some(history) => history,
+      None => return Self::error("History is not enabled".to_string()),
+    };
+    self.versions = match history.versions() {
+      Ok(versions) => versions,
+      Err(err) => return Self::error(format!("{}", err)),
+    };
+    self.version = None;
+    self.selection = 0;
+
+    self.prev_focused = cap.focused();
+    cap.focus(self.id);
+    (None as Option<Event>).update()
+  }
+
+  /// Hide the widget and hand the focus back to where it was before.
+  fn hide(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    cap.hide(self.id);
+    self.versions.clear();
+    self.version = None;
+
+    if let Some(prev_focused) = self.prev_focused.take() {
+      cap.focus(prev_focused);
+    }
+    (None as Option<Event>).update()
+  }
+
+  /// Go back from listing the tasks of a version to listing all
+  /// versions, or hide the widget if that is what we list already.
+  fn back(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match self.version.take() {
+      Some((idx, _)) => {
+        self.selection = idx as isize;
+        (None as Option<Event>).update()
+      },
+      None => self.hide(cap),
+    }
+  }
+
+  /// List the tasks of the selected version, or restore the selected
+  /// task if these are listed already.
+  fn enter(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match &self.version {
+      Some((_, tasks)) => {
+        let task = tasks.get(self.selection())?.clone();
+        let _ = self.tasks.borrow_mut().adopt(task);
+        self.hide(cap)
+      },
+      None => {
+        let idx = self.selection();
+        let version = self.versions.get(idx)?;
+        // We only get to see versions if the history is enabled.
+        let history = self.history.as_ref()?;
+        match history.tasks(&version.rev, &self.templates) {
+          Ok(tasks) => {
+            self.version = Some((idx, tasks));
+            self.selection = 0;
+            (None as Option<Event>).update()
+          },
+          Err(err) => Self::error(format!("{}", err)),
+        }
+      },
+    }
+  }
+
+  /// Handle a custom event.
+  fn handle_custom_event(&mut self,
+                         event: Box<TermUiEvent>,
+                         cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match *event {
+      TermUiEvent::ShowHistory => self.show(cap),
+      _ => Some(UiEvent::Custom(event).into()),
+    }
+  }
+}
+
+impl Handleable<Event> for HistoryView {
+  /// Check for new input and react to it.
+  fn handle(&mut self, event: Event, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    // While we are displayed we swallow all input, so that nothing
+    // happens behind our back.
+    match event {
+      Event::Key(key, _) => {
+        match self.bindings.action_in(Context::History, key) {
+          Some(Action::Back) |
+          Some(Action::Quit) => self.back(cap),
+          Some(Action::OpenEntry) => self.enter(cap),
+          Some(Action::SelectNext) |
+          Some(Action::SelectNextTask) => {
+            (None as Option<Event>).maybe_update(self.select(1))
+          },
+          Some(Action::SelectPrevious) |
+          Some(Action::SelectPreviousTask) => {
+            (None as Option<Event>).maybe_update(self.select(-1))
+          },
+          Some(Action::SelectFirstTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(0))
+          },
+          Some(Action::SelectLastTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(isize::MAX))
+          },
+          _ => None,
+        }
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(1))
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(-1))
+      },
+      Event::Mouse(..) => None,
+    }
+  }
+
+  /// Handle a custom event.
+  fn handle_custom(&mut self,
+                   event: Box<dyn Any>,
+                   cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match event.downcast::<TermUiEvent>() {
+      Ok(e) => self.handle_custom_event(e, cap),
+      Err(e) => panic!("Received unexpected custom event: {:?}", e),
+    }
+  }
+}
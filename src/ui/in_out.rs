@@ -108,6 +108,8 @@ pub struct InOutArea {
   id: Id,
   prev_focused: Option<Id>,
   in_out: InOutState,
+  /// Whether to report changes to the text being entered.
+  incremental: bool,
   #[cfg(feature = "readline")]
   readline: Readline,
 }
@@ -123,11 +125,20 @@ impl InOutArea {
       id: id,
       prev_focused: None,
       in_out: Default::default(),
+      incremental: false,
       #[cfg(feature = "readline")]
       readline: Readline::new(),
     }
   }
 
+  /// Create a new input/output area object initially displaying the
+  /// given `InOut` state.
+  pub fn with_in_out(id: Id, cap: &mut dyn MutCap<Event>, in_out: InOut) -> Self {
+    let mut area = Self::new(id, cap);
+    area.in_out.set(in_out);
+    area
+  }
+
   /// Conditionally change the `InOut` state of the widget.
   fn change_state(&mut self, in_out: InOut) -> Option<UiEvents<Event>> {
     // We received a request to change the state. Unconditionally bump
@@ -168,7 +179,8 @@ impl InOutArea {
       // about what happened and can determine whether we ultimately
       // want to set our state to "Clear" or not.
       match event {
-        Event::Key(..) => {
+        Event::Key(..) |
+        Event::Mouse(..) => {
           let event = Box::new(TermUiEvent::ClearInOut(in_out.in_out.gen));
           Some(UiEvent::Directed(in_out.id, event).into())
         },
@@ -190,10 +202,17 @@ impl InOutArea {
           debug_assert!(idx <= s.len());
 
           self.prev_focused = cap.focused();
+          self.incremental = false;
           cap.focus(self.id);
         };
         self.change_state(in_out)
       },
+      TermUiEvent::SetIncrementalInput(s, idx) => {
+        let event = Box::new(TermUiEvent::SetInOut(InOut::Input(s, idx)));
+        let update = self.handle_custom_event(event, cap);
+        self.incremental = true;
+        update
+      },
       TermUiEvent::ClearInOut(gen) => {
         // We only change our state to "Clear" if the generation number
         // is still the same, meaning that we did not change our state
@@ -219,10 +238,29 @@ impl InOutArea {
     }
   }
 
+  /// Change the text being entered, reporting it to the widget that
+  /// requested the input if it asked for that.
+  fn change_input(&mut self, s: String, idx: usize) -> Option<UiEvents<Event>> {
+    let changed = match self.in_out.get() {
+      InOut::Input(old, _) => *old != s,
+      _ => true,
+    };
+
+    match self.prev_focused {
+      Some(widget) if self.incremental && changed => {
+        let event = Box::new(TermUiEvent::ChangedText(s.clone()));
+        let update = self.change_state(InOut::Input(s, idx));
+        Some(ChainEvent::from(UiEvent::Directed(widget, event))).chain(update)
+      },
+      _ => self.change_state(InOut::Input(s, idx)),
+    }
+  }
+
   /// Finish text input by changing the internal state and emitting an event.
   fn finish_input(&mut self,
                   string: Option<String>,
                   cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    self.incremental = false;
     let update = self.change_state(InOut::Clear);
     let widget = self.restore_focus(cap);
     let event = if let Some(s) = string {
@@ -254,14 +292,14 @@ impl InOutArea {
       // would allow us to circumvent this restriction).
       Key::Char(c) if c.len_utf8() == 1 => {
         s.insert(idx, c);
-        self.change_state(InOut::Input(s, idx + 1))
+        self.change_input(s, idx + 1)
       },
       Key::Backspace => {
         if idx > 0 {
           let _ = s.remove(idx - 1);
           idx -= 1;
         }
-        self.change_state(InOut::Input(s, idx))
+        self.change_input(s, idx)
       },
       Key::Delete => {
         if idx < s.len() {
@@ -270,7 +308,7 @@ impl InOutArea {
             idx -= 1;
           }
         }
-        self.change_state(InOut::Input(s, idx))
+        self.change_input(s, idx)
       },
       Key::Left => {
         if idx > 0 {
@@ -342,7 +380,7 @@ impl InOutArea {
           self.readline = Readline::new();
           self.finish_input(None, cap)
         } else {
-          self.change_state(InOut::Input(s_.into_string().unwrap(), idx_))
+          self.change_input(s_.into_string().unwrap(), idx_)
         }
       },
     }
@@ -380,6 +418,8 @@ impl Handleable<Event> for InOutArea {
 
         self.handle_key(s, idx, key, &raw, cap)
       },
+      // While text is being entered we ignore the mouse.
+      Event::Mouse(..) => None,
     }
   }
 
@@ -0,0 +1,117 @@
+// layout.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+//! Infrastructure for mapping screen coordinates to UI elements.
+//!
+//! Only the renderer knows where exactly on the screen a tab or a task
+//! ended up. Widgets, on the other hand, are the ones that have to
+//! react to a mouse click. The `Layout` type bridges this gap: the
+//! renderer records the areas of all clickable elements while drawing
+//! them and the UI looks up the element below the mouse cursor once a
+//! click arrives.
+
+
+/// A UI element that can be clicked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target {
+  /// The tab with the given index in the `TabBar`.
+  Tab(usize),
+  /// The completion state marker of the task with the given index in
+  /// the currently displayed `TaskListBox`.
+  TaskState(usize),
+  /// The summary of the task with the given index in the currently
+  /// displayed `TaskListBox`.
+  Task(usize),
+}
+
+
+/// A single-line area on the screen.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Area {
+  x: u16,
+  y: u16,
+  w: u16,
+}
+
+impl Area {
+  /// Check whether the area contains the given position.
+  fn contains(&self, x: u16, y: u16) -> bool {
+    y == self.y && x >= self.x && x < self.x + self.w
+  }
+}
+
+
+/// A record of the screen areas occupied by clickable UI elements.
+///
+/// Coordinates are terminal coordinates, i.e., they are one-based and
+/// relative to the upper left corner of the screen, just like the ones
+/// reported for mouse events.
+#[derive(Debug, Default)]
+pub struct Layout {
+  areas: Vec<(Area, Target)>,
+}
+
+impl Layout {
+  /// Forget about all recorded areas.
+  pub fn clear(&mut self) {
+    self.areas.clear()
+  }
+
+  /// Record the area of a clickable element.
+  pub fn add(&mut self, x: u16, y: u16, w: u16, target: Target) {
+    self.areas.push((Area { x, y, w }, target))
+  }
+
+  /// Find the element at the given position, if any.
+  pub fn find(&self, x: u16, y: u16) -> Option<Target> {
+    self
+      .areas
+      .iter()
+      .find(|(area, _)| area.contains(x, y))
+      .map(|(_, target)| *target)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+
+  #[test]
+  fn find_target() {
+    let mut layout = Layout::default();
+    layout.add(2, 1, 30, Target::Tab(0));
+    layout.add(32, 1, 30, Target::Tab(1));
+    layout.add(4, 3, 3, Target::TaskState(0));
+    layout.add(8, 3, 20, Target::Task(0));
+
+    assert_eq!(layout.find(1, 1), None);
+    assert_eq!(layout.find(2, 1), Some(Target::Tab(0)));
+    assert_eq!(layout.find(31, 1), Some(Target::Tab(0)));
+    assert_eq!(layout.find(32, 1), Some(Target::Tab(1)));
+    assert_eq!(layout.find(6, 3), Some(Target::TaskState(0)));
+    assert_eq!(layout.find(7, 3), None);
+    assert_eq!(layout.find(27, 3), Some(Target::Task(0)));
+    assert_eq!(layout.find(8, 4), None);
+
+    layout.clear();
+    assert_eq!(layout.find(2, 1), None);
+  }
+}
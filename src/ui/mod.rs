@@ -17,10 +17,16 @@
 // * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
 // *************************************************************************
 
+pub mod bindings;
 pub mod event;
+pub mod help_box;
+pub mod history_view;
 pub mod in_out;
 pub mod iteration;
+pub mod layout;
 pub mod tab_bar;
+pub mod tag_box;
+pub mod tag_manager;
 pub mod task_list_box;
 pub mod term_renderer;
 pub mod termui;
@@ -22,6 +22,7 @@ use std::cmp::max;
 use std::cmp::min;
 use std::isize;
 use std::mem::replace;
+use std::rc::Rc;
 
 use gui::ChainEvent;
 use gui::derive::Widget;
@@ -32,14 +33,17 @@ use gui::MutCap;
 use gui::UiEvent;
 use gui::UiEvents;
 
-use crate::query::Query;
-use crate::state::TaskState;
+use notnow::query::Query;
+use notnow::search::Pattern;
+use notnow::state::TaskState;
 
+use super::bindings::Action;
+use super::bindings::Bindings;
 use super::event::Event;
 use super::event::EventUpdate;
-use super::event::Key;
 use super::in_out::InOut;
 use super::iteration::IterationState as IterationStateT;
+use super::layout::Target;
 use super::task_list_box::TaskListBox;
 use super::termui::TermUiEvent;
 
@@ -56,20 +60,16 @@ where
 {
   /// No search is currently in progress.
   Unset,
-  /// The input/output widget has been asked for the string to search
-  /// for, but at this point we don't have it. We only know whether to
-  /// search clock-wise or counter clock-wise (i.e., reverse).
-  Preparing(bool),
   /// A search was started but the state is currently being transferred
   /// to another widget.
   // Note that in principle we could use the Unset variant to fudge this
   // case, but we would like to assert different invariants when the
   // state is taken versus when it was never actually set.
   Taken,
-  /// The full state of a search. The first value is the (lowercased)
-  /// text to search for, the second one represents the task that was
-  /// selected last, and the third one represents the iteration state.
-  State(String, SearchState, T),
+  /// The full state of a search. The first value is the pattern to
+  /// search for, the second one represents the task that was selected
+  /// last, and the third one represents the iteration state.
+  State(Pattern, SearchState, T),
 }
 
 impl<T> SearchT<T>
@@ -80,27 +80,26 @@ where
   fn take(&mut self) -> SearchT<T> {
     replace(self, SearchT::Taken)
   }
-
-  /// Check whether the search is meant to be a reverse one or not.
-  ///
-  /// # Panics
-  ///
-  /// Panics if the search is in anything but the `SearchT::Preparing`
-  /// state.
-  fn is_reverse(&self) -> bool {
-    match *self {
-      SearchT::Preparing(reverse) => reverse,
-      SearchT::Unset |
-      SearchT::Taken |
-      SearchT::State(..) => panic!("invalid search state"),
-    }
-  }
 }
 
 /// A search as used by a `TabBar`.
 type Search = SearchT<IterationState>;
 
 
+/// The state of a search whose pattern is still being entered.
+#[derive(Debug)]
+struct SearchPrompt {
+  /// Whether to search clock-wise or counter clock-wise (i.e.,
+  /// reverse).
+  reverse: bool,
+  /// The tab selected when the search got started.
+  selection: isize,
+  /// The search in effect before, reinstated if the new one gets
+  /// canceled.
+  previous: Search,
+}
+
+
 /// An enum capturing the search behavior on an individual tab.
 #[derive(Debug, PartialEq)]
 pub enum SearchState {
@@ -151,10 +150,15 @@ fn sanitize_selection(selection: isize, count: usize) -> usize {
 #[gui(Event = "Event")]
 pub struct TabBar {
   id: Id,
-  tabs: Vec<(String, Id)>,
+  /// The tabs, i.e., their names, their widgets, and whether they are
+  /// temporarily narrowed down.
+  tabs: Vec<(String, Id, bool)>,
   selection: isize,
   prev_selection: isize,
   search: Search,
+  /// The search being prepared, if its pattern is being entered.
+  prompt: Option<SearchPrompt>,
+  bindings: Rc<Bindings>,
 }
 
 impl TabBar {
@@ -163,22 +167,29 @@ impl TabBar {
              cap: &mut dyn MutCap<Event>,
              task_state: &TaskState,
              queries: Vec<(Query, Option<usize>)>,
-             selected: Option<usize>) -> Self {
-    let count = queries.len();
+             selected: Option<usize>,
+             bindings: Rc<Bindings>) -> Self {
+    // The archive is always displayed on the last tab.
+    let count = queries.len() + 1;
     let selected = selected
       .map(|x| min(x, isize::MAX as usize))
       .unwrap_or(0) as isize;
     let selected = sanitize_selection(selected, count);
 
-    let tabs = queries
+    let mut tabs = queries
       .into_iter()
       .enumerate()
       .map(|(i, (query, task))| {
-        let name = query.name().to_string();
+        // Tabs restricted to a single task file indicate which one.
+        let name = match query.source() {
+          Some((_, file)) => format!("{} ({})", query.name(), file),
+          None => query.name().to_string(),
+        };
         let mut query = Some(query);
         let task_list = cap.add_widget(id, &mut |id, _cap| {
           let query = query.take().unwrap();
-          Box::new(TaskListBox::new(id, task_state.tasks(), query, task))
+          let bindings = bindings.clone();
+          Box::new(TaskListBox::new(id, task_state.tasks(), query, task, bindings))
         });
 
         if i == selected {
@@ -186,8 +197,20 @@ impl TabBar {
         } else {
           cap.hide(task_list);
         }
-        (name, task_list)
-      }).collect();
+        (name, task_list, false)
+      }).collect::<Vec<_>>();
+
+    let archive = cap.add_widget(id, &mut |id, _cap| {
+      let archive = task_state.archive();
+      let bindings = bindings.clone();
+      Box::new(TaskListBox::with_archive(id, archive, task_state.tasks(), bindings))
+    });
+    if tabs.len() == selected {
+      cap.focus(archive);
+    } else {
+      cap.hide(archive);
+    }
+    tabs.push(("archive".to_string(), archive, false));
 
     TabBar {
       id: id,
@@ -195,12 +218,14 @@ impl TabBar {
       selection: selected as isize,
       prev_selection: selected as isize,
       search: SearchT::Unset,
+      prompt: None,
+      bindings: bindings,
     }
   }
 
   /// Handle a `TermUiEvent::SearchTask` event.
   fn handle_search_task(&mut self,
-                        string: String,
+                        pattern: Pattern,
                         search_state: SearchState,
                         mut iter_state: IterationState) -> Option<UiEvents<Event>> {
     match self.search {
@@ -216,11 +241,17 @@ impl TabBar {
         //    case we just store the search state and wait for
         //    additional user input to select the next one or similar.
         if iter_state.has_cycled(self.tabs.iter().len()) {
-          self.search = SearchT::State(string.clone(), search_state, iter_state);
-
-          let error = format!("Text '{}' not found", string).to_string();
-          let event = TermUiEvent::SetInOut(InOut::Error(error));
-          Some(UiEvent::Custom(Box::new(event)).into())
+          let error = format!("Text '{}' not found", pattern);
+          self.search = SearchT::State(pattern, search_state, iter_state);
+
+          // While the pattern is still being entered not finding a
+          // match is no error worth reporting.
+          if self.prompt.is_none() {
+            let event = TermUiEvent::SetInOut(InOut::Error(error));
+            Some(UiEvent::Custom(Box::new(event)).into())
+          } else {
+            None
+          }
         } else if iter_state.has_advanced() {
           debug_assert!(search_state.is_first());
 
@@ -228,21 +259,51 @@ impl TabBar {
           let new_idx = iter_state.normalize(iter);
           let tab = self.tabs[new_idx].1;
 
-          let event = TermUiEvent::SearchTask(string, SearchState::First, iter_state);
+          let event = TermUiEvent::SearchTask(pattern, SearchState::First, iter_state);
           let event = UiEvent::Returnable(self.id, tab, Box::new(event));
           Some(ChainEvent::Event(event))
         } else {
           iter_state.reset_cycled();
-          self.search = SearchT::State(string, search_state, iter_state);
+          self.search = SearchT::State(pattern, search_state, iter_state);
           None
         }
       },
       SearchT::Unset |
-      SearchT::Preparing(..) |
       SearchT::State(..) => panic!("invalid search state"),
     }
   }
 
+  /// Start a search for the given pattern at the selected task.
+  fn search_selected(&self, pattern: Pattern, reverse: bool) -> UiEvents<Event> {
+    let tab = self.selected_tab();
+    let mut state = IterationState::new(tab);
+    state.reverse(reverse);
+
+    let event = TermUiEvent::SearchTask(pattern, SearchState::Current, state);
+    UiEvent::Returnable(self.id, tab, Box::new(event)).into()
+  }
+
+  /// Send an event to each of the tabs.
+  fn to_all_tabs<F>(&self, event: F) -> UiEvents<Event>
+  where
+    F: Fn() -> TermUiEvent,
+  {
+    let (first, others) = self.tabs.split_first().unwrap();
+    let event1 = UiEvent::Directed(first.1, Box::new(event()));
+    others.iter().fold(event1.into(), |events, (_, tab, _)| {
+      events.chain(UiEvent::Directed(*tab, Box::new(event())))
+    })
+  }
+
+  /// Select the tab and the tasks that were selected when the search
+  /// prompt got opened.
+  fn restore_selection(&mut self,
+                       selection: isize,
+                       cap: &mut dyn MutCap<Event>) -> UiEvents<Event> {
+    let _ = self.set_select(selection, cap);
+    self.to_all_tabs(|| TermUiEvent::RestoreSelection)
+  }
+
   /// Handle a custom event.
   fn handle_custom_event(&mut self,
                          mut event: Box<TermUiEvent>,
@@ -265,29 +326,84 @@ impl TabBar {
         let update = self.set_select(select as isize, cap);
         (None as Option<Event>).maybe_update(update)
       },
-      TermUiEvent::EnteredText(mut string) => {
-        if !string.is_empty() && !self.tabs.is_empty() {
-          string.make_ascii_lowercase();
-
-          let reverse = self.search.take().is_reverse();
-          let tab = self.selected_tab();
-          let mut state = IterationState::new(tab);
-          state.reverse(reverse);
-
-          let event1 = TermUiEvent::SetInOut(InOut::Search(string.clone()));
-          let event1 = UiEvent::Custom(Box::new(event1));
-
-          let event2 = TermUiEvent::SearchTask(string, SearchState::Current, state);
-          let event2 = UiEvent::Returnable(self.id, tab, Box::new(event2));
-
-          Some(UiEvents::from(event1).chain(event2)).update()
+      TermUiEvent::NarrowedTab(widget_id, narrowed) => {
+        let tab = self.tabs.iter_mut().find(|x| x.1 == widget_id).unwrap();
+        tab.2 = narrowed;
+        None
+      },
+      TermUiEvent::Clicked(Target::Tab(idx), _) => {
+        let update = self.set_select(idx as isize, cap);
+        (None as Option<Event>).maybe_update(update)
+      },
+      TermUiEvent::Clicked(..) => {
+        // Any click not on a tab targets a task, and only the tasks of
+        // the selected tab are displayed.
+        let tab = self.selected_tab();
+        Some(UiEvent::Directed(tab, event).into())
+      },
+      TermUiEvent::ChangedText(ref string) if self.prompt.is_some() => {
+        let prompt = self.prompt.as_ref().unwrap();
+        let (reverse, selection) = (prompt.reverse, prompt.selection);
+        // Each incremental search starts out at the task that was
+        // selected originally.
+        let restore = self.restore_selection(selection, cap);
+
+        // Text not (yet) forming a valid pattern is just not searched
+        // for.
+        match string.parse::<Pattern>() {
+          Ok(pattern) => {
+            self.search = SearchT::Taken;
+            let search = self.search_selected(pattern, reverse);
+            Some(restore.chain(search)).update()
+          },
+          Err(..) => {
+            self.search = SearchT::Unset;
+            Some(restore).update()
+          },
+        }
+      },
+      TermUiEvent::EnteredText(string) => {
+        if let Some(prompt) = self.prompt.take() {
+          let restore = self.restore_selection(prompt.selection, cap);
+          if string.is_empty() {
+            self.search = prompt.previous;
+            return Some(restore).update()
+          }
+
+          match string.parse::<Pattern>() {
+            Ok(pattern) => {
+              self.search = SearchT::Taken;
+
+              let event1 = TermUiEvent::SetInOut(InOut::Search(pattern.to_string()));
+              let event1 = UiEvent::Custom(Box::new(event1));
+              let event2 = self.search_selected(pattern, prompt.reverse);
+
+              Some(restore.chain(event1).chain(event2)).update()
+            },
+            Err(err) => {
+              self.search = SearchT::Unset;
+
+              let event = TermUiEvent::SetInOut(InOut::Error(err.to_string()));
+              Some(restore.chain(UiEvent::Custom(Box::new(event)))).update()
+            },
+          }
+        } else {
+          None
+        }
+      },
+      TermUiEvent::InputCanceled => {
+        if let Some(prompt) = self.prompt.take() {
+          self.search = prompt.previous;
+          Some(self.restore_selection(prompt.selection, cap)).update()
         } else {
           None
         }
       },
-      TermUiEvent::InputCanceled => None,
+      TermUiEvent::ReplaceTag(from, tag) => {
+        Some(self.to_all_tabs(|| TermUiEvent::ReplaceTag(from, tag.clone())))
+      },
       TermUiEvent::CollectState(id) => {
-        if let Some((_, tab)) = self.tabs.first() {
+        if let Some((_, tab, _)) = self.tabs.first() {
           let tab_state = TabState{
             id: id,
             queries: Vec::new(),
@@ -321,16 +437,33 @@ impl TabBar {
           Some(UiEvent::Returnable(self.id, tab, Box::new(event)).into())
         }
       },
-      TermUiEvent::SearchTask(string, search_state, iter_state) => {
-        self.handle_search_task(string, search_state, iter_state)
+      TermUiEvent::SearchTask(pattern, search_state, iter_state) => {
+        self.handle_search_task(pattern, search_state, iter_state)
       },
       _ => Some(UiEvent::Custom(event).into()),
     }
   }
 
-  /// Retrieve an iterator over the names of all the tabs.
-  pub fn iter(&self) -> impl ExactSizeIterator<Item=&String> {
-    self.tabs.iter().map(|(x, _)| x)
+  /// Retrieve an iterator over the titles of all the tabs.
+  ///
+  /// The titles of tabs temporarily narrowed down are marked with a
+  /// trailing asterisk.
+  pub fn iter(&self) -> impl ExactSizeIterator<Item=String> + '_ {
+    self.tabs.iter().map(|(name, _, narrowed)| {
+      if *narrowed {
+        format!("{}*", name)
+      } else {
+        name.clone()
+      }
+    })
+  }
+
+  /// Retrieve the pattern of the last search, if any.
+  pub fn search_pattern(&self) -> Option<&Pattern> {
+    match self.search {
+      SearchT::State(ref pattern, ..) => Some(pattern),
+      _ => None,
+    }
   }
 
   /// Retrieve the index of the currently selected tab.
@@ -354,7 +487,13 @@ impl TabBar {
       cap.hide(self.selected_tab());
       self.prev_selection = self.selection;
       self.selection = selection;
-      cap.focus(self.selected_tab());
+      // While a search pattern is being entered the input/output area
+      // has to keep the focus.
+      if self.prompt.is_some() {
+        cap.show(self.selected_tab());
+      } else {
+        cap.focus(self.selected_tab());
+      }
       true
     } else {
       false
@@ -381,22 +520,20 @@ impl Handleable<Event> for TabBar {
   fn handle(&mut self, event: Event, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
     match event {
       Event::Key(key, _) => {
-        match key {
-          Key::Char('1') => (None as Option<Event>).maybe_update(self.set_select(0, cap)),
-          Key::Char('2') => (None as Option<Event>).maybe_update(self.set_select(1, cap)),
-          Key::Char('3') => (None as Option<Event>).maybe_update(self.set_select(2, cap)),
-          Key::Char('4') => (None as Option<Event>).maybe_update(self.set_select(3, cap)),
-          Key::Char('5') => (None as Option<Event>).maybe_update(self.set_select(4, cap)),
-          Key::Char('6') => (None as Option<Event>).maybe_update(self.set_select(5, cap)),
-          Key::Char('7') => (None as Option<Event>).maybe_update(self.set_select(6, cap)),
-          Key::Char('8') => (None as Option<Event>).maybe_update(self.set_select(7, cap)),
-          Key::Char('9') => (None as Option<Event>).maybe_update(self.set_select(8, cap)),
-          Key::Char('0') => (None as Option<Event>).maybe_update(self.set_select(isize::MAX, cap)),
-          Key::Char('`') => (None as Option<Event>).maybe_update(self.select_previous(cap)),
-          Key::Char('h') => (None as Option<Event>).maybe_update(self.select(-1, cap)),
-          Key::Char('l') => (None as Option<Event>).maybe_update(self.select(1, cap)),
-          Key::Char('n') |
-          Key::Char('N') => {
+        match self.bindings.action(key) {
+          Some(Action::SelectTab(idx)) => {
+            (None as Option<Event>).maybe_update(self.set_select(idx as isize, cap))
+          },
+          Some(Action::SelectLastTab) => {
+            (None as Option<Event>).maybe_update(self.set_select(isize::MAX, cap))
+          },
+          Some(Action::SelectPreviousTab) => {
+            (None as Option<Event>).maybe_update(self.select_previous(cap))
+          },
+          Some(Action::SelectTabLeft) => (None as Option<Event>).maybe_update(self.select(-1, cap)),
+          Some(Action::SelectTabRight) => (None as Option<Event>).maybe_update(self.select(1, cap)),
+          Some(action @ Action::SearchNext) |
+          Some(action @ Action::SearchPrevious) => {
             let event = match self.search.take() {
               SearchT::Unset => {
                 self.search = SearchT::Unset;
@@ -405,19 +542,18 @@ impl Handleable<Event> for TabBar {
                 let event = TermUiEvent::SetInOut(error);
                 UiEvent::Custom(Box::new(event)).into()
               },
-              SearchT::Taken |
-              SearchT::Preparing(..) => panic!("invalid search state"),
-              SearchT::State(string, search_state, mut iter_state) => {
+              SearchT::Taken => panic!("invalid search state"),
+              SearchT::State(pattern, search_state, mut iter_state) => {
                 let iter = self.tabs.iter().map(|x| x.1);
                 let new_idx = iter_state.normalize(iter);
                 let tab = self.tabs[new_idx].1;
-                let reverse = key == Key::Char('N');
+                let reverse = action == Action::SearchPrevious;
                 iter_state.reverse(reverse);
 
-                let event1 = TermUiEvent::SetInOut(InOut::Search(string.clone()));
+                let event1 = TermUiEvent::SetInOut(InOut::Search(pattern.to_string()));
                 let event1 = UiEvent::Custom(Box::new(event1));
 
-                let event2 = TermUiEvent::SearchTask(string, search_state, iter_state);
+                let event2 = TermUiEvent::SearchTask(pattern, search_state, iter_state);
                 let event2 = UiEvent::Returnable(self.id, tab, Box::new(event2));
 
                 UiEvents::from(event1).chain(event2)
@@ -425,18 +561,24 @@ impl Handleable<Event> for TabBar {
             };
             Some(event)
           },
-          Key::Char('/') |
-          Key::Char('?') => {
-            let reverse = key == Key::Char('?');
-            self.search = SearchT::Preparing(reverse);
-
-            let event = TermUiEvent::SetInOut(InOut::Input("".to_string(), 0));
+          Some(action @ Action::SearchForward) |
+          Some(action @ Action::SearchBackward) => {
+            self.prompt = Some(SearchPrompt {
+              reverse: action == Action::SearchBackward,
+              selection: self.selection,
+              previous: self.search.take(),
+            });
+            self.search = SearchT::Unset;
+
+            let save = self.to_all_tabs(|| TermUiEvent::SaveSelection);
+            let event = TermUiEvent::SetIncrementalInput("".to_string(), 0);
             let event = UiEvent::Custom(Box::new(event));
-            Some(event.into())
+            Some(save.chain(event))
           },
           _ => Some(event.into()),
         }
       },
+      Event::Mouse(..) => Some(event.into()),
     }
   }
 
@@ -470,11 +612,12 @@ mod tests {
     assert_eq!(search, SearchT::Taken);
 
     let iter_state = TestIterationState::new(42);
-    let mut search = SearchT::State("test".to_string(), SearchState::First, iter_state);
+    let pattern = Pattern::Substring("test".to_string());
+    let mut search = SearchT::State(pattern, SearchState::First, iter_state);
 
     match search.take() {
-      SearchT::State(string, search_state, iter_state) => {
-        assert_eq!(string, "test");
+      SearchT::State(pattern, search_state, iter_state) => {
+        assert_eq!(pattern.to_string(), "test");
         assert_eq!(search_state, SearchState::First);
         assert_eq!(iter_state, TestIterationState::new(42));
       },
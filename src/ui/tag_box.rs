@@ -0,0 +1,260 @@
+// tag_box.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+use std::any::Any;
+use std::cmp::max;
+use std::cmp::min;
+use std::rc::Rc;
+
+use gui::derive::Widget;
+use gui::Handleable;
+use gui::Id;
+use gui::MutCap;
+use gui::UiEvent;
+use gui::UiEvents;
+
+use notnow::tags::Id as TagId;
+use notnow::tags::TreeEntry;
+use notnow::tasks::Task;
+
+use super::bindings::Action;
+use super::bindings::Bindings;
+use super::bindings::Context;
+use super::event::Event;
+use super::event::EventUpdate;
+use super::event::MouseButton;
+use super::event::MouseEvent;
+use super::in_out::InOut;
+use super::termui::TermUiEvent;
+
+
+/// Sanitize a selection index.
+fn sanitize_selection(selection: isize, count: usize) -> usize {
+  if count == 0 {
+    0
+  } else {
+    max(0, min(count as isize - 1, selection)) as usize
+  }
+}
+
+
+/// A widget for editing the tags of a task, displayed on top of
+/// everything else.
+///
+/// Tags are arranged in a tree based on their hierarchical names.
+#[derive(Debug, Widget)]
+#[gui(Event = "Event")]
+pub struct TagBox {
+  id: Id,
+  prev_focused: Option<Id>,
+  bindings: Rc<Bindings>,
+  /// The task whose tags are being edited.
+  task: Option<Task>,
+  /// The tree of all available tags.
+  entries: Vec<TreeEntry>,
+  selection: isize,
+  /// The tag whose value is currently being entered, if any.
+  editing: Option<TagId>,
+}
+
+impl TagBox {
+  /// Create a new `TagBox` widget.
+  ///
+  /// The widget starts out hidden.
+  pub fn new(id: Id, cap: &mut dyn MutCap<Event>, bindings: Rc<Bindings>) -> Self {
+    cap.hide(id);
+
+    TagBox {
+      id: id,
+      prev_focused: None,
+      bindings: bindings,
+      task: None,
+      entries: Vec::new(),
+      selection: 0,
+      editing: None,
+    }
+  }
+
+  /// Retrieve the entries of the tag tree to display.
+  pub fn entries(&self) -> &[TreeEntry] {
+    &self.entries
+  }
+
+  /// Check whether the task being edited has the tag with the given ID.
+  pub fn has_tag(&self, id: TagId) -> bool {
+    matches!(&self.task, Some(task) if task.has_tag(id))
+  }
+
+  /// Retrieve the value the task being edited has for the tag with the
+  /// given ID, if any.
+  pub fn value(&self, id: TagId) -> Option<&str> {
+    let task = self.task.as_ref()?;
+    task.tags().find(|x| x.id() == id).and_then(|x| x.value())
+  }
+
+  /// Retrieve the index of the currently selected entry.
+  pub fn selection(&self) -> usize {
+    sanitize_selection(self.selection, self.entries.len())
+  }
+
+  /// Change the currently selected entry.
+  fn set_select(&mut self, selection: isize) -> bool {
+    let count = self.entries.len();
+    let old_selection = sanitize_selection(self.selection, count);
+    let new_selection = sanitize_selection(selection, count);
+
+    self.selection = new_selection as isize;
+    new_selection != old_selection
+  }
+
+  /// Change the currently selected entry by the given amount.
+  fn select(&mut self, change: isize) -> bool {
+    let selection = self.selection() as isize;
+    self.set_select(selection + change)
+  }
+
+  /// Add or remove the selected tag.
+  fn toggle_selected_tag(&mut self) -> Option<UiEvents<Event>> {
+    let id = self.entries.get(self.selection()).and_then(|x| x.id);
+    match (id, self.task.as_mut()) {
+      (Some(id), Some(task)) => {
+        task.toggle_tag(id);
+        (None as Option<Event>).update()
+      },
+      // Entries merely grouping other tags can not be toggled.
+      _ => None,
+    }
+  }
+
+  /// Start entering the value of the selected tag.
+  fn edit_selected_value(&mut self) -> Option<UiEvents<Event>> {
+    let id = self.entries.get(self.selection()).and_then(|x| x.id)?;
+    let string = self.value(id).unwrap_or_default().to_string();
+    let idx = string.len();
+    let event = TermUiEvent::SetInOut(InOut::Input(string, idx));
+
+    self.editing = Some(id);
+    Some(UiEvent::Custom(Box::new(event)).into())
+  }
+
+  /// Show the widget for editing the tags of the given task and take
+  /// over the focus.
+  fn show(&mut self, task: Task, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    self.entries = task.templates().tree();
+    self.task = Some(task);
+    self.selection = 0;
+    self.editing = None;
+    self.prev_focused = cap.focused();
+    cap.focus(self.id);
+    (None as Option<Event>).update()
+  }
+
+  /// Hide the widget and hand the focus back to where it was before,
+  /// optionally sending the edited task along.
+  fn hide(&mut self, accept: bool, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    cap.hide(self.id);
+    let task = self.task.take();
+
+    match self.prev_focused.take() {
+      Some(prev_focused) => {
+        cap.focus(prev_focused);
+
+        match task {
+          Some(task) if accept => {
+            let event = TermUiEvent::UpdateTask(task);
+            Some(UiEvent::Directed(prev_focused, Box::new(event))).update()
+          },
+          _ => (None as Option<Event>).update(),
+        }
+      },
+      None => (None as Option<Event>).update(),
+    }
+  }
+}
+
+impl Handleable<Event> for TagBox {
+  /// Check for new input and react to it.
+  fn handle(&mut self, event: Event, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    // While we are displayed we swallow all input, so that nothing
+    // happens behind our back.
+    match event {
+      Event::Key(key, _) => {
+        match self.bindings.action_in(Context::Tags, key) {
+          Some(Action::Close) |
+          Some(Action::Quit) => self.hide(false, cap),
+          Some(Action::ApplyTags) => self.hide(true, cap),
+          Some(Action::ToggleTag) => self.toggle_selected_tag(),
+          Some(Action::EditTagValue) => self.edit_selected_value(),
+          Some(Action::SelectNext) |
+          Some(Action::SelectNextTask) => {
+            (None as Option<Event>).maybe_update(self.select(1))
+          },
+          Some(Action::SelectPrevious) |
+          Some(Action::SelectPreviousTask) => {
+            (None as Option<Event>).maybe_update(self.select(-1))
+          },
+          Some(Action::SelectFirstTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(0))
+          },
+          Some(Action::SelectLastTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(isize::MAX))
+          },
+          _ => None,
+        }
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(1))
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(-1))
+      },
+      Event::Mouse(..) => None,
+    }
+  }
+
+  /// Handle a custom event.
+  fn handle_custom(&mut self,
+                   event: Box<dyn Any>,
+                   cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match event.downcast::<TermUiEvent>() {
+      Ok(e) => {
+        match *e {
+          TermUiEvent::EditTags(task) => self.show(task, cap),
+          TermUiEvent::EnteredText(text) => {
+            match (self.editing.take(), self.task.as_mut()) {
+              (Some(id), Some(task)) => {
+                // An empty value removes the value but keeps the tag.
+                let value = Some(text.trim().to_string()).filter(|x| !x.is_empty());
+                task.set_tag_value(id, value);
+                (None as Option<Event>).update()
+              },
+              _ => None,
+            }
+          },
+          TermUiEvent::InputCanceled => {
+            self.editing = None;
+            None
+          },
+          e => Some(UiEvent::Custom(Box::new(e)).into()),
+        }
+      },
+      Err(e) => panic!("Received unexpected custom event: {:?}", e),
+    }
+  }
+}
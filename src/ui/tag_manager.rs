@@ -0,0 +1,380 @@
+// tag_manager.rs
+
+// *************************************************************************
+// * Copyright (C) 2019 Daniel Mueller (deso@posteo.net)                   *
+// *                                                                       *
+// * This program is free software: you can redistribute it and/or modify  *
+// * it under the terms of the GNU General Public License as published by  *
+// * the Free Software Foundation, either version 3 of the License, or     *
+// * (at your option) any later version.                                   *
+// *                                                                       *
+// * This program is distributed in the hope that it will be useful,       *
+// * but WITHOUT ANY WARRANTY; without even the implied warranty of        *
+// * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the         *
+// * GNU General Public License for more details.                          *
+// *                                                                       *
+// * You should have received a copy of the GNU General Public License     *
+// * along with this program.  If not, see <http://www.gnu.org/licenses/>. *
+// *************************************************************************
+
+use std::any::Any;
+use std::cmp::max;
+use std::cmp::min;
+use std::rc::Rc;
+
+use cell::RefCell;
+
+use gui::derive::Widget;
+use gui::Handleable;
+use gui::Id;
+use gui::MutCap;
+use gui::UiEvent;
+use gui::UiEvents;
+
+use notnow::query::Query;
+use notnow::state::TaskState;
+use notnow::tags::Id as TagId;
+use notnow::tags::Templates;
+use notnow::tasks::Tasks;
+
+use super::bindings::Action;
+use super::bindings::Bindings;
+use super::bindings::Context;
+use super::event::Event;
+use super::event::EventUpdate;
+use super::event::MouseButton;
+use super::event::MouseEvent;
+use super::in_out::InOut;
+use super::termui::TermUiEvent;
+
+
+/// Sanitize a selection index.
+fn sanitize_selection(selection: isize, count: usize) -> usize {
+  if count == 0 {
+    0
+  } else {
+    max(0, min(count as isize - 1, selection)) as usize
+  }
+}
+
+
+/// A tag template as listed by the tag manager.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+  /// The ID of the template.
+  pub id: TagId,
+  /// The template's name.
+  pub name: String,
+  /// The number of tasks, including archived ones, tagged with the
+  /// template.
+  pub tasks: usize,
+  /// The number of queries referring to the template.
+  pub queries: usize,
+}
+
+
+/// An operation requiring text input from the user.
+#[derive(Debug)]
+enum State {
+  /// The template with the given ID is being renamed.
+  Rename(TagId),
+  /// The template with the given ID is to be merged into another one.
+  Merge(TagId),
+}
+
+
+/// A widget for managing tag templates, displayed on top of everything
+/// else.
+///
+/// Templates can be renamed, merged into other templates, and deleted
+/// once nothing refers to them anymore.
+#[derive(Debug, Widget)]
+#[gui(Event = "Event")]
+pub struct TagManager {
+  id: Id,
+  prev_focused: Option<Id>,
+  tab_bar: Id,
+  bindings: Rc<Bindings>,
+  templates: Rc<Templates>,
+  tasks: Rc<RefCell<Tasks>>,
+  archive: Rc<RefCell<Tasks>>,
+  /// The queries of all tabs, as retrieved when the widget got shown.
+  queries: Vec<Query>,
+  entries: Vec<Entry>,
+  selection: isize,
+  state: Option<State>,
+}
+
+impl TagManager {
+  /// Create a new `TagManager` widget.
+  ///
+  /// The widget starts out hidden. `tab_bar` is the `TabBar` managing
+  /// all the queries referring to tags.
+  pub fn new(id: Id,
+             cap: &mut dyn MutCap<Event>,
+             tab_bar: Id,
+             task_state: &TaskState,
+             bindings: Rc<Bindings>) -> Self {
+    cap.hide(id);
+
+    TagManager {
+      id: id,
+      prev_focused: None,
+      tab_bar: tab_bar,
+      bindings: bindings,
+      templates: task_state.templates(),
+      tasks: task_state.tasks(),
+      archive: task_state.archive(),
+      queries: Vec::new(),
+      entries: Vec::new(),
+      selection: 0,
+      state: None,
+    }
+  }
+
+  /// Retrieve the listed tag templates.
+  pub fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  /// Retrieve the index of the currently selected entry.
+  pub fn selection(&self) -> usize {
+    sanitize_selection(self.selection, self.entries.len())
+  }
+
+  /// Change the currently selected entry.
+  fn set_select(&mut self, selection: isize) -> bool {
+    let count = self.entries.len();
+    let old_selection = sanitize_selection(self.selection, count);
+    let new_selection = sanitize_selection(selection, count);
+
+    self.selection = new_selection as isize;
+    new_selection != old_selection
+  }
+
+  /// Change the currently selected entry by the given amount.
+  fn select(&mut self, change: isize) -> bool {
+    let selection = self.selection() as isize;
+    self.set_select(selection + change)
+  }
+
+  /// Retrieve the currently selected entry, if any.
+  fn selected(&self) -> Option<&Entry> {
+    self.entries.get(self.selection())
+  }
+
+  /// Recreate the list of templates along with their usage counts.
+  fn refresh(&mut self) {
+    let tasks = self.tasks.borrow();
+    let archive = self.archive.borrow();
+    let complete = self.templates.complete_tag().id();
+
+    let mut entries = self
+      .templates
+      .iter()
+      .filter(|x| x.id() != complete)
+      .map(|template| {
+        let id = template.id();
+        Entry {
+          id: id,
+          name: template.name(),
+          tasks: tasks.iter().chain(archive.iter()).filter(|x| x.has_tag(id)).count(),
+          queries: self.queries.iter().filter(|x| x.references_tag(id)).count(),
+        }
+      })
+      .collect::<Vec<_>>();
+    entries.sort_by(|x, y| x.name.cmp(&y.name));
+    self.entries = entries;
+  }
+
+  /// Select the entry for the template with the given ID.
+  fn select_id(&mut self, id: TagId) {
+    if let Some(idx) = self.entries.iter().position(|x| x.id == id) {
+      let _ = self.set_select(idx as isize);
+    }
+  }
+
+  /// Show the widget and take over the focus.
+  ///
+  /// The queries referring to tags are retrieved from the `TabBar`.
+  fn show(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    self.queries.clear();
+    self.refresh();
+    self.selection = 0;
+    self.state = None;
+    self.prev_focused = cap.focused();
+    cap.focus(self.id);
+
+    let event = TermUiEvent::CollectState(self.id);
+    Some(UiEvent::Directed(self.tab_bar, Box::new(event))).update()
+  }
+
+  /// Hide the widget and hand the focus back to where it was before.
+  fn hide(&mut self, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    cap.hide(self.id);
+    self.queries.clear();
+
+    if let Some(prev_focused) = self.prev_focused.take() {
+      cap.focus(prev_focused);
+    }
+    (None as Option<Event>).update()
+  }
+
+  /// Start text input for the given operation.
+  fn start_input(&mut self, state: State, string: String) -> Option<UiEvents<Event>> {
+    let idx = string.len();
+    let event = TermUiEvent::SetInOut(InOut::Input(string, idx));
+    self.state = Some(state);
+    Some(UiEvent::Custom(Box::new(event)).into())
+  }
+
+  /// Create an event reporting the given error.
+  fn error(error: String) -> Option<UiEvents<Event>> {
+    let event = TermUiEvent::SetInOut(InOut::Error(error));
+    Some(UiEvent::Custom(Box::new(event))).update()
+  }
+
+  /// Rename the template with the given ID.
+  fn rename(&mut self, id: TagId, name: String) -> Option<UiEvents<Event>> {
+    match self.templates.rename(id, &name) {
+      Ok(()) => {
+        self.refresh();
+        self.select_id(id);
+        (None as Option<Event>).update()
+      },
+      Err(err) => Self::error(format!("{}", err)),
+    }
+  }
+
+  /// Merge the template with ID `from` into the one with the given
+  /// name, retagging all tasks and rewriting all queries.
+  fn merge(&mut self, from: TagId, name: String) -> Option<UiEvents<Event>> {
+    let into = match self.templates.find(name.trim()) {
+      Some(into) if into != from => into,
+      Some(_) => return Self::error("Tag can not be merged into itself".to_string()),
+      None => return Self::error(format!("Tag '{}' does not exist", name.trim())),
+    };
+
+    let _ = self.tasks.borrow_mut().replace_tag(from, into);
+    let _ = self.archive.borrow_mut().replace_tag(from, into);
+
+    let tag = self.templates.instantiate(into);
+    self
+      .queries
+      .iter_mut()
+      .for_each(|query| query.replace_tag(from, &tag));
+    // With nothing referring to it anymore, the template can go away.
+    let _ = self.templates.remove(from);
+
+    self.refresh();
+    self.select_id(into);
+
+    let event = TermUiEvent::ReplaceTag(from, tag);
+    Some(UiEvent::Directed(self.tab_bar, Box::new(event))).update()
+  }
+
+  /// Delete the selected template, provided nothing refers to it.
+  fn delete_selected(&mut self) -> Option<UiEvents<Event>> {
+    let entry = self.selected()?.clone();
+    if entry.tasks > 0 || entry.queries > 0 {
+      let error = format!(
+        "Tag '{}' is still used by {} tasks and {} queries",
+        entry.name, entry.tasks, entry.queries
+      );
+      return Self::error(error)
+    }
+
+    match self.templates.remove(entry.id) {
+      Ok(()) => {
+        self.refresh();
+        (None as Option<Event>).update()
+      },
+      Err(err) => Self::error(format!("{}", err)),
+    }
+  }
+
+  /// Handle a custom event.
+  fn handle_custom_event(&mut self,
+                         event: Box<TermUiEvent>,
+                         cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match *event {
+      TermUiEvent::ManageTags => self.show(cap),
+      TermUiEvent::CollectedState(queries, _) => {
+        self.queries = queries.into_iter().map(|(query, _)| query).collect();
+        self.refresh();
+        (None as Option<Event>).update()
+      },
+      TermUiEvent::EnteredText(text) => {
+        match self.state.take() {
+          Some(State::Rename(id)) => self.rename(id, text),
+          Some(State::Merge(id)) => self.merge(id, text),
+          None => None,
+        }
+      },
+      TermUiEvent::InputCanceled => {
+        self.state = None;
+        None
+      },
+      _ => Some(UiEvent::Custom(event).into()),
+    }
+  }
+}
+
+impl Handleable<Event> for TagManager {
+  /// Check for new input and react to it.
+  fn handle(&mut self, event: Event, cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    // While we are displayed we swallow all input, so that nothing
+    // happens behind our back.
+    match event {
+      Event::Key(key, _) => {
+        match self.bindings.action_in(Context::TagManager, key) {
+          Some(Action::Close) |
+          Some(Action::Quit) => self.hide(cap),
+          Some(Action::RenameTag) => {
+            let entry = self.selected()?;
+            let state = State::Rename(entry.id);
+            let name = entry.name.clone();
+            self.start_input(state, name)
+          },
+          Some(Action::MergeTag) => {
+            let state = State::Merge(self.selected()?.id);
+            self.start_input(state, String::new())
+          },
+          Some(Action::DeleteTag) => self.delete_selected(),
+          Some(Action::SelectNext) |
+          Some(Action::SelectNextTask) => {
+            (None as Option<Event>).maybe_update(self.select(1))
+          },
+          Some(Action::SelectPrevious) |
+          Some(Action::SelectPreviousTask) => {
+            (None as Option<Event>).maybe_update(self.select(-1))
+          },
+          Some(Action::SelectFirstTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(0))
+          },
+          Some(Action::SelectLastTask) => {
+            (None as Option<Event>).maybe_update(self.set_select(isize::MAX))
+          },
+          _ => None,
+        }
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(1))
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(-1))
+      },
+      Event::Mouse(..) => None,
+    }
+  }
+
+  /// Handle a custom event.
+  fn handle_custom(&mut self,
+                   event: Box<dyn Any>,
+                   cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
+    match event.downcast::<TermUiEvent>() {
+      Ok(e) => self.handle_custom_event(e, cap),
+      Err(e) => panic!("Received unexpected custom event: {:?}", e),
+    }
+  }
+}
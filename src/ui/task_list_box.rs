@@ -32,15 +32,24 @@ use gui::UiEvent;
 use gui::UiEvents;
 use gui::derive::Widget;
 
-use crate::query::Query;
-use crate::tasks::Id as TaskId;
-use crate::tasks::Task;
-use crate::tasks::Tasks;
-
+use notnow::date::Date;
+use notnow::query::Query;
+use notnow::query::QueryBuilder;
+use notnow::query::Sort;
+use notnow::recurrence::Recurrence;
+use notnow::search::Pattern;
+use notnow::tasks::Id as TaskId;
+use notnow::tasks::Task;
+use notnow::tasks::Tasks;
+
+use super::bindings::Action;
+use super::bindings::Bindings;
 use super::event::Event;
 use super::event::EventUpdate;
-use super::event::Key;
+use super::event::MouseButton;
+use super::event::MouseEvent;
 use super::in_out::InOut;
+use super::layout::Target;
 use super::tab_bar::IterationState;
 use super::tab_bar::SearchState;
 use super::tab_bar::TabState;
@@ -62,6 +71,9 @@ fn sanitize_selection(selection: isize, count: usize) -> usize {
 enum State {
   Add,
   Edit(Task),
+  Due(Task),
+  Recurrence(Task),
+  Filter,
 }
 
 
@@ -73,13 +85,24 @@ pub struct TaskListBox {
   tasks: Rc<RefCell<Tasks>>,
   query: Query,
   selection: isize,
+  /// The selection remembered via `TermUiEvent::SaveSelection`.
+  saved_selection: isize,
   state: Option<State>,
+  /// The task marked as blocker for other tasks, if any.
+  blocker: Option<TaskId>,
+  /// The tasks that archived tasks are restored to. Only set for the
+  /// archive, which is read-only otherwise.
+  restore_to: Option<Rc<RefCell<Tasks>>>,
+  bindings: Rc<Bindings>,
 }
 
 impl TaskListBox {
   /// Create a new `TaskListBox` widget.
-  pub fn new(id: Id, tasks: Rc<RefCell<Tasks>>,
-             query: Query, selected: Option<usize>) -> Self {
+  pub fn new(id: Id,
+             tasks: Rc<RefCell<Tasks>>,
+             query: Query,
+             selected: Option<usize>,
+             bindings: Rc<Bindings>) -> Self {
     let count = query.iter().clone().count();
     let selected = selected
       .map(|x| min(x, isize::MAX as usize))
@@ -91,7 +114,25 @@ impl TaskListBox {
       tasks: tasks,
       query: query,
       selection: selected,
+      saved_selection: selected,
       state: None,
+      blocker: None,
+      restore_to: None,
+      bindings: bindings,
+    }
+  }
+
+  /// Create a new read-only `TaskListBox` widget displaying the given
+  /// archive, with tasks being restored into `tasks`.
+  pub fn with_archive(id: Id,
+                      archive: Rc<RefCell<Tasks>>,
+                      tasks: Rc<RefCell<Tasks>>,
+                      bindings: Rc<Bindings>) -> Self {
+    let query = QueryBuilder::new(archive.clone()).build("archive");
+
+    TaskListBox {
+      restore_to: Some(tasks),
+      ..Self::new(id, archive, query, None, bindings)
     }
   }
 
@@ -135,9 +176,9 @@ impl TaskListBox {
     self.handle_select_task(task_id, state)
   }
 
-  /// Search for a task containing the given string.
+  /// Search for a task matching the given pattern.
   fn search_task_index(&self,
-                       string: &str,
+                       pattern: &Pattern,
                        search_state: &mut SearchState,
                        iter_state: &mut IterationState) -> Option<usize> {
     // Note that because we use the count for index calculation
@@ -176,7 +217,7 @@ impl TaskListBox {
         .clone()
         .rev()
         .skip(start_idx)
-        .position(|x| x.summary.to_ascii_lowercase().contains(string))
+        .position(|x| pattern.matches(x))
         .and_then(|idx| Some((count - 1) - (start_idx + idx)))
     } else {
       self
@@ -184,19 +225,17 @@ impl TaskListBox {
         .iter()
         .clone()
         .skip(start_idx)
-        .position(|x| x.summary.to_ascii_lowercase().contains(string))
+        .position(|x| pattern.matches(x))
         .and_then(|idx| Some(start_idx + idx))
     }
   }
 
   /// Handle a `TermUiEvent::SearchTask` event.
   fn handle_search_task(&mut self,
-                        string: &str,
+                        pattern: &Pattern,
                         search_state: &mut SearchState,
                         iter_state: &mut IterationState) -> Option<UiEvents<Event>> {
-    debug_assert_eq!(string, &string.to_ascii_lowercase());
-
-    let idx = self.search_task_index(string, search_state, iter_state);
+    let idx = self.search_task_index(pattern, search_state, iter_state);
     if let Some(idx) = idx {
       *search_state = SearchState::Task(idx);
 
@@ -210,34 +249,460 @@ impl TaskListBox {
     }
   }
 
+  /// Toggle the completion state of the selected task.
+  fn toggle_selected_task(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let id = self.selected_task().id();
+      self.tasks.borrow_mut().toggle_complete(id);
+      self.handle_select_task_start(id).update()
+    } else {
+      None
+    }
+  }
+
+  /// Raise or lower the priority of the selected task.
+  fn change_selected_task_priority(&mut self, raise: bool) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let mut task = self.selected_task();
+      let id = task.id();
+      let changed = if raise {
+        task.raise_priority()
+      } else {
+        task.lower_priority()
+      };
+
+      if changed {
+        self.tasks.borrow_mut().update(task);
+        self.handle_select_task_start(id).update()
+      } else {
+        None
+      }
+    } else {
+      None
+    }
+  }
+
+  /// Toggle whether the displayed tasks are sorted by priority.
+  ///
+  /// The sort only affects this tab and is not persisted, leaving the
+  /// order of the underlying tasks untouched.
+  fn toggle_sort_by_priority(&mut self) -> Option<UiEvents<Event>> {
+    // Keep the currently selected task selected.
+    let selected = if !self.query.is_empty() {
+      Some(self.selected_task().id())
+    } else {
+      None
+    };
+
+    let sort = match self.query.sort_override() {
+      Some(Sort::Priority) => None,
+      _ => Some(Sort::Priority),
+    };
+    self.query = self.query.with_sort_override(sort);
+
+    let idx = selected
+      .and_then(|id| self.query.iter().position(|x| x.id() == id))
+      .unwrap_or(0);
+    let _ = self.set_select(idx as isize);
+    (None as Option<Event>).update()
+  }
+
+  /// Start setting the due date of the selected task.
+  fn set_selected_task_due(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let task = self.selected_task();
+      let string = task.due().map(|x| x.to_string()).unwrap_or_default();
+      let idx = string.len();
+      let event = TermUiEvent::SetInOut(InOut::Input(string, idx));
+      let event = UiEvent::Custom(Box::new(event));
+
+      self.state = Some(State::Due(task));
+      Some(event.into())
+    } else {
+      None
+    }
+  }
+
+  /// Start setting the recurrence rule of the selected task.
+  fn set_selected_task_recurrence(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let task = self.selected_task();
+      let string = task.recurrence().map(|x| x.to_string()).unwrap_or_default();
+      let idx = string.len();
+      let event = TermUiEvent::SetInOut(InOut::Input(string, idx));
+      let event = UiEvent::Custom(Box::new(event));
+
+      self.state = Some(State::Recurrence(task));
+      Some(event.into())
+    } else {
+      None
+    }
+  }
+
+  /// Check whether tasks may be moved in the current sort order,
+  /// returning an error event if not.
+  fn check_manual_sort(&self) -> Result<(), UiEvents<Event>> {
+    if self.query.sort() == Sort::Manual {
+      Ok(())
+    } else {
+      let error = "Tasks can only be moved on tabs sorted manually".to_string();
+      let event = TermUiEvent::SetInOut(InOut::Error(error));
+      Err(UiEvent::Custom(Box::new(event)).into())
+    }
+  }
+
+  /// Find the sibling of the selected task that is displayed right
+  /// after or before it, respectively.
+  fn selected_task_sibling(&self, next: bool) -> Option<Task> {
+    let query = self.query();
+    let tasks = query.iter().clone().with_depth().collect::<Vec<_>>();
+    let selection = self.selection();
+    let depth = tasks.get(selection)?.1;
+
+    // Subtasks of other tasks have a larger depth and are skipped. A
+    // smaller depth means that we left the parent's subtree.
+    let sibling = if next {
+      tasks[selection + 1..]
+        .iter()
+        .take_while(|(_, d)| *d >= depth)
+        .find(|(_, d)| *d == depth)
+    } else {
+      tasks[..selection]
+        .iter()
+        .rev()
+        .take_while(|(_, d)| *d >= depth)
+        .find(|(_, d)| *d == depth)
+    };
+
+    let sibling = sibling.map(|(task, _)| (*task).clone());
+    sibling
+  }
+
+  /// Select the task with the given `Id` on this tab, if present.
+  fn select_task_id(&mut self, id: TaskId) -> bool {
+    let idx = self.query.iter().position(|x| x.id() == id);
+    match idx {
+      Some(idx) => self.set_select(idx as isize),
+      None => false,
+    }
+  }
+
+  /// Move the selected task past its next or previous sibling.
+  fn move_selected_task(&mut self, down: bool) -> Option<UiEvents<Event>> {
+    if let Some(other) = self.selected_task_sibling(down) {
+      let to_move = self.selected_task().id();
+      if down {
+        self.tasks.borrow_mut().move_after(to_move, other.id());
+      } else {
+        self.tasks.borrow_mut().move_before(to_move, other.id());
+      }
+      (None as Option<Event>).maybe_update(self.select_task_id(to_move))
+    } else {
+      None
+    }
+  }
+
+  /// Make the selected task a subtask of its previous sibling.
+  fn indent_selected_task(&mut self) -> Option<UiEvents<Event>> {
+    if let Some(parent) = self.selected_task_sibling(false) {
+      let task = self.selected_task();
+      if task.source() != parent.source() {
+        let error = "Tasks from different task files can not be nested".to_string();
+        let event = TermUiEvent::SetInOut(InOut::Error(error));
+        return Some(UiEvent::Custom(Box::new(event)).into())
+      }
+
+      let id = task.id();
+      self.tasks.borrow_mut().indent(id, parent.id());
+      self.handle_select_task_start(id).update()
+    } else {
+      None
+    }
+  }
+
+  /// Make the selected task a sibling of its parent.
+  fn outdent_selected_task(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let id = self.selected_task().id();
+      if self.tasks.borrow_mut().outdent(id) {
+        self.handle_select_task_start(id).update()
+      } else {
+        None
+      }
+    } else {
+      None
+    }
+  }
+
+  /// Show or hide the subtasks of the selected task.
+  fn toggle_selected_task_collapsed(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let mut task = self.selected_task();
+      if self.tasks.borrow().children(task.id()).next().is_some() {
+        let id = task.id();
+        task.toggle_collapsed();
+        self.tasks.borrow_mut().update(task);
+        (None as Option<Event>).maybe_update(self.select_task_id(id)).update()
+      } else {
+        None
+      }
+    } else {
+      None
+    }
+  }
+
+  /// Mark the selected task as the one to block other tasks with.
+  fn mark_selected_task_as_blocker(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      self.blocker = Some(self.selected_task().id());
+    }
+    None
+  }
+
+  /// Toggle whether the selected task is blocked by the task marked as
+  /// blocker.
+  fn toggle_selected_task_blocked(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let mut task = self.selected_task();
+      let id = task.id();
+
+      let error = match self.blocker {
+        Some(blocker) if blocker == id => "A task can not block itself",
+        Some(blocker) => {
+          // The marked task may have been removed in the meantime.
+          let tasks = self.tasks.borrow();
+          let state = tasks
+            .iter()
+            .find(|x| x.id() == blocker)
+            .map(|x| (x.source(), x.is_complete()));
+          // Removing an existing blocker is always fine.
+          let blocked = task.blocked_by().any(|x| x == blocker);
+
+          match state {
+            Some((source, _)) if source != task.source() => {
+              "Tasks from different task files can not block each other"
+            },
+            Some((_, true)) if !blocked => "Completed tasks can not block other tasks",
+            Some(_) if !blocked && tasks.depends_on(blocker, id) => {
+              "A task can not be blocked by a task depending on it"
+            },
+            Some(_) => {
+              drop(tasks);
+              task.toggle_blocked_by(blocker);
+              self.tasks.borrow_mut().update(task);
+              return self.handle_select_task_start(id).update()
+            },
+            None => {
+              drop(tasks);
+              self.blocker = None;
+              return None
+            },
+          }
+        },
+        None => return None,
+      };
+
+      let event = TermUiEvent::SetInOut(InOut::Error(error.to_string()));
+      Some(UiEvent::Custom(Box::new(event)).into())
+    } else {
+      None
+    }
+  }
+
+  /// Move the selected task out of the archive.
+  fn restore_selected_task(&mut self) -> Option<UiEvents<Event>> {
+    match self.restore_to {
+      Some(ref tasks) if !self.query().is_empty() => {
+        let id = self.selected_task().id();
+        self.tasks.borrow_mut().restore(id, &mut tasks.borrow_mut());
+        (None as Option<Event>).update()
+      },
+      _ => None,
+    }
+  }
+
+  /// Start editing the selected task.
+  fn edit_selected_task(&mut self) -> Option<UiEvents<Event>> {
+    if !self.query().is_empty() {
+      let task = self.selected_task();
+      let string = task.summary.clone();
+      let idx = string.len();
+      let event = TermUiEvent::SetInOut(InOut::Input(string, idx));
+      let event = UiEvent::Custom(Box::new(event));
+
+      self.state = Some(State::Edit(task));
+      Some(event.into())
+    } else {
+      None
+    }
+  }
+
+  /// Start entering a pattern to narrow down the tasks with.
+  fn start_filter(&mut self) -> Option<UiEvents<Event>> {
+    let string = self
+      .query
+      .filter()
+      .map(|x| x.to_string())
+      .unwrap_or_default();
+    let idx = string.len();
+    let event = TermUiEvent::SetInOut(InOut::Input(string, idx));
+    let event = UiEvent::Custom(Box::new(event));
+
+    self.state = Some(State::Filter);
+    Some(event.into())
+  }
+
+  /// Narrow down the displayed tasks to those matching the given
+  /// pattern, or remove any narrowing.
+  fn set_filter(&mut self, filter: Option<Pattern>) -> Option<UiEvents<Event>> {
+    // The selected task stays selected if it is still displayed.
+    let selected = if !self.query.is_empty() {
+      Some(self.selected_task().id())
+    } else {
+      None
+    };
+
+    let narrowed = filter.is_some();
+    self.query = self.query.with_filter(filter);
+
+    let idx = selected
+      .and_then(|id| self.query.iter().position(|x| x.id() == id))
+      .unwrap_or(0);
+    let _ = self.set_select(idx as isize);
+
+    let event = TermUiEvent::NarrowedTab(self.id, narrowed);
+    Some(UiEvent::Custom(Box::new(event))).update()
+  }
+
+  /// Handle a click on one of our tasks.
+  fn handle_click(&mut self, target: Target, double: bool) -> Option<UiEvents<Event>> {
+    match target {
+      Target::Task(idx) => {
+        let update = self.set_select(idx as isize);
+        if double && self.restore_to.is_none() {
+          self.edit_selected_task().maybe_update(update)
+        } else {
+          (None as Option<Event>).maybe_update(update)
+        }
+      },
+      Target::TaskState(idx) => {
+        if self.restore_to.is_none() {
+          let _ = self.set_select(idx as isize);
+          self.toggle_selected_task()
+        } else {
+          (None as Option<Event>).maybe_update(self.set_select(idx as isize))
+        }
+      },
+      Target::Tab(..) => panic!("Unexpected click target: {:?}", target),
+    }
+  }
+
   /// Handle a custom event.
   fn handle_custom_event(&mut self, event: Box<TermUiEvent>) -> Option<UiEvents<Event>> {
     match *event {
       TermUiEvent::SelectTask(task_id, state) => {
         self.handle_select_task(task_id, state)
       },
+      TermUiEvent::Clicked(target, double) => self.handle_click(target, double),
+      TermUiEvent::UpdateTask(task) => {
+        let id = task.id();
+        self.tasks.borrow_mut().update(task);
+        self.handle_select_task_start(id).update()
+      },
+      TermUiEvent::ReplaceTag(from, tag) => {
+        self.query.replace_tag(from, &tag);
+        None
+      },
+      TermUiEvent::SaveSelection => {
+        self.saved_selection = self.selection;
+        None
+      },
+      TermUiEvent::RestoreSelection => {
+        let update = self.set_select(self.saved_selection);
+        (None as Option<Event>).maybe_update(update)
+      },
       TermUiEvent::EnteredText(ref text) => {
         if let Some(state) = self.state.take() {
           match state {
             State::Add => {
               if !text.is_empty() {
-                let tags = if !self.query.is_empty() {
+                // New tasks inherit the tags, parent, and task file of
+                // the selected one. Lacking that, they are added to the
+                // task file the query is restricted to or the first one.
+                let (tags, parent, source) = if !self.query.is_empty() {
                   let mut task = self.selected_task();
                   if task.is_complete() {
-                    task.toggle_complete()
+                    let _ = task.toggle_complete();
                   }
                   let tags = task.tags().cloned().collect();
-                  tags
+                  (tags, task.parent(), task.source())
                 } else {
-                  Default::default()
+                  let source = self.query.source().map_or(0, |(source, _)| source);
+                  (Default::default(), None, source)
                 };
 
-                let id = self.tasks.borrow_mut().add(text.clone(), tags);
+                let id = self.tasks.borrow_mut().add(text.clone(), tags, parent, source);
                 self.handle_select_task_start(id)
               } else {
                 None
               }
             },
+            State::Due(mut task) => {
+              let id = task.id();
+              // An empty input clears the due date.
+              let due = if !text.is_empty() {
+                text.parse::<Date>().map(Some)
+              } else {
+                Ok(None)
+              };
+
+              match due {
+                Ok(due) => {
+                  task.set_due(due);
+                  self.tasks.borrow_mut().update(task);
+                  self.handle_select_task_start(id).update()
+                },
+                Err(err) => {
+                  let event = TermUiEvent::SetInOut(InOut::Error(err.to_string()));
+                  Some(UiEvent::Custom(Box::new(event)).into())
+                },
+              }
+            },
+            State::Recurrence(mut task) => {
+              let id = task.id();
+              // An empty input makes the task non-recurring.
+              let recurrence = if !text.is_empty() {
+                text.parse::<Recurrence>().map(Some)
+              } else {
+                Ok(None)
+              };
+
+              match recurrence {
+                Ok(recurrence) => {
+                  task.set_recurrence(recurrence);
+                  self.tasks.borrow_mut().update(task);
+                  self.handle_select_task_start(id).update()
+                },
+                Err(err) => {
+                  let event = TermUiEvent::SetInOut(InOut::Error(err.to_string()));
+                  Some(UiEvent::Custom(Box::new(event)).into())
+                },
+              }
+            },
+            State::Filter => {
+              // An empty pattern removes the narrowing.
+              if text.is_empty() {
+                self.set_filter(None)
+              } else {
+                match text.parse::<Pattern>() {
+                  Ok(pattern) => self.set_filter(Some(pattern)),
+                  Err(err) => {
+                    let event = TermUiEvent::SetInOut(InOut::Error(err.to_string()));
+                    Some(UiEvent::Custom(Box::new(event)).into())
+                  },
+                }
+              }
+            },
             State::Edit(mut task) => {
               let id = task.id();
 
@@ -272,14 +737,17 @@ impl TaskListBox {
   /// Handle a "returnable" custom event.
   fn handle_custom_event_ref(&mut self, event: &mut TermUiEvent) -> Option<UiEvents<Event>> {
     match event {
-      TermUiEvent::SearchTask(string, search_state, iter_state) => {
-        self.handle_search_task(string, search_state, iter_state)
+      TermUiEvent::SearchTask(pattern, search_state, iter_state) => {
+        self.handle_search_task(pattern, search_state, iter_state)
       },
       TermUiEvent::GetTabState(ref mut tab_state, ref mut iter_state) => {
-        let TabState{ref mut queries, ..} = tab_state;
-        let selected = Some(self.selection());
+        // The archive is not backed by a query of its own.
+        if self.restore_to.is_none() {
+          let TabState{ref mut queries, ..} = tab_state;
+          let selected = Some(self.selection());
 
-        queries.push((self.query(), selected));
+          queries.push((self.query(), selected));
+        }
         iter_state.advance();
         None
       },
@@ -350,26 +818,22 @@ impl Handleable<Event> for TaskListBox {
   fn handle(&mut self, event: Event, _cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
     match event {
       Event::Key(key, _) => {
-        match key {
-          Key::Char(' ') => {
-            if !self.query().is_empty() {
-              let mut task = self.selected_task();
-              let id = task.id();
-              task.toggle_complete();
-              self.tasks.borrow_mut().update(task);
-              self.handle_select_task_start(id).update()
-            } else {
-              None
-            }
+        match self.bindings.action(key) {
+          Some(action) if action.modifies_tasks() && self.restore_to.is_some() => {
+            let error = InOut::Error("Archived tasks can not be changed".to_string());
+            let event = TermUiEvent::SetInOut(error);
+            Some(UiEvent::Custom(Box::new(event)).into())
           },
-          Key::Char('a') => {
+          Some(Action::RestoreTask) => self.restore_selected_task(),
+          Some(Action::ToggleComplete) => self.toggle_selected_task(),
+          Some(Action::AddTask) => {
             let event = TermUiEvent::SetInOut(InOut::Input("".to_string(), 0));
             let event = UiEvent::Custom(Box::new(event));
 
             self.state = Some(State::Add);
             Some(event.into())
           },
-          Key::Char('d') => {
+          Some(Action::RemoveTask) => {
             if !self.query().is_empty() {
               let id = self.selected_task().id();
               self.tasks.borrow_mut().remove(id);
@@ -378,57 +842,70 @@ impl Handleable<Event> for TaskListBox {
               None
             }
           },
-          Key::Char('e') => {
+          Some(Action::EditTask) => self.edit_selected_task(),
+          Some(Action::EditTags) => {
             if !self.query().is_empty() {
-              let task = self.selected_task();
-              let string = task.summary.clone();
-              let idx = string.len();
-              let event = TermUiEvent::SetInOut(InOut::Input(string, idx));
-              let event = UiEvent::Custom(Box::new(event));
-
-              self.state = Some(State::Edit(task));
-              Some(event.into())
+              let event = TermUiEvent::EditTags(self.selected_task());
+              Some(UiEvent::Custom(Box::new(event)).into())
             } else {
               None
             }
           },
-          Key::Char('J') => {
-            if !self.query().is_empty() {
-              let to_move = self.selected_task();
-              let query = self.query();
-              let other = query.iter().nth(self.some_selection(1));
-              if let Some(other) = other {
-                self.tasks.borrow_mut().move_after(to_move.id(), other.id());
-                (None as Option<Event>).maybe_update(self.select(1))
-              } else {
-                None
-              }
+          Some(Action::FilterTasks) => self.start_filter(),
+          Some(Action::ClearFilter) => {
+            if self.query.filter().is_some() {
+              self.set_filter(None)
             } else {
               None
             }
           },
-          Key::Char('K') => {
-            if !self.query().is_empty() && self.selection() > 0 {
-              let to_move = self.selected_task();
-              let query = self.query();
-              let other = query.iter().nth(self.some_selection(-1));
-              if let Some(other) = other {
-                self.tasks.borrow_mut().move_before(to_move.id(), other.id());
-                (None as Option<Event>).maybe_update(self.select(-1))
-              } else {
-                None
-              }
+          Some(Action::RaisePriority) => self.change_selected_task_priority(true),
+          Some(Action::LowerPriority) => self.change_selected_task_priority(false),
+          Some(Action::SortByPriority) => self.toggle_sort_by_priority(),
+          Some(Action::SetDueDate) => self.set_selected_task_due(),
+          Some(Action::SetRecurrence) => self.set_selected_task_recurrence(),
+          Some(Action::MoveTaskDown) => {
+            if let Err(event) = self.check_manual_sort() {
+              Some(event)
+            } else {
+              self.move_selected_task(true)
+            }
+          },
+          Some(Action::MoveTaskUp) => {
+            if let Err(event) = self.check_manual_sort() {
+              Some(event)
+            } else {
+              self.move_selected_task(false)
+            }
+          },
+          Some(Action::IndentTask) => self.indent_selected_task(),
+          Some(Action::OutdentTask) => self.outdent_selected_task(),
+          Some(Action::ToggleCollapsed) => self.toggle_selected_task_collapsed(),
+          Some(Action::MarkBlocker) => self.mark_selected_task_as_blocker(),
+          Some(Action::ToggleBlocked) => self.toggle_selected_task_blocked(),
+          Some(Action::ToggleCompleteWithSubtasks) => {
+            if !self.query().is_empty() {
+              let id = self.selected_task().id();
+              self.tasks.borrow_mut().toggle_complete_with_subtasks(id);
+              self.handle_select_task_start(id).update()
             } else {
               None
             }
           },
-          Key::Char('g') => (None as Option<Event>).maybe_update(self.set_select(0)),
-          Key::Char('G') => (None as Option<Event>).maybe_update(self.set_select(isize::MAX)),
-          Key::Char('j') => (None as Option<Event>).maybe_update(self.select(1)),
-          Key::Char('k') => (None as Option<Event>).maybe_update(self.select(-1)),
+          Some(Action::SelectFirstTask) => (None as Option<Event>).maybe_update(self.set_select(0)),
+          Some(Action::SelectLastTask) => (None as Option<Event>).maybe_update(self.set_select(isize::MAX)),
+          Some(Action::SelectNextTask) => (None as Option<Event>).maybe_update(self.select(1)),
+          Some(Action::SelectPreviousTask) => (None as Option<Event>).maybe_update(self.select(-1)),
           _ => Some(event.into()),
         }
       },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(1))
+      },
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, ..)) => {
+        (None as Option<Event>).maybe_update(self.select(-1))
+      },
+      Event::Mouse(..) => Some(event.into()),
     }
   }
 
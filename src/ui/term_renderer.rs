@@ -24,6 +24,7 @@ use std::io::BufWriter;
 use std::io::Result;
 use std::io::Write;
 use std::iter::repeat;
+use std::rc::Rc;
 
 use termion::clear::All;
 use termion::color::Bg;
@@ -34,6 +35,8 @@ use termion::color::Rgb;
 use termion::cursor::Goto;
 use termion::cursor::Hide;
 use termion::cursor::Show;
+use termion::style::Bold;
+use termion::style::Reset as StyleReset;
 use termion::terminal_size;
 
 use gui::BBox;
@@ -43,21 +46,37 @@ use gui::Object;
 use gui::Renderable;
 use gui::Renderer;
 
+use notnow::search::Pattern;
+use notnow::tasks::Priority;
+
+use super::help_box::HelpBox;
+use super::history_view::HistoryView;
 use super::in_out::InOut;
 use super::in_out::InOutArea;
+use super::layout::Layout;
+use super::layout::Target;
 use super::tab_bar::TabBar;
+use super::tag_box::TagBox;
+use super::tag_manager::TagManager;
 use super::task_list_box::TaskListBox;
 use super::termui::TermUi;
 
 const MAIN_MARGIN_X: u16 = 3;
 const MAIN_MARGIN_Y: u16 = 2;
 const TASK_SPACE: u16 = 2;
+const TASK_INDENT: u16 = 4;
 const TAB_TITLE_WIDTH: u16 = 30;
+const HELP_MARGIN_X: u16 = 6;
+const HELP_MARGIN_Y: u16 = 2;
 
 const SAVED_TEXT: &str = " Saved ";
 const SEARCH_TEXT: &str = " Search ";
 const ERROR_TEXT: &str = " Error ";
 const INPUT_TEXT: &str = " > ";
+const HELP_TEXT: &str = "Key Bindings";
+const TAGS_TEXT: &str = "Tags";
+const TAG_MANAGER_TEXT: &str = "Manage Tags";
+const HISTORY_TEXT: &str = "History";
 
 // TODO: Make the colors run time configurable at some point.
 /// Color 15.
@@ -80,6 +99,10 @@ const UNSELECTED_TASK_BG: Reset = Reset;
 const SELECTED_TASK_FG: Rgb = Rgb(0xff, 0xff, 0xff);
 /// Color 240.
 const SELECTED_TASK_BG: Rgb = Rgb(0x58, 0x58, 0x58);
+/// Color 245.
+const BLOCKED_TASK_FG: Rgb = Rgb(0x8a, 0x8a, 0x8a);
+/// Color 250.
+const SELECTED_BLOCKED_TASK_FG: Rgb = Rgb(0xbc, 0xbc, 0xbc);
 /// Soft red.
 const TASK_NOT_STARTED_FG: Rgb = Rgb(0xfe, 0x0d, 0x0c);
 /// Color 15.
@@ -88,6 +111,34 @@ const TASK_NOT_STARTED_BG: Reset = Reset;
 const TASK_DONE_FG: Rgb = Rgb(0x00, 0xd7, 0x00);
 /// Color 15.
 const TASK_DONE_BG: Reset = Reset;
+/// Color 196.
+const TASK_PRIORITY_A_FG: Rgb = Rgb(0xff, 0x00, 0x00);
+/// Color 208.
+const TASK_PRIORITY_B_FG: Rgb = Rgb(0xff, 0x87, 0x00);
+/// Color 220.
+const TASK_PRIORITY_C_FG: Rgb = Rgb(0xff, 0xd7, 0x00);
+/// Color 40.
+const TASK_PRIORITY_D_FG: Rgb = Rgb(0x00, 0xd7, 0x00);
+/// Color 33.
+const TASK_PRIORITY_E_FG: Rgb = Rgb(0x00, 0x87, 0xff);
+/// The terminal default background.
+const TASK_PRIORITY_BG: Reset = Reset;
+/// Color 0.
+const SEARCH_MATCH_FG: Rgb = Rgb(0x00, 0x00, 0x00);
+/// Color 220.
+const SEARCH_MATCH_BG: Rgb = Rgb(0xff, 0xd7, 0x00);
+/// Color 244.
+const TASK_PROGRESS_FG: Rgb = Rgb(0x80, 0x80, 0x80);
+/// The terminal default background.
+const TASK_PROGRESS_BG: Reset = Reset;
+/// Color 244.
+const TASK_DUE_FG: Rgb = Rgb(0x80, 0x80, 0x80);
+/// The terminal default background.
+const TASK_DUE_BG: Reset = Reset;
+/// Color 67.
+const TASK_TAG_FG: Rgb = Rgb(0x5f, 0x87, 0xaf);
+/// The terminal default background.
+const TASK_TAG_BG: Reset = Reset;
 /// Color 0.
 const IN_OUT_SUCCESS_FG: Rgb = Rgb(0x00, 0x00, 0x00);
 /// Color 40.
@@ -104,6 +155,18 @@ const IN_OUT_ERROR_BG: Rgb = Rgb(0xff, 0x00, 0x00);
 const IN_OUT_STRING_FG: Rgb = Rgb(0x00, 0x00, 0x00);
 /// The terminal default background.
 const IN_OUT_STRING_BG: Reset = Reset;
+/// Color 0.
+const HELP_TITLE_FG: Rgb = Rgb(0x00, 0x00, 0x00);
+/// Color 40.
+const HELP_TITLE_BG: Rgb = Rgb(0x00, 0xd7, 0x00);
+/// Color 15.
+const HELP_FG: Rgb = Rgb(0xff, 0xff, 0xff);
+/// Color 235.
+const HELP_BG: Rgb = Rgb(0x26, 0x26, 0x26);
+/// Color 15.
+const HELP_SELECTED_FG: Rgb = Rgb(0xff, 0xff, 0xff);
+/// Color 240.
+const HELP_SELECTED_BG: Rgb = Rgb(0x58, 0x58, 0x58);
 
 
 /// Sanitize an offset.
@@ -117,6 +180,17 @@ fn sanitize_offset(offset: usize, selection: usize, limit: usize) -> usize {
   }
 }
 
+/// Retrieve the color used for displaying the given priority.
+fn priority_color(priority: Priority) -> Rgb {
+  match priority {
+    Priority::A => TASK_PRIORITY_A_FG,
+    Priority::B => TASK_PRIORITY_B_FG,
+    Priority::C => TASK_PRIORITY_C_FG,
+    Priority::D => TASK_PRIORITY_D_FG,
+    Priority::E => TASK_PRIORITY_E_FG,
+  }
+}
+
 /// Align string centrally in the given `width` or cut it off if it is too long.
 fn align_center(string: impl Into<String>, width: usize) -> String {
   let mut string = string.into();
@@ -139,6 +213,21 @@ fn align_center(string: impl Into<String>, width: usize) -> String {
   string
 }
 
+/// Align string to the left in the given `width` or cut it off if it is too long.
+fn align_left(string: impl Into<String>, width: usize) -> String {
+  let mut string = string.into();
+  let length = string.len();
+
+  if length > width {
+    // Note: May underflow if width < 3. That's not really a supported
+    //       use case, though, so we ignore it here.
+    string.replace_range(width - 3..length, "...");
+  } else {
+    string.push_str(&" ".repeat(width - length));
+  }
+  string
+}
+
 /// Clip a string according to the active bounding box.
 fn clip(x: u16, y: u16, string: &str, bbox: BBox) -> &str {
   let w = bbox.w;
@@ -184,6 +273,16 @@ where
 
   /// Write a string to the terminal.
   fn write<F, B, S>(&self, x: u16, y: u16, fg: F, bg: B, string: S) -> Result<()>
+  where
+    F: Color,
+    B: Color,
+    S: AsRef<str>,
+  {
+    self.write_styled(x, y, fg, bg, false, string)
+  }
+
+  /// Write a string to the terminal, optionally in bold.
+  fn write_styled<F, B, S>(&self, x: u16, y: u16, fg: F, bg: B, bold: bool, string: S) -> Result<()>
   where
     F: Color,
     B: Color,
@@ -197,22 +296,28 @@ where
       let x = self.bbox.get().x + x + 1;
       let y = self.bbox.get().y + y + 1;
 
-      write!(
-        self.writer.borrow_mut(),
-        "{}{}{}{}",
-        Goto(x, y),
-        Fg(fg),
-        Bg(bg),
-        string,
-      )?
+      let mut writer = self.writer.borrow_mut();
+      write!(writer, "{}{}{}", Goto(x, y), Fg(fg), Bg(bg))?;
+      if bold {
+        // Resetting the text attributes resets the colors as well, but
+        // every write sets them anew.
+        write!(writer, "{}{}{}", Bold, string, StyleReset)?
+      } else {
+        write!(writer, "{}", string)?
+      }
     }
     Ok(())
   }
 
+  /// Convert a position relative to the bounding box into terminal coordinates.
+  fn position(&self, x: u16, y: u16) -> (u16, u16) {
+    // Termion works with an origin at (1,1).
+    (self.bbox.get().x + x + 1, self.bbox.get().y + y + 1)
+  }
+
   /// Move the cursor to the given position.
   fn goto(&self, x: u16, y: u16) -> Result<()> {
-    let x = self.bbox.get().x + x + 1;
-    let y = self.bbox.get().y + y + 1;
+    let (x, y) = self.position(x, y);
     write!(self.writer.borrow_mut(), "{}", Goto(x, y))
   }
 
@@ -251,6 +356,9 @@ where
 {
   writer: ClippingWriter<BufWriter<W>>,
   data: RefCell<HashMap<Id, OffsetData>>,
+  layout: Rc<RefCell<Layout>>,
+  /// The pattern of the last search, as of rendering the tab bar.
+  search: RefCell<Option<Pattern>>,
 }
 
 impl<W> TermRenderer<W>
@@ -271,9 +379,16 @@ where
     Ok(TermRenderer {
       writer: writer,
       data: Default::default(),
+      layout: Default::default(),
+      search: Default::default(),
     })
   }
 
+  /// Retrieve the `Layout` of clickable elements, as of the last render.
+  pub fn layout(&self) -> Rc<RefCell<Layout>> {
+    self.layout.clone()
+  }
+
   /// Retrieve the number of tasks that fit in the given `BBox`.
   fn displayable_tasks(&self, bbox: BBox) -> usize {
     ((bbox.h - MAIN_MARGIN_Y) / TASK_SPACE) as usize
@@ -293,6 +408,9 @@ where
   fn render_tab_bar(&self, tab_bar: &TabBar, mut bbox: BBox) -> Result<BBox> {
     let mut map = self.data.borrow_mut();
     let data = map.entry(tab_bar.id()).or_default();
+    // The tab bar is rendered before the tasks, which highlight matches
+    // of the last search.
+    *self.search.borrow_mut() = tab_bar.search_pattern().cloned();
 
     let mut x = 1;
     let w = bbox.w - 1;
@@ -336,10 +454,13 @@ where
         (UNSELECTED_QUERY_FG, UNSELECTED_QUERY_BG)
       };
 
-      let title = align_center(tab.clone(), TAB_TITLE_WIDTH as usize - 4);
+      let title = align_center(tab, TAB_TITLE_WIDTH as usize - 4);
       let padded = format!("  {}  ", title);
       self.writer.write(x, 0, fg, bg, padded)?;
 
+      let (abs_x, abs_y) = self.writer.position(x, 0);
+      self.layout.borrow_mut().add(abs_x, abs_y, TAB_TITLE_WIDTH, Target::Tab(i));
+
       x += TAB_TITLE_WIDTH;
     }
 
@@ -374,7 +495,8 @@ where
     let selection = task_list.selection();
     let offset = sanitize_offset(data.offset, selection, limit);
 
-    for (i, task) in query.iter().clone().enumerate().skip(offset).take(limit) {
+    let tasks = query.iter().clone().with_depth();
+    for (i, (task, depth)) in tasks.enumerate().skip(offset).take(limit) {
       let complete = task.is_complete();
       let (state, state_fg, state_bg) = if !complete {
         ("[ ]", TASK_NOT_STARTED_FG, TASK_NOT_STARTED_BG)
@@ -382,16 +504,94 @@ where
         ("[X]", TASK_DONE_FG, TASK_DONE_BG)
       };
 
-      let (task_fg, task_bg) = if i == selection {
-        (SELECTED_TASK_FG, &SELECTED_TASK_BG as &dyn Color)
-      } else {
-        (UNSELECTED_TASK_FG, &UNSELECTED_TASK_BG as &dyn Color)
+      // Tasks carrying a tag asking for it are tinted in the tag's
+      // color, unless selected or blocked.
+      let tint = task
+        .tags()
+        .map(|x| x.style())
+        .find_map(|x| if x.tint { x.color } else { None })
+        .map(|(r, g, b)| Rgb(r, g, b));
+
+      // Blocked tasks are dimmed.
+      let (task_fg, task_bg) = match (i == selection, task.is_blocked()) {
+        (true, false) => (SELECTED_TASK_FG, &SELECTED_TASK_BG as &dyn Color),
+        (true, true) => (SELECTED_BLOCKED_TASK_FG, &SELECTED_TASK_BG as &dyn Color),
+        (false, false) => (tint.unwrap_or(UNSELECTED_TASK_FG), &UNSELECTED_TASK_BG as &dyn Color),
+        (false, true) => (BLOCKED_TASK_FG, &UNSELECTED_TASK_BG as &dyn Color),
       };
 
+      let x = x + depth as u16 * TASK_INDENT;
       self.writer.write(x, y, state_fg, state_bg, state)?;
-      let x = x + state.len() as u16 + 1;
+      let (abs_x, abs_y) = self.writer.position(x, y);
+      let mut layout = self.layout.borrow_mut();
+      layout.add(abs_x, abs_y, state.len() as u16, Target::TaskState(i));
+
+      let mut x = x + state.len() as u16 + 1;
+      let (abs_x, abs_y) = self.writer.position(x, y);
+      layout.add(abs_x, abs_y, bbox.w.saturating_sub(x), Target::Task(i));
+
+      if let Some(priority) = task.priority() {
+        let marker = format!("({})", priority);
+        self.writer.write(x, y, priority_color(priority), TASK_PRIORITY_BG, &marker)?;
+        x += marker.len() as u16 + 1;
+      }
+
       self.writer.write(x, y, task_fg, task_bg, &task.summary)?;
 
+      let search = self.search.borrow();
+      let matches = search.as_ref().and_then(|x| x.find(&task.summary));
+      for range in matches.unwrap_or_default() {
+        let offset = task.summary[..range.start].chars().count() as u16;
+        let string = &task.summary[range];
+        self.writer.write(x + offset, y, SEARCH_MATCH_FG, SEARCH_MATCH_BG, string)?;
+      }
+      let mut info_x = x + task.summary.chars().count() as u16 + 1;
+
+      let (done, total) = query.progress(task);
+      if total > 0 {
+        let progress = if task.is_collapsed() {
+          format!("({}/{} done, collapsed)", done, total)
+        } else {
+          format!("({}/{} done)", done, total)
+        };
+        self.writer.write(info_x, y, TASK_PROGRESS_FG, TASK_PROGRESS_BG, &progress)?;
+        info_x += progress.len() as u16 + 1;
+      }
+
+      if let Some(due) = task.due() {
+        let due = format!("(due {})", due);
+        self.writer.write(info_x, y, TASK_DUE_FG, TASK_DUE_BG, &due)?;
+        info_x += due.len() as u16 + 1;
+      }
+
+      if let Some(recurrence) = task.recurrence() {
+        let recurrence = format!("(repeats {})", recurrence);
+        self.writer.write(info_x, y, TASK_DUE_FG, TASK_DUE_BG, &recurrence)?;
+        info_x += recurrence.len() as u16 + 1;
+      }
+
+      // Tags carrying a value or display attributes are displayed
+      // inline, as `name:value` or `name`, respectively. So are tags
+      // whose name matches the last search, with the match highlighted.
+      for tag in task.tags() {
+        let style = tag.style();
+        let name = tag.name();
+        let matches = search.as_ref().and_then(|x| x.find(&name));
+        if tag.value().is_some() || style.color.is_some() || style.bold || matches.is_some() {
+          let fg = style.color.map_or(TASK_TAG_FG, |(r, g, b)| Rgb(r, g, b));
+          let tag = tag.to_string();
+          self.writer.write_styled(info_x, y, fg, TASK_TAG_BG, style.bold, &tag)?;
+
+          // The name is the first part of the displayed tag.
+          for range in matches.unwrap_or_default() {
+            let offset = name[..range.start].chars().count() as u16;
+            let string = &name[range];
+            self.writer.write(info_x + offset, y, SEARCH_MATCH_FG, SEARCH_MATCH_BG, string)?;
+          }
+          info_x += tag.chars().count() as u16 + 1;
+        }
+      }
+
       if i == selection {
         cursor = Some((x, y));
       }
@@ -412,6 +612,225 @@ where
     Ok(bbox)
   }
 
+  /// Render a `HelpBox`.
+  fn render_help_box(&self, help: &HelpBox, bbox: BBox) -> Result<BBox> {
+    let mut map = self.data.borrow_mut();
+    let data = map.entry(help.id()).or_default();
+
+    let x = HELP_MARGIN_X;
+    let y = HELP_MARGIN_Y;
+    let w = bbox.w.saturating_sub(2 * HELP_MARGIN_X) as usize;
+    let h = bbox.h.saturating_sub(2 * HELP_MARGIN_Y);
+    // We need space for the title, a line of padding above and below
+    // the bindings, and at least one binding.
+    if w < 3 || h < 4 {
+      return Ok(Default::default())
+    }
+
+    let lines = help.lines();
+    let key_width = lines.iter().map(|(keys, _)| keys.len()).max().unwrap_or(0);
+    let limit = (h - 3) as usize;
+    let selection = help.selection();
+    let offset = sanitize_offset(data.offset, selection, limit);
+
+    let title = align_center(HELP_TEXT, w);
+    self.writer.write(x, y, HELP_TITLE_FG, HELP_TITLE_BG, title)?;
+
+    // The overlay is drawn on top of whatever else was rendered, so
+    // make sure to fill each of its lines completely.
+    for row in 1..h {
+      let idx = (row as usize).checked_sub(2).map(|x| x + offset);
+      let line = match idx {
+        Some(idx) if row < h - 1 && idx < lines.len() => {
+          let (keys, description) = &lines[idx];
+          format!("  {:width$}  {}", keys, description, width = key_width)
+        },
+        _ => String::new(),
+      };
+
+      let (fg, bg) = if idx == Some(selection) && row < h - 1 {
+        (HELP_SELECTED_FG, HELP_SELECTED_BG)
+      } else {
+        (HELP_FG, HELP_BG)
+      };
+      self.writer.write(x, y + row, fg, bg, align_left(line, w))?;
+    }
+
+    data.offset = offset;
+    Ok(Default::default())
+  }
+
+  /// Render a `TagBox`.
+  ///
+  /// The tag box is an overlay looking just like the help box.
+  fn render_tag_box(&self, tags: &TagBox, bbox: BBox) -> Result<BBox> {
+    let mut map = self.data.borrow_mut();
+    let data = map.entry(tags.id()).or_default();
+
+    let x = HELP_MARGIN_X;
+    let y = HELP_MARGIN_Y;
+    let w = bbox.w.saturating_sub(2 * HELP_MARGIN_X) as usize;
+    let h = bbox.h.saturating_sub(2 * HELP_MARGIN_Y);
+    if w < 3 || h < 4 {
+      return Ok(Default::default())
+    }
+
+    let entries = tags.entries();
+    let limit = (h - 3) as usize;
+    let selection = tags.selection();
+    let offset = sanitize_offset(data.offset, selection, limit);
+
+    let title = align_center(TAGS_TEXT, w);
+    self.writer.write(x, y, HELP_TITLE_FG, HELP_TITLE_BG, title)?;
+
+    for row in 1..h {
+      let idx = (row as usize).checked_sub(2).map(|x| x + offset);
+      let line = match idx {
+        Some(idx) if row < h - 1 && idx < entries.len() => {
+          let entry = &entries[idx];
+          let indent = "  ".repeat(entry.depth);
+          // Entries merely grouping nested tags are not tags themselves
+          // and can not be toggled.
+          match entry.id {
+            Some(id) => {
+              let mark = if tags.has_tag(id) { "[x]" } else { "[ ]" };
+              match tags.value(id) {
+                Some(value) => format!("  {}{} {}:{}", indent, mark, entry.name, value),
+                None => format!("  {}{} {}", indent, mark, entry.name),
+              }
+            },
+            None => format!("  {}    {}/", indent, entry.name),
+          }
+        },
+        _ => String::new(),
+      };
+
+      let (fg, bg) = if idx == Some(selection) && row < h - 1 {
+        (HELP_SELECTED_FG, HELP_SELECTED_BG)
+      } else {
+        (HELP_FG, HELP_BG)
+      };
+      self.writer.write(x, y + row, fg, bg, align_left(line, w))?;
+    }
+
+    data.offset = offset;
+    Ok(Default::default())
+  }
+
+  /// Render a `TagManager`.
+  ///
+  /// Just like the tag box, the tag manager is an overlay looking like
+  /// the help box.
+  fn render_tag_manager(&self, manager: &TagManager, bbox: BBox) -> Result<BBox> {
+    let mut map = self.data.borrow_mut();
+    let data = map.entry(manager.id()).or_default();
+
+    let x = HELP_MARGIN_X;
+    let y = HELP_MARGIN_Y;
+    let w = bbox.w.saturating_sub(2 * HELP_MARGIN_X) as usize;
+    let h = bbox.h.saturating_sub(2 * HELP_MARGIN_Y);
+    if w < 3 || h < 4 {
+      return Ok(Default::default())
+    }
+
+    let entries = manager.entries();
+    let limit = (h - 3) as usize;
+    let selection = manager.selection();
+    let offset = sanitize_offset(data.offset, selection, limit);
+    let width = entries.iter().map(|x| x.name.len()).max().unwrap_or(0);
+
+    let title = align_center(TAG_MANAGER_TEXT, w);
+    self.writer.write(x, y, HELP_TITLE_FG, HELP_TITLE_BG, title)?;
+
+    for row in 1..h {
+      let idx = (row as usize).checked_sub(2).map(|x| x + offset);
+      let line = match idx {
+        Some(idx) if row < h - 1 && idx < entries.len() => {
+          let entry = &entries[idx];
+          format!(
+            "  {:<width$}  {:>4} tasks  {:>3} queries",
+            entry.name, entry.tasks, entry.queries, width = width,
+          )
+        },
+        _ => String::new(),
+      };
+
+      let (fg, bg) = if idx == Some(selection) && row < h - 1 {
+        (HELP_SELECTED_FG, HELP_SELECTED_BG)
+      } else {
+        (HELP_FG, HELP_BG)
+      };
+      self.writer.write(x, y + row, fg, bg, align_left(line, w))?;
+    }
+
+    data.offset = offset;
+    Ok(Default::default())
+  }
+
+  /// Render a `HistoryView`.
+  ///
+  /// Depending on its state, the view lists either all versions or the
+  /// tasks as of one of them.
+  fn render_history_view(&self, view: &HistoryView, bbox: BBox) -> Result<BBox> {
+    let mut map = self.data.borrow_mut();
+    let data = map.entry(view.id()).or_default();
+
+    let x = HELP_MARGIN_X;
+    let y = HELP_MARGIN_Y;
+    let w = bbox.w.saturating_sub(2 * HELP_MARGIN_X) as usize;
+    let h = bbox.h.saturating_sub(2 * HELP_MARGIN_Y);
+    if w < 3 || h < 4 {
+      return Ok(Default::default())
+    }
+
+    let (title, lines) = match view.version() {
+      Some((version, tasks)) => {
+        let title = format!("{} as of {}", HISTORY_TEXT, version.date);
+        let lines = tasks
+          .iter()
+          .map(|task| {
+            let complete = if task.is_complete() { "x" } else { " " };
+            format!("  [{}] {}", complete, task.summary)
+          })
+          .collect::<Vec<_>>();
+        (title, lines)
+      },
+      None => {
+        let lines = view
+          .versions()
+          .iter()
+          .map(|version| format!("  {}  {}", version.date, version.summary))
+          .collect::<Vec<_>>();
+        (HISTORY_TEXT.to_string(), lines)
+      },
+    };
+
+    let limit = (h - 3) as usize;
+    let selection = view.selection();
+    let offset = sanitize_offset(data.offset, selection, limit);
+
+    let title = align_center(title, w);
+    self.writer.write(x, y, HELP_TITLE_FG, HELP_TITLE_BG, title)?;
+
+    for row in 1..h {
+      let idx = (row as usize).checked_sub(2).map(|x| x + offset);
+      let line = match idx {
+        Some(idx) if row < h - 1 && idx < lines.len() => lines[idx].clone(),
+        _ => String::new(),
+      };
+
+      let (fg, bg) = if idx == Some(selection) && row < h - 1 {
+        (HELP_SELECTED_FG, HELP_SELECTED_BG)
+      } else {
+        (HELP_FG, HELP_BG)
+      };
+      self.writer.write(x, y + row, fg, bg, align_left(line, w))?;
+    }
+
+    data.offset = offset;
+    Ok(Default::default())
+  }
+
   /// Render an `InOutArea`.
   fn render_input_output(&self, in_out: &InOutArea, bbox: BBox, cap: &dyn Cap) -> Result<BBox> {
     let (prefix, fg, bg, string) = match in_out.state() {
@@ -460,6 +879,9 @@ where
   }
 
   fn pre_render(&self) {
+    // Whatever was clickable before may have moved or vanished.
+    self.layout.borrow_mut().clear();
+
     // By default we disable the cursor, but we may opt for enabling it
     // again when rendering certain widgets.
     let err = self.writer.clear_all().and_then(|_| self.writer.hide());
@@ -482,6 +904,14 @@ where
       result = self.render_tab_bar(tab_bar, bbox);
     } else if let Some(task_list) = widget.downcast_ref::<TaskListBox>() {
       result = self.render_task_list_box(task_list, bbox);
+    } else if let Some(help) = widget.downcast_ref::<HelpBox>() {
+      result = self.render_help_box(help, bbox);
+    } else if let Some(tags) = widget.downcast_ref::<TagBox>() {
+      result = self.render_tag_box(tags, bbox);
+    } else if let Some(manager) = widget.downcast_ref::<TagManager>() {
+      result = self.render_tag_manager(manager, bbox);
+    } else if let Some(view) = widget.downcast_ref::<HistoryView>() {
+      result = self.render_history_view(view, bbox);
     } else {
       panic!("Widget {:?} is unknown to the renderer", widget)
     }
@@ -533,6 +963,15 @@ mod tests {
     assert_eq!(align_center("that's a test", 8), "that'...");
   }
 
+  #[test]
+  fn align_string_left() {
+    assert_eq!(align_left("", 0), "");
+    assert_eq!(align_left("", 3), "   ");
+    assert_eq!(align_left("a", 3), "a  ");
+    assert_eq!(align_left("abc", 3), "abc");
+    assert_eq!(align_left("hello", 4), "h...");
+  }
+
   #[test]
   fn clip_string() {
     let bbox = BBox {
@@ -18,8 +18,12 @@
 // *************************************************************************
 
 use std::any::Any;
+use std::cell::RefCell;
 use std::io::Result;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use std::time::Instant;
 
 use gui::derive::Widget;
 use gui::Handleable;
@@ -28,22 +32,37 @@ use gui::MutCap;
 use gui::UiEvent;
 use gui::UiEvents;
 
-use crate::query::Query;
-use crate::state::State;
-use crate::state::TaskState;
-use crate::state::UiState;
-use crate::tasks::Id as TaskId;
-#[cfg(all(test, not(feature = "readline")))]
-use crate::tasks::Task;
-
+use notnow::query::Query;
+use notnow::search::Pattern;
+use notnow::state::State;
+use notnow::state::TaskState;
+use notnow::state::UiState;
+use notnow::tags::Id as TagId;
+use notnow::tags::Tag;
+use notnow::tasks::Id as TaskId;
+use notnow::tasks::Task;
+
+use super::bindings::Action;
+use super::bindings::Bindings;
 use super::event::Event;
-use super::event::Key;
+use super::event::EventUpdate;
+use super::event::MouseButton;
+use super::event::MouseEvent;
+use super::help_box::HelpBox;
+use super::history_view::HistoryView;
 use super::in_out::InOut;
 use super::in_out::InOutArea;
+use super::layout::Layout;
+use super::layout::Target;
 use super::tab_bar::IterationState;
 use super::tab_bar::SearchState;
 use super::tab_bar::TabBar;
 use super::tab_bar::TabState;
+use super::tag_box::TagBox;
+use super::tag_manager::TagManager;
+
+/// The maximum time between two clicks for them to form a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
 
 
 /// An enumeration comprising all custom events we support.
@@ -54,12 +73,21 @@ pub enum TermUiEvent {
   SelectTask(TaskId, IterationState),
   /// Search for a task containing the given string in its summary and
   /// select it.
-  SearchTask(String, SearchState, IterationState),
+  SearchTask(Pattern, SearchState, IterationState),
   /// The tab with the given `Id` has selected the task as indicated by
   /// `SelectTask` or `SearchTask`.
   SelectedTask(Id),
+  /// The tab with the given `Id` got temporarily narrowed down to tasks
+  /// matching a pattern, or got its narrowing removed.
+  NarrowedTab(Id, bool),
+  /// The given UI element was clicked. The flag indicates whether the
+  /// click was a double click.
+  Clicked(Target, bool),
   /// Set the state of the input/output area.
   SetInOut(InOut),
+  /// Start text input just like `SetInOut` with `InOut::Input` does,
+  /// but report every change to the text via `ChangedText`.
+  SetIncrementalInput(String, usize),
   /// Change the state of the input/output area to Clear, unless the
   /// generation ID supplied does not match the current generation ID.
   /// This event is internal to the InOutArea, there is no need for
@@ -69,12 +97,31 @@ pub enum TermUiEvent {
   EnteredText(String),
   /// Text input has been canceled.
   InputCanceled,
+  /// The text being entered changed.
+  ChangedText(String),
+  /// Remember the currently selected task.
+  SaveSelection,
+  /// Select the task remembered via `SaveSelection` again.
+  RestoreSelection,
   /// An event used to collect the state from the `TabBar`.
   CollectState(Id),
   /// The response to the `CollectState` event.
   CollectedState(Vec<(Query, Option<usize>)>, Option<usize>),
   /// An event used to collect the state of all tabs.
   GetTabState(TabState, IterationState),
+  /// Show the overlay listing all key bindings.
+  ShowHelp,
+  /// Show the overlay for editing the tags of the given task.
+  EditTags(Task),
+  /// Replace a task with the given, modified version of it.
+  UpdateTask(Task),
+  /// Show the overlay for managing tag templates.
+  ManageTags,
+  /// Show the overlay for browsing the history.
+  ShowHistory,
+  /// Make all queries referring to the tag template with the given ID
+  /// refer to the given tag instead.
+  ReplaceTag(TagId, Tag),
   /// A indication that some component changed and that we should
   /// re-render everything.
   Updated,
@@ -111,40 +158,112 @@ pub struct TermUi {
   id: Id,
   in_out: Id,
   tab_bar: Id,
+  help: Id,
+  tags: Id,
+  tag_manager: Id,
+  history: Id,
   task_state: TaskState,
   ui_state_path: PathBuf,
+  archive_after: Option<u32>,
+  bindings: Rc<Bindings>,
+  layout: Rc<RefCell<Layout>>,
+  last_click: Option<(Target, Instant)>,
 }
 
 
 impl TermUi {
   /// Create a new view associated with the given `State` object.
-  pub fn new(id: Id, cap: &mut dyn MutCap<Event>, state: State) -> Self {
-    let State(task_state, UiState{path, queries, selected}) = state;
+  ///
+  /// The `Layout` is used for finding the UI elements targeted by mouse
+  /// clicks.
+  pub fn new(id: Id,
+             cap: &mut dyn MutCap<Event>,
+             state: State,
+             layout: Rc<RefCell<Layout>>) -> Self {
+    let State(task_state, UiState{path, queries, selected, archive_after, ..}) = state;
     let mut queries = Some(queries);
-
+    let bindings = Rc::new(Bindings::default());
+
+    // Let the user know if we had to drop invalid tag references
+    // while loading. Saving writes back the repaired state.
+    let in_out = match task_state.dropped() {
+      [] => InOut::Clear,
+      [dropped] => InOut::Error(format!("{}; save to repair", dropped)),
+      dropped => InOut::Error(format!(
+        "Dropped {} invalid tag references; save to repair", dropped.len()
+      )),
+    };
+    let mut in_out = Some(in_out);
     let in_out = cap.add_widget(id, &mut |id, cap| {
-      Box::new(InOutArea::new(id, cap))
+      Box::new(InOutArea::with_in_out(id, cap, in_out.take().unwrap()))
     });
     let tab_bar = cap.add_widget(id, &mut |id, cap| {
       let queries = queries.take().unwrap();
-      Box::new(TabBar::new(id, cap, &task_state, queries, selected))
+      let bindings = bindings.clone();
+      Box::new(TabBar::new(id, cap, &task_state, queries, selected, bindings))
+    });
+    let help = cap.add_widget(id, &mut |id, cap| {
+      Box::new(HelpBox::new(id, cap, bindings.clone()))
+    });
+    let tags = cap.add_widget(id, &mut |id, cap| {
+      Box::new(TagBox::new(id, cap, bindings.clone()))
+    });
+    let tag_manager = cap.add_widget(id, &mut |id, cap| {
+      Box::new(TagManager::new(id, cap, tab_bar, &task_state, bindings.clone()))
+    });
+    let history = cap.add_widget(id, &mut |id, cap| {
+      Box::new(HistoryView::new(id, cap, &task_state, bindings.clone()))
     });
 
     TermUi {
       id: id,
       in_out: in_out,
       tab_bar: tab_bar,
+      help: help,
+      tags: tags,
+      tag_manager: tag_manager,
+      history: history,
       task_state: task_state,
       ui_state_path: path,
+      archive_after: archive_after,
+      bindings: bindings,
+      layout: layout,
+      last_click: None,
+    }
+  }
+
+  /// Handle a mouse event.
+  fn handle_mouse(&mut self, mouse: MouseEvent) -> Option<UiEvents<Event>> {
+    match mouse {
+      MouseEvent::Press(MouseButton::Left, x, y) => {
+        let target = self.layout.borrow().find(x, y)?;
+        let now = Instant::now();
+        // A click on the same element shortly after the previous one
+        // constitutes a double click. We do not want a third click to
+        // be treated as yet another double click, though.
+        let double = match self.last_click.take() {
+          Some((last, time)) => last == target && now - time <= DOUBLE_CLICK_TIME,
+          None => false,
+        };
+        if !double {
+          self.last_click = Some((target, now));
+        }
+
+        let event = TermUiEvent::Clicked(target, double);
+        Some(UiEvent::Directed(self.tab_bar, Box::new(event)).into())
+      },
+      _ => None,
     }
   }
 
   /// Persist the state into a file.
   fn save_all(&self, ui_state: &UiState) -> Result<()> {
-    self.task_state.save()?;
+    // The UI state goes first, so that it is part of the version the
+    // task state records in the history.
+    ui_state.save()?;
     // TODO: We risk data inconsistencies if the second save operation
     //       fails.
-    ui_state.save()?;
+    self.task_state.save()?;
     Ok(())
   }
 
@@ -172,12 +291,16 @@ impl TermUi {
           path: self.ui_state_path.clone(),
           queries: queries,
           selected: selected,
+          archive_after: self.archive_after,
+          history: self.task_state.history().is_some(),
         };
         Some(self.save_and_report(&ui_state))
       },
-      TermUiEvent::SetInOut(_) => {
+      TermUiEvent::SetInOut(_) |
+      TermUiEvent::SetIncrementalInput(..) => {
         Some(UiEvent::Directed(self.in_out, event).into())
       },
+      TermUiEvent::EditTags(_) => Some(UiEvent::Directed(self.tags, event).into()),
       #[cfg(all(test, not(feature = "readline")))]
       TermUiEvent::GetTasks => {
         let tasks = self.task_state.tasks();
@@ -201,12 +324,29 @@ impl Handleable<Event> for TermUi {
   fn handle(&mut self, event: Event, _cap: &mut dyn MutCap<Event>) -> Option<UiEvents<Event>> {
     match event {
       Event::Key(key, _) => {
-        match key {
-          Key::Char('q') => Some(UiEvent::Quit.into()),
-          Key::Char('w') => Some(self.save()),
+        match self.bindings.action(key) {
+          Some(Action::Quit) => Some(UiEvent::Quit.into()),
+          Some(Action::Save) => Some(self.save()),
+          Some(Action::ArchiveTasks) => {
+            let archived = self.task_state.archive_completed();
+            (None as Option<Event>).maybe_update(archived)
+          },
+          Some(Action::Help) => {
+            let event = TermUiEvent::ShowHelp;
+            Some(UiEvent::Directed(self.help, Box::new(event)).into())
+          },
+          Some(Action::ManageTags) => {
+            let event = TermUiEvent::ManageTags;
+            Some(UiEvent::Directed(self.tag_manager, Box::new(event)).into())
+          },
+          Some(Action::ShowHistory) => {
+            let event = TermUiEvent::ShowHistory;
+            Some(UiEvent::Directed(self.history, Box::new(event)).into())
+          },
           _ => Some(event.into()),
         }
       },
+      Event::Mouse(mouse) => self.handle_mouse(mouse),
     }
   }
 
@@ -234,19 +374,25 @@ mod tests {
   use gui::UnhandledEvent;
   use gui::UnhandledEvents;
 
-  use crate::ser::query::Query as SerQuery;
-  use crate::ser::query::TagLit as SerTagLit;
-  use crate::ser::state::TaskState as SerTaskState;
-  use crate::ser::state::UiState as SerUiState;
-  use crate::ser::tags::Templates as SerTemplates;
-  use crate::ser::tasks::Task as SerTask;
-  use crate::ser::tasks::Tasks as SerTasks;
-  use crate::ser::ToSerde;
-  use crate::state::State;
+  use notnow::ser::query::Query as SerQuery;
+  use notnow::ser::query::Sort as SerSort;
+  use notnow::ser::query::TagLit as SerTagLit;
+  use notnow::ser::state::TaskState as SerTaskState;
+  use notnow::ser::state::UiState as SerUiState;
+  use notnow::ser::id::Id as SerId;
+  use notnow::ser::tags::Template as SerTemplate;
+  use notnow::ser::tags::Templates as SerTemplates;
+  use notnow::ser::tasks::Priority as SerPriority;
+  use notnow::ser::tasks::Task as SerTask;
+  use notnow::ser::tasks::Tasks as SerTasks;
+  use notnow::ser::ToSerde;
+  use notnow::state::State;
+
   use crate::test::make_tasks;
   use crate::test::make_tasks_with_tags;
-  use crate::test::NamedTempFile;
+  use crate::test::TempDir;
   use crate::ui::event::EventUpdated;
+  use crate::ui::event::Key;
   use crate::ui::event::tests::CustomEvent;
 
 
@@ -271,35 +417,42 @@ mod tests {
     let task_state = SerTaskState {
       templates: SerTemplates(templates),
       tasks: SerTasks(tasks),
+      ..Default::default()
     };
     let ui_state = SerUiState {
       queries: vec![
         (SerQuery {
           name: "all".to_string(),
           lits: vec![],
+          ..Default::default()
         }, None),
         (SerQuery {
           name: "tag complete".to_string(),
-          lits: vec![vec![SerTagLit::Pos(tags[0])]],
+          lits: vec![vec![SerTagLit::Pos(tags[0].clone())]],
+          ..Default::default()
         }, None),
         (SerQuery {
           name: "tag2 || tag3".to_string(),
           lits: vec![
             vec![
-              SerTagLit::Pos(tags[2]),
-              SerTagLit::Pos(tags[3]),
+              SerTagLit::Pos(tags[2].clone()),
+              SerTagLit::Pos(tags[3].clone()),
             ],
           ],
+          ..Default::default()
         }, None),
         (SerQuery {
           name: "tag1 && tag3".to_string(),
           lits: vec![
-            vec![SerTagLit::Pos(tags[1])],
-            vec![SerTagLit::Pos(tags[3])],
+            vec![SerTagLit::Pos(tags[1].clone())],
+            vec![SerTagLit::Pos(tags[3].clone())],
           ],
+          ..Default::default()
         }, None),
       ],
       selected: None,
+      archive_after: None,
+      ..Default::default()
     };
 
     (task_state, ui_state)
@@ -332,6 +485,7 @@ mod tests {
         task_state: SerTaskState {
           templates: Default::default(),
           tasks: SerTasks(tasks.into()),
+          ..Default::default()
         },
         ui_state: Default::default(),
       }
@@ -348,20 +502,30 @@ mod tests {
     fn build(self) -> TestUi {
       let mut task_state = Some(self.task_state);
       let mut ui_state = Some(self.ui_state);
-      let task_file = NamedTempFile::new();
-      let ui_file = NamedTempFile::new();
+      // All files live in a directory of their own, which doubles as
+      // the repository in case the history is enabled.
+      let dir = TempDir::new();
+      let task_file = dir.path().join("tasks.json");
+      let archive_file = dir.path().join("archive.json");
+      let ui_file = dir.path().join("notnow.json");
+      let layout = Rc::new(RefCell::new(Layout::default()));
 
       let (ui, _) = Ui::new(&mut |id, cap| {
         let task_state = task_state.take().unwrap();
         let ui_state = ui_state.take().unwrap();
-        let state = State::with_serde(task_state, task_file.path(), ui_state, ui_file.path());
-        Box::new(TermUi::new(id, cap, state.unwrap()))
+        // Invalid tag references are dropped, to be reported in the UI.
+        let files = vec![(task_state, &task_file, Default::default(), &archive_file)];
+        let state = State::with_serde_files(files, ui_state, &ui_file, true);
+        Box::new(TermUi::new(id, cap, state.unwrap(), layout.clone()))
       });
 
       TestUi {
+        dir: dir,
         task_file: task_file,
+        archive_file: archive_file,
         ui_file: ui_file,
         ui: ui,
+        layout: layout,
       }
     }
   }
@@ -370,12 +534,21 @@ mod tests {
   /// `Ui`.
   #[allow(unused)]
   struct TestUi {
-    task_file: NamedTempFile,
-    ui_file: NamedTempFile,
+    dir: TempDir,
+    task_file: PathBuf,
+    archive_file: PathBuf,
+    ui_file: PathBuf,
     ui: Ui<Event>,
+    layout: Rc<RefCell<Layout>>,
   }
 
   impl TestUi {
+    /// Pretend the given element was rendered at the given position.
+    fn place(&mut self, x: u16, y: u16, target: Target) -> &mut Self {
+      self.layout.borrow_mut().add(x, y, 1, target);
+      self
+    }
+
     /// Handle a single event and directly return the result.
     fn evaluate<E>(&mut self, event: E) -> Option<UnhandledEvents<Event>>
     where
@@ -429,14 +602,25 @@ mod tests {
     }
 
     /// Retrieve the current set of tasks in the form of `SerTask` objects from the UI.
+    ///
+    /// Creation times of tasks are not reported, as they depend on the
+    /// time a test ran.
     fn ser_tasks(&mut self) -> Vec<SerTask> {
-      self.tasks().iter().map(|x| x.to_serde()).collect()
+      self
+        .tasks()
+        .iter()
+        .map(|x| SerTask {
+          created: None,
+          ..x.to_serde()
+        })
+        .collect()
     }
 
     /// Load the UI's state from a file. Note that unless the state has
     /// been saved, the result will probably just be the default state.
     fn load_state(&self) -> Result<State> {
-      State::new(self.task_file.path(), self.ui_file.path())
+      let files = vec![(&self.task_file, &self.archive_file)];
+      State::with_files(files, &self.ui_file, false)
     }
   }
 
@@ -1199,9 +1383,12 @@ mod tests {
 
   #[test]
   fn select_last_tab_plus_one() {
+    // The last tab is the archive, which is read-only. Going back one
+    // tab from there lands on the last one that can be modified.
     let events = vec![
       Event::from('0').into(),
       Event::from('l').into(),
+      Event::from('h').into(),
       Event::from('d').into(),
     ];
 
@@ -1224,206 +1411,1113 @@ mod tests {
   }
 
   #[test]
-  fn in_out_state_after_write() {
-    let tasks = make_tasks(2);
+  fn select_tab_by_click() {
     let events = vec![
-      Event::from('w').into(),
+      Event::Mouse(MouseEvent::Press(MouseButton::Left, 3, 1)).into(),
+      Event::from('d').into(),
     ];
 
-    let state = TestUiBuilder::with_ser_tasks(tasks)
+    let tasks = TestUiBuilder::with_default_tasks_and_tags()
       .build()
+      .place(1, 1, Target::Tab(0))
+      .place(3, 1, Target::Tab(2))
       .handle(events)
-      .in_out();
-
-    assert_eq!(state, InOut::Saved);
-  }
-
-  #[test]
-  fn in_out_state_after_write_and_key_press() {
-    fn with_key(key: impl Into<Event>) -> InOut {
-      let tasks = make_tasks(2);
-      let events = vec![
-        Event::from('w').into(),
-        key.into().into(),
-      ];
-
-      TestUiBuilder::with_ser_tasks(tasks)
-        .build()
-        .handle(events)
-        .in_out()
-    }
-
-    // We test all ASCII chars.
-    for c in 0u8..127u8 {
-      let c = c as char;
-      if c != 'a' && c != 'e' && c != 'n' && c != 'N' && c != 'w' && c != '/' && c != '?' {
-        assert_eq!(with_key(c), InOut::Clear, "char: {} ({})", c, c as u8);
-      }
-    }
-
-    assert_eq!(with_key(Key::Esc), InOut::Clear);
-    assert_eq!(with_key('\n'), InOut::Clear);
-    assert_eq!(with_key(Key::PageDown), InOut::Clear);
-  }
-
-  #[test]
-  fn in_out_state_on_edit() {
-    fn with_key(key: impl Into<Event>) -> InOut {
-      let tasks = make_tasks(4);
-      let events = vec![
-        Event::from('a').into(),
-        key.into().into(),
-      ];
-
-      TestUiBuilder::with_ser_tasks(tasks)
-        .build()
-        .handle(events)
-        .in_out()
-    }
-
-    for c in 0u8..127u8 {
-      if c == b'\n' {
-        continue
-      }
+      .tasks()
+      .into_iter()
+      .map(|x| x.summary)
+      .collect::<Vec<_>>();
 
-      let state = with_key(c);
-      match state {
-        InOut::Input(_, _) => (),
-        _ => assert!(false, "Unexpected state {:?} for char {}", state, c),
-      }
-    }
+    let (.., mut expected) = make_tasks_with_tags(15);
+    expected.remove(8);
+    let expected = expected
+      .into_iter()
+      .map(|x| x.summary)
+      .collect::<Vec<_>>();
 
-    assert_eq!(with_key(Key::Esc), InOut::Clear);
-    assert_eq!(with_key('\n'), InOut::Clear);
+    assert_eq!(tasks, expected);
   }
 
   #[test]
-  fn search_abort() {
-    fn test(c: char) {
-      let tasks = make_tasks(4);
-      let events = vec![
-        Event::from(c).into(),
-        Event::from('f').into(),
-        Event::from(Key::Esc).into(),
-      ];
-
-      let state = TestUiBuilder::with_ser_tasks(tasks)
-        .build()
-        .handle(events)
-        .in_out();
+  fn select_task_by_click() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::Mouse(MouseEvent::Press(MouseButton::Left, 5, 5)).into(),
+      Event::Mouse(MouseEvent::Press(MouseButton::Left, 1, 1)).into(),
+      Event::from('d').into(),
+    ];
 
-      assert_eq!(state, InOut::Clear);
-    }
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .place(5, 3, Target::Task(0))
+      .place(5, 5, Target::Task(1))
+      .handle(events)
+      .ser_tasks();
 
-    test('/');
-    test('?');
+    let mut expected = make_tasks(3);
+    expected.remove(1);
+    assert_eq!(tasks, expected)
   }
 
   #[test]
-  fn search_tasks_not_found() {
-    fn test(c: char) {
-      let tasks = make_tasks(8);
-      let events = vec![
-        Event::from(c).into(),
-        Event::from('f').into(),
-        Event::from('o').into(),
-        Event::from('o').into(),
-        Event::from('\n').into(),
-      ];
-
-      let state = TestUiBuilder::with_ser_tasks(tasks)
-        .build()
-        .handle(events)
-        .in_out();
+  fn complete_task_by_click() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::Mouse(MouseEvent::Press(MouseButton::Left, 1, 5)).into(),
+    ];
 
-      let expected = InOut::Error("Text 'foo' not found".to_string());
-      assert_eq!(state, expected);
-    }
+    let complete = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .place(1, 5, Target::TaskState(2))
+      .handle(events)
+      .tasks()
+      .iter()
+      .map(|x| x.is_complete())
+      .collect::<Vec<_>>();
 
-    test('/');
-    test('?');
+    assert_eq!(complete, vec![false, false, true])
   }
 
   #[test]
-  fn search_multiple_tabs_not_found() {
-    fn test(c: char) {
-      let events = vec![
-        Event::from(c).into(),
-        Event::from('f').into(),
-        Event::from('o').into(),
-        Event::from('o').into(),
-        Event::from('\n').into(),
-      ];
-
-      let state = TestUiBuilder::with_default_tasks_and_tags()
-        .build()
-        .handle(events)
-        .in_out();
+  fn edit_task_by_double_click() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::Mouse(MouseEvent::Press(MouseButton::Left, 5, 3)).into(),
+      Event::Mouse(MouseEvent::Press(MouseButton::Left, 5, 5)).into(),
+      Event::Mouse(MouseEvent::Press(MouseButton::Left, 5, 5)).into(),
+      Event::from('x').into(),
+      Event::from('\n').into(),
+    ];
 
-      let expected = InOut::Error("Text 'foo' not found".to_string());
-      assert_eq!(state, expected);
-    }
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .place(5, 3, Target::Task(0))
+      .place(5, 5, Target::Task(1))
+      .handle(events)
+      .ser_tasks();
 
-    test('/');
-    test('?');
+    let mut expected = make_tasks(3);
+    expected[1].summary = "2x".to_string();
+    assert_eq!(tasks, expected)
   }
 
   #[test]
-  fn search_continue_without_start() {
-    fn test(c: char) {
-      let tasks = make_tasks(4);
-      let events = vec![
-        Event::from(c).into(),
-        Event::from(c).into(),
-        Event::from(c).into(),
-      ];
-
-      let state = TestUiBuilder::with_ser_tasks(tasks)
-        .build()
-        .handle(events)
-        .in_out();
+  fn select_task_by_scrolling() {
+    let tasks = make_tasks(4);
+    let events = vec![
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1)).into(),
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1)).into(),
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1)).into(),
+      Event::Mouse(MouseEvent::Press(MouseButton::WheelUp, 1, 1)).into(),
+      Event::from('d').into(),
+    ];
 
-      let expected = InOut::Error("Nothing to search for".to_string());
-      assert_eq!(state, expected);
-    }
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
 
-    test('n');
-    test('N');
+    let mut expected = make_tasks(4);
+    expected.remove(2);
+    assert_eq!(tasks, expected)
   }
 
   #[test]
-  fn search_tasks_repeatedly_not_found() {
-    fn test(c: char) {
-      let tasks = make_tasks(8);
-      let events = vec![
-        Event::from('/').into(),
-        Event::from('z').into(),
-        Event::from('\n').into(),
-        Event::from(c).into(),
-      ];
+  fn change_task_priority() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('+').into(),
+      Event::from('+').into(),
+      Event::from('j').into(),
+      Event::from('+').into(),
+      Event::from('k').into(),
+      Event::from('-').into(),
+    ];
 
-      let state = TestUiBuilder::with_ser_tasks(tasks)
-        .build()
-        .handle(events)
-        .in_out();
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
 
-      let expected = InOut::Error("Text 'z' not found".to_string());
-      assert_eq!(state, expected);
-    }
+    let mut expected = make_tasks(3);
+    expected[1].priority = Some(SerPriority::E);
+    expected[2].priority = Some(SerPriority::E);
 
-    test('n');
-    test('N');
+    assert_eq!(tasks, expected)
   }
 
   #[test]
-  fn search_tasks() {
-    let tasks = make_tasks(12);
+  fn sort_tasks_by_priority() {
+    let tasks = make_tasks(4);
     let events = vec![
-      Event::from('/').into(),
-      Event::from('2').into(),
-      Event::from('\n').into(),
-      Event::from('d').into(),
-      Event::from('n').into(),
+      Event::from('j').into(),
+      Event::from('j').into(),
+      Event::from('+').into(),
+      Event::from('G').into(),
+      Event::from('+').into(),
+      Event::from('+').into(),
+      Event::from('P').into(),
+      Event::from('j').into(),
+      Event::from('d').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    // The tab displays the task with the highest priority first, with
+    // the selection following it, but the order of tasks is retained.
+    let mut expected = make_tasks(4);
+    expected[3].priority = Some(SerPriority::D);
+    let expected = vec![
+      expected[0].clone(),
+      expected[1].clone(),
+      expected[3].clone(),
+    ];
+
+    assert_eq!(tasks, expected)
+  }
+
+  #[test]
+  fn toggle_sort_by_priority() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('G').into(),
+      Event::from('+').into(),
+      Event::from('P').into(),
+      Event::from('P').into(),
+      Event::from('g').into(),
+      Event::from('d').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    let mut expected = make_tasks(3);
+    expected[2].priority = Some(SerPriority::E);
+    let expected = vec![expected[1].clone(), expected[2].clone()];
+
+    assert_eq!(tasks, expected)
+  }
+
+  #[test]
+  fn set_task_due_date() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('D').into(),
+      Event::from('2').into(),
+      Event::from('0').into(),
+      Event::from('1').into(),
+      Event::from('9').into(),
+      Event::from('-').into(),
+      Event::from('0').into(),
+      Event::from('5').into(),
+      Event::from('-').into(),
+      Event::from('0').into(),
+      Event::from('7').into(),
+      Event::from('\n').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    let mut expected = make_tasks(3);
+    expected[1].due = Some("2019-05-07".to_string());
+
+    assert_eq!(tasks, expected)
+  }
+
+  #[test]
+  fn set_invalid_task_due_date() {
+    let tasks = make_tasks(2);
+    let events = vec![
+      Event::from('D').into(),
+      Event::from('x').into(),
+      Event::from('\n').into(),
+    ];
+
+    let mut ui = TestUiBuilder::with_ser_tasks(tasks).build();
+    let in_out = ui.handle(events).in_out();
+    let expected = InOut::Error("Encountered invalid date 'x'".to_string());
+
+    assert_eq!(in_out, expected);
+    assert_eq!(ui.ser_tasks(), make_tasks(2));
+  }
+
+  #[test]
+  fn clear_task_due_date() {
+    let mut tasks = make_tasks(2);
+    tasks[0].due = Some("2019-05-07".to_string());
+    let events = vec![
+      Event::from('D').into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from(Key::Backspace).into(),
+      Event::from('\n').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    assert_eq!(tasks, make_tasks(2))
+  }
+
+  #[test]
+  fn set_task_recurrence() {
+    let tasks = make_tasks(3);
+    let mut events = vec![
+      Event::from('j').into(),
+      Event::from('R').into(),
+    ];
+    events.extend("weekly mon,thu\n".chars().map(|c| Event::from(c).into()));
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    let mut expected = make_tasks(3);
+    expected[1].recurrence = Some("weekly mon,thu".to_string());
+
+    assert_eq!(tasks, expected)
+  }
+
+  #[test]
+  fn set_invalid_task_recurrence() {
+    let tasks = make_tasks(2);
+    let mut events = vec![Event::from('R').into()];
+    events.extend("yearly\n".chars().map(|c| Event::from(c).into()));
+
+    let mut ui = TestUiBuilder::with_ser_tasks(tasks).build();
+    let in_out = ui.handle(events).in_out();
+    let expected = InOut::Error("Encountered invalid recurrence 'yearly'".to_string());
+
+    assert_eq!(in_out, expected);
+    assert_eq!(ui.ser_tasks(), make_tasks(2));
+  }
+
+  #[test]
+  fn complete_recurring_task() {
+    let mut tasks = make_tasks(2);
+    tasks[0].recurrence = Some("daily".to_string());
+    let events = vec![
+      Event::from(' ').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    assert_eq!(tasks.len(), 3);
+    assert_eq!(tasks[0].summary, "1");
+    assert_eq!(tasks[0].recurrence, None);
+    assert!(!tasks[0].tags.is_empty());
+
+    assert_eq!(tasks[1].summary, "1");
+    assert_eq!(tasks[1].recurrence, Some("daily".to_string()));
+    assert!(tasks[1].tags.is_empty());
+    assert!(tasks[1].due.is_some());
+
+    assert_eq!(tasks[2], make_tasks(2)[1]);
+  }
+
+  #[test]
+  fn block_and_unblock_task() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('b').into(),
+      Event::from('j').into(),
+      Event::from('j').into(),
+      Event::from('B').into(),
+    ];
+
+    let mut ui = TestUiBuilder::with_ser_tasks(tasks).build();
+    let tasks = ui.handle(events).tasks();
+    assert!(!tasks[1].is_blocked());
+    assert_eq!(tasks[2].blocked_by().collect::<Vec<_>>(), vec![tasks[0].id()]);
+
+    // Completing the blocker unblocks the task.
+    let events = vec![
+      Event::from('g').into(),
+      Event::from(' ').into(),
+    ];
+    let tasks = ui.handle(events).tasks();
+    assert!(tasks[0].is_complete());
+    assert!(!tasks[2].is_blocked());
+  }
+
+  #[test]
+  fn block_task_invalid() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('b').into(),
+      Event::from('B').into(),
+    ];
+
+    let mut ui = TestUiBuilder::with_ser_tasks(tasks).build();
+    let in_out = ui.handle(events).in_out();
+    let expected = InOut::Error("A task can not block itself".to_string());
+    assert_eq!(in_out, expected);
+
+    // Task 2 is blocked by task 1, so task 1 can not be blocked by 2.
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('B').into(),
+      Event::from('b').into(),
+      Event::from('k').into(),
+      Event::from('B').into(),
+    ];
+    let in_out = ui.handle(events).in_out();
+    let expected = "A task can not be blocked by a task depending on it".to_string();
+    assert_eq!(in_out, InOut::Error(expected));
+
+    // Task 1 can also not be blocked by task 3, transitively.
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('j').into(),
+      Event::from('B').into(),
+      Event::from('b').into(),
+      Event::from('g').into(),
+      Event::from('B').into(),
+    ];
+    let in_out = ui.handle(events).in_out();
+    let expected = "A task can not be blocked by a task depending on it".to_string();
+    assert_eq!(in_out, InOut::Error(expected));
+
+    let tasks = ui.tasks();
+    assert!(!tasks[0].is_blocked());
+    assert_eq!(tasks[1].blocked_by().collect::<Vec<_>>(), vec![tasks[0].id()]);
+    assert_eq!(tasks[2].blocked_by().collect::<Vec<_>>(), vec![tasks[1].id()]);
+
+    // A completed task can not become a blocker.
+    let events = vec![
+      Event::from(' ').into(),
+      Event::from('b').into(),
+      Event::from('G').into(),
+      Event::from('B').into(),
+    ];
+    let in_out = ui.handle(events).in_out();
+    let expected = "Completed tasks can not block other tasks".to_string();
+    assert_eq!(in_out, InOut::Error(expected));
+  }
+
+  #[test]
+  fn archive_and_restore_task() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from(' ').into(),
+      Event::from('A').into(),
+    ];
+
+    let mut ui = TestUiBuilder::with_ser_tasks(tasks).build();
+    let summaries = ui
+      .handle(events)
+      .tasks()
+      .into_iter()
+      .map(|x| x.summary)
+      .collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["2", "3"]);
+
+    let events = vec![
+      Event::from('0').into(),
+      Event::from('U').into(),
+    ];
+    let tasks = ui.handle(events).ser_tasks();
+    let mut expected = make_tasks(3);
+    let task = expected.remove(0);
+    expected.push(task);
+
+    assert_eq!(tasks, expected);
+  }
+
+  #[test]
+  fn archive_is_read_only() {
+    let tasks = make_tasks(2);
+    let events = vec![
+      Event::from(' ').into(),
+      Event::from('A').into(),
+      Event::from('0').into(),
+      Event::from('d').into(),
+    ];
+
+    let mut ui = TestUiBuilder::with_ser_tasks(tasks).build();
+    let in_out = ui.handle(events).in_out();
+    let expected = InOut::Error("Archived tasks can not be changed".to_string());
+
+    assert_eq!(in_out, expected);
+    assert_eq!(ui.ser_tasks(), make_tasks(2)[1..].to_vec());
+  }
+
+  #[test]
+  fn save_archive() {
+    let tasks = make_tasks(2);
+    let events = vec![
+      Event::from(' ').into(),
+      Event::from('A').into(),
+      Event::from('w').into(),
+    ];
+
+    let state = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .load_state()
+      .unwrap();
+
+    let archive = state.0.archive();
+    let archive = archive.borrow();
+    let archived = archive.iter().collect::<Vec<_>>();
+    assert_eq!(archived.len(), 1);
+    assert_eq!(archived[0].summary, "1");
+    assert!(archived[0].is_complete());
+  }
+
+  #[test]
+  fn sorted_tab_order() {
+    let mut tasks = make_tasks(3);
+    tasks[0].summary = "c".to_string();
+    tasks[1].summary = "a".to_string();
+    tasks[2].summary = "b".to_string();
+    let ui_state = SerUiState {
+      queries: vec![
+        (SerQuery {
+          name: "by summary".to_string(),
+          sort: SerSort::Summary,
+          ..Default::default()
+        }, None),
+        (SerQuery {
+          name: "manual".to_string(),
+          ..Default::default()
+        }, None),
+      ],
+      selected: None,
+      archive_after: None,
+      ..Default::default()
+    };
+    let builder = TestUiBuilder {
+      task_state: SerTaskState {
+        templates: Default::default(),
+        tasks: SerTasks(tasks.clone()),
+        ..Default::default()
+      },
+      ui_state: ui_state,
+    };
+    let mut ui = builder.build();
+
+    // Moving tasks is not possible on a sorted tab.
+    let events = vec![
+      Event::from('J').into(),
+    ];
+    let in_out = ui.handle(events).in_out();
+    let error = "Tasks can only be moved on tabs sorted manually".to_string();
+    assert_eq!(in_out, InOut::Error(error));
+    assert_eq!(ui.ser_tasks(), tasks);
+
+    // Deleting the first task on the sorted tab deletes "a".
+    let events = vec![
+      Event::from('d').into(),
+    ];
+    let mut expected = tasks.clone();
+    let _ = expected.remove(1);
+    assert_eq!(ui.handle(events).ser_tasks(), expected);
+
+    // On the manual tab the order of the underlying tasks is used.
+    let events = vec![
+      Event::from('l').into(),
+      Event::from('J').into(),
+    ];
+    let expected = vec![expected[1].clone(), expected[0].clone()];
+    assert_eq!(ui.handle(events).ser_tasks(), expected);
+  }
+
+  #[test]
+  fn indent_outdent_and_move_subtasks() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('>').into(),
+      Event::from('>').into(),
+      Event::from('j').into(),
+      Event::from('>').into(),
+    ];
+
+    let mut ui = TestUiBuilder::with_ser_tasks(tasks).build();
+    let tasks = ui.handle(events).tasks();
+    let summaries = tasks.iter().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    let parents = tasks.iter().map(|x| x.parent()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "2", "3"]);
+    assert_eq!(parents, vec![None, Some(tasks[0].id()), Some(tasks[0].id())]);
+
+    // Moving a subtask only swaps it with its siblings.
+    let events = vec![
+      Event::from('k').into(),
+      Event::from('J').into(),
+      Event::from('J').into(),
+    ];
+    let tasks = ui.handle(events).tasks();
+    let summaries = tasks.iter().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "3", "2"]);
+
+    let events = vec![
+      Event::from('<').into(),
+    ];
+    let tasks = ui.handle(events).tasks();
+    let summaries = tasks.iter().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    let parents = tasks.iter().map(|x| x.parent()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "2", "3"]);
+    assert_eq!(parents, vec![None, None, Some(tasks[0].id())]);
+  }
+
+  #[test]
+  fn collapse_subtasks() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('>').into(),
+      Event::from('z').into(),
+      Event::from('k').into(),
+      Event::from('z').into(),
+      Event::from('j').into(),
+      Event::from('d').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    let mut expected = make_tasks(2);
+    expected[0].collapsed = true;
+    assert_eq!(tasks, expected);
+  }
+
+  #[test]
+  fn complete_task_with_subtasks() {
+    let tasks = make_tasks(3);
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('>').into(),
+      Event::from('k').into(),
+      Event::from('X').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .tasks();
+
+    let complete = tasks.iter().map(|x| x.is_complete()).collect::<Vec<_>>();
+    assert_eq!(complete, vec![true, true, false]);
+  }
+
+  #[test]
+  fn add_subtask() {
+    let tasks = make_tasks(2);
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('>').into(),
+      Event::from('a').into(),
+      Event::from('x').into(),
+      Event::from('\n').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .tasks();
+
+    let summaries = tasks.iter().map(|x| x.summary.clone()).collect::<Vec<_>>();
+    let parents = tasks.iter().map(|x| x.parent()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["1", "2", "x"]);
+    assert_eq!(parents, vec![None, Some(tasks[0].id()), Some(tasks[0].id())]);
+  }
+
+  #[test]
+  fn help_swallows_input() {
+    let tasks = make_tasks(2);
+    let events = vec![
+      Event::from('H').into(),
+      Event::from('d').into(),
+      Event::from('j').into(),
+      Event::from(' ').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(tasks.clone())
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    assert_eq!(tasks, make_tasks(2))
+  }
+
+  #[test]
+  fn help_close() {
+    fn test(open: Key, close: Key) {
+      let tasks = make_tasks(2);
+      let events = vec![
+        Event::from(open).into(),
+        Event::from(close).into(),
+        Event::from('d').into(),
+      ];
+
+      let tasks = TestUiBuilder::with_ser_tasks(tasks)
+        .build()
+        .handle(events)
+        .ser_tasks();
+
+      let mut expected = make_tasks(2);
+      let _ = expected.remove(0);
+      assert_eq!(tasks, expected)
+    }
+
+    test(Key::Char('H'), Key::Esc);
+    test(Key::Char('H'), Key::Char('H'));
+    test(Key::F(1), Key::Char('q'));
+    test(Key::F(1), Key::F(1));
+  }
+
+  #[test]
+  fn edit_tags() {
+    fn test(close: Key) -> Vec<String> {
+      let events = vec![
+        Event::from('t').into(),
+        // Tags are listed sorted by name, i.e., 'tag1', 'tag2', 'tag3'.
+        Event::from('j').into(),
+        Event::from(' ').into(),
+        Event::from('G').into(),
+        Event::from(' ').into(),
+        Event::from(close).into(),
+      ];
+
+      let tasks = TestUiBuilder::with_default_tasks_and_tags()
+        .build()
+        .handle(events)
+        .tasks();
+
+      tasks[0].tags().map(|x| x.name().to_string()).collect()
+    }
+
+    assert_eq!(test(Key::Char('\n')), vec!["tag2", "tag3"]);
+    assert_eq!(test(Key::Esc), Vec::<String>::new());
+  }
+
+  #[test]
+  fn set_tag_value() {
+    let events = vec![
+      Event::from('t').into(),
+      Event::from('j').into(),
+      Event::from('=').into(),
+      Event::from('3').into(),
+      Event::from('h').into(),
+      Event::from('\n').into(),
+      Event::from('\n').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_default_tasks_and_tags()
+      .build()
+      .handle(events)
+      .tasks();
+
+    let tags = tasks[0].tags().map(|x| x.to_string()).collect::<Vec<_>>();
+    assert_eq!(tags, vec!["tag2:3h"]);
+  }
+
+  #[test]
+  fn rename_tag() {
+    let events = vec![
+      Event::from('T').into(),
+      // Tags are listed sorted by name, i.e., 'tag1', 'tag2', 'tag3'.
+      Event::from('j').into(),
+      Event::from('r').into(),
+      Event::from(Key::Backspace).into(),
+      Event::from('t').into(),
+      Event::from('w').into(),
+      Event::from('o').into(),
+      Event::from('\n').into(),
+      Event::from(Key::Esc).into(),
+    ];
+
+    let tasks = TestUiBuilder::with_default_tasks_and_tags()
+      .build()
+      .handle(events)
+      .tasks();
+
+    let tags = tasks[8].tags().map(|x| x.name()).collect::<Vec<_>>();
+    assert_eq!(tags, vec!["tagtwo"]);
+  }
+
+  #[test]
+  fn merge_tags() {
+    let events = vec![
+      Event::from('T').into(),
+      Event::from('m').into(),
+      Event::from('t').into(),
+      Event::from('a').into(),
+      Event::from('g').into(),
+      Event::from('3').into(),
+      Event::from('\n').into(),
+      Event::from(Key::Esc).into(),
+      Event::from('w').into(),
+    ];
+
+    let state = TestUiBuilder::with_default_tasks_and_tags()
+      .build()
+      .handle(events)
+      .load_state()
+      .unwrap();
+    let State(task_state, ui_state) = state;
+    let templates = task_state.templates();
+    assert_eq!(templates.find("tag1"), None);
+
+    let tag3 = templates.find("tag3").unwrap();
+    let tasks = task_state.tasks();
+    let tagged = tasks.borrow().iter().filter(|x| x.has_tag(tag3)).count();
+    // Tasks 5 to 8, 11, 12, and 15 were tagged with 'tag1', tasks 13
+    // to 15 with 'tag3'.
+    assert_eq!(tagged, 9);
+
+    // The query "tag1 && tag3" now refers to 'tag3' only, matching all
+    // of these tasks.
+    let (query, _) = &ui_state.queries[3];
+    assert!(query.references_tag(tag3));
+    let count = query.iter().clone().count();
+    assert_eq!(count, 9);
+  }
+
+  #[test]
+  fn delete_tags() {
+    let events = vec![
+      Event::from('T').into(),
+      Event::from('d').into(),
+    ];
+
+    let in_out = TestUiBuilder::with_default_tasks_and_tags()
+      .build()
+      .handle(events)
+      .in_out();
+
+    let error = "Tag 'tag1' is still used by 7 tasks and 1 queries".to_string();
+    assert_eq!(in_out, InOut::Error(error));
+
+    let task_state = SerTaskState {
+      templates: SerTemplates(vec![SerTemplate {
+        id: SerId::new(1),
+        name: "unused".to_string(),
+        style: Default::default(),
+      }]),
+      tasks: SerTasks(make_tasks(2)),
+      ..Default::default()
+    };
+    let builder = TestUiBuilder {
+      task_state: task_state,
+      ui_state: Default::default(),
+    };
+    let events = vec![
+      Event::from('T').into(),
+      Event::from('d').into(),
+      Event::from(Key::Esc).into(),
+      Event::from('w').into(),
+    ];
+
+    let state = builder
+      .build()
+      .handle(events)
+      .load_state()
+      .unwrap();
+    assert_eq!(state.0.templates().find("unused"), None);
+  }
+
+  #[test]
+  fn in_out_state_after_write() {
+    let tasks = make_tasks(2);
+    let events = vec![
+      Event::from('w').into(),
+    ];
+
+    let state = TestUiBuilder::with_ser_tasks(tasks)
+      .build()
+      .handle(events)
+      .in_out();
+
+    assert_eq!(state, InOut::Saved);
+  }
+
+  #[test]
+  fn in_out_state_with_invalid_tags() {
+    // Without any templates all tag references are invalid.
+    let (_, _, tasks) = make_tasks_with_tags(8);
+    let count = tasks.iter().map(|x| x.tags.len()).sum::<usize>();
+    assert!(count > 1);
+
+    let builder = TestUiBuilder {
+      task_state: SerTaskState {
+        templates: Default::default(),
+        tasks: SerTasks(tasks),
+        ..Default::default()
+      },
+      ui_state: Default::default(),
+    };
+    let mut ui = builder.build();
+
+    let expected = format!("Dropped {} invalid tag references; save to repair", count);
+    assert_eq!(ui.in_out(), InOut::Error(expected));
+
+    let tasks = ui.tasks();
+    assert!(tasks.iter().all(|x| x.tags().next().is_none()));
+
+    let events = vec![
+      Event::from('w').into(),
+    ];
+    assert_eq!(ui.handle(events).in_out(), InOut::Saved);
+  }
+
+  #[test]
+  fn show_history_not_enabled() {
+    let events = vec![
+      Event::from('L').into(),
+    ];
+    let state = TestUiBuilder::with_ser_tasks(make_tasks(2))
+      .build()
+      .handle(events)
+      .in_out();
+    assert_eq!(state, InOut::Error("History is not enabled".to_string()));
+  }
+
+  #[test]
+  fn restore_task_from_history() {
+    let builder = TestUiBuilder {
+      task_state: SerTaskState {
+        tasks: SerTasks(make_tasks(3)),
+        ..Default::default()
+      },
+      ui_state: SerUiState {
+        history: true,
+        ..Default::default()
+      },
+    };
+    let events = vec![
+      Event::from('w').into(),
+      Event::from('d').into(),
+      Event::from('w').into(),
+      Event::from('L').into(),
+      Event::from('j').into(),
+      Event::from('\n').into(),
+      Event::from('\n').into(),
+      Event::from('w').into(),
+    ];
+    let mut ui = builder.build();
+    let tasks = ui.handle(events).ser_tasks();
+    let summaries = tasks.iter().map(|x| x.summary.as_str()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["2", "3", "1"]);
+
+    let state = ui.load_state().unwrap();
+    let versions = state.0.history().unwrap().versions().unwrap();
+    let summaries = versions.iter().map(|x| x.summary.as_str()).collect::<Vec<_>>();
+    assert_eq!(summaries, vec!["Add task '1'", "Remove task '1'", "Update tasks"]);
+  }
+
+  #[test]
+  fn in_out_state_after_write_and_key_press() {
+    fn with_key(key: impl Into<Event>) -> InOut {
+      let tasks = make_tasks(2);
+      let events = vec![
+        Event::from('w').into(),
+        key.into().into(),
+      ];
+
+      TestUiBuilder::with_ser_tasks(tasks)
+        .build()
+        .handle(events)
+        .in_out()
+    }
+
+    // We test all ASCII chars.
+    for c in 0u8..127u8 {
+      let c = c as char;
+      if c != 'a' && c != 'e' && c != 'n' && c != 'N' && c != 'w' && c != '/' && c != '?' &&
+         c != 'D' && c != 'R' && c != 'f' && c != 'L' {
+        assert_eq!(with_key(c), InOut::Clear, "char: {} ({})", c, c as u8);
+      }
+    }
+
+    assert_eq!(with_key(Key::Esc), InOut::Clear);
+    assert_eq!(with_key('\n'), InOut::Clear);
+    assert_eq!(with_key(Key::PageDown), InOut::Clear);
+  }
+
+  #[test]
+  fn in_out_state_on_edit() {
+    fn with_key(key: impl Into<Event>) -> InOut {
+      let tasks = make_tasks(4);
+      let events = vec![
+        Event::from('a').into(),
+        key.into().into(),
+      ];
+
+      TestUiBuilder::with_ser_tasks(tasks)
+        .build()
+        .handle(events)
+        .in_out()
+    }
+
+    for c in 0u8..127u8 {
+      if c == b'\n' {
+        continue
+      }
+
+      let state = with_key(c);
+      match state {
+        InOut::Input(_, _) => (),
+        _ => assert!(false, "Unexpected state {:?} for char {}", state, c),
+      }
+    }
+
+    assert_eq!(with_key(Key::Esc), InOut::Clear);
+    assert_eq!(with_key('\n'), InOut::Clear);
+  }
+
+  #[test]
+  fn search_abort() {
+    fn test(c: char) {
+      let tasks = make_tasks(4);
+      let events = vec![
+        Event::from(c).into(),
+        Event::from('f').into(),
+        Event::from(Key::Esc).into(),
+      ];
+
+      let state = TestUiBuilder::with_ser_tasks(tasks)
+        .build()
+        .handle(events)
+        .in_out();
+
+      assert_eq!(state, InOut::Clear);
+    }
+
+    test('/');
+    test('?');
+  }
+
+  #[test]
+  fn search_tasks_not_found() {
+    fn test(c: char) {
+      let tasks = make_tasks(8);
+      let events = vec![
+        Event::from(c).into(),
+        Event::from('f').into(),
+        Event::from('o').into(),
+        Event::from('o').into(),
+        Event::from('\n').into(),
+      ];
+
+      let state = TestUiBuilder::with_ser_tasks(tasks)
+        .build()
+        .handle(events)
+        .in_out();
+
+      let expected = InOut::Error("Text 'foo' not found".to_string());
+      assert_eq!(state, expected);
+    }
+
+    test('/');
+    test('?');
+  }
+
+  #[test]
+  fn search_multiple_tabs_not_found() {
+    fn test(c: char) {
+      let events = vec![
+        Event::from(c).into(),
+        Event::from('f').into(),
+        Event::from('o').into(),
+        Event::from('o').into(),
+        Event::from('\n').into(),
+      ];
+
+      let state = TestUiBuilder::with_default_tasks_and_tags()
+        .build()
+        .handle(events)
+        .in_out();
+
+      let expected = InOut::Error("Text 'foo' not found".to_string());
+      assert_eq!(state, expected);
+    }
+
+    test('/');
+    test('?');
+  }
+
+  #[test]
+  fn search_continue_without_start() {
+    fn test(c: char) {
+      let tasks = make_tasks(4);
+      let events = vec![
+        Event::from(c).into(),
+        Event::from(c).into(),
+        Event::from(c).into(),
+      ];
+
+      let state = TestUiBuilder::with_ser_tasks(tasks)
+        .build()
+        .handle(events)
+        .in_out();
+
+      let expected = InOut::Error("Nothing to search for".to_string());
+      assert_eq!(state, expected);
+    }
+
+    test('n');
+    test('N');
+  }
+
+  #[test]
+  fn search_tasks_repeatedly_not_found() {
+    fn test(c: char) {
+      let tasks = make_tasks(8);
+      let events = vec![
+        Event::from('/').into(),
+        Event::from('z').into(),
+        Event::from('\n').into(),
+        Event::from(c).into(),
+      ];
+
+      let state = TestUiBuilder::with_ser_tasks(tasks)
+        .build()
+        .handle(events)
+        .in_out();
+
+      let expected = InOut::Error("Text 'z' not found".to_string());
+      assert_eq!(state, expected);
+    }
+
+    test('n');
+    test('N');
+  }
+
+  #[test]
+  fn search_tasks() {
+    let tasks = make_tasks(12);
+    let events = vec![
+      Event::from('/').into(),
+      Event::from('2').into(),
+      Event::from('\n').into(),
+      Event::from('d').into(),
+      Event::from('n').into(),
       Event::from('d').into(),
     ];
 
@@ -1467,10 +2561,12 @@ mod tests {
         SerTask {
           summary: "First".to_string(),
           tags: Default::default(),
+          ..Default::default()
         },
         SerTask {
           summary: "SeCOnd".to_string(),
           tags: Default::default(),
+          ..Default::default()
         },
       ];
       let events = vec![
@@ -1492,6 +2588,7 @@ mod tests {
         SerTask {
           summary: "First".to_string(),
           tags: Default::default(),
+          ..Default::default()
         },
       ];
       assert_eq!(tasks, expected);
@@ -1612,6 +2709,31 @@ mod tests {
       Event::from('d').into(),
     ];
 
+    // Note that we use untagged tasks, as tag names are searched as
+    // well.
+    let tasks = TestUiBuilder::with_ser_tasks(make_tasks(15))
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    let mut expected = make_tasks(15);
+    expected[11].summary = "aa".to_string();
+    expected.remove(1);
+
+    assert_eq!(tasks, expected);
+  }
+
+  #[test]
+  fn search_tag_names() {
+    let events = vec![
+      Event::from('/').into(),
+      Event::from('t').into(),
+      Event::from('a').into(),
+      Event::from('g').into(),
+      Event::from('\n').into(),
+      Event::from('d').into(),
+    ];
+
     let tasks = TestUiBuilder::with_default_tasks_and_tags()
       .build()
       .handle(events)
@@ -1620,14 +2742,173 @@ mod tests {
       .map(|x| x.summary)
       .collect::<Vec<_>>();
 
+    // Task 5 is the first one with a tag other than 'complete'.
     let (.., mut expected) = make_tasks_with_tags(15);
-    expected[11].summary = "aa".to_string();
-    expected.remove(1);
+    expected.remove(4);
     let expected = expected.into_iter().map(|x| x.summary).collect::<Vec<_>>();
 
     assert_eq!(tasks, expected);
   }
 
+  #[test]
+  fn search_regex_and_fuzzy() {
+    fn test(pattern: &str, expected: usize) {
+      let mut events = vec![Event::from('/').into()];
+      events.extend(pattern.chars().map(|x| Event::from(x).into()));
+      events.push(Event::from('\n').into());
+      events.push(Event::from('d').into());
+
+      let tasks = TestUiBuilder::with_ser_tasks(make_tasks(15))
+        .build()
+        .handle(events)
+        .ser_tasks();
+
+      let mut tasks_ = make_tasks(15);
+      tasks_.remove(expected);
+      assert_eq!(tasks, tasks_);
+    }
+
+    test("re:^1.$", 9);
+    test("re:4|3", 2);
+    test("fz:15", 14);
+    test("fz:1 3", 12);
+  }
+
+  #[test]
+  fn search_incremental() {
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('j').into(),
+      Event::from('/').into(),
+      Event::from('1').into(),
+      Event::from('5').into(),
+      // Searches always start at the originally selected task, i.e.,
+      // '3', so we should end up at task '10'.
+      Event::from(Key::Backspace).into(),
+      Event::from('\n').into(),
+      Event::from('d').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(make_tasks(15))
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    let mut expected = make_tasks(15);
+    expected.remove(9);
+    assert_eq!(tasks, expected);
+  }
+
+  #[test]
+  fn search_incremental_cancel() {
+    let events = vec![
+      Event::from('j').into(),
+      Event::from('/').into(),
+      Event::from('1').into(),
+      Event::from('2').into(),
+    ];
+
+    // Jumping to matching tasks must not take away the focus from the
+    // input area.
+    let mut ui = TestUiBuilder::with_ser_tasks(make_tasks(15)).build();
+    let state = ui.handle(events).in_out();
+    assert_eq!(state, InOut::Input("12".to_string(), 2));
+
+    let events = vec![
+      Event::from(Key::Esc).into(),
+      Event::from('d').into(),
+    ];
+    let tasks = ui.handle(events).ser_tasks();
+
+    let mut expected = make_tasks(15);
+    expected.remove(1);
+    assert_eq!(tasks, expected);
+  }
+
+  #[test]
+  fn filter_tasks() {
+    let events = vec![
+      Event::from('f').into(),
+      Event::from('2').into(),
+      Event::from('\n').into(),
+      // Only tasks '2' and '12' are displayed now.
+      Event::from('G').into(),
+      Event::from('d').into(),
+      Event::from('F').into(),
+      Event::from('G').into(),
+      Event::from('d').into(),
+    ];
+
+    let tasks = TestUiBuilder::with_ser_tasks(make_tasks(15))
+      .build()
+      .handle(events)
+      .ser_tasks();
+
+    let mut expected = make_tasks(15);
+    expected.remove(14);
+    expected.remove(11);
+    assert_eq!(tasks, expected);
+  }
+
+  #[test]
+  fn filter_tasks_invalid() {
+    let events = vec![
+      Event::from('f').into(),
+      Event::from('r').into(),
+      Event::from('e').into(),
+      Event::from(':').into(),
+      Event::from('[').into(),
+      Event::from('\n').into(),
+    ];
+
+    let state = TestUiBuilder::with_ser_tasks(make_tasks(2))
+      .build()
+      .handle(events)
+      .in_out();
+
+    match state {
+      InOut::Error(error) => assert!(error.starts_with("Encountered invalid regular expression")),
+      state => panic!("Unexpected state: {:?}", state),
+    }
+  }
+
+  #[test]
+  fn search_invalid_regex() {
+    let events = vec![
+      Event::from('/').into(),
+      Event::from('r').into(),
+      Event::from('e').into(),
+      Event::from(':').into(),
+      Event::from('(').into(),
+      Event::from('\n').into(),
+    ];
+
+    let state = TestUiBuilder::with_ser_tasks(make_tasks(2))
+      .build()
+      .handle(events)
+      .in_out();
+
+    match state {
+      InOut::Error(error) => assert!(error.starts_with("Encountered invalid regular expression")),
+      state => panic!("Unexpected state: {:?}", state),
+    }
+
+    // Without a search pattern there is nothing to continue.
+    let events = vec![
+      Event::from('/').into(),
+      Event::from('r').into(),
+      Event::from('e').into(),
+      Event::from(':').into(),
+      Event::from('\n').into(),
+      Event::from('n').into(),
+    ];
+    let state = TestUiBuilder::with_ser_tasks(make_tasks(2))
+      .build()
+      .handle(events)
+      .in_out();
+    assert_eq!(state, InOut::Error("Nothing to search for".to_string()));
+  }
+
   #[test]
   fn valid_update_events() {
     for c in 0u8..127u8 {
@@ -1637,7 +2918,11 @@ mod tests {
         .map_or(false, |x| x.is_updated());
 
       let c = c as char;
-      let expected = c == '/' || c == '?' || c == 'a' || c == 'n' || c == 'N' || c == 'w';
+      // Selecting another tab is always possible, as there is the
+      // archive in addition to the default one.
+      let expected = c == '/' || c == '?' || c == 'a' || c == 'n' || c == 'N' || c == 'w' ||
+                     c == 'H' || c == 'T' || c == 'L' || c == 'l' || c == '0' || c == 'f' ||
+                     c == 'P' || ('2'..='9').contains(&c);
       assert_eq!(updated, expected, "char: {} ({})", c, c as u8);
     }
   }
@@ -1677,9 +2962,12 @@ mod tests {
         (SerQuery {
           name: "all".to_string(),
           lits: vec![],
+          ..Default::default()
         }, Some(0)),
       ],
       selected: Some(0),
+      archive_after: None,
+      ..Default::default()
     };
     assert_eq!(state, expected)
   }
@@ -1705,9 +2993,12 @@ mod tests {
         (SerQuery {
           name: "all".to_string(),
           lits: vec![],
+          ..Default::default()
         }, Some(2)),
       ],
       selected: Some(0),
+      archive_after: None,
+      ..Default::default()
     };
     assert_eq!(state, expected)
   }